@@ -0,0 +1,484 @@
+//! Admin HTTP endpoint for health checks.
+//!
+//! Serves a tiny read-only HTTP/1.0 interface on a side port so load
+//! balancers can probe the instance without speaking a cache protocol:
+//! - `GET /ready`: 200 once startup has finished, 503 before that
+//! - `GET /metrics`: cache and connection metrics in the Prometheus text
+//!   format, for scrapers and autoscalers
+//!
+//! Each connection is served on a thread of its own and cut off if its
+//! request takes too long or runs too large, so a stalled or slow client
+//! can't hold up a probe.
+//!
+//! Readiness is gated by a `Readiness` countdown: one count for the startup
+//! snapshot load plus one per worker thread. Each participant counts down
+//! once it is done, and the instance reports ready when the count hits zero.
+
+use crate::request::ConnectionStats;
+use crate::storage::Storage;
+use std::borrow::Cow;
+use std::fmt::{Display, Write as _};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// How long a probe may take to send its whole request.
+const REQUEST_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Most of a request read; a probe's is a few hundred bytes.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Startup countdown shared between the loader, workers, and admin endpoint.
+#[derive(Debug)]
+pub struct Readiness {
+    pending: AtomicUsize,
+}
+
+impl Readiness {
+    /// Create a gate that opens after `pending` calls to `count_down`.
+    pub fn new(pending: usize) -> Arc<Self> {
+        Arc::new(Self {
+            pending: AtomicUsize::new(pending),
+        })
+    }
+
+    /// Mark one startup participant as done.
+    pub fn count_down(&self) {
+        // Saturate at zero so a stray extra call can't wrap around
+        let _ = self
+            .pending
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+
+    /// Whether every participant has counted down.
+    pub fn is_ready(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+}
+
+/// Bind the admin listener and serve it on a background thread.
+///
+/// Returns the bound address (useful when binding port 0).
+pub fn spawn(
+    addr: SocketAddr,
+    readiness: Arc<Readiness>,
+    storage: Arc<Storage>,
+    connections: Arc<ConnectionStats>,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::Builder::new()
+        .name("admin".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!(error = %e, "Admin accept failed");
+                        continue;
+                    }
+                };
+                let (readiness, storage, connections) = (
+                    Arc::clone(&readiness),
+                    Arc::clone(&storage),
+                    Arc::clone(&connections),
+                );
+                let spawned = thread::Builder::new()
+                    .name("admin-client".to_string())
+                    .spawn(move || {
+                        if let Err(e) = handle_client(stream, &readiness, &storage, &connections) {
+                            debug!(error = %e, "Admin request failed");
+                        }
+                    });
+                if let Err(e) = spawned {
+                    debug!(error = %e, "Could not start an admin request thread");
+                }
+            }
+        })?;
+
+    info!(addr = %local_addr, "Admin endpoint listening");
+    Ok(local_addr)
+}
+
+/// Serve a single request and close the connection.
+fn handle_client(
+    stream: TcpStream,
+    readiness: &Readiness,
+    storage: &Storage,
+    connections: &ConnectionStats,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(RequestReader {
+        stream,
+        deadline: Instant::now() + REQUEST_DEADLINE,
+        budget: MAX_REQUEST_BYTES,
+    });
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain headers up to the blank line
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body): (_, Cow<str>) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/ready")) if readiness.is_ready() => ("200 OK", "ready\n".into()),
+        (Some("GET"), Some("/ready")) => ("503 Service Unavailable", "not ready\n".into()),
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics(storage, connections).into()),
+        (Some("GET"), Some(_)) => ("404 Not Found", "not found\n".into()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".into()),
+    };
+
+    let mut stream = reader.into_inner().stream;
+    write!(
+        stream,
+        "HTTP/1.0 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// The request half of an admin connection, failing once the request has
+/// taken `REQUEST_DEADLINE` in all, however slowly its bytes trickle in, or
+/// `MAX_REQUEST_BYTES`.
+struct RequestReader {
+    stream: TcpStream,
+    deadline: Instant,
+    /// Bytes the request may still take
+    budget: usize,
+}
+
+impl Read for RequestReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request took too long",
+            ));
+        }
+        if self.budget == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request too large",
+            ));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        let len = buf.len().min(self.budget);
+        let n = self.stream.read(&mut buf[..len])?;
+        self.budget -= n;
+        Ok(n)
+    }
+}
+
+/// Every metric in the Prometheus text format.
+///
+/// Counters follow `stats`, so `stats reset` restarts them, which scrapers
+/// see as a counter reset.
+fn metrics(storage: &Storage, connections: &ConnectionStats) -> String {
+    let stats = storage.stats();
+    let pressure = storage.pressure();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Display| {
+        let _ = write!(
+            out,
+            "# HELP grow_a_cache_{name} {help}\n\
+             # TYPE grow_a_cache_{name} {kind}\n\
+             grow_a_cache_{name} {value}\n"
+        );
+    };
+    metric("items", "gauge", "Items stored", &stats.item_count);
+    metric(
+        "memory_bytes",
+        "gauge",
+        "Memory charged for stored items",
+        &stats.memory_used,
+    );
+    metric(
+        "max_memory_bytes",
+        "gauge",
+        "Memory limit before eviction",
+        &stats.max_memory,
+    );
+    metric(
+        "hits_total",
+        "counter",
+        "Keys looked up and found",
+        &stats.get_hits,
+    );
+    metric(
+        "misses_total",
+        "counter",
+        "Keys looked up and missing or expired",
+        &stats.get_misses,
+    );
+    metric(
+        "evictions_total",
+        "counter",
+        "Live items evicted under memory pressure",
+        &stats.evictions,
+    );
+    metric(
+        "expired_total",
+        "counter",
+        "Expired items removed",
+        &stats.expired,
+    );
+    metric(
+        "connections",
+        "gauge",
+        "Client connections open",
+        &connections.open(),
+    );
+    metric(
+        "eviction_rate",
+        "gauge",
+        "Evictions per second over the last minute",
+        &pressure.eviction_rate,
+    );
+    metric(
+        "memory_pressure",
+        "gauge",
+        "Fraction of max_memory in use",
+        &pressure.memory_pressure,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{SnapshotFormat, Storage};
+
+    /// Issue a GET and return the status code and body.
+    fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, body.to_string())
+    }
+
+    /// Issue a GET and return the status code.
+    fn get_status(addr: SocketAddr, path: &str) -> u16 {
+        get(addr, path).0
+    }
+
+    /// Reader that trickles out its data to simulate a slow snapshot source.
+    struct SlowReader {
+        data: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(5));
+            let n = buf.len().min(16).min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    /// The value of metric `name` in a `/metrics` body.
+    fn sample(body: &str, name: &str) -> f64 {
+        body.lines()
+            .find_map(|line| line.strip_prefix(&format!("{name} ")))
+            .unwrap_or_else(|| panic!("no {name} in:\n{body}"))
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_readiness_countdown() {
+        let readiness = Readiness::new(2);
+        assert!(!readiness.is_ready());
+        readiness.count_down();
+        assert!(!readiness.is_ready());
+        readiness.count_down();
+        assert!(readiness.is_ready());
+
+        // Extra calls stay at zero
+        readiness.count_down();
+        assert!(readiness.is_ready());
+    }
+
+    #[test]
+    fn test_ready_during_slow_snapshot_load() {
+        let source = Storage::new(1024 * 1024, 0);
+        for i in 0..20 {
+            source.set(&format!("key{i}"), vec![b'x'; 32], 0, 0);
+        }
+        let mut snapshot = Vec::new();
+        source.dump(&mut snapshot, SnapshotFormat::Binary).unwrap();
+
+        // One count for the snapshot load, one for a single worker
+        let readiness = Readiness::new(2);
+        let addr = spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Arc::clone(&readiness),
+            Storage::new(1024 * 1024, 0),
+            Arc::default(),
+        )
+        .unwrap();
+        assert_eq!(get_status(addr, "/ready"), 503);
+
+        let storage = Storage::new(1024 * 1024, 0);
+        let loader = {
+            let storage = Arc::clone(&storage);
+            let readiness = Arc::clone(&readiness);
+            thread::spawn(move || {
+                let mut reader = SlowReader {
+                    data: snapshot,
+                    pos: 0,
+                };
+                let loaded = storage.load(&mut reader, SnapshotFormat::Binary).unwrap();
+                readiness.count_down();
+                loaded
+            })
+        };
+
+        // Still loading
+        assert_eq!(get_status(addr, "/ready"), 503);
+        assert_eq!(loader.join().unwrap(), 20);
+
+        // Snapshot done, worker not yet in its loop
+        assert_eq!(get_status(addr, "/ready"), 503);
+
+        readiness.count_down();
+        assert_eq!(get_status(addr, "/ready"), 200);
+        assert_eq!(get_status(addr, "/other"), 404);
+    }
+
+    #[test]
+    fn test_stalled_client_does_not_block_probes() {
+        let addr = spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Readiness::new(0),
+            Storage::new(1024 * 1024, 0),
+            Arc::default(),
+        )
+        .unwrap();
+
+        // Half a request line, then nothing
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET /re").unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let started = Instant::now();
+        assert_eq!(get_status(addr, "/ready"), 200);
+        assert!(started.elapsed() < REQUEST_DEADLINE);
+        drop(stalled);
+    }
+
+    #[test]
+    fn test_oversized_request_is_cut_off() {
+        let addr = spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Readiness::new(0),
+            Storage::new(1024 * 1024, 0),
+            Arc::default(),
+        )
+        .unwrap();
+
+        // A request line that never ends: dropped once over the cap,
+        // without an answer
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let line = format!("GET /{}", "x".repeat(2 * MAX_REQUEST_BYTES));
+        let _ = stream.write_all(line.as_bytes());
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        assert!(
+            response.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+
+        assert_eq!(get_status(addr, "/ready"), 200);
+    }
+
+    #[test]
+    fn test_metrics_report_pressure() {
+        let storage = Storage::new(64 * 1024, 0);
+        for i in 0..200 {
+            storage.set(&format!("key{i}"), vec![b'x'; 1024], 0, 0);
+        }
+        let addr = spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Readiness::new(0),
+            Arc::clone(&storage),
+            Arc::default(),
+        )
+        .unwrap();
+
+        let (status, body) = get(addr, "/metrics");
+        assert_eq!(status, 200);
+        let gauge = |name: &str| sample(&body, name);
+        assert!(gauge("grow_a_cache_eviction_rate") > 0.0);
+        let memory_used = storage.stats().memory_used as f64;
+        assert_eq!(
+            gauge("grow_a_cache_memory_pressure"),
+            memory_used / (64 * 1024) as f64
+        );
+    }
+
+    #[test]
+    fn test_metrics_count_operations() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let connections = Arc::<ConnectionStats>::default();
+        let addr = spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Readiness::new(0),
+            Arc::clone(&storage),
+            Arc::clone(&connections),
+        )
+        .unwrap();
+
+        storage.set("a", b"1".to_vec(), 0, 0);
+        storage.set("b", b"2".to_vec(), 0, 1);
+        storage.get("a");
+        storage.get("a");
+        storage.get("missing");
+        let open = [connections.opened(), connections.opened()];
+        thread::sleep(Duration::from_millis(1100));
+        storage.cleanup_expired();
+
+        let (status, body) = get(addr, "/metrics");
+        assert_eq!(status, 200);
+        assert!(
+            body.contains("# TYPE grow_a_cache_hits_total counter\n"),
+            "{body}"
+        );
+        assert!(body.contains("# TYPE grow_a_cache_items gauge\n"), "{body}");
+        let stats = storage.stats();
+        for (name, value) in [
+            ("grow_a_cache_items", 1.0),
+            ("grow_a_cache_memory_bytes", stats.memory_used as f64),
+            ("grow_a_cache_max_memory_bytes", 1024.0 * 1024.0),
+            ("grow_a_cache_hits_total", 2.0),
+            ("grow_a_cache_misses_total", 1.0),
+            ("grow_a_cache_evictions_total", 0.0),
+            ("grow_a_cache_expired_total", 1.0),
+            ("grow_a_cache_connections", 2.0),
+        ] {
+            assert_eq!(sample(&body, name), value, "{name}");
+        }
+
+        // Closing a connection drops the gauge
+        drop(open);
+        let (_, body) = get(addr, "/metrics");
+        assert_eq!(sample(&body, "grow_a_cache_connections"), 0.0);
+    }
+}
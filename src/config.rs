@@ -3,8 +3,12 @@
 //! Supports both command-line arguments and TOML configuration file.
 //! CLI arguments take precedence over config file values.
 
+use crate::privileges::Credentials;
+use crate::storage::{EvictionPolicy, SnapshotFormat};
 use clap::{Parser, ValueEnum};
 use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 /// Protocol type for the server
@@ -20,6 +24,8 @@ pub enum ProtocolType {
     Ping,
     /// Echo protocol (echoes back input, for throughput testing with varied sizes)
     Echo,
+    /// Memcached or RESP, told apart by each connection's first bytes
+    Auto,
 }
 
 /// Runtime backend for the server
@@ -39,14 +45,14 @@ pub enum RuntimeType {
 #[derive(Parser, Debug)]
 #[command(name = "grow-a-cache")]
 #[command(author = "grow-a-cache authors")]
-#[command(version = "0.1.0")]
+#[command(version)]
 #[command(about = "A memcached-compatible cache server", long_about = None)]
 pub struct CliArgs {
     /// Path to TOML configuration file
     #[arg(short, long)]
     pub config: Option<PathBuf>,
 
-    /// Address to bind to (e.g., 127.0.0.1:11211)
+    /// Address to bind to (e.g., 127.0.0.1:11211, or unix:/tmp/cache.sock)
     #[arg(short = 'l', long)]
     pub listen: Option<String>,
 
@@ -54,6 +60,10 @@ pub struct CliArgs {
     #[arg(short = 'm', long)]
     pub max_memory: Option<usize>,
 
+    /// Cap on the sum of stored value lengths, excluding per-item overhead
+    #[arg(long)]
+    pub max_value_bytes: Option<usize>,
+
     /// Default TTL for items in seconds (0 = no expiration)
     #[arg(short = 't', long)]
     pub default_ttl: Option<u64>,
@@ -66,7 +76,8 @@ pub struct CliArgs {
     #[arg(long, default_value = "info")]
     pub log_level: String,
 
-    /// Protocol to use (memcached or resp)
+    /// Protocol to use (memcached, resp, ping, echo, or auto to detect
+    /// memcached or RESP per connection)
     #[arg(long, value_enum, default_value = "memcached")]
     pub protocol: ProtocolType,
 
@@ -77,6 +88,46 @@ pub struct CliArgs {
     /// Maximum value size in bytes (e.g., 10485760 for 10MB)
     #[arg(long)]
     pub max_value_size: Option<usize>,
+
+    /// Maximum concurrent connections from a single IP (0 = unlimited)
+    #[arg(long)]
+    pub max_connections_per_ip: Option<usize>,
+
+    /// Address for the admin HTTP endpoint serving /ready and /metrics (disabled if unset)
+    #[arg(long)]
+    pub admin_listen: Option<String>,
+
+    /// Snapshot file to load at startup and save on shutdown
+    #[arg(long)]
+    pub snapshot_path: Option<PathBuf>,
+
+    /// Validate the configuration, print the resolved settings, and exit
+    #[arg(long)]
+    pub check_config: bool,
+
+    /// Frame echo payloads with a 4-byte big-endian length instead of ASCII
+    #[arg(long)]
+    pub echo_binary_framing: bool,
+
+    /// Stack size in bytes for worker threads (defaults to the platform's)
+    #[arg(long)]
+    pub worker_stack_size: Option<usize>,
+
+    /// Start in read-only mode (client writes are rejected)
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Deployment tag reported by `version` and `INFO`
+    #[arg(long)]
+    pub server_id: Option<String>,
+
+    /// User to switch to once the listeners are bound (when started as root)
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// Group to switch to with --user, instead of the user's primary group
+    #[arg(long)]
+    pub group: Option<String>,
 }
 
 /// TOML configuration file structure
@@ -88,19 +139,73 @@ pub struct TomlConfig {
     pub storage: StorageConfig,
     #[serde(default)]
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub disabled_commands: DisabledCommands,
 }
 
 /// Server-related configuration
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
-    /// Address to bind to
+    /// Address to bind to, or `unix:<path>` for a Unix domain socket
     #[serde(default = "default_listen")]
     pub listen: String,
     /// Number of worker threads
     pub workers: Option<usize>,
+    /// Stack size in bytes for worker threads
+    pub worker_stack_size: Option<usize>,
     /// Protocol to use
     #[serde(default)]
     pub protocol: ProtocolType,
+    /// Maximum concurrent connections from a single peer IP (0 = unlimited)
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+    /// Address for the admin HTTP endpoint (disabled if unset)
+    pub admin_listen: Option<String>,
+    /// Retry policy for transient listener bind failures
+    #[serde(default)]
+    pub bind_retry: BindRetry,
+    /// Frame echo payloads with a 4-byte big-endian length instead of ASCII
+    #[serde(default)]
+    pub echo_binary_framing: bool,
+    /// Most arguments accepted in a single RESP command
+    #[serde(default = "default_max_command_args")]
+    pub max_command_args: usize,
+    /// Highest estimated cost of a command that runs (0 = unlimited)
+    #[serde(default)]
+    pub max_command_cost: usize,
+    /// Longest a single command may take to fully arrive (0 = unbounded)
+    #[serde(default)]
+    pub command_assembly_timeout_secs: u64,
+    /// Close connections with no reads or writes for this long (0 = never)
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Suppress replies to memcached commands sent with `noreply`
+    #[serde(default = "default_honor_noreply")]
+    pub honor_noreply: bool,
+    /// Most large values a worker accumulates at once (0 = unlimited)
+    #[serde(default)]
+    pub max_concurrent_large_values: usize,
+    /// Most pool buffers a single response may be written from (0 = unlimited)
+    #[serde(default)]
+    pub max_response_chain_buffers: usize,
+    /// Pin each worker thread to one CPU
+    #[serde(default)]
+    pub pin_workers: bool,
+    /// Whether accepted connections set `TCP_NODELAY`, per protocol
+    #[serde(default)]
+    pub nodelay: NodelayByProtocol,
+    /// Deployment tag reported by `version` and `INFO`
+    pub server_id: Option<String>,
+    /// User to run as once the listeners are bound
+    pub user: Option<String>,
+    /// Group to run as with `user`, instead of its primary group
+    pub group: Option<String>,
+    /// Longest a SIGTERM/SIGINT shutdown waits for in-flight writes
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Input a connection processes per turn while replies are owed (0 = unlimited)
+    #[serde(default = "default_fair_share_bytes")]
+    pub fair_share_bytes: usize,
 }
 
 impl Default for ServerConfig {
@@ -108,7 +213,88 @@ impl Default for ServerConfig {
         Self {
             listen: default_listen(),
             workers: None,
+            worker_stack_size: None,
             protocol: ProtocolType::default(),
+            max_connections_per_ip: 0,
+            admin_listen: None,
+            bind_retry: BindRetry::default(),
+            echo_binary_framing: false,
+            max_command_args: default_max_command_args(),
+            max_command_cost: 0,
+            command_assembly_timeout_secs: 0,
+            idle_timeout_secs: 0,
+            honor_noreply: true,
+            max_concurrent_large_values: 0,
+            max_response_chain_buffers: 0,
+            pin_workers: false,
+            nodelay: NodelayByProtocol::default(),
+            server_id: None,
+            user: None,
+            group: None,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            fair_share_bytes: default_fair_share_bytes(),
+        }
+    }
+}
+
+/// Retry policy for binding the listener when the address is still in use
+/// (e.g. the previous process is closing its sockets during a restart).
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BindRetry {
+    /// Retries after the first failed bind (0 = fail immediately)
+    pub attempts: u32,
+    /// Backoff before the first retry, doubled after each one
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the backoff
+    pub max_backoff_ms: u64,
+}
+
+impl Default for BindRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 2000,
+        }
+    }
+}
+
+/// Whether each protocol's connections set `TCP_NODELAY`.
+///
+/// Request/response protocols want small replies sent at once. Echo is
+/// used for bulk transfers, where Nagle's coalescing saves packets.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NodelayByProtocol {
+    pub memcached: bool,
+    pub resp: bool,
+    pub ping: bool,
+    pub echo: bool,
+}
+
+impl Default for NodelayByProtocol {
+    fn default() -> Self {
+        Self {
+            memcached: true,
+            resp: true,
+            ping: true,
+            echo: false,
+        }
+    }
+}
+
+impl NodelayByProtocol {
+    /// Whether connections speaking `protocol` set `TCP_NODELAY`.
+    pub fn get(&self, protocol: ProtocolType) -> bool {
+        match protocol {
+            ProtocolType::Memcached => self.memcached,
+            ProtocolType::Resp => self.resp,
+            ProtocolType::Ping => self.ping,
+            ProtocolType::Echo => self.echo,
+            // Set before the client's protocol is known, so either wanting it
+            // is enough
+            ProtocolType::Auto => self.memcached || self.resp,
         }
     }
 }
@@ -119,24 +305,74 @@ pub struct StorageConfig {
     /// Maximum memory usage in bytes
     #[serde(default = "default_max_memory")]
     pub max_memory: usize,
+    /// Cap on the sum of stored value lengths (unset = only max_memory)
+    pub max_value_bytes: Option<usize>,
+    /// Which item a write evicts once a limit is reached
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
     /// Default TTL for items in seconds
     #[serde(default)]
     pub default_ttl: u64,
-    /// Interval for running expiration cleanup in seconds
+    /// Interval for running expiration cleanup in seconds (0 = never)
     #[serde(default = "default_cleanup_interval")]
     pub cleanup_interval: u64,
     /// Maximum value size in bytes
     #[serde(default = "default_max_value_size")]
     pub max_value_size: usize,
+    /// Value limit for memcached, overriding `max_value_size`
+    #[serde(default)]
+    pub memcached_max_value_size: Option<usize>,
+    /// Value limit for RESP, overriding `max_value_size`
+    #[serde(default)]
+    pub resp_max_value_size: Option<usize>,
+    /// Serialization format for snapshots
+    #[serde(default)]
+    pub snapshot_format: SnapshotFormat,
+    /// Snapshot file to load at startup
+    pub snapshot_path: Option<PathBuf>,
+    /// Start in read-only mode
+    #[serde(default)]
+    pub read_only: bool,
+    /// Flags given to items stored over RESP
+    #[serde(default)]
+    pub default_flags: u32,
+    /// How long a delete leaves a tombstone for meta recache flags (0 = off)
+    #[serde(default)]
+    pub tombstone_ttl_secs: u64,
+    /// How long any `mg` miss leases its recache to one client (0 = off)
+    #[serde(default)]
+    pub recache_lease_ms: u64,
+    /// Most items `stats sizes` examines before extrapolating (0 = all)
+    #[serde(default = "default_stats_sample_size")]
+    pub stats_sample_size: usize,
+    /// `incr`/`decr` on a missing key create it at 0 instead of `NOT_FOUND`
+    #[serde(default)]
+    pub incr_autocreate: bool,
+    /// Sample reads to report the most read keys
+    #[serde(default)]
+    pub hotkey_tracking: bool,
 }
 
 impl Default for StorageConfig {
     fn default() -> Self {
         Self {
             max_memory: default_max_memory(),
+            max_value_bytes: None,
+            eviction_policy: EvictionPolicy::default(),
             default_ttl: 0,
             cleanup_interval: default_cleanup_interval(),
             max_value_size: default_max_value_size(),
+            memcached_max_value_size: None,
+            resp_max_value_size: None,
+            snapshot_format: SnapshotFormat::default(),
+            snapshot_path: None,
+            read_only: false,
+            default_flags: 0,
+            tombstone_ttl_secs: 0,
+            recache_lease_ms: 0,
+            stats_sample_size: default_stats_sample_size(),
+            incr_autocreate: false,
+            hotkey_tracking: false,
         }
     }
 }
@@ -157,10 +393,76 @@ impl Default for LoggingConfig {
     }
 }
 
+/// Commands disabled per protocol, rejected as if they didn't exist.
+///
+/// Names are matched case-insensitively.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DisabledCommands {
+    /// Memcached command names (e.g. `flush_all`)
+    #[serde(default)]
+    pub memcached: HashSet<String>,
+    /// RESP command names (e.g. `FLUSHALL`, `KEYS`)
+    #[serde(default)]
+    pub resp: HashSet<String>,
+}
+
+impl DisabledCommands {
+    /// Normalize names to the case each protocol's dispatcher uses.
+    fn normalized(self) -> Self {
+        Self {
+            memcached: self.memcached.iter().map(|c| c.to_lowercase()).collect(),
+            resp: self.resp.iter().map(|c| c.to_uppercase()).collect(),
+        }
+    }
+
+    /// Whether a memcached command (lowercase name) is disabled.
+    pub fn memcached(&self, name: &str) -> bool {
+        self.memcached.contains(name)
+    }
+
+    /// Whether a RESP command (uppercase name) is disabled.
+    pub fn resp(&self, name: &str) -> bool {
+        self.resp.contains(name)
+    }
+}
+
+/// Smallest worker stack accepted; below this the event loop's own frames
+/// risk overflowing.
+pub const MIN_WORKER_STACK_SIZE: usize = 128 * 1024;
+
 fn default_listen() -> String {
     "127.0.0.1:11211".to_string()
 }
 
+/// Default bound on RESP command arguments.
+pub const DEFAULT_MAX_COMMAND_ARGS: usize = 64 * 1024;
+
+fn default_max_command_args() -> usize {
+    DEFAULT_MAX_COMMAND_ARGS
+}
+
+/// Default bound on items scanned for detailed stats.
+pub const DEFAULT_STATS_SAMPLE_SIZE: usize = 100_000;
+
+fn default_stats_sample_size() -> usize {
+    DEFAULT_STATS_SAMPLE_SIZE
+}
+
+fn default_honor_noreply() -> bool {
+    true
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+/// Default per-turn input share, a quarter of a connection's read buffer.
+const DEFAULT_FAIR_SHARE_BYTES: usize = 16 * 1024;
+
+fn default_fair_share_bytes() -> usize {
+    DEFAULT_FAIR_SHARE_BYTES
+}
+
 fn default_max_memory() -> usize {
     64 * 1024 * 1024 // 64 MB
 }
@@ -183,8 +485,15 @@ fn default_max_value_size() -> usize {
 pub struct Config {
     pub host: String,
     pub port: u16,
+    /// Unix domain socket to listen on instead of `host` and `port`
+    pub unix_socket: Option<PathBuf>,
     pub max_memory: usize,
+    /// Cap on the sum of stored value lengths (`None` = only `max_memory`)
+    pub max_value_bytes: Option<usize>,
+    /// LRU, LFU, random, or refusing writes when full
+    pub eviction_policy: EvictionPolicy,
     pub default_ttl: u64,
+    /// Seconds between background sweeps for expired items (0 = never)
     pub cleanup_interval: u64,
     pub workers: usize,
     pub log_level: String,
@@ -194,17 +503,78 @@ pub struct Config {
     pub ring_size: usize,
     pub buffer_size: usize,
     pub max_connections: usize,
+    /// Maximum concurrent connections from a single peer IP (0 = unlimited)
+    pub max_connections_per_ip: usize,
     pub batch_size: usize,
     /// Maximum size for a single value (requests with larger values are rejected)
     pub max_value_size: usize,
+    /// Memcached value limit; `max_value_size` applies when unset
+    pub memcached_max_value_size: Option<usize>,
+    /// RESP value limit; `max_value_size` applies when unset
+    pub resp_max_value_size: Option<usize>,
+    /// Serialization format for snapshots (binary by default, JSON for inspection)
+    pub snapshot_format: SnapshotFormat,
+    /// Snapshot file loaded at startup (readiness waits for it) and saved
+    /// after a graceful shutdown
+    pub snapshot_path: Option<PathBuf>,
+    /// Address for the admin HTTP endpoint serving `/ready`
+    pub admin_listen: Option<SocketAddr>,
+    /// Commands rejected as unknown, per protocol
+    pub disabled_commands: DisabledCommands,
+    /// Retry policy for transient listener bind failures
+    pub bind_retry: BindRetry,
+    /// Echo protocol uses binary (4-byte big-endian) length framing
+    pub echo_binary_framing: bool,
+    /// Stack size for worker threads (`None` = platform default)
+    pub worker_stack_size: Option<usize>,
+    /// Start with client writes rejected (toggle with `DEBUG READONLY`)
+    pub read_only: bool,
+    /// Most arguments accepted in a single RESP command
+    pub max_command_args: usize,
+    /// Reject commands estimated to cost more, e.g. touching more keys (0 = unlimited)
+    pub max_command_cost: usize,
+    /// Flags stamped on items stored over RESP (memcached stores send their own)
+    pub default_flags: u32,
+    /// Close connections whose current command takes longer to arrive (0 = never)
+    pub command_assembly_timeout_secs: u64,
+    /// Close connections that neither read nor write for this long (0 = never)
+    pub idle_timeout_secs: u64,
+    /// Suppress replies for `noreply`; off answers every command, for debugging
+    pub honor_noreply: bool,
+    /// Large values each worker may accumulate at once, others are refused (0 = unlimited)
+    pub max_concurrent_large_values: usize,
+    /// Responses needing more pool buffers than this are refused (0 = unlimited)
+    pub max_response_chain_buffers: usize,
+    /// Pin worker N to CPU N (mod CPU count), best-effort
+    pub pin_workers: bool,
+    /// `TCP_NODELAY` on accepted connections, per protocol
+    pub nodelay_by_protocol: NodelayByProtocol,
+    /// Deleted keys answer `mg` misses with recache flags for this long (0 = off)
+    pub tombstone_ttl_secs: u64,
+    /// Concurrent `mg` misses of a key elect one recacher for this long (0 = off)
+    pub recache_lease_ms: u64,
+    /// Items scanned for `stats sizes` before the result is extrapolated (0 = all)
+    pub stats_sample_size: usize,
+    /// Counters spring into existence at 0, Redis-style, rather than `NOT_FOUND`
+    pub incr_autocreate: bool,
+    /// Sample reads for `stats hotkeys` / `HOTKEYS`
+    pub hotkey_tracking: bool,
+    /// Tag appended to the `version` reply and reported as `INFO` `run_id`
+    pub server_id: Option<String>,
+    /// Drop root privileges to this user once the listeners are bound
+    pub user: Option<String>,
+    /// Group for `user`, in place of its primary group
+    pub group: Option<String>,
+    /// Shutdown closes connections still writing after this many seconds
+    pub shutdown_timeout_secs: u64,
+    /// Input bytes a connection's turn processes before others get theirs (0 = unlimited)
+    pub fair_share_bytes: usize,
 }
 
 impl Config {
-    /// Load configuration from CLI args and optional TOML file.
+    /// Load configuration from parsed CLI args and optional TOML file.
     /// CLI arguments take precedence over TOML file values.
-    pub fn load() -> Result<Self, ConfigError> {
-        let cli = CliArgs::parse();
-
+    pub fn from_cli(cli: CliArgs) -> Result<Self, ConfigError> {
         // Load TOML config if specified
         let toml_config = if let Some(ref config_path) = cli.config {
             let contents = std::fs::read_to_string(config_path)
@@ -216,12 +586,29 @@ impl Config {
 
         // Merge CLI args with TOML config (CLI takes precedence)
         let listen = cli.listen.unwrap_or(toml_config.server.listen);
-        let (host, port) = parse_listen_address(&listen)?;
+        let (host, port, unix_socket) = match listen.strip_prefix("unix:") {
+            Some(path) => (String::new(), 0, Some(PathBuf::from(path))),
+            None => {
+                let (host, port) = parse_listen_address(&listen)?;
+                (host, port, None)
+            }
+        };
+        let admin_listen = cli
+            .admin_listen
+            .or(toml_config.server.admin_listen)
+            .map(|addr| {
+                addr.parse()
+                    .map_err(|_| ConfigError::InvalidAddress(addr.clone()))
+            })
+            .transpose()?;
 
         Ok(Config {
             host,
             port,
+            unix_socket,
             max_memory: cli.max_memory.unwrap_or(toml_config.storage.max_memory),
+            max_value_bytes: cli.max_value_bytes.or(toml_config.storage.max_value_bytes),
+            eviction_policy: toml_config.storage.eviction_policy,
             default_ttl: cli.default_ttl.unwrap_or(toml_config.storage.default_ttl),
             cleanup_interval: toml_config.storage.cleanup_interval,
             workers: cli.workers.or(toml_config.server.workers).unwrap_or(0),
@@ -240,12 +627,157 @@ impl Config {
             ring_size: 4096,
             buffer_size: 64 * 1024, // 64KB per connection
             max_connections: 10000,
+            max_connections_per_ip: cli
+                .max_connections_per_ip
+                .unwrap_or(toml_config.server.max_connections_per_ip),
             batch_size: 64,
             max_value_size: cli
                 .max_value_size
                 .unwrap_or(toml_config.storage.max_value_size),
+            memcached_max_value_size: toml_config.storage.memcached_max_value_size,
+            resp_max_value_size: toml_config.storage.resp_max_value_size,
+            snapshot_format: toml_config.storage.snapshot_format,
+            snapshot_path: cli.snapshot_path.or(toml_config.storage.snapshot_path),
+            admin_listen,
+            disabled_commands: toml_config.disabled_commands.normalized(),
+            bind_retry: toml_config.server.bind_retry,
+            echo_binary_framing: cli.echo_binary_framing || toml_config.server.echo_binary_framing,
+            worker_stack_size: cli
+                .worker_stack_size
+                .or(toml_config.server.worker_stack_size),
+            read_only: cli.read_only || toml_config.storage.read_only,
+            max_command_args: toml_config.server.max_command_args,
+            max_command_cost: toml_config.server.max_command_cost,
+            default_flags: toml_config.storage.default_flags,
+            command_assembly_timeout_secs: toml_config.server.command_assembly_timeout_secs,
+            idle_timeout_secs: toml_config.server.idle_timeout_secs,
+            honor_noreply: toml_config.server.honor_noreply,
+            max_concurrent_large_values: toml_config.server.max_concurrent_large_values,
+            max_response_chain_buffers: toml_config.server.max_response_chain_buffers,
+            pin_workers: toml_config.server.pin_workers,
+            nodelay_by_protocol: toml_config.server.nodelay,
+            tombstone_ttl_secs: toml_config.storage.tombstone_ttl_secs,
+            recache_lease_ms: toml_config.storage.recache_lease_ms,
+            stats_sample_size: toml_config.storage.stats_sample_size,
+            incr_autocreate: toml_config.storage.incr_autocreate,
+            hotkey_tracking: toml_config.storage.hotkey_tracking,
+            server_id: cli.server_id.or(toml_config.server.server_id),
+            user: cli.user.or(toml_config.server.user),
+            group: cli.group.or(toml_config.server.group),
+            shutdown_timeout_secs: toml_config.server.shutdown_timeout_secs,
+            fair_share_bytes: toml_config.server.fair_share_bytes,
         })
     }
+
+    /// Check the resolved settings for values the server cannot run with.
+    ///
+    /// Every problem is reported, not just the first.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        match &self.unix_socket {
+            None if self.port == 0 => {
+                problems.push("listen port must not be 0".to_string());
+            }
+            None => {}
+            Some(path) if path.as_os_str().is_empty() => {
+                problems.push("unix socket path must not be empty".to_string());
+            }
+            Some(_) if self.runtime != RuntimeType::Mio => {
+                problems.push("listening on a unix socket requires the mio runtime".to_string());
+            }
+            Some(_) => {}
+        }
+        if self.max_memory == 0 {
+            problems.push("max_memory must be greater than 0".to_string());
+        }
+        if self.max_value_bytes == Some(0) {
+            problems.push("max_value_bytes must be greater than 0 when set".to_string());
+        }
+        if self.max_value_size == 0 {
+            problems.push("max_value_size must be greater than 0".to_string());
+        } else if self.max_value_size > self.max_memory {
+            problems.push(format!(
+                "max_value_size ({}) exceeds max_memory ({})",
+                self.max_value_size, self.max_memory
+            ));
+        }
+        for (name, limit) in [
+            ("memcached_max_value_size", self.memcached_max_value_size),
+            ("resp_max_value_size", self.resp_max_value_size),
+        ] {
+            match limit {
+                Some(0) => problems.push(format!("{name} must be greater than 0 when set")),
+                Some(limit) if limit > self.max_memory => problems.push(format!(
+                    "{name} ({limit}) exceeds max_memory ({})",
+                    self.max_memory
+                )),
+                _ => {}
+            }
+        }
+        if !["trace", "debug", "info", "warn", "error"].contains(&self.log_level.as_str()) {
+            problems.push(format!(
+                "log level '{}' is not one of trace, debug, info, warn, error",
+                self.log_level
+            ));
+        }
+        if self.bind_retry.max_backoff_ms < self.bind_retry.initial_backoff_ms {
+            problems.push(format!(
+                "bind_retry max_backoff_ms ({}) is below initial_backoff_ms ({})",
+                self.bind_retry.max_backoff_ms, self.bind_retry.initial_backoff_ms
+            ));
+        }
+        if self.max_command_args == 0 {
+            problems.push("max_command_args must be greater than 0".to_string());
+        }
+        if let Some(size) = self.worker_stack_size {
+            if size < MIN_WORKER_STACK_SIZE {
+                problems.push(format!(
+                    "worker_stack_size ({size}) is below the minimum of {MIN_WORKER_STACK_SIZE}"
+                ));
+            }
+        }
+        if let Some(ref id) = self.server_id {
+            if id.is_empty() || id.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                problems.push(format!(
+                    "server_id {id:?} must be non-empty, without spaces or control characters"
+                ));
+            }
+        }
+        match (&self.user, &self.group) {
+            (Some(user), group) => {
+                if let Err(e) = Credentials::resolve(user, group.as_deref()) {
+                    problems.push(format!("cannot run as user '{user}': {e}"));
+                }
+            }
+            (None, Some(group)) => {
+                problems.push(format!("group '{group}' is set without a user"));
+            }
+            (None, None) => {}
+        }
+        if let (Some(admin), None) = (self.admin_listen, &self.unix_socket) {
+            if admin.port() == self.port && admin.ip().to_string() == self.host {
+                problems.push(format!("admin_listen {admin} collides with listen"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// Number of worker threads to spawn (0 resolves to the CPU count).
+    pub fn worker_count(&self) -> usize {
+        if self.workers == 0 {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        } else {
+            self.workers
+        }
+    }
 }
 
 fn parse_listen_address(addr: &str) -> Result<(String, u16), ConfigError> {
@@ -265,6 +797,7 @@ pub enum ConfigError {
     FileRead(PathBuf, std::io::Error),
     TomlParse(PathBuf, toml::de::Error),
     InvalidAddress(String),
+    Invalid(Vec<String>),
 }
 
 impl std::fmt::Display for ConfigError {
@@ -279,6 +812,9 @@ impl std::fmt::Display for ConfigError {
             ConfigError::InvalidAddress(addr) => {
                 write!(f, "Invalid listen address '{addr}': expected host:port")
             }
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid configuration: {}", problems.join("; "))
+            }
         }
     }
 }
@@ -295,6 +831,10 @@ mod tests {
         assert_eq!(config.server.listen, "127.0.0.1:11211");
         assert_eq!(config.storage.max_memory, 64 * 1024 * 1024);
         assert_eq!(config.storage.default_ttl, 0);
+        assert_eq!(config.storage.snapshot_format, SnapshotFormat::Binary);
+        assert_eq!(config.storage.eviction_policy, EvictionPolicy::Lru);
+        assert!(config.server.nodelay.get(ProtocolType::Memcached));
+        assert!(!config.server.nodelay.get(ProtocolType::Echo));
     }
 
     #[test]
@@ -303,13 +843,36 @@ mod tests {
             [server]
             listen = "0.0.0.0:11211"
             workers = 4
+            admin_listen = "127.0.0.1:9090"
+            bind_retry = { attempts = 3, initial_backoff_ms = 10 }
+            nodelay = { resp = false, echo = true }
+            server_id = "cache-eu-1"
+            user = "nobody"
+            group = "nogroup"
+            shutdown_timeout_secs = 30
+            fair_share_bytes = 4096
+            max_command_cost = 1000
+            max_response_chain_buffers = 64
+            idle_timeout_secs = 300
 
             [storage]
             max_memory = 134217728
             default_ttl = 3600
+            snapshot_format = "json"
+            snapshot_path = "/var/lib/grow-a-cache/snapshot.bin"
+            default_flags = 7
+            stats_sample_size = 5000
+            eviction_policy = "noeviction"
+            incr_autocreate = true
+            hotkey_tracking = true
+            recache_lease_ms = 250
+            resp_max_value_size = 536870912
 
             [logging]
             level = "debug"
+
+            [disabled_commands]
+            resp = ["FlushAll", "keys"]
         "#;
 
         let config: TomlConfig = toml::from_str(toml_str).unwrap();
@@ -317,6 +880,151 @@ mod tests {
         assert_eq!(config.server.workers, Some(4));
         assert_eq!(config.storage.max_memory, 134217728);
         assert_eq!(config.storage.default_ttl, 3600);
+        assert_eq!(config.storage.snapshot_format, SnapshotFormat::Json);
+        assert_eq!(config.storage.default_flags, 7);
+        assert_eq!(config.storage.stats_sample_size, 5000);
+        assert_eq!(config.storage.eviction_policy, EvictionPolicy::NoEviction);
+        assert!(config.storage.incr_autocreate);
+        assert!(config.storage.hotkey_tracking);
+        assert_eq!(config.storage.recache_lease_ms, 250);
+        assert_eq!(config.storage.memcached_max_value_size, None);
+        assert_eq!(config.storage.resp_max_value_size, Some(536870912));
+        assert_eq!(config.server.server_id.as_deref(), Some("cache-eu-1"));
+        assert_eq!(config.server.user.as_deref(), Some("nobody"));
+        assert_eq!(config.server.group.as_deref(), Some("nogroup"));
+        assert_eq!(config.server.shutdown_timeout_secs, 30);
+        assert_eq!(config.server.fair_share_bytes, 4096);
+        assert_eq!(config.server.max_command_cost, 1000);
+        assert_eq!(config.server.max_response_chain_buffers, 64);
+        assert_eq!(config.server.idle_timeout_secs, 300);
+        assert_eq!(
+            config.server.admin_listen.as_deref(),
+            Some("127.0.0.1:9090")
+        );
+        assert_eq!(
+            config.server.bind_retry,
+            BindRetry {
+                attempts: 3,
+                initial_backoff_ms: 10,
+                max_backoff_ms: 2000,
+            }
+        );
+        assert_eq!(
+            config.storage.snapshot_path,
+            Some(PathBuf::from("/var/lib/grow-a-cache/snapshot.bin"))
+        );
+        assert_eq!(
+            config.server.nodelay,
+            NodelayByProtocol {
+                memcached: true,
+                resp: false,
+                ping: true,
+                echo: true,
+            }
+        );
         assert_eq!(config.logging.level, "debug");
+
+        let disabled = config.disabled_commands.normalized();
+        assert!(disabled.resp("FLUSHALL"));
+        assert!(disabled.resp("KEYS"));
+        assert!(!disabled.memcached("flush_all"));
+    }
+
+    fn config_from(args: &[&str]) -> Result<Config, ConfigError> {
+        let mut argv = vec!["grow-a-cache"];
+        argv.extend_from_slice(args);
+        Config::from_cli(CliArgs::parse_from(argv))
+    }
+
+    #[test]
+    fn test_unix_socket_listen() {
+        let config = config_from(&["--listen", "unix:/tmp/cache.sock"]).unwrap();
+        assert_eq!(config.unix_socket, Some(PathBuf::from("/tmp/cache.sock")));
+        assert!(config.validate().is_ok());
+
+        let config = config_from(&["--listen", "unix:/tmp/cache.sock", "--runtime", "uring"]);
+        match config.unwrap().validate() {
+            Err(ConfigError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 1, "{problems:?}");
+                assert!(problems[0].contains("mio runtime"));
+            }
+            other => panic!("Expected invalid config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_defaults() {
+        let config = config_from(&[]).unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let config = config_from(&[
+            "--max-memory",
+            "1024",
+            "--max-value-size",
+            "4096",
+            "--log-level",
+            "loud",
+            "--server-id",
+            "cache eu",
+        ])
+        .unwrap();
+
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 3, "{problems:?}");
+                assert!(problems[0].contains("max_value_size"));
+                assert!(problems[1].contains("loud"));
+                assert!(problems[2].contains("server_id"));
+            }
+            other => panic!("Expected invalid config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_user_and_group() {
+        let config = config_from(&["--user", "root"]).unwrap();
+        assert!(config.validate().is_ok());
+
+        for (args, expected) in [
+            (&["--user", "no-such-user-here"][..], "no such user"),
+            (
+                &["--user", "root", "--group", "no-such-group-here"],
+                "no such group",
+            ),
+            (&["--group", "root"], "without a user"),
+        ] {
+            match config_from(args).unwrap().validate() {
+                Err(ConfigError::Invalid(problems)) => {
+                    assert_eq!(problems.len(), 1, "{problems:?}");
+                    assert!(problems[0].contains(expected), "{problems:?}");
+                }
+                other => panic!("Expected invalid config, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_per_protocol_value_limits() {
+        let mut config = config_from(&["--max-memory", "1024", "--max-value-size", "512"]).unwrap();
+        config.memcached_max_value_size = Some(0);
+        config.resp_max_value_size = Some(4096);
+
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 2, "{problems:?}");
+                assert!(problems[0].contains("memcached_max_value_size"));
+                assert!(problems[1].contains("resp_max_value_size"));
+            }
+            other => panic!("Expected invalid config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check_config_flag() {
+        let cli = CliArgs::parse_from(["grow-a-cache", "--check-config"]);
+        assert!(cli.check_config);
     }
 }
@@ -0,0 +1,181 @@
+//! Sampled tracking of the most read keys.
+//!
+//! Only one read in `SAMPLE_RATE` is counted, picked at random so a client
+//! cycling through keys can't fall in step with the sampler, and a sample
+//! that finds the tracker busy is dropped rather than waited for. Counts are
+//! kept for at most `CAPACITY` keys using the space-saving algorithm: a new
+//! key takes over the smallest count plus one, which bounds memory while
+//! never losing a key hotter than that. Counts halve every `WINDOW`, so the
+//! report follows recent traffic.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One read in this many is counted.
+const SAMPLE_RATE: u64 = 16;
+
+/// Most keys counted at once.
+const CAPACITY: usize = 128;
+
+/// Counts halve once per window.
+const WINDOW: Duration = Duration::from_secs(60);
+
+thread_local! {
+    /// xorshift64 state, seeded per thread
+    static SAMPLER: Cell<u64> = Cell::new(RandomState::new().hash_one(0u8) | 1);
+}
+
+/// Whether this read is one of the sampled ones.
+fn sampled() -> bool {
+    SAMPLER.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x % SAMPLE_RATE == 0
+    })
+}
+
+pub(crate) struct HotKeys {
+    enabled: AtomicBool,
+    state: Mutex<Counts>,
+}
+
+struct Counts {
+    /// Sampled reads per key
+    counts: HashMap<String, u64>,
+    /// Start of the current window
+    window_start: Instant,
+}
+
+impl Counts {
+    /// Halve every count once per window elapsed, forgetting keys that reach 0.
+    fn decay(&mut self) {
+        let windows = (self.window_start.elapsed().as_secs() / WINDOW.as_secs()).min(63) as u32;
+        if windows == 0 {
+            return;
+        }
+        self.counts.retain(|_, count| {
+            *count >>= windows;
+            *count > 0
+        });
+        self.window_start += WINDOW * windows;
+    }
+}
+
+impl HotKeys {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            state: Mutex::new(Counts {
+                counts: HashMap::new(),
+                window_start: Instant::now(),
+            }),
+        }
+    }
+
+    /// Start or stop tracking; stopping forgets what was counted.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.state.lock().unwrap().counts.clear();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Note a read of `key`, if tracking is on and the read is sampled.
+    pub(crate) fn record(&self, key: &str) {
+        if !self.is_enabled() || !sampled() {
+            return;
+        }
+        let Ok(mut state) = self.state.try_lock() else {
+            return;
+        };
+        state.decay();
+
+        if let Some(count) = state.counts.get_mut(key) {
+            *count += 1;
+            return;
+        }
+        let mut count = 1;
+        if state.counts.len() >= CAPACITY {
+            let (coldest, min) = state
+                .counts
+                .iter()
+                .min_by_key(|(_, &count)| count)
+                .map(|(key, &count)| (key.clone(), count))
+                .expect("tracker is at capacity");
+            state.counts.remove(&coldest);
+            count += min;
+        }
+        state.counts.insert(key.to_string(), count);
+    }
+
+    /// Up to `n` keys with their estimated reads, hottest first.
+    pub(crate) fn top(&self, n: usize) -> Vec<(String, u64)> {
+        let mut state = self.state.lock().unwrap();
+        state.decay();
+        let mut top: Vec<(String, u64)> = state
+            .counts
+            .iter()
+            .map(|(key, &count)| (key.clone(), count * SAMPLE_RATE))
+            .collect();
+        top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_keys_rise_above_many_cold_ones() {
+        let tracker = HotKeys::new();
+        tracker.record("ignored");
+        assert!(tracker.top(10).is_empty());
+
+        tracker.set_enabled(true);
+        // Far more cold keys than the tracker has room for
+        for round in 0..2000 {
+            for hot in ["hot-a", "hot-b", "hot-c"] {
+                tracker.record(hot);
+            }
+            tracker.record(&format!("cold-{}", round % 1000));
+        }
+
+        let top = tracker.top(3);
+        let mut keys: Vec<&str> = top.iter().map(|(key, _)| key.as_str()).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["hot-a", "hot-b", "hot-c"], "{top:?}");
+        // Roughly 2000 reads each, estimated from samples
+        assert!(
+            top.iter().all(|(_, reads)| (1000..4000).contains(reads)),
+            "{top:?}"
+        );
+
+        tracker.set_enabled(false);
+        assert!(tracker.top(10).is_empty());
+    }
+
+    #[test]
+    fn test_counts_halve_each_window() {
+        let mut counts = Counts {
+            counts: HashMap::from([("a".to_string(), 8), ("b".to_string(), 1)]),
+            window_start: Instant::now() - WINDOW * 2,
+        };
+        counts.decay();
+        assert_eq!(counts.counts, HashMap::from([("a".to_string(), 2)]));
+        assert!(counts.window_start.elapsed() < WINDOW);
+    }
+}
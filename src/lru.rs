@@ -0,0 +1,221 @@
+//! Intrusive doubly-linked list ordering keys by recency of use.
+//!
+//! Nodes live in a slab and link to each other by slot index, with a side
+//! index from key to slot, so marking a key used, removing it, and finding
+//! the least recently used key are all O(1).
+
+use slab::Slab;
+use std::collections::HashMap;
+
+/// Slot index standing in for a null link.
+const NIL: usize = usize::MAX;
+
+struct Node<V> {
+    key: String,
+    value: V,
+    /// Next more recently used node
+    prev: usize,
+    /// Next less recently used node
+    next: usize,
+}
+
+/// Keys ordered from most to least recently used, each carrying a `V`.
+pub(crate) struct LruList<V> {
+    nodes: Slab<Node<V>>,
+    index: HashMap<String, usize>,
+    /// Most recently used
+    head: usize,
+    /// Least recently used
+    tail: usize,
+}
+
+impl<V> LruList<V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: Slab::new(),
+            index: HashMap::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    /// Mark `key` most recently used, replacing its value.
+    pub(crate) fn touch(&mut self, key: &str, value: V) {
+        if let Some(&slot) = self.index.get(key) {
+            self.nodes[slot].value = value;
+            self.detach(slot);
+            self.push_front(slot);
+            return;
+        }
+        let slot = self.nodes.insert(Node {
+            key: key.to_string(),
+            value,
+            prev: NIL,
+            next: NIL,
+        });
+        self.index.insert(key.to_string(), slot);
+        self.push_front(slot);
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&V> {
+        self.index.get(key).map(|&slot| &self.nodes[slot].value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<V> {
+        let slot = self.index.remove(key)?;
+        self.detach(slot);
+        Some(self.nodes.remove(slot).value)
+    }
+
+    /// The least recently used key.
+    #[cfg(test)]
+    pub(crate) fn back(&self) -> Option<&str> {
+        (self.tail != NIL).then(|| self.nodes[self.tail].key.as_str())
+    }
+
+    /// Keys from least to most recently used.
+    pub(crate) fn iter_from_back(&self) -> impl Iterator<Item = &str> + '_ {
+        let mut slot = self.tail;
+        std::iter::from_fn(move || {
+            let node = self.nodes.get(slot)?;
+            slot = node.prev;
+            Some(node.key.as_str())
+        })
+    }
+
+    /// An arbitrary key chosen by `seed`, e.g. a random number.
+    ///
+    /// Probes slab slots from `seed`, so it is O(1) unless the slab is
+    /// mostly vacant.
+    pub(crate) fn pick(&self, seed: u64) -> Option<&str> {
+        if self.index.is_empty() {
+            return None;
+        }
+        let slots = self.nodes.capacity();
+        let start = (seed % slots as u64) as usize;
+        (0..slots)
+            .map(|i| (start + i) % slots)
+            .find_map(|slot| self.nodes.get(slot))
+            .map(|node| node.key.as_str())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.index.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Unlink `slot` from its neighbours, leaving it in the slab.
+    fn detach(&mut self, slot: usize) {
+        let (prev, next) = {
+            let node = &self.nodes[slot];
+            (node.prev, node.next)
+        };
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        let old_head = self.head;
+        {
+            let node = &mut self.nodes[slot];
+            node.prev = NIL;
+            node.next = old_head;
+        }
+        match old_head {
+            NIL => self.tail = slot,
+            old_head => self.nodes[old_head].prev = slot,
+        }
+        self.head = slot;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys from least to most recently used.
+    fn order<V>(list: &LruList<V>) -> Vec<&str> {
+        list.iter_from_back().collect()
+    }
+
+    #[test]
+    fn test_touch_moves_to_front() {
+        let mut list = LruList::new();
+        list.touch("a", 1);
+        list.touch("b", 2);
+        list.touch("c", 3);
+        assert_eq!(order(&list), ["a", "b", "c"]);
+
+        // From the back, the middle, and the front
+        list.touch("a", 10);
+        assert_eq!(order(&list), ["b", "c", "a"]);
+        list.touch("c", 30);
+        assert_eq!(order(&list), ["b", "a", "c"]);
+        list.touch("c", 31);
+        assert_eq!(order(&list), ["b", "a", "c"]);
+
+        assert_eq!(list.back(), Some("b"));
+        assert_eq!(list.get("a"), Some(&10));
+        assert_eq!(list.get("c"), Some(&31));
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_remove_relinks_neighbours() {
+        let mut list = LruList::new();
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            list.touch(key, i);
+        }
+
+        assert_eq!(list.remove("b"), Some(1));
+        assert_eq!(order(&list), ["a", "c", "d"]);
+        assert_eq!(list.remove("a"), Some(0));
+        assert_eq!(list.back(), Some("c"));
+        assert_eq!(list.remove("d"), Some(3));
+        assert_eq!(order(&list), ["c"]);
+        assert_eq!(list.remove("missing"), None);
+
+        assert_eq!(list.remove("c"), Some(2));
+        assert_eq!(list.back(), None);
+        assert_eq!(list.len(), 0);
+
+        // Freed slots are reused without stale links
+        list.touch("e", 4);
+        list.touch("f", 5);
+        assert_eq!(order(&list), ["e", "f"]);
+
+        list.clear();
+        assert_eq!(list.back(), None);
+        assert_eq!(list.pick(7), None);
+        assert!(list.get("e").is_none());
+    }
+
+    #[test]
+    fn test_pick_finds_live_keys() {
+        let mut list = LruList::new();
+        for i in 0..8 {
+            list.touch(&i.to_string(), i);
+        }
+        for i in 0..6 {
+            list.remove(&i.to_string());
+        }
+
+        // Every seed lands on one of the two left, both are reachable
+        let picked: std::collections::HashSet<_> =
+            (0..16).filter_map(|seed| list.pick(seed)).collect();
+        assert_eq!(picked, ["6", "7"].into_iter().collect());
+    }
+}
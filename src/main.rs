@@ -10,19 +10,33 @@
 //! - Memory usage capping with LRU eviction
 //! - Configuration via CLI arguments or TOML file
 
+mod admin;
 mod config;
+mod hotkeys;
+mod lru;
+mod privileges;
 mod protocols;
 mod request;
 mod runtime;
+mod scan;
 mod storage;
+mod transaction;
 
-use config::{Config, RuntimeType};
+use clap::Parser;
+use config::{CliArgs, Config, RuntimeType};
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load configuration
-    let config = Config::load()?;
+    let cli = CliArgs::parse();
+    let check_only = cli.check_config;
+    let config = Config::from_cli(cli)?;
+
+    if check_only {
+        check_config(&config);
+    }
+    config.validate()?;
 
     // Initialize logging
     let filter =
@@ -33,9 +47,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_target(false)
         .init();
 
+    let listen = match &config.unix_socket {
+        Some(path) => format!("unix:{}", path.display()),
+        None => format!("{}:{}", config.host, config.port),
+    };
     info!(
-        host = %config.host,
-        port = config.port,
+        listen,
         protocol = ?config.protocol,
         runtime = ?config.runtime,
         max_memory_mb = config.max_memory / 1024 / 1024,
@@ -49,6 +66,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+/// Validate `config` and exit without binding sockets or allocating storage.
+///
+/// Prints the resolved settings and exits 0 if valid, otherwise prints the
+/// problems to stderr and exits 1.
+fn check_config(config: &Config) -> ! {
+    match config.validate() {
+        Ok(()) => {
+            println!("{config:#?}");
+            println!("Configuration OK");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
 /// Run with mio runtime (epoll on Linux, kqueue on macOS)
 fn run_mio(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     info!("Using mio runtime (epoll/kqueue)");
@@ -0,0 +1,161 @@
+//! Dropping root privileges once the listeners are bound.
+//!
+//! A server started as root, e.g. to bind a port below 1024, switches to
+//! the configured `user` before its workers serve anything: supplementary
+//! groups and the gid first, while still allowed to, then the uid. The C
+//! library applies each change to every thread, so threads started earlier
+//! (like the admin endpoint's) lose root too.
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::ptr;
+use tracing::info;
+
+/// The account the server runs as after binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+impl Credentials {
+    /// Look up `user`, with `group` in place of the user's primary group if
+    /// given.
+    pub fn resolve(user: &str, group: Option<&str>) -> io::Result<Self> {
+        let (uid, primary_gid) = lookup_user(user)?;
+        let gid = match group {
+            Some(group) => lookup_group(group)?,
+            None => primary_gid,
+        };
+        Ok(Self { uid, gid })
+    }
+}
+
+/// Switch the process to `user` (and `group`), if a user is configured.
+///
+/// Already running as that account is fine; otherwise this needs root, and
+/// fails rather than serving with privileges it was told to give up.
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>) -> io::Result<()> {
+    let Some(user) = user else {
+        return Ok(());
+    };
+    let Credentials { uid, gid } = Credentials::resolve(user, group)?;
+    // SAFETY: these calls only read the process's ids
+    if unsafe { (libc::geteuid(), libc::getegid()) } == (uid, gid) {
+        return Ok(());
+    }
+
+    // SAFETY: plain system calls taking ids; `gid` outlives `setgroups`
+    unsafe {
+        check(libc::setgroups(1, &gid), "setgroups")?;
+        check(libc::setgid(gid), "setgid")?;
+        check(libc::setuid(uid), "setuid")?;
+        // Had root somehow been kept, it could be regained
+        if uid != 0 && libc::setuid(0) == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "root privileges were not dropped",
+            ));
+        }
+    }
+    info!(user, uid, gid, "Dropped privileges");
+    Ok(())
+}
+
+/// Turn a failed (-1) system call into an error naming it.
+fn check(result: libc::c_int, call: &str) -> io::Result<()> {
+    if result == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    Err(io::Error::new(e.kind(), format!("{call} failed: {e}")))
+}
+
+/// Uid and primary gid of the user called `name`.
+fn lookup_user(name: &str) -> io::Result<(libc::uid_t, libc::gid_t)> {
+    let c_name = c_string(name)?;
+    // SAFETY: `passwd` is plain data that getpwnam_r fills in
+    let mut entry: libc::passwd = unsafe { mem::zeroed() };
+    let found = lookup(|buf, found: &mut *mut libc::passwd| unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            found,
+        )
+    })?;
+    if !found {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such user '{name}'"),
+        ));
+    }
+    Ok((entry.pw_uid, entry.pw_gid))
+}
+
+/// Gid of the group called `name`.
+fn lookup_group(name: &str) -> io::Result<libc::gid_t> {
+    let c_name = c_string(name)?;
+    // SAFETY: `group` is plain data that getgrnam_r fills in
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let found = lookup(|buf, found: &mut *mut libc::group| unsafe {
+        libc::getgrnam_r(
+            c_name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            found,
+        )
+    })?;
+    if !found {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such group '{name}'"),
+        ));
+    }
+    Ok(entry.gr_gid)
+}
+
+/// Run a `get*nam_r` call, growing its string buffer until the entry fits.
+/// Returns whether an entry was found.
+fn lookup<T>(
+    mut call: impl FnMut(&mut Vec<libc::c_char>, &mut *mut T) -> libc::c_int,
+) -> io::Result<bool> {
+    let mut buf = vec![0; 1024];
+    loop {
+        let mut found = ptr::null_mut();
+        match call(&mut buf, &mut found) {
+            0 => return Ok(!found.is_null()),
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            errno => return Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+fn c_string(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name:?} contains a NUL byte"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_accounts() {
+        let root = Credentials::resolve("root", None).unwrap();
+        assert_eq!(root, Credentials { uid: 0, gid: 0 });
+
+        let err = Credentials::resolve("no-such-user-here", None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("no-such-user-here"), "{err}");
+        let err = Credentials::resolve("root", Some("no-such-group-here")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
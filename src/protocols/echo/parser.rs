@@ -1,4 +1,8 @@
 //! Echo protocol parser.
+//!
+//! Two framings are supported:
+//! - ASCII: `<length>\r\n<data>`, plus `QUIT\r\n`
+//! - Binary: a 4-byte big-endian length followed by exactly that many bytes
 
 /// Parsed echo command.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,7 +11,7 @@ pub enum Command {
     Echo {
         /// Length of data to echo.
         length: usize,
-        /// Bytes consumed by the header (length + \r\n).
+        /// Bytes consumed by the header (length + \r\n, or 4 for binary).
         header_len: usize,
     },
     /// Quit command.
@@ -59,6 +63,29 @@ pub fn parse(input: &[u8]) -> ParseResult {
     ParseResult::Complete(Command::Echo { length, header_len })
 }
 
+/// Length of a binary frame header.
+pub const BINARY_HEADER_LEN: usize = 4;
+
+/// Parse a binary-framed echo command (4-byte big-endian length prefix).
+pub fn parse_binary(input: &[u8]) -> ParseResult {
+    match input.first_chunk::<BINARY_HEADER_LEN>() {
+        Some(prefix) => ParseResult::Complete(Command::Echo {
+            length: u32::from_be_bytes(*prefix) as usize,
+            header_len: BINARY_HEADER_LEN,
+        }),
+        None => ParseResult::Incomplete,
+    }
+}
+
+/// Format a binary echo response header.
+pub fn binary_response_header(length: usize, output: &mut [u8]) -> usize {
+    if output.len() < BINARY_HEADER_LEN {
+        return 0;
+    }
+    output[..BINARY_HEADER_LEN].copy_from_slice(&(length as u32).to_be_bytes());
+    BINARY_HEADER_LEN
+}
+
 /// Format an echo response header.
 pub fn response_header(length: usize, output: &mut [u8]) -> usize {
     let header = format!("{length}\r\n");
@@ -119,6 +146,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_binary() {
+        match parse_binary(&[0, 1, 0, 2, b'x']) {
+            ParseResult::Complete(Command::Echo { length, header_len }) => {
+                assert_eq!(length, 65538);
+                assert_eq!(header_len, 4);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(matches!(parse_binary(&[0, 0, 1]), ParseResult::Incomplete));
+
+        let mut buf = [0u8; 4];
+        assert_eq!(binary_response_header(258, &mut buf), 4);
+        assert_eq!(buf, [0, 0, 1, 2]);
+    }
+
     #[test]
     fn test_response_header() {
         let mut buf = [0u8; 20];
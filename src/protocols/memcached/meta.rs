@@ -0,0 +1,423 @@
+//! Memcached meta protocol commands.
+//!
+//! Meta commands take a key followed by single-letter flags that select what
+//! the server returns, so clients only pay for the fields they need:
+//! - `mg <key> <flags>*`: meta get
+//! - `ms <key> <datalen> <flags>*`: meta set, followed by a data block
+//! - `md <key> <flags>*`: meta delete
+//!
+//! A get replies `VA <size> <flags>` with a data block when the value is
+//! requested, `HD <flags>` for a metadata-only hit, and `EN` for a miss. A
+//! set or delete that took effect replies `HD <flags>`, and a delete of a
+//! missing key `NF`.
+//! An opaque token (`O<token>`) is echoed on every response, so pipelining
+//! clients can match replies to requests.
+//!
+//! A miss on a key deleted moments ago (with tombstones enabled) carries a
+//! recache flag for stampede control: `W` tells the first client it won the
+//! job of repopulating the key, `Z` tells the rest a winner already exists.
+
+use super::parser::{parse_exptime, Command, ParseError, ParseResult, MAX_KEY_LENGTH};
+
+/// Longest opaque token accepted, as in memcached.
+pub const MAX_OPAQUE_LENGTH: usize = 32;
+
+/// Flags requested on a meta command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetaFlags {
+    /// `v`: return the value
+    pub value: bool,
+    /// `f`: return client flags
+    pub return_flags: bool,
+    /// `t`: return remaining TTL in seconds (-1 for no expiry)
+    pub return_ttl: bool,
+    /// `c`: return the CAS value
+    pub return_cas: bool,
+    /// `k`: return the key
+    pub return_key: bool,
+    /// `s`: return the value size
+    pub return_size: bool,
+    /// `q`: quiet mode, suppress the `EN` miss response, and `HD` for a
+    /// set or delete
+    pub quiet: bool,
+    /// `T<ttl>`: set the TTL, as a storage command's exptime; on a get,
+    /// touch the item
+    pub ttl: Option<u64>,
+    /// `F<flags>`: client flags to store with a set
+    pub client_flags: Option<u32>,
+    /// `O<token>`: opaque token echoed back in the response
+    pub opaque: Option<String>,
+}
+
+impl MetaFlags {
+    /// Parse flag tokens following the key.
+    pub fn parse(tokens: &[&str]) -> Result<Self, ParseError> {
+        let mut flags = MetaFlags::default();
+        for token in tokens {
+            match token.as_bytes() {
+                [b'v'] => flags.value = true,
+                [b'f'] => flags.return_flags = true,
+                [b't'] => flags.return_ttl = true,
+                [b'c'] => flags.return_cas = true,
+                [b'k'] => flags.return_key = true,
+                [b's'] => flags.return_size = true,
+                [b'q'] => flags.quiet = true,
+                [b'T', ttl @ ..] if !ttl.is_empty() => match parse_exptime(&token[1..]) {
+                    Some(ttl) => flags.ttl = Some(ttl),
+                    None => return Err(ParseError::InvalidNumber(format!("Invalid TTL: {token}"))),
+                },
+                [b'F', client_flags @ ..] if !client_flags.is_empty() => match token[1..].parse() {
+                    Ok(client_flags) => flags.client_flags = Some(client_flags),
+                    Err(_) => {
+                        return Err(ParseError::InvalidNumber(format!(
+                            "Invalid client flags: {token}"
+                        )))
+                    }
+                },
+                [b'O', opaque @ ..] if !opaque.is_empty() => {
+                    if opaque.len() > MAX_OPAQUE_LENGTH {
+                        return Err(ParseError::InvalidCommand(format!(
+                            "Opaque token too long: {token}"
+                        )));
+                    }
+                    flags.opaque = Some(token[1..].to_string());
+                }
+                _ => {
+                    return Err(ParseError::InvalidCommand(format!(
+                        "Invalid meta flag: {token}"
+                    )))
+                }
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// Item fields a meta response may echo back.
+pub struct MetaItem<'a> {
+    pub key: &'a str,
+    pub value: &'a [u8],
+    pub flags: u32,
+    pub cas: u64,
+    /// Remaining TTL in seconds, `None` if the item never expires
+    pub ttl: Option<u64>,
+}
+
+/// Parse `mg <key> <flags>*`.
+pub fn parse_get(parts: &[&str], command_bytes: usize) -> ParseResult {
+    if parts.len() < 2 {
+        return ParseResult::Error(ParseError::InvalidCommand("mg requires a key".to_string()));
+    }
+
+    let key = parts[1];
+    if key.len() > MAX_KEY_LENGTH {
+        return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
+    }
+
+    match MetaFlags::parse(&parts[2..]) {
+        Ok(flags) => ParseResult::Complete(
+            Command::MetaGet {
+                key: key.to_string(),
+                flags,
+            },
+            command_bytes,
+        ),
+        Err(e) => ParseResult::Error(e),
+    }
+}
+
+/// Parse `ms <key> <datalen> <flags>*`, the line before the data block.
+pub fn parse_set(parts: &[&str], command_bytes: usize) -> ParseResult {
+    if parts.len() < 3 {
+        return ParseResult::Error(ParseError::InvalidCommand(
+            "ms requires a key and data length".to_string(),
+        ));
+    }
+
+    let key = parts[1];
+    if key.len() > MAX_KEY_LENGTH {
+        return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
+    }
+    let Ok(bytes) = parts[2].parse::<usize>() else {
+        return ParseResult::Error(ParseError::InvalidNumber(format!(
+            "Invalid data length: {}",
+            parts[2]
+        )));
+    };
+
+    match MetaFlags::parse(&parts[3..]) {
+        Ok(flags) => ParseResult::Complete(
+            Command::MetaSet {
+                key: key.to_string(),
+                bytes,
+                flags,
+            },
+            command_bytes,
+        ),
+        // The data block is on its way regardless, so it gets skipped
+        Err(_) => ParseResult::Error(ParseError::BadCommandLine {
+            command_bytes,
+            data_bytes: bytes,
+        }),
+    }
+}
+
+/// Parse `md <key> <flags>*`.
+pub fn parse_delete(parts: &[&str], command_bytes: usize) -> ParseResult {
+    if parts.len() < 2 {
+        return ParseResult::Error(ParseError::InvalidCommand("md requires a key".to_string()));
+    }
+
+    let key = parts[1];
+    if key.len() > MAX_KEY_LENGTH {
+        return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
+    }
+
+    match MetaFlags::parse(&parts[2..]) {
+        Ok(flags) => ParseResult::Complete(
+            Command::MetaDelete {
+                key: key.to_string(),
+                flags,
+            },
+            command_bytes,
+        ),
+        Err(e) => ParseResult::Error(e),
+    }
+}
+
+/// Build the response for a meta get hit.
+///
+/// The value block is only included when the `v` flag was requested.
+pub fn hit_response(flags: &MetaFlags, item: &MetaItem) -> Vec<u8> {
+    let mut ret = String::new();
+    if flags.return_flags {
+        ret.push_str(&format!(" f{}", item.flags));
+    }
+    if flags.return_ttl {
+        match item.ttl {
+            Some(secs) => ret.push_str(&format!(" t{secs}")),
+            None => ret.push_str(" t-1"),
+        }
+    }
+    if flags.return_cas {
+        ret.push_str(&format!(" c{}", item.cas));
+    }
+    if flags.return_key {
+        ret.push_str(&format!(" k{}", item.key));
+    }
+    if flags.return_size {
+        ret.push_str(&format!(" s{}", item.value.len()));
+    }
+    ret.push_str(&opaque(flags));
+
+    let mut response = Vec::new();
+    if flags.value {
+        response.extend_from_slice(format!("VA {}{ret}\r\n", item.value.len()).as_bytes());
+        response.extend_from_slice(item.value);
+        response.extend_from_slice(b"\r\n");
+    } else {
+        response.extend_from_slice(format!("HD{ret}\r\n").as_bytes());
+    }
+    response
+}
+
+/// Recache state reported on a miss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recache {
+    /// `W`: this client should repopulate the key
+    Won,
+    /// `Z`: another client already won the recache
+    Claimed,
+}
+
+/// Build the response for a meta get miss.
+///
+/// Quiet mode only suppresses plain misses; a recache flag is always sent,
+/// since the client has to act on it.
+pub fn miss_response(flags: &MetaFlags, recache: Option<Recache>) -> Vec<u8> {
+    let recache = match recache {
+        Some(Recache::Won) => " W",
+        Some(Recache::Claimed) => " Z",
+        None if flags.quiet => return Vec::new(),
+        None => "",
+    };
+    format!("EN{recache}{}\r\n", opaque(flags)).into_bytes()
+}
+
+/// Build the `HD` response for a meta set or delete that took effect, with
+/// the new CAS value when there is one. Quiet mode sends nothing.
+pub fn done_response(flags: &MetaFlags, key: &str, cas: Option<u64>) -> Vec<u8> {
+    if flags.quiet {
+        return Vec::new();
+    }
+    let mut ret = String::new();
+    if let (true, Some(cas)) = (flags.return_cas, cas) {
+        ret.push_str(&format!(" c{cas}"));
+    }
+    if flags.return_key {
+        ret.push_str(&format!(" k{key}"));
+    }
+    ret.push_str(&opaque(flags));
+    format!("HD{ret}\r\n").into_bytes()
+}
+
+/// Build the `NF` response for a meta delete of a missing key.
+pub fn not_found_response(flags: &MetaFlags, key: &str) -> Vec<u8> {
+    let key = match flags.return_key {
+        true => format!(" k{key}"),
+        false => String::new(),
+    };
+    format!("NF{key}{}\r\n", opaque(flags)).into_bytes()
+}
+
+/// The ` O<token>` response flag, or nothing if no token was sent.
+fn opaque(flags: &MetaFlags) -> String {
+    match &flags.opaque {
+        Some(token) => format!(" O{token}"),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::memcached::parser::Parser;
+
+    fn item(value: &[u8]) -> MetaItem<'_> {
+        MetaItem {
+            key: "foo",
+            value,
+            flags: 30,
+            cas: 7,
+            ttl: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_mg() {
+        match Parser::parse(b"mg foo v f t\r\n") {
+            ParseResult::Complete(Command::MetaGet { key, flags }, consumed) => {
+                assert_eq!(key, "foo");
+                assert!(flags.value && flags.return_flags && flags.return_ttl);
+                assert!(!flags.return_cas);
+                assert_eq!(consumed, 14);
+            }
+            other => panic!("Expected MetaGet, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_ms() {
+        // The data block is read like a classic storage command's
+        match Parser::parse(b"ms foo 3 T60 F7 c\r\nbar\r\n") {
+            ParseResult::NeedData {
+                command_bytes,
+                data_bytes,
+            } => assert_eq!((command_bytes, data_bytes), (19, 3)),
+            other => panic!("Expected NeedData, got {other:?}"),
+        }
+        match Parser::parse_storage_header(b"ms foo 3 T60 F7 c\r\nbar\r\n") {
+            ParseResult::Complete(Command::MetaSet { key, bytes, flags }, consumed) => {
+                assert_eq!((key.as_str(), bytes, consumed), ("foo", 3, 19));
+                assert_eq!(flags.ttl, Some(60));
+                assert_eq!(flags.client_flags, Some(7));
+                assert!(flags.return_cas);
+            }
+            other => panic!("Expected MetaSet, got {other:?}"),
+        }
+
+        match Parser::parse(b"ms foo 3 Tx\r\nbar\r\n") {
+            ParseResult::Error(ParseError::BadCommandLine {
+                command_bytes,
+                data_bytes,
+            }) => assert_eq!((command_bytes, data_bytes), (13, 3)),
+            other => panic!("Expected a bad command line, got {other:?}"),
+        }
+        assert!(matches!(
+            Parser::parse(b"ms foo\r\n"),
+            ParseResult::Error(ParseError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_md() {
+        match Parser::parse(b"md foo q k\r\n") {
+            ParseResult::Complete(Command::MetaDelete { key, flags }, consumed) => {
+                assert_eq!(key, "foo");
+                assert!(flags.quiet && flags.return_key);
+                assert_eq!(consumed, 12);
+            }
+            other => panic!("Expected MetaDelete, got {other:?}"),
+        }
+        assert!(matches!(
+            Parser::parse(b"md foo F\r\n"),
+            ParseResult::Error(ParseError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_and_delete_responses() {
+        let flags = MetaFlags::parse(&["c", "k", "O9"]).unwrap();
+        assert_eq!(done_response(&flags, "foo", Some(4)), b"HD c4 kfoo O9\r\n");
+        assert_eq!(done_response(&flags, "foo", None), b"HD kfoo O9\r\n");
+        assert_eq!(not_found_response(&flags, "foo"), b"NF kfoo O9\r\n");
+
+        let quiet = MetaFlags::parse(&["q"]).unwrap();
+        assert_eq!(done_response(&quiet, "foo", Some(4)), b"");
+        assert_eq!(not_found_response(&quiet, "foo"), b"NF\r\n");
+    }
+
+    #[test]
+    fn test_parse_mg_invalid_flag() {
+        match Parser::parse(b"mg foo z\r\n") {
+            ParseResult::Error(ParseError::InvalidCommand(_)) => {}
+            other => panic!("Expected error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_hit_without_value() {
+        let flags = MetaFlags::parse(&["f"]).unwrap();
+        assert_eq!(hit_response(&flags, &item(b"bar")), b"HD f30\r\n");
+
+        let flags = MetaFlags::default();
+        assert_eq!(hit_response(&flags, &item(b"bar")), b"HD\r\n");
+    }
+
+    #[test]
+    fn test_hit_with_value() {
+        let flags = MetaFlags::parse(&["v", "f", "t", "c"]).unwrap();
+        assert_eq!(
+            hit_response(&flags, &item(b"bar")),
+            b"VA 3 f30 t-1 c7\r\nbar\r\n"
+        );
+    }
+
+    #[test]
+    fn test_miss() {
+        assert_eq!(miss_response(&MetaFlags::default(), None), b"EN\r\n");
+        assert_eq!(miss_response(&MetaFlags::parse(&["q"]).unwrap(), None), b"");
+        assert_eq!(
+            miss_response(&MetaFlags::parse(&["q"]).unwrap(), Some(Recache::Won)),
+            b"EN W\r\n"
+        );
+    }
+
+    #[test]
+    fn test_opaque_round_trips() {
+        let flags = MetaFlags::parse(&["Oabc", "v"]).unwrap();
+        assert_eq!(flags.opaque.as_deref(), Some("abc"));
+        assert_eq!(hit_response(&flags, &item(b"bar")), b"VA 3 Oabc\r\nbar\r\n");
+        assert_eq!(miss_response(&flags, None), b"EN Oabc\r\n");
+        assert_eq!(
+            miss_response(&flags, Some(Recache::Claimed)),
+            b"EN Z Oabc\r\n"
+        );
+
+        let flags = MetaFlags::parse(&["O17", "c"]).unwrap();
+        assert_eq!(hit_response(&flags, &item(b"bar")), b"HD c7 O17\r\n");
+
+        assert!(MetaFlags::parse(&["O"]).is_err());
+        assert!(MetaFlags::parse(&[&format!("O{}", "x".repeat(33))]).is_err());
+    }
+}
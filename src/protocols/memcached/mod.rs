@@ -1,3 +1,4 @@
 //! Memcached text protocol implementation.
 
+pub mod meta;
 pub mod parser;
@@ -5,13 +5,22 @@
 //! - Storage: set, add, replace, append, prepend, cas
 //! - Deletion: delete
 //! - Other: flush_all, stats, version, quit
+//! - Meta: mg, ms, md (see the `meta` module)
 
+use super::meta::{self, MetaFlags};
+use crate::storage::EXPIRED_TTL;
 use bytes::{Bytes, BytesMut};
 use std::str;
 
 /// Maximum key length allowed by memcached protocol
 pub const MAX_KEY_LENGTH: usize = 250;
 
+#[cfg(test)]
+thread_local! {
+    /// Storage command lines parsed on this thread, for asserting reuse.
+    pub static HEADER_PARSES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 /// Parsed memcached command
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
@@ -21,6 +30,12 @@ pub enum Command {
     /// Get one or more keys with CAS tokens
     Gets { keys: Vec<String> },
 
+    /// Get one or more keys, giving each a new expiration
+    Gat { exptime: u64, keys: Vec<String> },
+
+    /// Get one or more keys with CAS tokens, giving each a new expiration
+    Gats { exptime: u64, keys: Vec<String> },
+
     /// Store a value
     Set {
         key: String,
@@ -79,6 +94,13 @@ pub enum Command {
     /// Delete a key
     Delete { key: String, noreply: bool },
 
+    /// Update a key's expiration without fetching it
+    Touch {
+        key: String,
+        exptime: u64,
+        noreply: bool,
+    },
+
     /// Increment a numeric value
     Incr {
         key: String,
@@ -96,14 +118,118 @@ pub enum Command {
     /// Flush all items (optionally with delay)
     FlushAll { delay: u64, noreply: bool },
 
-    /// Get server statistics
-    Stats,
+    /// Get server statistics, or one detailed group (e.g. `stats sizes`)
+    Stats { group: Option<String> },
 
     /// Get server version
     Version,
 
     /// Close connection
     Quit,
+
+    /// Meta get: return the metadata (and optionally value) selected by flags
+    MetaGet { key: String, flags: MetaFlags },
+
+    /// Meta set: store a value, with its TTL and client flags given as flags
+    MetaSet {
+        key: String,
+        bytes: usize,
+        flags: MetaFlags,
+    },
+
+    /// Meta delete
+    MetaDelete { key: String, flags: MetaFlags },
+}
+
+impl Command {
+    /// Command name as sent on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Get { .. } => "get",
+            Command::Gets { .. } => "gets",
+            Command::Gat { .. } => "gat",
+            Command::Gats { .. } => "gats",
+            Command::Set { .. } => "set",
+            Command::Add { .. } => "add",
+            Command::Replace { .. } => "replace",
+            Command::Append { .. } => "append",
+            Command::Prepend { .. } => "prepend",
+            Command::Cas { .. } => "cas",
+            Command::Delete { .. } => "delete",
+            Command::Touch { .. } => "touch",
+            Command::Incr { .. } => "incr",
+            Command::Decr { .. } => "decr",
+            Command::FlushAll { .. } => "flush_all",
+            Command::Stats { .. } => "stats",
+            Command::Version => "version",
+            Command::Quit => "quit",
+            Command::MetaGet { .. } => "mg",
+            Command::MetaSet { .. } => "ms",
+            Command::MetaDelete { .. } => "md",
+        }
+    }
+
+    /// Whether the command modifies stored data.
+    pub fn is_write(&self) -> bool {
+        if let Command::MetaGet { flags, .. } = self {
+            // Touches the item
+            return flags.ttl.is_some();
+        }
+        matches!(
+            self,
+            Command::Set { .. }
+                | Command::Add { .. }
+                | Command::Replace { .. }
+                | Command::Append { .. }
+                | Command::Prepend { .. }
+                | Command::Cas { .. }
+                | Command::Delete { .. }
+                | Command::Touch { .. }
+                | Command::Gat { .. }
+                | Command::Gats { .. }
+                | Command::Incr { .. }
+                | Command::Decr { .. }
+                | Command::FlushAll { .. }
+                | Command::MetaSet { .. }
+                | Command::MetaDelete { .. }
+        )
+    }
+
+    /// Drop the client's `noreply`, so the command is answered.
+    pub fn clear_noreply(&mut self) {
+        match self {
+            Command::Set { noreply, .. }
+            | Command::Add { noreply, .. }
+            | Command::Replace { noreply, .. }
+            | Command::Append { noreply, .. }
+            | Command::Prepend { noreply, .. }
+            | Command::Cas { noreply, .. }
+            | Command::Delete { noreply, .. }
+            | Command::Touch { noreply, .. }
+            | Command::Incr { noreply, .. }
+            | Command::Decr { noreply, .. }
+            | Command::FlushAll { noreply, .. } => *noreply = false,
+            // Quiet mode is the meta commands' noreply
+            Command::MetaGet { flags, .. }
+            | Command::MetaSet { flags, .. }
+            | Command::MetaDelete { flags, .. } => flags.quiet = false,
+            _ => {}
+        }
+    }
+
+    /// Size of the data block that follows a storage command line.
+    pub fn data_len(&self) -> Option<usize> {
+        match *self {
+            Command::Set { bytes, .. }
+            | Command::Add { bytes, .. }
+            | Command::Replace { bytes, .. }
+            | Command::Append { bytes, .. }
+            | Command::Prepend { bytes, .. }
+            | Command::Cas { bytes, .. }
+            | Command::MetaSet { bytes, .. } => Some(bytes),
+            _ => None,
+        }
+    }
 }
 
 /// Protocol parsing errors
@@ -119,6 +245,13 @@ pub enum ParseError {
     InvalidNumber(String),
     /// Unknown command
     UnknownCommand(String),
+    /// Storage command with out-of-range flags or exptime, or a trailing
+    /// token other than `noreply`; the sizes let the caller skip the data
+    /// block that follows
+    BadCommandLine {
+        command_bytes: usize,
+        data_bytes: usize,
+    },
 }
 
 impl std::fmt::Display for ParseError {
@@ -129,6 +262,7 @@ impl std::fmt::Display for ParseError {
             ParseError::KeyTooLong(key) => write!(f, "Key too long: {key}"),
             ParseError::InvalidNumber(msg) => write!(f, "Invalid number: {msg}"),
             ParseError::UnknownCommand(cmd) => write!(f, "Unknown command: {cmd}"),
+            ParseError::BadCommandLine { .. } => write!(f, "bad command line format"),
         }
     }
 }
@@ -181,6 +315,8 @@ impl Parser {
         match command_name.as_str() {
             "get" => Self::parse_get(&parts, false, command_line_bytes),
             "gets" => Self::parse_get(&parts, true, command_line_bytes),
+            "gat" => Self::parse_gat(&parts, false, command_line_bytes),
+            "gats" => Self::parse_gat(&parts, true, command_line_bytes),
             "set" => Self::parse_storage(&parts, "set", command_line_bytes),
             "add" => Self::parse_storage(&parts, "add", command_line_bytes),
             "replace" => Self::parse_storage(&parts, "replace", command_line_bytes),
@@ -188,16 +324,40 @@ impl Parser {
             "prepend" => Self::parse_storage(&parts, "prepend", command_line_bytes),
             "cas" => Self::parse_cas(&parts, command_line_bytes),
             "delete" => Self::parse_delete(&parts, command_line_bytes),
+            "touch" => Self::parse_touch(&parts, command_line_bytes),
             "incr" => Self::parse_incr_decr(&parts, true, command_line_bytes),
             "decr" => Self::parse_incr_decr(&parts, false, command_line_bytes),
             "flush_all" => Self::parse_flush_all(&parts, command_line_bytes),
-            "stats" => ParseResult::Complete(Command::Stats, command_line_bytes),
+            "stats" => ParseResult::Complete(
+                Command::Stats {
+                    group: parts.get(1).map(|group| group.to_string()),
+                },
+                command_line_bytes,
+            ),
             "version" => ParseResult::Complete(Command::Version, command_line_bytes),
             "quit" => ParseResult::Complete(Command::Quit, command_line_bytes),
+            "mg" => meta::parse_get(&parts, command_line_bytes),
+            // Its data block follows, as for the classic storage commands
+            "ms" => match meta::parse_set(&parts, command_line_bytes) {
+                ParseResult::Complete(command, command_bytes) => ParseResult::NeedData {
+                    command_bytes,
+                    data_bytes: command.data_len().unwrap_or(0),
+                },
+                other => other,
+            },
+            "md" => meta::parse_delete(&parts, command_line_bytes),
             _ => ParseResult::Error(ParseError::UnknownCommand(command_name)),
         }
     }
 
+    /// Lowercased name of the command at the start of the buffer, if a
+    /// complete command line is present.
+    pub fn command_name(buffer: &[u8]) -> Option<String> {
+        let line_end = find_crlf(buffer)?;
+        let line = str::from_utf8(&buffer[..line_end]).ok()?;
+        line.split_whitespace().next().map(str::to_lowercase)
+    }
+
     /// Parse get/gets command
     fn parse_get(parts: &[&str], with_cas: bool, command_bytes: usize) -> ParseResult {
         if parts.len() < 2 {
@@ -223,6 +383,43 @@ impl Parser {
         ParseResult::Complete(command, command_bytes)
     }
 
+    /// Parse gat/gats commands
+    fn parse_gat(parts: &[&str], with_cas: bool, command_bytes: usize) -> ParseResult {
+        // Format: gat|gats <exptime> <key>*
+        if parts.len() < 3 {
+            return ParseResult::Error(ParseError::InvalidCommand(format!(
+                "{} requires exptime and at least one key",
+                parts[0]
+            )));
+        }
+
+        let exptime = match parse_exptime(parts[1]) {
+            Some(e) => e,
+            None => {
+                return ParseResult::Error(ParseError::InvalidNumber(format!(
+                    "Invalid exptime: {}",
+                    parts[1]
+                )))
+            }
+        };
+
+        let mut keys = Vec::new();
+        for &key in &parts[2..] {
+            if key.len() > MAX_KEY_LENGTH {
+                return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
+            }
+            keys.push(key.to_string());
+        }
+
+        let command = if with_cas {
+            Command::Gats { exptime, keys }
+        } else {
+            Command::Gat { exptime, keys }
+        };
+
+        ParseResult::Complete(command, command_bytes)
+    }
+
     /// Parse storage commands (set, add, replace, append, prepend)
     fn parse_storage(parts: &[&str], cmd: &str, command_bytes: usize) -> ParseResult {
         // Format: <command> <key> <flags> <exptime> <bytes> [noreply]
@@ -237,22 +434,6 @@ impl Parser {
             return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
         }
 
-        // Validate flags
-        if parts[2].parse::<u32>().is_err() {
-            return ParseResult::Error(ParseError::InvalidNumber(format!(
-                "Invalid flags: {}",
-                parts[2]
-            )));
-        }
-
-        // Validate exptime
-        if parts[3].parse::<u64>().is_err() {
-            return ParseResult::Error(ParseError::InvalidNumber(format!(
-                "Invalid exptime: {}",
-                parts[3]
-            )));
-        }
-
         let bytes = match parts[4].parse::<usize>() {
             Ok(b) => b,
             Err(_) => {
@@ -263,7 +444,16 @@ impl Parser {
             }
         };
 
-        // noreply is validated in parse_with_data
+        // With the size known, a bad field costs only this command
+        if parts[2].parse::<u32>().is_err()
+            || parse_exptime(parts[3]).is_none()
+            || parse_noreply(&parts[5..]).is_none()
+        {
+            return ParseResult::Error(ParseError::BadCommandLine {
+                command_bytes,
+                data_bytes: bytes,
+            });
+        }
 
         ParseResult::NeedData {
             command_bytes,
@@ -295,6 +485,16 @@ impl Parser {
             }
         };
 
+        if parts[2].parse::<u32>().is_err()
+            || parse_exptime(parts[3]).is_none()
+            || parse_noreply(&parts[6..]).is_none()
+        {
+            return ParseResult::Error(ParseError::BadCommandLine {
+                command_bytes,
+                data_bytes: bytes,
+            });
+        }
+
         ParseResult::NeedData {
             command_bytes,
             data_bytes: bytes,
@@ -326,6 +526,42 @@ impl Parser {
         )
     }
 
+    /// Parse touch command
+    fn parse_touch(parts: &[&str], command_bytes: usize) -> ParseResult {
+        // Format: touch <key> <exptime> [noreply]
+        if parts.len() < 3 {
+            return ParseResult::Error(ParseError::InvalidCommand(
+                "touch requires key and exptime".to_string(),
+            ));
+        }
+
+        let key = parts[1];
+        if key.len() > MAX_KEY_LENGTH {
+            return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
+        }
+
+        let exptime = match parse_exptime(parts[2]) {
+            Some(e) => e,
+            None => {
+                return ParseResult::Error(ParseError::InvalidNumber(format!(
+                    "Invalid exptime: {}",
+                    parts[2]
+                )))
+            }
+        };
+
+        let noreply = parts.len() > 3 && parts[3].eq_ignore_ascii_case("noreply");
+
+        ParseResult::Complete(
+            Command::Touch {
+                key: key.to_string(),
+                exptime,
+                noreply,
+            },
+            command_bytes,
+        )
+    }
+
     /// Parse incr/decr commands
     fn parse_incr_decr(parts: &[&str], is_incr: bool, command_bytes: usize) -> ParseResult {
         // Format: incr|decr <key> <value> [noreply]
@@ -391,7 +627,41 @@ impl Parser {
     }
 
     /// Parse a complete storage command with data block
+    #[allow(dead_code)] // Request processing parks the header instead
     pub fn parse_with_data(buffer: &[u8]) -> ParseResult {
+        let (command, command_line_bytes) = match Self::parse_storage_header(buffer) {
+            ParseResult::Complete(command, command_line_bytes) => (command, command_line_bytes),
+            other => return other,
+        };
+        let data_bytes = command.data_len().unwrap_or(0);
+
+        // Check if we have enough data
+        let total_needed = command_line_bytes + data_bytes + 2; // +2 for trailing \r\n
+        if buffer.len() < total_needed {
+            return ParseResult::Error(ParseError::Incomplete);
+        }
+
+        // Verify trailing \r\n after data
+        if buffer[command_line_bytes + data_bytes] != b'\r'
+            || buffer[command_line_bytes + data_bytes + 1] != b'\n'
+        {
+            return ParseResult::Error(ParseError::InvalidCommand(
+                "Data block must end with \\r\\n".to_string(),
+            ));
+        }
+
+        ParseResult::Complete(command, total_needed)
+    }
+
+    /// Parse the command line of a storage command, without its data block.
+    ///
+    /// Returns the command (whose `bytes` gives the data block size) and the
+    /// length of the command line. Callers waiting for a large value can keep
+    /// the result instead of re-parsing the line on every read.
+    pub fn parse_storage_header(buffer: &[u8]) -> ParseResult {
+        #[cfg(test)]
+        HEADER_PARSES.with(|n| n.set(n.get() + 1));
+
         // First parse the command line
         let line_end = match find_crlf(buffer) {
             Some(pos) => pos,
@@ -414,6 +684,9 @@ impl Parser {
 
         let command_name = parts[0].to_lowercase();
         let command_line_bytes = line_end + 2;
+        if command_name == "ms" {
+            return meta::parse_set(&parts, command_line_bytes);
+        }
 
         // Parse the command to get data size
         let (data_bytes, is_cas) = match command_name.as_str() {
@@ -454,34 +727,25 @@ impl Parser {
             }
         };
 
-        // Check if we have enough data
-        let total_needed = command_line_bytes + data_bytes + 2; // +2 for trailing \r\n
-        if buffer.len() < total_needed {
-            return ParseResult::Error(ParseError::Incomplete);
-        }
-
-        // Verify trailing \r\n after data
-        if buffer[command_line_bytes + data_bytes] != b'\r'
-            || buffer[command_line_bytes + data_bytes + 1] != b'\n'
-        {
-            return ParseResult::Error(ParseError::InvalidCommand(
-                "Data block must end with \\r\\n".to_string(),
-            ));
-        }
-
         // Build the complete command
         let key = parts[1];
         if key.len() > MAX_KEY_LENGTH {
             return ParseResult::Error(ParseError::KeyTooLong(key.to_string()));
         }
 
-        let flags = parts[2].parse::<u32>().unwrap_or(0);
-        let exptime = parts[3].parse::<u64>().unwrap_or(0);
-
-        let noreply = if is_cas {
-            parts.len() > 6 && parts[6].eq_ignore_ascii_case("noreply")
-        } else {
-            parts.len() > 5 && parts[5].eq_ignore_ascii_case("noreply")
+        let trailing = if is_cas { &parts[6..] } else { &parts[5..] };
+        let (flags, exptime, noreply) = match (
+            parts[2].parse::<u32>(),
+            parse_exptime(parts[3]),
+            parse_noreply(trailing),
+        ) {
+            (Ok(flags), Some(exptime), Some(noreply)) => (flags, exptime, noreply),
+            _ => {
+                return ParseResult::Error(ParseError::BadCommandLine {
+                    command_bytes: command_line_bytes,
+                    data_bytes,
+                })
+            }
         };
 
         let command = match command_name.as_str() {
@@ -534,7 +798,7 @@ impl Parser {
             _ => unreachable!(),
         };
 
-        ParseResult::Complete(command, total_needed)
+        ParseResult::Complete(command, command_line_bytes)
     }
 
     /// Extract data from buffer for a storage command
@@ -599,11 +863,21 @@ impl Response {
         b"DELETED\r\n"
     }
 
+    /// Generate TOUCHED response
+    pub fn touched() -> &'static [u8] {
+        b"TOUCHED\r\n"
+    }
+
     /// Generate OK response
     pub fn ok() -> &'static [u8] {
         b"OK\r\n"
     }
 
+    /// Generate RESET response, the reply to `stats reset`
+    pub fn reset() -> &'static [u8] {
+        b"RESET\r\n"
+    }
+
     /// Generate ERROR response
     pub fn error() -> &'static [u8] {
         b"ERROR\r\n"
@@ -617,16 +891,23 @@ impl Response {
     }
 
     /// Generate SERVER_ERROR response
-    #[allow(dead_code)]
     pub fn server_error(msg: &str) -> BytesMut {
         let mut response = BytesMut::new();
         response.extend_from_slice(format!("SERVER_ERROR {msg}\r\n").as_bytes());
         response
     }
 
-    /// Generate VERSION response
-    pub fn version() -> &'static [u8] {
-        b"VERSION grow-a-cache 0.1.0\r\n"
+    /// Generate VERSION response, with the deployment's tag if it has one
+    pub fn version(server_id: Option<&str>) -> BytesMut {
+        let mut response = BytesMut::new();
+        response.extend_from_slice(
+            format!("VERSION grow-a-cache {}", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        if let Some(id) = server_id {
+            response.extend_from_slice(format!(" {id}").as_bytes());
+        }
+        response.extend_from_slice(b"\r\n");
+        response
     }
 
     /// Generate numeric response (for incr/decr)
@@ -644,6 +925,28 @@ impl Response {
     }
 }
 
+/// Parse the tokens after a storage command's required arguments.
+///
+/// Only a single `noreply` (or nothing) is allowed; `None` means the line is
+/// malformed.
+fn parse_noreply(trailing: &[&str]) -> Option<bool> {
+    match trailing {
+        [] => Some(false),
+        [token] if token.eq_ignore_ascii_case("noreply") => Some(true),
+        _ => None,
+    }
+}
+
+/// Parse an exptime: relative seconds or an absolute Unix timestamp, as
+/// storage reads it. A negative exptime stores the item already expired, so
+/// it becomes a timestamp in the past.
+pub(super) fn parse_exptime(token: &str) -> Option<u64> {
+    match token.parse::<u64>() {
+        Ok(exptime) => Some(exptime),
+        Err(_) => token.parse::<i64>().ok().map(|_| EXPIRED_TTL),
+    }
+}
+
 /// Find \r\n in buffer
 fn find_crlf(buffer: &[u8]) -> Option<usize> {
     (0..buffer.len().saturating_sub(1)).find(|&i| buffer[i] == b'\r' && buffer[i + 1] == b'\n')
@@ -676,6 +979,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_gat() {
+        match Parser::parse(b"gat 60 key1 key2\r\n") {
+            ParseResult::Complete(Command::Gat { exptime, keys }, bytes) => {
+                assert_eq!(exptime, 60);
+                assert_eq!(keys, vec!["key1", "key2"]);
+                assert_eq!(bytes, 18);
+            }
+            _ => panic!("Expected Gat command"),
+        }
+
+        match Parser::parse(b"gats 0 key1\r\n") {
+            ParseResult::Complete(Command::Gats { exptime, keys }, _) => {
+                assert_eq!(exptime, 0);
+                assert_eq!(keys, vec!["key1"]);
+            }
+            _ => panic!("Expected Gats command"),
+        }
+
+        // The exptime comes first, so a lone key is missing its exptime
+        assert!(matches!(
+            Parser::parse(b"gat key1\r\n"),
+            ParseResult::Error(ParseError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            Parser::parse(b"gat key1 key2\r\n"),
+            ParseResult::Error(ParseError::InvalidNumber(_))
+        ));
+    }
+
     #[test]
     fn test_parse_set() {
         let buffer = b"set mykey 0 3600 5\r\n";
@@ -727,6 +1060,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_storage_trailing_token_must_be_noreply() {
+        for line in [
+            &b"set mykey 0 3600 5 norepl\r\n"[..],
+            b"set mykey 0 3600 5 1 2\r\n",
+        ] {
+            let bad = ParseError::BadCommandLine {
+                command_bytes: line.len(),
+                data_bytes: 5,
+            };
+            assert!(matches!(Parser::parse(line), ParseResult::Error(e) if e == bad));
+            assert!(
+                matches!(Parser::parse_storage_header(line), ParseResult::Error(e) if e == bad)
+            );
+        }
+
+        let line = b"cas mykey 0 0 5 12345 xx\r\n";
+        let bad = ParseError::BadCommandLine {
+            command_bytes: line.len(),
+            data_bytes: 5,
+        };
+        assert!(matches!(Parser::parse(line), ParseResult::Error(e) if e == bad));
+        assert!(matches!(Parser::parse_storage_header(line), ParseResult::Error(e) if e == bad));
+        assert!(matches!(
+            Parser::parse_storage_header(b"cas mykey 0 0 5 12345 noreply\r\n"),
+            ParseResult::Complete(Command::Cas { noreply: true, .. }, _)
+        ));
+    }
+
+    #[test]
+    fn test_storage_flags_and_exptime_must_be_in_range() {
+        for line in [
+            &b"set mykey 99999999999 0 5\r\n"[..],
+            b"set mykey -1 0 5\r\n",
+            b"add mykey 0 soon 5\r\n",
+            b"cas mykey 4294967296 0 5 12345\r\n",
+        ] {
+            let bad = ParseError::BadCommandLine {
+                command_bytes: line.len(),
+                data_bytes: 5,
+            };
+            assert!(matches!(Parser::parse(line), ParseResult::Error(e) if e == bad));
+            assert!(
+                matches!(Parser::parse_storage_header(line), ParseResult::Error(e) if e == bad)
+            );
+        }
+
+        match Parser::parse_storage_header(b"set mykey 4294967295 0 5\r\n") {
+            ParseResult::Complete(Command::Set { flags, .. }, _) => assert_eq!(flags, u32::MAX),
+            other => panic!("Expected Set command, got {other:?}"),
+        }
+    }
+
     #[test]
     fn test_parse_cas() {
         let buffer = b"cas mykey 0 3600 5 12345\r\nhello\r\n";
@@ -768,6 +1154,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_touch() {
+        match Parser::parse(b"touch mykey 300\r\n") {
+            ParseResult::Complete(
+                Command::Touch {
+                    key,
+                    exptime,
+                    noreply,
+                },
+                len,
+            ) => {
+                assert_eq!(key, "mykey");
+                assert_eq!(exptime, 300);
+                assert!(!noreply);
+                assert_eq!(len, 17);
+            }
+            _ => panic!("Expected Touch command"),
+        }
+
+        match Parser::parse(b"touch mykey 0 noreply\r\n") {
+            ParseResult::Complete(Command::Touch { noreply, .. }, _) => assert!(noreply),
+            _ => panic!("Expected Touch command"),
+        }
+
+        assert!(matches!(
+            Parser::parse(b"touch mykey\r\n"),
+            ParseResult::Error(ParseError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            Parser::parse(b"touch mykey soon\r\n"),
+            ParseResult::Error(ParseError::InvalidNumber(_))
+        ));
+
+        // Negative means already expired
+        match Parser::parse(b"touch mykey -1\r\n") {
+            ParseResult::Complete(Command::Touch { exptime, .. }, _) => {
+                assert_eq!(exptime, EXPIRED_TTL);
+            }
+            _ => panic!("Expected Touch command"),
+        }
+    }
+
     #[test]
     fn test_parse_flush_all() {
         let buffer = b"flush_all\r\n";
@@ -795,7 +1223,14 @@ mod tests {
     fn test_parse_stats() {
         let buffer = b"stats\r\n";
         match Parser::parse(buffer) {
-            ParseResult::Complete(Command::Stats, _) => {}
+            ParseResult::Complete(Command::Stats { group: None }, _) => {}
+            _ => panic!("Expected Stats command"),
+        }
+
+        match Parser::parse(b"stats sizes\r\n") {
+            ParseResult::Complete(Command::Stats { group: Some(group) }, _) => {
+                assert_eq!(group, "sizes");
+            }
             _ => panic!("Expected Stats command"),
         }
     }
@@ -0,0 +1,123 @@
+//! Glob-style patterns, as taken by `SCAN ... MATCH`.
+//!
+//! Like Redis: `*` matches any run of bytes, `?` any single byte, `[abc]`
+//! one of a set (`[^abc]` one outside it, `[a-z]` a range), and `\` makes
+//! the next byte literal.
+
+/// Whether all of `subject` matches `pattern`.
+pub fn matches(pattern: &[u8], subject: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*`: its pattern position, and the
+    // subject position it is currently stretched to
+    let mut star: Option<(usize, usize)> = None;
+
+    while s < subject.len() {
+        if let Some(next) = match_one(pattern, p, subject[s]) {
+            p = next;
+            s += 1;
+        } else if pattern.get(p) == Some(&b'*') {
+            star = Some((p + 1, s));
+            p += 1;
+        } else if let Some((after_star, stretched)) = star {
+            // Let the `*` take one more byte and try again
+            star = Some((after_star, stretched + 1));
+            p = after_star;
+            s = stretched + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// If the pattern element at `p` matches `byte` (a `*` never does), the
+/// position of the next element.
+fn match_one(pattern: &[u8], p: usize, byte: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'*' => None,
+        b'?' => Some(p + 1),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == byte).then_some(p + 2),
+        b'[' => match_class(pattern, p + 1, byte),
+        literal => (literal == byte).then_some(p + 1),
+    }
+}
+
+/// Match `byte` against the class whose contents start at `p`, just past
+/// its `[`. An unclosed class runs to the end of the pattern.
+fn match_class(pattern: &[u8], mut p: usize, byte: u8) -> Option<usize> {
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut found = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        let (low, len) = match pattern[p] {
+            b'\\' if p + 1 < pattern.len() => (pattern[p + 1], 2),
+            b => (b, 1),
+        };
+        p += len;
+        if pattern.get(p) == Some(&b'-') && p + 1 < pattern.len() && pattern[p + 1] != b']' {
+            let high = pattern[p + 1];
+            p += 2;
+            let (low, high) = (low.min(high), low.max(high));
+            found |= (low..=high).contains(&byte);
+        } else {
+            found |= low == byte;
+        }
+    }
+    // Past the `]`, if there is one
+    let next = (p + 1).min(pattern.len());
+    (found != negated).then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        for (pattern, subject, expected) in [
+            ("*", "", true),
+            ("*", "anything", true),
+            ("user:*", "user:42", true),
+            ("user:*", "users:42", false),
+            ("*:42", "user:42", true),
+            ("a*b*c", "aXXbYYc", true),
+            ("a*b*c", "aXXbYY", false),
+            ("a*b", "abab", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("exact", "exact", true),
+            ("exact", "exactly", false),
+        ] {
+            assert_eq!(
+                matches(pattern.as_bytes(), subject.as_bytes()),
+                expected,
+                "{pattern} ~ {subject}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_classes_and_escapes() {
+        for (pattern, subject, expected) in [
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("key[0-9]", "key7", true),
+            ("key[0-9]", "keyx", false),
+            ("key[9-0]", "key7", true),
+            (r"a\*b", "a*b", true),
+            (r"a\*b", "aXb", false),
+            (r"[\]]", "]", true),
+            ("[abc", "b", true),
+        ] {
+            assert_eq!(
+                matches(pattern.as_bytes(), subject.as_bytes()),
+                expected,
+                "{pattern} ~ {subject}"
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! RESP (Redis Serialization Protocol) implementation.
 //!
-//! Supports RESP2 by default with RESP3 upgrade via HELLO command.
+//! Speaks RESP2 by default; a connection switches to RESP3 with `HELLO 3`.
 
+pub mod glob;
 pub mod parser;
@@ -2,6 +2,7 @@
 //!
 //! Implements parsing for RESP2 and RESP3 protocol frames.
 //! RESP is a binary-safe protocol that uses length-prefixed strings.
+//! Commands may also arrive inline (`PING\r\n`), as typed in telnet.
 
 use bytes::{Bytes, BytesMut};
 
@@ -18,18 +19,52 @@ pub enum Frame {
     Bulk(Option<Bytes>),
     /// Array: *2\r\n... or *-1\r\n (null)
     Array(Option<Vec<Frame>>),
+    /// Map (RESP3): %1\r\n+key\r\n:1\r\n
+    Map(Vec<(Frame, Frame)>),
+    /// Double (RESP3): ,1.5\r\n
+    #[allow(dead_code)] // No command replies with one yet
+    Double(f64),
+    /// Boolean (RESP3): #t\r\n
+    #[allow(dead_code)] // No command replies with one yet
+    Boolean(bool),
+    /// Big number (RESP3), as its decimal digits: (12345678901234567890\r\n
+    #[allow(dead_code)] // No command replies with one yet
+    BigNumber(String),
+    /// Null (RESP3): _\r\n
+    #[allow(dead_code)] // No command replies with one yet
+    Null,
+}
+
+/// Protocol version a connection speaks, as negotiated by HELLO.
+///
+/// Frames only RESP3 has are encoded for a RESP2 client as their nearest
+/// RESP2 equivalent: a map as a flat array of keys and values, a double or
+/// big number as a bulk string, a boolean as 1 or 0, and null as a null
+/// bulk string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RespVersion {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
 impl Frame {
-    /// Encode a frame to bytes
+    /// Encode a frame to bytes, as RESP2
+    #[allow(dead_code)] // Replies follow the session's version; tests encode commands
     pub fn encode(&self) -> BytesMut {
+        self.encode_as(RespVersion::Resp2)
+    }
+
+    /// Encode a frame to bytes in the given protocol version
+    pub fn encode_as(&self, version: RespVersion) -> BytesMut {
         let mut buf = BytesMut::new();
-        self.encode_into(&mut buf);
+        self.encode_into(&mut buf, version);
         buf
     }
 
     /// Encode a frame into an existing buffer
-    pub fn encode_into(&self, buf: &mut BytesMut) {
+    pub fn encode_into(&self, buf: &mut BytesMut, version: RespVersion) {
+        let resp3 = version == RespVersion::Resp3;
         match self {
             Frame::Simple(s) => {
                 buf.extend_from_slice(b"+");
@@ -46,7 +81,11 @@ impl Frame {
                 buf.extend_from_slice(n.to_string().as_bytes());
                 buf.extend_from_slice(b"\r\n");
             }
-            Frame::Bulk(None) => {
+            // RESP3 has a single null for all types
+            Frame::Bulk(None) | Frame::Array(None) | Frame::Null if resp3 => {
+                buf.extend_from_slice(b"_\r\n");
+            }
+            Frame::Bulk(None) | Frame::Null => {
                 buf.extend_from_slice(b"$-1\r\n");
             }
             Frame::Bulk(Some(data)) => {
@@ -64,9 +103,52 @@ impl Frame {
                 buf.extend_from_slice(frames.len().to_string().as_bytes());
                 buf.extend_from_slice(b"\r\n");
                 for frame in frames {
-                    frame.encode_into(buf);
+                    frame.encode_into(buf, version);
+                }
+            }
+            Frame::Map(pairs) => {
+                // As RESP2, keys and values alternate in an array
+                let (prefix, len) = if resp3 {
+                    (b"%", pairs.len())
+                } else {
+                    (b"*", pairs.len() * 2)
+                };
+                buf.extend_from_slice(prefix);
+                buf.extend_from_slice(len.to_string().as_bytes());
+                buf.extend_from_slice(b"\r\n");
+                for (key, value) in pairs {
+                    key.encode_into(buf, version);
+                    value.encode_into(buf, version);
+                }
+            }
+            Frame::Double(n) => {
+                let text = if n.is_nan() {
+                    "nan".to_string()
+                } else {
+                    n.to_string()
+                };
+                if resp3 {
+                    buf.extend_from_slice(b",");
+                    buf.extend_from_slice(text.as_bytes());
+                    buf.extend_from_slice(b"\r\n");
+                } else {
+                    Frame::bulk(text).encode_into(buf, version);
                 }
             }
+            Frame::Boolean(b) if resp3 => {
+                buf.extend_from_slice(if *b { b"#t\r\n" } else { b"#f\r\n" });
+            }
+            Frame::Boolean(b) => {
+                Frame::integer(i64::from(*b)).encode_into(buf, version);
+            }
+            Frame::BigNumber(digits) if resp3 => {
+                buf.extend_from_slice(b"(");
+                buf.extend_from_slice(digits.as_bytes());
+                buf.extend_from_slice(b"\r\n");
+            }
+            Frame::BigNumber(digits) => {
+                Frame::bulk(digits.clone()).encode_into(buf, version);
+            }
         }
     }
 
@@ -113,8 +195,56 @@ pub enum ParseResult {
     Error(String),
 }
 
-/// Parse a RESP frame from a buffer
-pub fn parse(buffer: &[u8]) -> ParseResult {
+/// Parse a command from a buffer: a RESP frame or an inline command.
+///
+/// A command with more than `max_args` arguments, or whose bulk strings
+/// declare more than `max_bytes` in total, is a protocol error. Both are
+/// reported as soon as the offending header arrives, before the elements
+/// behind it are waited for or allocated.
+pub fn parse(buffer: &[u8], max_args: usize, max_bytes: usize) -> ParseResult {
+    match buffer.first() {
+        None => ParseResult::Incomplete,
+        Some(b'*') => parse_array(buffer, max_args, max_bytes, 0),
+        Some(b'|') => parse_attribute(buffer, max_args, max_bytes),
+        Some(b'+' | b'-' | b':' | b'$') => parse_frame(buffer, 0),
+        Some(_) => parse_inline(buffer, max_args),
+    }
+}
+
+/// Parse an inline command: space-separated arguments ending in CRLF.
+///
+/// Returned as an array of bulk strings, the same shape as a multibulk
+/// command, so execution doesn't care which form the client used.
+fn parse_inline(buffer: &[u8], max_args: usize) -> ParseResult {
+    let end = match find_crlf(buffer) {
+        Some(end) => end,
+        None => return ParseResult::Incomplete,
+    };
+
+    let mut args = Vec::new();
+    for arg in buffer[..end]
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|arg| !arg.is_empty())
+    {
+        if args.len() == max_args {
+            return ParseResult::Error(format!("Protocol error: more than {max_args} arguments"));
+        }
+        args.push(Frame::bulk(Bytes::copy_from_slice(arg)));
+    }
+    ParseResult::Complete(Frame::Array(Some(args)), end + 2)
+}
+
+/// Shortest encoded frame (`+\r\n`).
+const MIN_FRAME_LEN: usize = 3;
+
+/// Deepest arrays may nest, so a hostile frame can't recurse the parser
+/// off the end of its stack.
+const MAX_DEPTH: usize = 32;
+
+/// Parse a RESP frame from a buffer, `depth` arrays down.
+///
+/// Attributes are only taken at the start of a command (see `parse`).
+fn parse_frame(buffer: &[u8], depth: usize) -> ParseResult {
     if buffer.is_empty() {
         return ParseResult::Incomplete;
     }
@@ -124,7 +254,8 @@ pub fn parse(buffer: &[u8]) -> ParseResult {
         b'-' => parse_error(buffer),
         b':' => parse_integer(buffer),
         b'$' => parse_bulk_string(buffer),
-        b'*' => parse_array(buffer),
+        b'*' => parse_array(buffer, usize::MAX, usize::MAX, depth),
+        b'|' => ParseResult::Error("Protocol error: nested attribute".to_string()),
         _ => ParseResult::Error(format!("Unknown frame type: {}", buffer[0] as char)),
     }
 }
@@ -216,7 +347,15 @@ fn parse_bulk_string(buffer: &[u8]) -> ParseResult {
 }
 
 /// Parse an array: *2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n or *-1\r\n
-fn parse_array(buffer: &[u8]) -> ParseResult {
+///
+/// At most `max_len` elements, whose bulk strings may declare at most
+/// `max_bytes` between them, and at most `MAX_DEPTH` arrays down.
+fn parse_array(buffer: &[u8], max_len: usize, max_bytes: usize, depth: usize) -> ParseResult {
+    if depth >= MAX_DEPTH {
+        return ParseResult::Error(format!(
+            "Protocol error: arrays nested more than {MAX_DEPTH} deep"
+        ));
+    }
     if let Some(len_end) = find_crlf(buffer) {
         let len_str = match std::str::from_utf8(&buffer[1..len_end]) {
             Ok(s) => s,
@@ -234,15 +373,27 @@ fn parse_array(buffer: &[u8]) -> ParseResult {
         }
 
         let len = len as usize;
+        if len > max_len {
+            return ParseResult::Error(format!("Protocol error: more than {max_len} arguments"));
+        }
         let mut offset = len_end + 2;
-        let mut frames = Vec::with_capacity(len);
+        // Reserve only what the buffered bytes could hold, so a huge
+        // declared count (nested arrays aren't bounded by `max_len`) can't
+        // allocate ahead of input that may never arrive
+        let buffered = (buffer.len() - offset) / MIN_FRAME_LEN;
+        let mut frames = Vec::with_capacity(len.min(buffered));
+        let mut declared = 0usize;
 
         for _ in 0..len {
             if offset >= buffer.len() {
                 return ParseResult::Incomplete;
             }
 
-            match parse(&buffer[offset..]) {
+            if let Some(result) = check_declared(&buffer[offset..], &mut declared, max_bytes) {
+                return result;
+            }
+
+            match parse_frame(&buffer[offset..], depth + 1) {
                 ParseResult::Complete(frame, consumed) => {
                     frames.push(frame);
                     offset += consumed;
@@ -258,14 +409,102 @@ fn parse_array(buffer: &[u8]) -> ParseResult {
     }
 }
 
+/// Add the length of the bulk string starting `buffer`, if it is one, to
+/// `declared`, returning the result to give up with if it is incomplete or
+/// takes the total over `max_bytes`.
+///
+/// Checks the size from the header alone, rather than buffering up to a
+/// length that will be rejected anyway.
+fn check_declared(buffer: &[u8], declared: &mut usize, max_bytes: usize) -> Option<ParseResult> {
+    if buffer.first() != Some(&b'$') {
+        return None;
+    }
+    match declared_len(buffer) {
+        Some(n) => *declared = declared.saturating_add(n),
+        None => return Some(ParseResult::Incomplete),
+    }
+    (*declared > max_bytes).then(|| {
+        ParseResult::Error(format!(
+            "Protocol error: command larger than {max_bytes} bytes"
+        ))
+    })
+}
+
+/// Length a bulk string header declares (`None` until the header is
+/// complete). Malformed or null lengths count as 0 and are left to the
+/// bulk string parser to deal with.
+fn declared_len(buffer: &[u8]) -> Option<usize> {
+    let end = find_crlf(buffer)?;
+    Some(
+        std::str::from_utf8(&buffer[1..end])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .unwrap_or(0),
+    )
+}
+
+/// Parse a RESP3 attribute map followed by the command it annotates:
+/// |1\r\n+key\r\n+value\r\n<command>
+///
+/// Attributes are auxiliary metadata, so they are consumed and discarded and
+/// the command is returned in their place. Its keys and values count toward
+/// the command's `max_args` and `max_bytes`, and may not be attributes
+/// themselves; nor may the command.
+fn parse_attribute(buffer: &[u8], max_args: usize, max_bytes: usize) -> ParseResult {
+    if let Some(len_end) = find_crlf(buffer) {
+        let len_str = match std::str::from_utf8(&buffer[1..len_end]) {
+            Ok(s) => s,
+            Err(_) => return ParseResult::Error("Invalid UTF-8 in attribute length".to_string()),
+        };
+
+        let len: usize = match len_str.parse() {
+            Ok(n) => n,
+            Err(_) => return ParseResult::Error(format!("Invalid attribute length: {len_str}")),
+        };
+
+        // Each attribute is a key frame followed by a value frame
+        let Some(frames) = len.checked_mul(2).filter(|&n| n <= max_args) else {
+            return ParseResult::Error(format!("Protocol error: more than {max_args} arguments"));
+        };
+        let mut offset = len_end + 2;
+        let mut declared = 0usize;
+        for _ in 0..frames {
+            if let Some(result) = check_declared(&buffer[offset..], &mut declared, max_bytes) {
+                return result;
+            }
+            match parse_frame(&buffer[offset..], 1) {
+                ParseResult::Complete(_, consumed) => offset += consumed,
+                ParseResult::Incomplete => return ParseResult::Incomplete,
+                ParseResult::Error(e) => return ParseResult::Error(e),
+            }
+        }
+
+        let rest = &buffer[offset..];
+        if rest.first() == Some(&b'|') {
+            return ParseResult::Error("Protocol error: nested attribute".to_string());
+        }
+        match parse(rest, max_args, max_bytes.saturating_sub(declared)) {
+            ParseResult::Complete(frame, consumed) => {
+                ParseResult::Complete(frame, offset + consumed)
+            }
+            other => other,
+        }
+    } else {
+        ParseResult::Incomplete
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const MAX_ARGS: usize = 1024;
+    const MAX_BYTES: usize = 1024 * 1024;
+
     #[test]
     fn test_parse_simple_string() {
         let buffer = b"+OK\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Simple(s), consumed) => {
                 assert_eq!(s, "OK");
                 assert_eq!(consumed, 5);
@@ -277,7 +516,7 @@ mod tests {
     #[test]
     fn test_parse_error() {
         let buffer = b"-ERR unknown command\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Error(s), consumed) => {
                 assert_eq!(s, "ERR unknown command");
                 assert_eq!(consumed, 22);
@@ -289,7 +528,7 @@ mod tests {
     #[test]
     fn test_parse_integer() {
         let buffer = b":1000\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Integer(n), consumed) => {
                 assert_eq!(n, 1000);
                 assert_eq!(consumed, 7);
@@ -301,7 +540,7 @@ mod tests {
     #[test]
     fn test_parse_negative_integer() {
         let buffer = b":-42\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Integer(n), _) => {
                 assert_eq!(n, -42);
             }
@@ -312,7 +551,7 @@ mod tests {
     #[test]
     fn test_parse_bulk_string() {
         let buffer = b"$5\r\nhello\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Bulk(Some(data)), consumed) => {
                 assert_eq!(&data[..], b"hello");
                 assert_eq!(consumed, 11);
@@ -324,7 +563,7 @@ mod tests {
     #[test]
     fn test_parse_null_bulk_string() {
         let buffer = b"$-1\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Bulk(None), consumed) => {
                 assert_eq!(consumed, 5);
             }
@@ -335,7 +574,7 @@ mod tests {
     #[test]
     fn test_parse_empty_bulk_string() {
         let buffer = b"$0\r\n\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Bulk(Some(data)), consumed) => {
                 assert_eq!(data.len(), 0);
                 assert_eq!(consumed, 6);
@@ -347,7 +586,7 @@ mod tests {
     #[test]
     fn test_parse_array() {
         let buffer = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Array(Some(frames)), consumed) => {
                 assert_eq!(frames.len(), 2);
                 assert_eq!(consumed, 22);
@@ -367,7 +606,7 @@ mod tests {
     #[test]
     fn test_parse_null_array() {
         let buffer = b"*-1\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Array(None), consumed) => {
                 assert_eq!(consumed, 5);
             }
@@ -378,7 +617,7 @@ mod tests {
     #[test]
     fn test_parse_empty_array() {
         let buffer = b"*0\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Complete(Frame::Array(Some(frames)), consumed) => {
                 assert_eq!(frames.len(), 0);
                 assert_eq!(consumed, 4);
@@ -390,24 +629,129 @@ mod tests {
     #[test]
     fn test_parse_incomplete() {
         let buffer = b"+OK";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Incomplete => {}
             _ => panic!("Expected incomplete"),
         }
 
         let buffer = b"$5\r\nhel";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Incomplete => {}
             _ => panic!("Expected incomplete"),
         }
 
         let buffer = b"*2\r\n$3\r\nfoo\r\n";
-        match parse(buffer) {
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
             ParseResult::Incomplete => {}
             _ => panic!("Expected incomplete"),
         }
     }
 
+    #[test]
+    fn test_parse_inline() {
+        match parse(b"SET  foo bar\r\n*1\r\n", MAX_ARGS, MAX_BYTES) {
+            ParseResult::Complete(Frame::Array(Some(frames)), consumed) => {
+                assert_eq!(
+                    frames,
+                    vec![
+                        Frame::bulk(Bytes::from_static(b"SET")),
+                        Frame::bulk(Bytes::from_static(b"foo")),
+                        Frame::bulk(Bytes::from_static(b"bar")),
+                    ]
+                );
+                assert_eq!(consumed, 14);
+            }
+            other => panic!("Expected inline command, got {other:?}"),
+        }
+
+        assert!(matches!(
+            parse(b"PING", MAX_ARGS, MAX_BYTES),
+            ParseResult::Incomplete
+        ));
+
+        // Inline parsing only applies at the top level
+        assert!(matches!(
+            parse(b"*1\r\nPING\r\n", MAX_ARGS, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_attribute_prefixed_command() {
+        let buffer = b"|1\r\n+trace-id\r\n:42\r\n*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n";
+        match parse(buffer, MAX_ARGS, MAX_BYTES) {
+            ParseResult::Complete(Frame::Array(Some(frames)), consumed) => {
+                assert_eq!(frames.len(), 2);
+                assert_eq!(frames[0], Frame::bulk(Bytes::from_static(b"GET")));
+                assert_eq!(frames[1], Frame::bulk(Bytes::from_static(b"foo")));
+                assert_eq!(consumed, buffer.len());
+            }
+            other => panic!("Expected array, got {other:?}"),
+        }
+
+        // Incomplete until the annotated frame arrives
+        match parse(b"|1\r\n+trace-id\r\n:42\r\n", MAX_ARGS, MAX_BYTES) {
+            ParseResult::Incomplete => {}
+            other => panic!("Expected incomplete, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_is_refused() {
+        let is_error =
+            |buffer: &[u8]| matches!(parse(buffer, MAX_ARGS, MAX_BYTES), ParseResult::Error(_));
+
+        // Each would overflow the stack if followed all the way down
+        assert!(is_error(&b"|1\r\n+k\r\n".repeat(7000)));
+        assert!(is_error(&b"*1\r\n".repeat(7000)));
+        let mut in_attribute = b"|1\r\n+k\r\n".to_vec();
+        in_attribute.extend_from_slice(&b"*1\r\n".repeat(7000));
+        assert!(is_error(&in_attribute));
+
+        // Nesting within the limit still parses
+        let mut nested = b"*1\r\n".repeat(MAX_DEPTH - 1);
+        nested.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        assert!(matches!(
+            parse(&nested, MAX_ARGS, MAX_BYTES),
+            ParseResult::Complete(_, len) if len == nested.len()
+        ));
+    }
+
+    #[test]
+    fn test_attribute_keeps_command_limits() {
+        let command = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n";
+        assert!(matches!(
+            parse(command, 2, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+        let annotated = [&b"|0\r\n"[..], command].concat();
+        assert!(matches!(
+            parse(&annotated, 2, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+        assert!(matches!(parse(&annotated, 3, 2), ParseResult::Error(_)));
+        assert!(matches!(
+            parse(&annotated, 3, MAX_BYTES),
+            ParseResult::Complete(_, len) if len == annotated.len()
+        ));
+
+        // The attribute's own values count toward the command's bytes
+        let large = [&b"|1\r\n+k\r\n$10\r\n0123456789\r\n"[..], command].concat();
+        assert!(matches!(parse(&large, 3, 14), ParseResult::Error(_)));
+        assert!(matches!(parse(&large, 3, 15), ParseResult::Complete(..)));
+
+        // A count of pairs whose frames overflow, or exceed the arguments
+        let huge = format!("|{}\r\n", usize::MAX);
+        assert!(matches!(
+            parse(huge.as_bytes(), MAX_ARGS, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+        assert!(matches!(
+            parse(b"|2\r\n", 3, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+    }
+
     #[test]
     fn test_encode_simple_string() {
         let frame = Frame::simple("OK");
@@ -446,4 +790,129 @@ mod tests {
         ]);
         assert_eq!(&frame.encode()[..], b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
     }
+
+    /// A frame encoded as RESP2 and as RESP3.
+    fn encodings(frame: &Frame) -> (Vec<u8>, Vec<u8>) {
+        (
+            frame.encode_as(RespVersion::Resp2).to_vec(),
+            frame.encode_as(RespVersion::Resp3).to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_encode_map() {
+        let frame = Frame::Map(vec![
+            (Frame::bulk("proto"), Frame::integer(3)),
+            (Frame::bulk("modules"), Frame::array(vec![])),
+        ]);
+        let (resp2, resp3) = encodings(&frame);
+        assert_eq!(resp2, b"*4\r\n$5\r\nproto\r\n:3\r\n$7\r\nmodules\r\n*0\r\n");
+        assert_eq!(resp3, b"%2\r\n$5\r\nproto\r\n:3\r\n$7\r\nmodules\r\n*0\r\n");
+        assert_eq!(frame.encode().to_vec(), resp2);
+
+        // Nested frames follow the version too
+        let nested = Frame::array(vec![Frame::Map(vec![(Frame::bulk("k"), Frame::Null)])]);
+        let (resp2, resp3) = encodings(&nested);
+        assert_eq!(resp2, b"*1\r\n*2\r\n$1\r\nk\r\n$-1\r\n");
+        assert_eq!(resp3, b"*1\r\n%1\r\n$1\r\nk\r\n_\r\n");
+    }
+
+    #[test]
+    fn test_encode_double() {
+        for (n, text) in [
+            (1.5, "1.5"),
+            (-0.25, "-0.25"),
+            (3.0, "3"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+        ] {
+            let (resp2, resp3) = encodings(&Frame::Double(n));
+            assert_eq!(resp2, format!("${}\r\n{text}\r\n", text.len()).as_bytes());
+            assert_eq!(resp3, format!(",{text}\r\n").as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_encode_boolean() {
+        assert_eq!(
+            encodings(&Frame::Boolean(true)),
+            (b":1\r\n".to_vec(), b"#t\r\n".to_vec())
+        );
+        assert_eq!(
+            encodings(&Frame::Boolean(false)),
+            (b":0\r\n".to_vec(), b"#f\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_big_number() {
+        let digits = "3492890328409238509324850943850943825024385";
+        let (resp2, resp3) = encodings(&Frame::BigNumber(digits.to_string()));
+        assert_eq!(resp2, format!("$43\r\n{digits}\r\n").as_bytes());
+        assert_eq!(resp3, format!("({digits}\r\n").as_bytes());
+    }
+
+    #[test]
+    fn test_encode_nulls() {
+        for frame in [Frame::Null, Frame::null()] {
+            assert_eq!(encodings(&frame), (b"$-1\r\n".to_vec(), b"_\r\n".to_vec()));
+        }
+        assert_eq!(
+            encodings(&Frame::Array(None)),
+            (b"*-1\r\n".to_vec(), b"_\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_argument_count_is_bounded() {
+        // A million arguments, rejected without collecting them all
+        let mut line = b"DEL".to_vec();
+        line.extend(b" k".repeat(1_000_000));
+        line.extend_from_slice(b"\r\n");
+        match parse(&line, MAX_ARGS, MAX_BYTES) {
+            ParseResult::Error(e) => assert!(e.contains("more than 1024 arguments")),
+            other => panic!("Expected error, got {other:?}"),
+        }
+
+        // The declared multibulk length is checked before any allocation
+        assert!(matches!(
+            parse(b"*4294967296\r\n", MAX_ARGS, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+
+        // A nested array's count isn't bounded by the argument limit, and
+        // must not reserve space for elements that haven't arrived
+        let mut input = b"*1\r\n*4294967295\r\n".to_vec();
+        for _ in 0..1000 {
+            input.extend_from_slice(b":1\r\n");
+            assert!(matches!(
+                parse(&input, MAX_ARGS, MAX_BYTES),
+                ParseResult::Incomplete
+            ));
+        }
+
+        // Declared bulk sizes are summed and checked header by header
+        assert!(matches!(
+            parse(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1048577\r\n", MAX_ARGS, MAX_BYTES),
+            ParseResult::Error(e) if e.contains("larger than 1048576 bytes")
+        ));
+        assert!(matches!(
+            parse(b"*2\r\n$524288\r\n", MAX_ARGS, MAX_BYTES),
+            ParseResult::Incomplete
+        ));
+        let mut input = b"*3\r\n$524288\r\n".to_vec();
+        input.extend(vec![b'x'; 524288]);
+        input.extend_from_slice(b"\r\n$524289\r\n");
+        assert!(matches!(
+            parse(&input, MAX_ARGS, MAX_BYTES),
+            ParseResult::Error(_)
+        ));
+
+        // Exactly at the limit is fine
+        assert!(matches!(
+            parse(b"a b c\r\n", 3, MAX_BYTES),
+            ParseResult::Complete(Frame::Array(Some(args)), _) if args.len() == 3
+        ));
+    }
 }
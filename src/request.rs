@@ -4,12 +4,58 @@
 //! It sits between the I/O runtime (which handles bytes) and the protocol
 //! parsers (which handle syntax), executing commands against storage.
 
+use crate::config::{Config, DisabledCommands, DEFAULT_MAX_COMMAND_ARGS};
 use crate::protocols::echo::parser as echo_parser;
-use crate::protocols::memcached::parser::{Command, ParseResult, Parser, Response};
+use crate::protocols::memcached::meta::{self, MetaItem};
+use crate::protocols::memcached::parser::{Command, ParseError, ParseResult, Parser, Response};
 use crate::protocols::ping::parser as ping_parser;
+use crate::protocols::resp::glob;
 use crate::protocols::resp::parser as resp_parser;
-use crate::storage::{Storage, StorageResult};
-use std::sync::Arc;
+use crate::storage::{
+    CounterResult, EvictionPolicy, SetCondition, SignedCounterResult, SnapshotStream, Storage,
+    StorageResult, TxnOp, ValueType, MAX_RELATIVE_TTL,
+};
+use crate::transaction::Transaction;
+use std::io::{Cursor, Read};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// RESP error for writes while storage is read-only.
+const READONLY_ERROR: &str = "READONLY You can't write against a read only server.";
+
+/// Memcached error for a write refused under the `noeviction` policy.
+const OUT_OF_MEMORY_ERROR: &str = "out of memory storing object";
+
+/// RESP error for a write refused under the `noeviction` policy.
+const OOM_ERROR: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+
+/// Keys listed by `stats hotkeys`, and by `HOTKEYS` without a count.
+const HOT_KEYS_REPORTED: usize = 10;
+
+/// Error for a hot key report while tracking is off.
+const HOTKEYS_DISABLED_ERROR: &str = "hot key tracking is disabled";
+
+/// Keys a `SCAN` page visits without a `COUNT`.
+const SCAN_DEFAULT_COUNT: usize = 10;
+
+/// RESP error for an argument or stored value that isn't an i64.
+const NOT_AN_INTEGER_ERROR: &str = "ERR value is not an integer or out of range";
+
+/// RESP error for a command used on a key holding another kind of value.
+const WRONGTYPE_ERROR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Bytes a RESP command may declare beyond its value limit, for its name,
+/// key and options. A value slightly over the limit still gets a
+/// "value too large" reply; anything far larger closes the connection at
+/// its header.
+const RESP_COMMAND_HEADROOM: usize = 64 * 1024;
+
+/// Buffers a streamed response (e.g. `SYNC`) is written from at a time,
+/// so it holds a few buffers however large it is.
+const STREAM_CHAIN_BUFFERS: usize = 4;
 
 /// Protocol type for command processing.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,6 +64,415 @@ pub enum Protocol {
     Resp,
     Ping,
     Echo,
+    /// Not known yet: `detect_protocol` picks memcached or RESP from the
+    /// connection's first bytes
+    Auto,
+}
+
+/// The protocol a client speaks, judged by the first bytes it sent.
+///
+/// Every RESP frame opens with a type marker, which no memcached command
+/// does; anything else is taken as memcached text.
+pub fn detect_protocol(first_bytes: &[u8]) -> Protocol {
+    match first_bytes.first() {
+        Some(b'*' | b'$' | b'+' | b'-' | b':') => Protocol::Resp,
+        _ => Protocol::Memcached,
+    }
+}
+
+/// Per-server settings that shape command processing.
+#[derive(Debug, Clone)]
+pub struct RequestConfig {
+    /// Maximum size for a single value (larger values are rejected)
+    pub max_value_size: usize,
+    /// Memcached value limit, when it differs from `max_value_size`
+    pub memcached_max_value_size: Option<usize>,
+    /// RESP value limit, when it differs from `max_value_size`
+    pub resp_max_value_size: Option<usize>,
+    /// Commands rejected as if they didn't exist
+    pub disabled_commands: DisabledCommands,
+    /// Echo frames use a 4-byte big-endian length instead of ASCII
+    pub echo_binary_framing: bool,
+    /// Most arguments accepted in a single RESP command
+    pub max_command_args: usize,
+    /// Highest estimated cost of a command that runs (0 = unlimited)
+    pub max_command_cost: usize,
+    /// Flags stamped on items stored over RESP, which has no flags field
+    pub default_flags: u32,
+    /// Longest a single command may take to arrive (`None` = unbounded)
+    pub command_assembly_timeout: Option<Duration>,
+    /// Suppress replies to memcached commands sent with `noreply`
+    pub honor_noreply: bool,
+    /// I/O backend serving requests, reported by `stats` and `INFO`
+    pub runtime: &'static str,
+    /// Worker threads serving requests
+    pub workers: usize,
+    /// Most items `stats sizes` scans before extrapolating (0 = all)
+    pub stats_sample_size: usize,
+    /// `incr`/`decr` create a missing counter at 0 instead of `NOT_FOUND`
+    pub incr_autocreate: bool,
+    /// Deployment tag reported by `version` and `INFO`
+    pub server_id: Option<String>,
+    /// Input bytes per connection turn once replies are owed (0 = unlimited)
+    pub fair_share_bytes: usize,
+    /// Most pool buffers a single response may take (0 = unlimited)
+    pub max_response_chain_buffers: usize,
+    /// How connections ended, shared by every worker cloned from this config
+    pub connection_stats: Arc<ConnectionStats>,
+    /// Responses written from buffer chains, shared like `connection_stats`
+    pub response_chain_stats: Arc<ResponseChainStats>,
+}
+
+/// Why connections closed, counted across all workers.
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    /// Peers that closed their end (a read returned EOF)
+    pub clean_disconnects: AtomicU64,
+    /// Connections dropped because a read failed, e.g. reset by the peer
+    pub read_errors: AtomicU64,
+    /// Connections closed for sitting idle past `idle_timeout_secs`
+    pub idle_kicks: AtomicU64,
+    /// Connections open now, one per `OpenConnection`; not reset
+    open: AtomicUsize,
+    /// Id of the last connection accepted; ids start at 1
+    last_id: AtomicU64,
+    /// Counts at the last `reset`, subtracted when reported
+    baseline: Mutex<ConnectionCounts>,
+}
+
+/// An accepted connection, counted as open until dropped.
+#[derive(Debug)]
+pub struct OpenConnection {
+    stats: Arc<ConnectionStats>,
+    /// Unique across the process, as reported by `CLIENT ID`
+    id: u64,
+}
+
+impl OpenConnection {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.stats.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of `ConnectionStats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ConnectionCounts {
+    pub clean_disconnects: u64,
+    pub read_errors: u64,
+    pub idle_kicks: u64,
+}
+
+impl ConnectionStats {
+    /// Counts since the last `reset`.
+    pub fn counts(&self) -> ConnectionCounts {
+        let baseline = self.baseline.lock().unwrap();
+        let current = self.current();
+        ConnectionCounts {
+            clean_disconnects: current
+                .clean_disconnects
+                .saturating_sub(baseline.clean_disconnects),
+            read_errors: current.read_errors.saturating_sub(baseline.read_errors),
+            idle_kicks: current.idle_kicks.saturating_sub(baseline.idle_kicks),
+        }
+    }
+
+    /// Restart the counts from zero, like `Storage::reset_stats`.
+    pub fn reset(&self) {
+        let mut baseline = self.baseline.lock().unwrap();
+        *baseline = self.current();
+    }
+
+    /// Count a newly accepted connection as open and give it the next id;
+    /// stored on the connection, the guard uncounts it on every close path.
+    pub fn opened(self: &Arc<Self>) -> OpenConnection {
+        self.open.fetch_add(1, Ordering::Relaxed);
+        OpenConnection {
+            stats: Arc::clone(self),
+            id: self.last_id.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+
+    /// Connections open now, across all workers.
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    fn current(&self) -> ConnectionCounts {
+        ConnectionCounts {
+            clean_disconnects: self.clean_disconnects.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            idle_kicks: self.idle_kicks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Responses too large for one buffer, across all workers.
+#[derive(Debug, Default)]
+pub struct ResponseChainStats {
+    /// Most buffers a single response was written from
+    peak: AtomicUsize,
+    /// Responses refused for needing over `max_response_chain_buffers`
+    rejections: AtomicU64,
+}
+
+impl ResponseChainStats {
+    /// Note a response written from a chain of `buffers` buffers.
+    pub fn record(&self, buffers: usize) {
+        self.peak.fetch_max(buffers, Ordering::Relaxed);
+    }
+
+    /// Longest chain written since the last `reset`.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Responses refused since the last `reset`.
+    pub fn rejections(&self) -> u64 {
+        self.rejections.load(Ordering::Relaxed)
+    }
+
+    /// Restart the peak and count from zero, like `Storage::reset_stats`.
+    pub fn reset(&self) {
+        self.peak.store(0, Ordering::Relaxed);
+        self.rejections.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for RequestConfig {
+    fn default() -> Self {
+        Self {
+            max_value_size: 0,
+            memcached_max_value_size: None,
+            resp_max_value_size: None,
+            disabled_commands: DisabledCommands::default(),
+            echo_binary_framing: false,
+            max_command_args: DEFAULT_MAX_COMMAND_ARGS,
+            max_command_cost: 0,
+            default_flags: 0,
+            command_assembly_timeout: None,
+            honor_noreply: true,
+            runtime: "none",
+            workers: 0,
+            stats_sample_size: 0,
+            incr_autocreate: false,
+            server_id: None,
+            fair_share_bytes: 0,
+            max_response_chain_buffers: 0,
+            connection_stats: Arc::default(),
+            response_chain_stats: Arc::default(),
+        }
+    }
+}
+
+impl RequestConfig {
+    /// Extract the processing settings from the server config, for workers
+    /// of the named `runtime` backend.
+    ///
+    /// Workers share one result by cloning it, so that their connection
+    /// stats add up.
+    pub fn from_config(config: &Config, runtime: &'static str) -> Self {
+        Self {
+            max_value_size: config.max_value_size,
+            memcached_max_value_size: config.memcached_max_value_size,
+            resp_max_value_size: config.resp_max_value_size,
+            disabled_commands: config.disabled_commands.clone(),
+            echo_binary_framing: config.echo_binary_framing,
+            max_command_args: config.max_command_args,
+            max_command_cost: config.max_command_cost,
+            default_flags: config.default_flags,
+            command_assembly_timeout: match config.command_assembly_timeout_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            honor_noreply: config.honor_noreply,
+            runtime,
+            workers: config.worker_count(),
+            stats_sample_size: config.stats_sample_size,
+            incr_autocreate: config.incr_autocreate,
+            server_id: config.server_id.clone(),
+            fair_share_bytes: config.fair_share_bytes,
+            max_response_chain_buffers: config.max_response_chain_buffers,
+            connection_stats: Arc::default(),
+            response_chain_stats: Arc::default(),
+        }
+    }
+
+    /// Largest value a connection speaking `protocol` may send.
+    pub fn max_value_size_for(&self, protocol: Protocol) -> usize {
+        let limit = match protocol {
+            Protocol::Memcached => self.memcached_max_value_size,
+            Protocol::Resp => self.resp_max_value_size,
+            Protocol::Ping | Protocol::Echo | Protocol::Auto => None,
+        };
+        limit.unwrap_or(self.max_value_size)
+    }
+
+    /// Why `command`, estimated to cost `cost`, may not run: it's over
+    /// `max_command_cost`. Logged, as the client is likely misbehaving.
+    fn cost_error(&self, command: &str, cost: usize) -> Option<String> {
+        if self.max_command_cost == 0 || cost <= self.max_command_cost {
+            return None;
+        }
+        warn!(
+            command,
+            cost,
+            limit = self.max_command_cost,
+            "Rejected command over max_command_cost"
+        );
+        Some(format!(
+            "command cost {cost} exceeds max_command_cost {}",
+            self.max_command_cost
+        ))
+    }
+
+    /// Most buffers a streamed response is written from at a time: a few,
+    /// within `max_response_chain_buffers`.
+    pub fn stream_chain_buffers(&self) -> usize {
+        match self.max_response_chain_buffers {
+            0 => STREAM_CHAIN_BUFFERS,
+            max => max.min(STREAM_CHAIN_BUFFERS),
+        }
+    }
+
+    /// Whether a `len`-byte response, written from buffers of
+    /// `buffer_size`, would take more than `max_response_chain_buffers` of
+    /// them. Counted and logged, as it asks for a large share of the pool.
+    fn over_chain_limit(&self, len: usize, buffer_size: usize) -> bool {
+        let buffers = len.div_ceil(buffer_size);
+        if self.max_response_chain_buffers == 0 || buffers <= self.max_response_chain_buffers {
+            return false;
+        }
+        self.response_chain_stats
+            .rejections
+            .fetch_add(1, Ordering::Relaxed);
+        warn!(
+            buffers,
+            limit = self.max_response_chain_buffers,
+            "Refused response over max_response_chain_buffers"
+        );
+        true
+    }
+}
+
+/// Estimated cost of a memcached command: the keys a multi-get looks up.
+fn memcached_command_cost(command: &Command) -> usize {
+    match command {
+        Command::Get { keys }
+        | Command::Gets { keys }
+        | Command::Gat { keys, .. }
+        | Command::Gats { keys, .. } => keys.len(),
+        _ => 1,
+    }
+}
+
+/// Estimated cost of a RESP command: the keys it names, or for a snapshot
+/// every item stored.
+fn resp_command_cost(cmd: &str, args: &[resp_parser::Frame], storage: &Storage) -> usize {
+    match cmd {
+        "DEL" | "EXISTS" => args.len().saturating_sub(1),
+        "SYNC" => storage.stats().item_count,
+        "SCAN" => ScanArgs::parse(args).map_or(1, |scan| scan.count),
+        _ => 1,
+    }
+}
+
+/// Per-connection protocol state carried across requests.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Memcached storage command still waiting for its data block
+    pending_storage: Option<PendingStorage>,
+    /// Optimistic transaction state (RESP WATCH/MULTI/EXEC)
+    transaction: Transaction,
+    /// Reply shape of each command queued since MULTI, in order
+    queued_replies: Vec<QueuedReply>,
+    /// A command failed to queue, so EXEC must abort
+    multi_error: bool,
+    /// This connection's place in the worker's byte-fair scheduling
+    fair_share: FairShare,
+    /// RESP version negotiated by HELLO, which replies are encoded in
+    resp_version: resp_parser::RespVersion,
+    /// The connection's id, from `OpenConnection` (0 if never accepted)
+    client_id: u64,
+    /// Where the connection comes from (`None` over a Unix socket)
+    peer_addr: Option<SocketAddr>,
+    /// Set by `CLIENT SETNAME`
+    client_name: Option<String>,
+    /// Taken by `SYNC`, for `process_resp` to stream out as its reply
+    sync_snapshot: Option<SnapshotStream>,
+}
+
+impl Session {
+    /// State for a newly accepted connection.
+    pub fn new(client_id: u64, peer_addr: Option<SocketAddr>) -> Self {
+        Self {
+            client_id,
+            peer_addr,
+            ..Self::default()
+        }
+    }
+}
+
+/// Deficit round robin over the input of a worker's connections.
+///
+/// Each turn may process `fair_share_bytes` of input once it has replies
+/// to send, less whatever earlier turns overshot by (a command is never
+/// split, so a large value overshoots). Input left over waits for the
+/// connection's next turn, after the write, while other connections take
+/// theirs. A connection that drains its input owes nothing.
+#[derive(Debug, Default)]
+struct FairShare {
+    /// Input processed beyond past turns' shares
+    debt: usize,
+    /// Input the current turn may still process (`None` = unlimited)
+    turn_budget: Option<usize>,
+}
+
+/// A parsed storage command line, kept while its data block streams in.
+///
+/// The command line stays at the start of the input until the command
+/// completes, so later reads only need to check whether the data arrived.
+#[derive(Debug)]
+struct PendingStorage {
+    command: Command,
+    command_bytes: usize,
+}
+
+/// How EXEC reports the results of one queued command.
+#[derive(Debug, Clone, Copy)]
+enum QueuedReply {
+    /// SET: +OK, or nil if its condition kept it from writing
+    Set,
+    /// DEL over this many keys: :<deleted count>
+    Deleted(usize),
+    /// GET: the value, or nil
+    Get,
+}
+
+/// A response read out piece by piece as it is written.
+pub struct ResponseStream(Box<dyn Read>);
+
+impl ResponseStream {
+    fn new(reader: impl Read + 'static) -> Self {
+        Self(Box::new(reader))
+    }
+}
+
+impl Read for ResponseStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl std::fmt::Debug for ResponseStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResponseStream")
+    }
 }
 
 /// Result of processing a buffer.
@@ -44,12 +499,179 @@ pub enum ProcessResult {
         consumed: usize,
         response_data: Vec<u8>,
     },
+    /// Response produced as it is written, for one too large to build
+    /// whole. The event loop should write it through a chain of at most
+    /// `stream_chain_buffers`, refilled from `response` as it drains.
+    StreamResponse {
+        consumed: usize,
+        response: ResponseStream,
+    },
     /// Client sent quit command.
     Quit,
+    /// Client is speaking another protocol. The event loop should send
+    /// `response` best-effort and close the connection.
+    Reject { response: &'static [u8] },
+    /// Error written to the output buffer, after which the input can't be
+    /// followed (an oversized value's data block is still on its way). The
+    /// event loop should send it best-effort and close the connection.
+    ErrorAfterResponse { response_len: usize },
     /// Protocol error, connection should be closed.
     Error,
 }
 
+/// Output room a pipeline keeps free before running another command.
+///
+/// Processors copy small fixed replies without checking the space left, so
+/// a batch stops short of a nearly full buffer and writes what it has.
+const PIPELINE_OUTPUT_RESERVE: usize = 1024;
+
+/// Process every complete command in `input`, concatenating the responses.
+///
+/// Runs `protocol`'s processor until the input is drained or a command
+/// needs more data, so commands sent in one segment are answered with one
+/// write. Anything other than a response ends the batch. If responses are
+/// waiting, or a partial command follows silent (`noreply`) ones, the batch
+/// so far is returned and the rest is processed again on the next call. A
+/// response
+/// too large for `output` is returned as one `LargeResponse` carrying the
+/// batch's earlier responses too.
+///
+/// With `fair_share_bytes` set, a batch with responses also ends once it
+/// used up the connection's turn (see [`FairShare`]).
+pub fn process_pipeline(
+    protocol: Protocol,
+    input: &[u8],
+    output: &mut [u8],
+    storage: &Arc<Storage>,
+    config: &RequestConfig,
+    session: &mut Session,
+) -> ProcessResult {
+    let share = config.fair_share_bytes;
+    if share == 0 {
+        return process_batch(protocol, input, output, storage, config, session, None);
+    }
+
+    let budget = share.saturating_sub(session.fair_share.debt);
+    let result = process_batch(
+        protocol,
+        input,
+        output,
+        storage,
+        config,
+        session,
+        Some(budget),
+    );
+    session.fair_share.turn_budget = None;
+    session.fair_share.debt = match result {
+        ProcessResult::Response { consumed, .. }
+        | ProcessResult::LargeResponse { consumed, .. }
+        | ProcessResult::StreamResponse { consumed, .. }
+            if consumed < input.len() =>
+        {
+            (session.fair_share.debt + consumed).saturating_sub(share)
+        }
+        _ => 0,
+    };
+    result
+}
+
+/// Run one batch for `process_pipeline`, ending it once a response is
+/// waiting and `budget` bytes of input were processed.
+#[allow(clippy::too_many_arguments)]
+fn process_batch(
+    protocol: Protocol,
+    input: &[u8],
+    output: &mut [u8],
+    storage: &Arc<Storage>,
+    config: &RequestConfig,
+    session: &mut Session,
+    budget: Option<usize>,
+) -> ProcessResult {
+    let mut consumed = 0;
+    let mut written = 0;
+    // The runtimes hand over a whole buffer, the unit a chain is made of
+    let buffer_size = output.len();
+
+    loop {
+        let (rest, out) = (&input[consumed..], &mut output[written..]);
+        // For processors that run several commands per call
+        session.fair_share.turn_budget = budget.map(|budget| budget.saturating_sub(consumed));
+        let result = match protocol {
+            Protocol::Memcached => process_memcached(rest, out, storage, config, session),
+            Protocol::Resp => process_resp(rest, out, storage, config, session),
+            Protocol::Ping => process_ping(rest, out, storage),
+            Protocol::Echo => process_echo(rest, out, storage, config),
+            // The runtimes settle this on a connection's first bytes
+            Protocol::Auto => match detect_protocol(rest) {
+                Protocol::Resp => process_resp(rest, out, storage, config, session),
+                _ => process_memcached(rest, out, storage, config, session),
+            },
+        };
+
+        // Refused whole; the command was still run, so it's consumed
+        let result = match result {
+            ProcessResult::LargeResponse {
+                consumed: used,
+                response_data,
+            } if config.over_chain_limit(response_data.len(), buffer_size) => {
+                ProcessResult::Response {
+                    consumed: used,
+                    response_len: copy_response(response_too_large(protocol), out),
+                }
+            }
+            result => result,
+        };
+
+        match result {
+            ProcessResult::Response {
+                consumed: used,
+                response_len,
+            } => {
+                consumed += used;
+                written += response_len;
+                if used == 0
+                    || consumed == input.len()
+                    || output.len() - written < PIPELINE_OUTPUT_RESERVE
+                    || (written > 0 && budget.is_some_and(|budget| consumed >= budget))
+                {
+                    break;
+                }
+            }
+            ProcessResult::LargeResponse {
+                consumed: used,
+                response_data,
+            } if consumed > 0 => {
+                let mut batch = Vec::with_capacity(written + response_data.len());
+                batch.extend_from_slice(&output[..written]);
+                batch.extend_from_slice(&response_data);
+                return ProcessResult::LargeResponse {
+                    consumed: consumed + used,
+                    response_data: batch,
+                };
+            }
+            ProcessResult::StreamResponse {
+                consumed: used,
+                response,
+            } if consumed > 0 => {
+                let batch = Cursor::new(output[..written].to_vec());
+                return ProcessResult::StreamResponse {
+                    consumed: consumed + used,
+                    response: ResponseStream::new(batch.chain(response)),
+                };
+            }
+            // More input is on its way, so the rest waits for it
+            ProcessResult::NeedData | ProcessResult::NeedChain { .. } if consumed > 0 => break,
+            _ if written > 0 => break,
+            other => return other,
+        }
+    }
+
+    ProcessResult::Response {
+        consumed,
+        response_len: written,
+    }
+}
+
 /// Process a Memcached protocol buffer.
 ///
 /// Parses commands from `input`, executes them against `storage`,
@@ -60,10 +682,44 @@ pub fn process_memcached(
     input: &[u8],
     output: &mut [u8],
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    config: &RequestConfig,
+    session: &mut Session,
 ) -> ProcessResult {
+    if let Some(pending) = session.pending_storage.take() {
+        return complete_storage(pending, input, output, storage, session);
+    }
+
+    // A RESP array is never a memcached command
+    if input.first() == Some(&b'*') {
+        return ProcessResult::Reject {
+            response: b"ERROR this port speaks memcached, not RESP\r\n",
+        };
+    }
+
+    let max_value_size = config.max_value_size_for(Protocol::Memcached);
     match Parser::parse(input) {
-        ParseResult::Complete(command, consumed) => {
+        ParseResult::Complete(mut command, consumed) => {
+            if !config.honor_noreply {
+                command.clear_noreply();
+            }
+            if config.disabled_commands.memcached(command.name()) {
+                let len = copy_response(Response::error(), output);
+                return ProcessResult::Response {
+                    consumed,
+                    response_len: len,
+                };
+            }
+            // Before anything runs; storage commands cost 1, so no value
+            // follows one rejected here
+            if let Some(error) = config.cost_error(command.name(), memcached_command_cost(&command))
+            {
+                let len = copy_response(&Response::client_error(&error), output);
+                return ProcessResult::Response {
+                    consumed,
+                    response_len: len,
+                };
+            }
+
             if matches!(command, Command::Quit) {
                 return ProcessResult::Quit;
             }
@@ -75,16 +731,13 @@ pub fn process_memcached(
                 | Command::Replace { bytes, .. }
                 | Command::Append { bytes, .. }
                 | Command::Prepend { bytes, .. }
-                | Command::Cas { bytes, .. } => {
+                | Command::Cas { bytes, .. }
+                | Command::MetaSet { bytes, .. } => {
                     // Check max value size
                     if *bytes > max_value_size {
                         let response = Response::client_error("value too large");
                         let len = copy_response(&response, output);
-                        // Consume the command but not the data (connection will be closed)
-                        return ProcessResult::Response {
-                            consumed,
-                            response_len: len,
-                        };
+                        return ProcessResult::ErrorAfterResponse { response_len: len };
                     }
 
                     let data_end = consumed + bytes + 2; // +2 for \r\n
@@ -108,10 +761,12 @@ pub fn process_memcached(
                         response_len: len,
                     }
                 }
-                Command::Get { .. } | Command::Gets { .. } => {
-                    let response = execute_command(&command, storage);
+                _ => {
+                    let response = execute_command(&command, storage, config);
 
-                    // Check if response fits in output buffer
+                    // Check if response fits in output buffer (values and
+                    // stats can outgrow it, and pipelined commands only get
+                    // what the batch left)
                     if response.len() > output.len() {
                         return ProcessResult::LargeResponse {
                             consumed,
@@ -125,119 +780,275 @@ pub fn process_memcached(
                         response_len: len,
                     }
                 }
-                _ => {
-                    let response = execute_command(&command, storage);
-                    let len = copy_response(&response, output);
-
-                    ProcessResult::Response {
-                        consumed,
-                        response_len: len,
-                    }
-                }
             }
         }
         ParseResult::NeedData {
             command_bytes,
             data_bytes,
         } => {
-            // Check max value size early
+            if let Some(name) = Parser::command_name(input) {
+                if config.disabled_commands.memcached(&name) {
+                    return reject_storage(
+                        input,
+                        output,
+                        command_bytes,
+                        data_bytes,
+                        Response::error(),
+                    );
+                }
+            }
+
+            // Check max value size early; the data block isn't skipped, as
+            // it may be huge, so the connection closes after the error
             if data_bytes > max_value_size {
                 let response = Response::client_error("value too large");
                 let len = copy_response(&response, output);
-                return ProcessResult::Response {
-                    consumed: command_bytes + 2, // Skip past command line
-                    response_len: len,
-                };
+                return ProcessResult::ErrorAfterResponse { response_len: len };
             }
 
-            let total_needed = command_bytes + data_bytes + 2;
-            if input.len() >= total_needed {
-                // We have enough data, re-parse with data
-                match Parser::parse_with_data(input) {
-                    ParseResult::Complete(command, consumed) => {
-                        let data = &input[command_bytes..command_bytes + data_bytes];
-                        let response = execute_storage_command(&command, storage, data);
-                        let len = copy_response(&response, output);
-
-                        ProcessResult::Response {
-                            consumed,
-                            response_len: len,
-                        }
+            match Parser::parse_storage_header(input) {
+                ParseResult::Complete(mut command, command_bytes) => {
+                    if !config.honor_noreply {
+                        command.clear_noreply();
                     }
-                    _ => ProcessResult::NeedData,
-                }
-            } else {
-                // Check if we need chain buffers for large value
-                if data_bytes > output.len() {
-                    return ProcessResult::NeedChain {
-                        command_len: command_bytes,
-                        value_len: data_bytes,
-                    };
+                    complete_storage(
+                        PendingStorage {
+                            command,
+                            command_bytes,
+                        },
+                        input,
+                        output,
+                        storage,
+                        session,
+                    )
                 }
-                ProcessResult::NeedData
+                _ => ProcessResult::Error,
             }
         }
-        ParseResult::Error(crate::protocols::memcached::parser::ParseError::Incomplete) => {
-            ProcessResult::NeedData
-        }
+        ParseResult::Error(ParseError::Incomplete) => ProcessResult::NeedData,
+        ParseResult::Error(ParseError::BadCommandLine {
+            command_bytes,
+            data_bytes,
+        }) => reject_storage(
+            input,
+            output,
+            command_bytes,
+            data_bytes,
+            &Response::client_error("bad command line format"),
+        ),
         ParseResult::Error(_) => ProcessResult::Error,
     }
 }
 
+/// Execute a storage command once its data block has arrived.
+///
+/// Until then the parsed command is parked in the session, so a value
+/// streamed over many reads has its command line parsed only once.
+fn complete_storage(
+    pending: PendingStorage,
+    input: &[u8],
+    output: &mut [u8],
+    storage: &Arc<Storage>,
+    session: &mut Session,
+) -> ProcessResult {
+    let command_bytes = pending.command_bytes;
+    let data_bytes = pending.command.data_len().unwrap_or(0);
+    let data_end = command_bytes + data_bytes;
+
+    if input.len() < data_end + 2 {
+        // Check if we need chain buffers for large value
+        let result = if data_bytes > output.len() {
+            ProcessResult::NeedChain {
+                command_len: command_bytes,
+                value_len: data_bytes,
+            }
+        } else {
+            ProcessResult::NeedData
+        };
+        session.pending_storage = Some(pending);
+        return result;
+    }
+
+    let response = if &input[data_end..data_end + 2] == b"\r\n" {
+        execute_storage_command(&pending.command, storage, &input[command_bytes..data_end])
+    } else {
+        Response::client_error("bad data chunk").to_vec()
+    };
+    let len = copy_response(&response, output);
+
+    ProcessResult::Response {
+        consumed: data_end + 2,
+        response_len: len,
+    }
+}
+
+/// Reply `response` to a rejected storage command, skipping its data block.
+///
+/// The data block is swallowed so it isn't parsed as a command. If it can't
+/// fit in a buffer it can't be skipped either, so the connection closes
+/// after the reply, as for a value over the size limit.
+fn reject_storage(
+    input: &[u8],
+    output: &mut [u8],
+    command_bytes: usize,
+    data_bytes: usize,
+    response: &[u8],
+) -> ProcessResult {
+    let total_needed = command_bytes.saturating_add(data_bytes).saturating_add(2);
+    if input.len() < total_needed && total_needed <= output.len() {
+        return ProcessResult::NeedData;
+    }
+
+    let len = copy_response(response, output);
+    if input.len() < total_needed {
+        return ProcessResult::ErrorAfterResponse { response_len: len };
+    }
+    ProcessResult::Response {
+        consumed: total_needed,
+        response_len: len,
+    }
+}
+
 /// Process a RESP protocol buffer.
+///
+/// Every complete command in `input` is executed in order and the replies
+/// are concatenated, so a pipeline sent in one write (inline and multibulk
+/// commands mixed freely) is answered in one response. A trailing partial
+/// command is left unconsumed.
 pub fn process_resp(
     input: &[u8],
     output: &mut [u8],
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    config: &RequestConfig,
+    session: &mut Session,
 ) -> ProcessResult {
-    match resp_parser::parse(input) {
-        resp_parser::ParseResult::Complete(frame, consumed) => {
-            // Check for large values in SET command
-            if let resp_parser::Frame::Array(Some(args)) = &frame {
-                if args.len() >= 3 {
-                    if let resp_parser::Frame::Bulk(Some(cmd)) = &args[0] {
-                        if cmd.eq_ignore_ascii_case(b"SET") {
-                            if let resp_parser::Frame::Bulk(Some(value)) = &args[2] {
-                                if value.len() > max_value_size {
-                                    let response =
-                                        resp_parser::Frame::error("ERR value too large");
-                                    let encoded = response.encode();
-                                    let len = encoded.len().min(output.len());
-                                    output[..len].copy_from_slice(&encoded[..len]);
-                                    return ProcessResult::Response {
-                                        consumed,
-                                        response_len: len,
-                                    };
-                                }
-                            }
-                        }
-                    }
-                }
+    let mut consumed = 0;
+    let mut written = 0;
+
+    loop {
+        if is_memcached_command(&input[consumed..]) {
+            if consumed > 0 {
+                break;
             }
+            return ProcessResult::Reject {
+                response: b"ERROR this port speaks RESP, not memcached\r\n",
+            };
+        }
 
-            let response = execute_resp_command(&frame, storage);
-            let encoded = response.encode();
+        let (frame, frame_len) = match resp_parser::parse(
+            &input[consumed..],
+            config.max_command_args,
+            config
+                .max_value_size_for(Protocol::Resp)
+                .saturating_add(RESP_COMMAND_HEADROOM),
+        ) {
+            resp_parser::ParseResult::Complete(frame, len) => (frame, len),
+            resp_parser::ParseResult::Incomplete => break,
+            // Answer what came before the bad frame; the next call fails on it
+            resp_parser::ParseResult::Error(_) if consumed > 0 => break,
+            resp_parser::ParseResult::Error(_) => return ProcessResult::Error,
+        };
+        consumed += frame_len;
 
-            // Check if response fits in output buffer
-            if encoded.len() > output.len() {
-                return ProcessResult::LargeResponse {
-                    consumed,
-                    response_data: encoded.to_vec(),
-                };
-            }
+        let response = execute_resp_frame(&frame, storage, config, session);
+
+        // A bulk string read out of the snapshot as the socket drains
+        if let Some(snapshot) = session.sync_snapshot.take() {
+            let mut head = output[..written].to_vec();
+            head.extend_from_slice(format!("${}\r\n", snapshot.remaining()).as_bytes());
+            let reply = Cursor::new(head).chain(snapshot).chain(&b"\r\n"[..]);
+            return ProcessResult::StreamResponse {
+                consumed,
+                response: ResponseStream::new(reply),
+            };
+        }
 
-            let len = encoded.len();
-            output[..len].copy_from_slice(&encoded[..len]);
+        let encoded = response.encode_as(session.resp_version);
 
-            ProcessResult::Response {
+        // Check if response fits in output buffer
+        if written + encoded.len() > output.len() {
+            let mut response_data = Vec::with_capacity(written + encoded.len());
+            response_data.extend_from_slice(&output[..written]);
+            response_data.extend_from_slice(&encoded);
+            return ProcessResult::LargeResponse {
                 consumed,
-                response_len: len,
+                response_data,
+            };
+        }
+
+        output[written..written + encoded.len()].copy_from_slice(&encoded);
+        written += encoded.len();
+
+        if session
+            .fair_share
+            .turn_budget
+            .is_some_and(|budget| consumed >= budget)
+        {
+            break;
+        }
+    }
+
+    if consumed == 0 {
+        return ProcessResult::NeedData;
+    }
+    ProcessResult::Response {
+        consumed,
+        response_len: written,
+    }
+}
+
+/// Whether `input` starts with a memcached text command that can't be a
+/// RESP inline command (e.g. `set k 0 0 5` or `gets k`).
+///
+/// Commands valid in both protocols, like `get k` and `quit`, are left to
+/// the RESP parser.
+fn is_memcached_command(input: &[u8]) -> bool {
+    if matches!(
+        input.first(),
+        None | Some(b'+' | b'-' | b':' | b'$' | b'*' | b'|')
+    ) {
+        return false;
+    }
+    match Parser::parse(input) {
+        ParseResult::Complete(Command::Get { keys }, _) => keys.len() > 1,
+        ParseResult::Complete(Command::Quit, _) => false,
+        ParseResult::Complete(..)
+        | ParseResult::NeedData { .. }
+        | ParseResult::Error(ParseError::BadCommandLine { .. }) => true,
+        ParseResult::Error(_) => false,
+    }
+}
+
+/// Execute one parsed RESP command, rejecting oversized SET and CVS values.
+fn execute_resp_frame(
+    frame: &resp_parser::Frame,
+    storage: &Arc<Storage>,
+    config: &RequestConfig,
+    session: &mut Session,
+) -> resp_parser::Frame {
+    let max_value_size = config.max_value_size_for(Protocol::Resp);
+    if let resp_parser::Frame::Array(Some(args)) = frame {
+        if let [resp_parser::Frame::Bulk(Some(cmd)), _, resp_parser::Frame::Bulk(Some(value)), ..] =
+            args.as_slice()
+        {
+            let sets_value = [&b"SET"[..], b"GETSET", b"SETNX"]
+                .iter()
+                .any(|name| cmd.eq_ignore_ascii_case(name));
+            if sets_value && value.len() > max_value_size {
+                return resp_parser::Frame::error("ERR value too large");
+            }
+        }
+        if let [resp_parser::Frame::Bulk(Some(cmd)), _, _, resp_parser::Frame::Bulk(Some(value))] =
+            args.as_slice()
+        {
+            let sets_value = cmd.eq_ignore_ascii_case(b"CVS") || cmd.eq_ignore_ascii_case(b"SETEX");
+            if sets_value && value.len() > max_value_size {
+                return resp_parser::Frame::error("ERR value too large");
             }
         }
-        resp_parser::ParseResult::Incomplete => ProcessResult::NeedData,
-        resp_parser::ParseResult::Error(_) => ProcessResult::Error,
     }
+
+    execute_resp_command(frame, storage, config, session)
 }
 
 /// Process a Ping protocol buffer.
@@ -294,14 +1105,29 @@ pub fn process_echo(
     input: &[u8],
     output: &mut [u8],
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    config: &RequestConfig,
 ) -> ProcessResult {
-    match echo_parser::parse(input) {
+    let max_value_size = config.max_value_size_for(Protocol::Echo);
+    let (parsed, response_header): (_, fn(usize, &mut [u8]) -> usize) =
+        if config.echo_binary_framing {
+            (
+                echo_parser::parse_binary(input),
+                echo_parser::binary_response_header,
+            )
+        } else {
+            (echo_parser::parse(input), echo_parser::response_header)
+        };
+
+    match parsed {
         echo_parser::ParseResult::Complete(cmd) => match cmd {
             echo_parser::Command::Quit => ProcessResult::Quit,
             echo_parser::Command::Echo { length, header_len } => {
                 // Check max value size
                 if length > max_value_size {
+                    // Binary frames have no way to carry an error reply
+                    if config.echo_binary_framing {
+                        return ProcessResult::Error;
+                    }
                     let err = echo_parser::response_error("value too large");
                     let len = err.len().min(output.len());
                     output[..len].copy_from_slice(&err[..len]);
@@ -324,8 +1150,8 @@ pub fn process_echo(
                     return ProcessResult::NeedData;
                 }
 
-                // Echo back: length + \r\n + data
-                let resp_header_len = echo_parser::response_header(length, output);
+                // Echo back: length header + data
+                let resp_header_len = response_header(length, output);
                 let response_len = resp_header_len + length;
 
                 // Check if response fits in output buffer
@@ -361,7 +1187,11 @@ pub fn process_echo(
     }
 }
 
-fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
+fn execute_command(command: &Command, storage: &Arc<Storage>, config: &RequestConfig) -> Vec<u8> {
+    if command.is_write() && storage.is_read_only() {
+        return Response::server_error("read only").to_vec();
+    }
+
     match command {
         Command::Get { keys } => {
             let keys_ref: Vec<&str> = keys.iter().map(|s| s.as_str()).collect();
@@ -375,6 +1205,24 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
             response
         }
 
+        Command::Gat { exptime, keys } | Command::Gats { exptime, keys } => {
+            let keys_ref: Vec<&str> = keys.iter().map(|s| s.as_str()).collect();
+            let items = storage.get_and_touch(&keys_ref, *exptime);
+            let with_cas = matches!(command, Command::Gats { .. });
+
+            let mut response = Vec::new();
+            for (key, item) in items {
+                response.extend_from_slice(&Response::value(
+                    &key,
+                    item.flags,
+                    &item.value,
+                    with_cas.then_some(item.cas_unique),
+                ));
+            }
+            response.extend_from_slice(Response::end());
+            response
+        }
+
         Command::Gets { keys } => {
             let keys_ref: Vec<&str> = keys.iter().map(|s| s.as_str()).collect();
             let items = storage.get_multi(&keys_ref);
@@ -392,6 +1240,40 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
             response
         }
 
+        Command::MetaGet { key, flags } => {
+            // Touched first, so `t` reports the new TTL
+            if let Some(ttl) = flags.ttl {
+                storage.touch(key, ttl);
+            }
+            match storage.get_with_ttl(key) {
+                Some((item, ttl)) => meta::hit_response(
+                    flags,
+                    &MetaItem {
+                        key,
+                        value: &item.value,
+                        flags: item.flags,
+                        cas: item.cas_unique,
+                        ttl: ttl.map(|ttl| ttl.as_secs()),
+                    },
+                ),
+                None => {
+                    let recache = storage.claim_recache(key).map(|won| {
+                        if won {
+                            meta::Recache::Won
+                        } else {
+                            meta::Recache::Claimed
+                        }
+                    });
+                    meta::miss_response(flags, recache)
+                }
+            }
+        }
+
+        Command::MetaDelete { key, flags } => match storage.delete(key) {
+            StorageResult::Deleted => meta::done_response(flags, key, None),
+            _ => meta::not_found_response(flags, key),
+        },
+
         Command::Delete { key, noreply } => {
             let result = storage.delete(key);
             if *noreply {
@@ -404,12 +1286,28 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
             }
         }
 
+        Command::Touch {
+            key,
+            exptime,
+            noreply,
+        } => {
+            let result = storage.touch(key, *exptime);
+            if *noreply {
+                Vec::new()
+            } else {
+                match result {
+                    StorageResult::Touched => Response::touched().to_vec(),
+                    _ => Response::not_found().to_vec(),
+                }
+            }
+        }
+
         Command::Incr {
             key,
             value,
             noreply,
         } => {
-            let result = handle_incr_decr(storage, key, *value, true);
+            let result = handle_incr_decr(storage, key, *value, true, config);
             if *noreply {
                 Vec::new()
             } else {
@@ -422,7 +1320,7 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
             value,
             noreply,
         } => {
-            let result = handle_incr_decr(storage, key, *value, false);
+            let result = handle_incr_decr(storage, key, *value, false, config);
             if *noreply {
                 Vec::new()
             } else {
@@ -440,21 +1338,137 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
             }
         }
 
-        Command::Stats => {
+        Command::Stats { group: Some(group) } => match group.as_str() {
+            "sizes" => {
+                let detail = storage.detail_stats(config.stats_sample_size);
+                let mut response = Vec::new();
+                for (size, count) in &detail.sizes {
+                    response
+                        .extend_from_slice(&Response::stat(&size.to_string(), &count.to_string()));
+                }
+                if let Some(age) = detail.oldest_age {
+                    response.extend_from_slice(&Response::stat(
+                        "oldest_age",
+                        &age.as_secs().to_string(),
+                    ));
+                }
+                // Lets clients tell an estimate from an exact count
+                response.extend_from_slice(&Response::stat(
+                    "approximate",
+                    if detail.approximate { "1" } else { "0" },
+                ));
+                response.extend_from_slice(Response::end());
+                response
+            }
+            // Hottest first, with estimated reads
+            "hotkeys" => match storage.hot_keys(HOT_KEYS_REPORTED) {
+                Some(hot_keys) => {
+                    let mut response = Vec::new();
+                    for (key, reads) in &hot_keys {
+                        response.extend_from_slice(&Response::stat(key, &reads.to_string()));
+                    }
+                    response.extend_from_slice(Response::end());
+                    response
+                }
+                None => Response::server_error(HOTKEYS_DISABLED_ERROR).to_vec(),
+            },
+            // Load signals for autoscaling
+            "pressure" => {
+                let pressure = storage.pressure();
+                let mut response = Vec::new();
+                response.extend_from_slice(&Response::stat(
+                    "eviction_rate",
+                    &format!("{:.2}", pressure.eviction_rate),
+                ));
+                response.extend_from_slice(&Response::stat(
+                    "memory_pressure",
+                    &format!("{:.4}", pressure.memory_pressure),
+                ));
+                response.extend_from_slice(Response::end());
+                response
+            }
+            // Counters restart from zero; gauges like curr_items stay
+            "reset" => {
+                storage.reset_stats();
+                config.connection_stats.reset();
+                config.response_chain_stats.reset();
+                Response::reset().to_vec()
+            }
+            _ => Response::error().to_vec(),
+        },
+
+        Command::Stats { group: None } => {
             let stats = storage.stats();
             let mut response = Vec::new();
             response
                 .extend_from_slice(&Response::stat("curr_items", &stats.item_count.to_string()));
+            response.extend_from_slice(&Response::stat(
+                "total_items",
+                &stats.total_items.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat("cmd_set", &stats.cmd_set.to_string()));
+            response.extend_from_slice(&Response::stat("get_hits", &stats.get_hits.to_string()));
+            response
+                .extend_from_slice(&Response::stat("get_misses", &stats.get_misses.to_string()));
+            response.extend_from_slice(&Response::stat("evictions", &stats.evictions.to_string()));
+            response.extend_from_slice(&Response::stat(
+                "expired_unfetched",
+                &stats.expired_unfetched.to_string(),
+            ));
             response.extend_from_slice(&Response::stat("bytes", &stats.memory_used.to_string()));
             response.extend_from_slice(&Response::stat(
                 "limit_maxbytes",
                 &stats.max_memory.to_string(),
             ));
+            response.extend_from_slice(&Response::stat(
+                "value_bytes",
+                &stats.value_bytes.to_string(),
+            ));
+            if let Some(max) = stats.max_value_bytes {
+                response.extend_from_slice(&Response::stat("limit_value_bytes", &max.to_string()));
+            }
+            response.extend_from_slice(&Response::stat(
+                "evicted_bytes",
+                &stats.evicted_bytes.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat(
+                "expired_bytes",
+                &stats.expired_bytes.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat(
+                "cas_counter",
+                &stats.cas_counter.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat("runtime", config.runtime));
+            response
+                .extend_from_slice(&Response::stat("worker_count", &config.workers.to_string()));
+            let connections = config.connection_stats.counts();
+            response.extend_from_slice(&Response::stat(
+                "clean_disconnects",
+                &connections.clean_disconnects.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat(
+                "read_errors",
+                &connections.read_errors.to_string(),
+            ));
+            response.extend_from_slice(&Response::stat(
+                "idle_kicks",
+                &connections.idle_kicks.to_string(),
+            ));
+            let chains = &config.response_chain_stats;
+            response.extend_from_slice(&Response::stat(
+                "response_chain_peak",
+                &chains.peak().to_string(),
+            ));
+            response.extend_from_slice(&Response::stat(
+                "response_chain_rejections",
+                &chains.rejections().to_string(),
+            ));
             response.extend_from_slice(Response::end());
             response
         }
 
-        Command::Version => Response::version().to_vec(),
+        Command::Version => Response::version(config.server_id.as_deref()).to_vec(),
 
         Command::Quit => Vec::new(),
 
@@ -463,6 +1477,10 @@ fn execute_command(command: &Command, storage: &Arc<Storage>) -> Vec<u8> {
 }
 
 fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8]) -> Vec<u8> {
+    if storage.is_read_only() {
+        return Response::server_error("read only").to_vec();
+    }
+
     match command {
         Command::Set {
             key,
@@ -475,10 +1493,7 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
             if *noreply {
                 Vec::new()
             } else {
-                match result {
-                    StorageResult::Stored => Response::stored().to_vec(),
-                    _ => Response::not_stored().to_vec(),
-                }
+                store_response(result)
             }
         }
 
@@ -493,10 +1508,7 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
             if *noreply {
                 Vec::new()
             } else {
-                match result {
-                    StorageResult::Stored => Response::stored().to_vec(),
-                    _ => Response::not_stored().to_vec(),
-                }
+                store_response(result)
             }
         }
 
@@ -511,10 +1523,7 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
             if *noreply {
                 Vec::new()
             } else {
-                match result {
-                    StorageResult::Stored => Response::stored().to_vec(),
-                    _ => Response::not_stored().to_vec(),
-                }
+                store_response(result)
             }
         }
 
@@ -523,10 +1532,7 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
             if *noreply {
                 Vec::new()
             } else {
-                match result {
-                    StorageResult::Stored => Response::stored().to_vec(),
-                    _ => Response::not_stored().to_vec(),
-                }
+                store_response(result)
             }
         }
 
@@ -535,10 +1541,7 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
             if *noreply {
                 Vec::new()
             } else {
-                match result {
-                    StorageResult::Stored => Response::stored().to_vec(),
-                    _ => Response::not_stored().to_vec(),
-                }
+                store_response(result)
             }
         }
 
@@ -558,16 +1561,31 @@ fn execute_storage_command(command: &Command, storage: &Arc<Storage>, data: &[u8
                     StorageResult::Stored => Response::stored().to_vec(),
                     StorageResult::CasMismatch => Response::exists().to_vec(),
                     StorageResult::NotFound => Response::not_found().to_vec(),
-                    _ => Response::not_stored().to_vec(),
+                    result => store_response(result),
                 }
             }
         }
 
+        Command::MetaSet { key, flags, .. } => match storage.set_returning_cas(
+            key,
+            data.to_vec(),
+            flags.client_flags.unwrap_or(0),
+            flags.ttl.unwrap_or(0),
+        ) {
+            Some(cas) => meta::done_response(flags, key, Some(cas)),
+            None => Response::server_error(OUT_OF_MEMORY_ERROR).to_vec(),
+        },
+
         _ => Response::error().to_vec(),
     }
 }
 
-fn execute_resp_command(frame: &resp_parser::Frame, storage: &Arc<Storage>) -> resp_parser::Frame {
+fn execute_resp_command(
+    frame: &resp_parser::Frame,
+    storage: &Arc<Storage>,
+    config: &RequestConfig,
+    session: &mut Session,
+) -> resp_parser::Frame {
     use resp_parser::Frame;
 
     let args = match frame {
@@ -584,6 +1602,65 @@ fn execute_resp_command(frame: &resp_parser::Frame, storage: &Arc<Storage>) -> r
         _ => return Frame::error("ERR invalid command"),
     };
 
+    if config.disabled_commands.resp(&cmd) {
+        return Frame::error(format!("ERR unknown command '{cmd}'"));
+    }
+
+    // Applies at once, even inside MULTI. The session holds all
+    // per-connection state, i.e. the transaction and its watches.
+    if cmd == "RESET" {
+        if args.len() != 1 {
+            return Frame::error("ERR wrong number of arguments for 'reset' command");
+        }
+        // Scheduling state isn't the client's to reset, nor is who it is
+        *session = Session {
+            fair_share: std::mem::take(&mut session.fair_share),
+            ..Session::new(session.client_id, session.peer_addr)
+        };
+        return Frame::simple("RESET");
+    }
+
+    if let Some(error) = config.cost_error(&cmd, resp_command_cost(&cmd, args, storage)) {
+        // Like any command rejected while queueing, this aborts EXEC
+        if session.transaction.in_multi() {
+            session.multi_error = true;
+        }
+        return Frame::error(format!("ERR {error}"));
+    }
+
+    if storage.is_read_only()
+        && matches!(
+            cmd.as_str(),
+            "SET"
+                | "CVS"
+                | "GETSET"
+                | "SETNX"
+                | "SETEX"
+                | "GETDEL"
+                | "DEL"
+                | "INCR"
+                | "DECR"
+                | "INCRBY"
+                | "DECRBY"
+                | "EXPIRE"
+                | "PERSIST"
+                | "FLUSHALL"
+                | "FLUSHDB"
+        )
+    {
+        // Like any command rejected while queueing, this aborts EXEC
+        if session.transaction.in_multi() {
+            session.multi_error = true;
+        }
+        return Frame::error(READONLY_ERROR);
+    }
+
+    if let Some(response) =
+        execute_resp_transaction(&cmd, args, storage, config.default_flags, session)
+    {
+        return response;
+    }
+
     match cmd.as_str() {
         "PING" => {
             if args.len() > 1 {
@@ -602,27 +1679,137 @@ fn execute_resp_command(frame: &resp_parser::Frame, storage: &Arc<Storage>) -> r
                 _ => return Frame::error("ERR invalid key"),
             };
             match storage.get(&key) {
+                Some(item) if item.value_type != ValueType::String => Frame::error(WRONGTYPE_ERROR),
                 Some(item) => Frame::bulk(item.value),
                 None => Frame::null(),
             }
         }
 
+        // Like Redis, replaces a value of any kind
         "SET" => {
-            if args.len() < 3 {
+            let [_, key, value, options @ ..] = args.as_slice() else {
                 return Frame::error("ERR wrong number of arguments for 'set' command");
-            }
-            let key = match &args[1] {
+            };
+            let key = match key {
                 Frame::Bulk(Some(k)) => String::from_utf8_lossy(k).to_string(),
                 _ => return Frame::error("ERR invalid key"),
             };
-            let value = match &args[2] {
+            let value = match value {
                 Frame::Bulk(Some(v)) => v.to_vec(),
                 _ => return Frame::error("ERR invalid value"),
             };
-            storage.set(&key, value, 0, 0);
-            Frame::simple("OK")
+            let options = match SetOptions::parse(options) {
+                Ok(options) => options,
+                Err(e) => return Frame::error(e),
+            };
+            // Without EX/PX the default TTL applies, as for memcached sets
+            let expires_at = options.expires_at.or_else(|| storage.calculate_expiry(0));
+            let flags = config.default_flags;
+            let result = match options.condition {
+                SetCondition::Always => storage.set_until(&key, value, flags, expires_at),
+                SetCondition::IfAbsent => storage.add_until(&key, value, flags, expires_at),
+                SetCondition::IfPresent => storage.replace_until(&key, value, flags, expires_at),
+            };
+            match result {
+                StorageResult::OutOfMemory => Frame::error(OOM_ERROR),
+                StorageResult::NotStored => Frame::null(),
+                _ => Frame::simple("OK"),
+            }
+        }
+
+        // Extension: compare-and-set by value. Replies 1 if swapped, 0 if
+        // the value differs, nil if the key is missing.
+        "CVS" => {
+            let [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(expected)), Frame::Bulk(Some(new))] =
+                args.as_slice()
+            else {
+                return Frame::error("ERR wrong number of arguments for 'cvs' command");
+            };
+            let key = String::from_utf8_lossy(key);
+            if let Some(wrong_type) = expect_string(storage, &key) {
+                return wrong_type;
+            }
+            match storage.compare_value_and_set(
+                &key,
+                expected,
+                new.to_vec(),
+                config.default_flags,
+                0,
+            ) {
+                StorageResult::Stored => Frame::integer(1),
+                StorageResult::CasMismatch => Frame::integer(0),
+                StorageResult::OutOfMemory => Frame::error(OOM_ERROR),
+                _ => Frame::null(),
+            }
+        }
+
+        // The old value and the new one swap under one hold of the lock
+        "GETSET" => {
+            let [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(value))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'getset' command");
+            };
+            let key = String::from_utf8_lossy(key);
+            if let Some(wrong_type) = expect_string(storage, &key) {
+                return wrong_type;
+            }
+            match storage.get_and_set(&key, value.to_vec(), config.default_flags, 0) {
+                Some(Some(old)) => Frame::bulk(old.value),
+                Some(None) => Frame::null(),
+                None => Frame::error(OOM_ERROR),
+            }
+        }
+
+        "SETNX" => {
+            let [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(value))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'setnx' command");
+            };
+            let key = String::from_utf8_lossy(key);
+            match storage.add(&key, value.to_vec(), config.default_flags, 0) {
+                StorageResult::Stored => Frame::integer(1),
+                StorageResult::OutOfMemory => Frame::error(OOM_ERROR),
+                _ => Frame::integer(0),
+            }
+        }
+
+        "SETEX" => {
+            let [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(seconds)), Frame::Bulk(Some(value))] =
+                args.as_slice()
+            else {
+                return Frame::error("ERR wrong number of arguments for 'setex' command");
+            };
+            let Some(seconds) = std::str::from_utf8(seconds)
+                .ok()
+                .and_then(|n| n.parse::<i64>().ok())
+            else {
+                return Frame::error(NOT_AN_INTEGER_ERROR);
+            };
+            let Some(ttl) = relative_ttl(seconds) else {
+                return Frame::error("ERR invalid expire time in 'setex' command");
+            };
+            let key = String::from_utf8_lossy(key);
+            match storage.set(&key, value.to_vec(), config.default_flags, ttl) {
+                StorageResult::OutOfMemory => Frame::error(OOM_ERROR),
+                _ => Frame::simple("OK"),
+            }
+        }
+
+        // Returns exactly the value it deletes
+        "GETDEL" => {
+            let [_, Frame::Bulk(Some(key))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'getdel' command");
+            };
+            let key = String::from_utf8_lossy(key);
+            if let Some(wrong_type) = expect_string(storage, &key) {
+                return wrong_type;
+            }
+            match storage.get_and_delete(&key) {
+                Some(item) => Frame::bulk(item.value),
+                None => Frame::null(),
+            }
         }
 
+        "INCR" | "DECR" | "INCRBY" | "DECRBY" => execute_resp_counter(&cmd, args, storage),
+
         "DEL" => {
             if args.len() < 2 {
                 return Frame::error("ERR wrong number of arguments for 'del' command");
@@ -655,6 +1842,61 @@ fn execute_resp_command(frame: &resp_parser::Frame, storage: &Arc<Storage>) -> r
             Frame::integer(count)
         }
 
+        // Expiry is a single hard deadline (there is no stale window), so
+        // this is the time until the key becomes a miss
+        "TTL" | "PTTL" => {
+            if args.len() != 2 {
+                return Frame::error(format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    cmd.to_lowercase()
+                ));
+            }
+            let key = match &args[1] {
+                Frame::Bulk(Some(k)) => String::from_utf8_lossy(k),
+                _ => return Frame::error("ERR invalid key"),
+            };
+            match storage.ttl(&key) {
+                None => Frame::integer(-2),
+                Some(None) => Frame::integer(-1),
+                Some(Some(ttl)) if cmd == "PTTL" => Frame::integer(ttl.as_millis() as i64),
+                // Rounded like Redis, so a fresh `EX 10` reads as 10
+                Some(Some(ttl)) => Frame::integer(((ttl.as_millis() + 500) / 1000) as i64),
+            }
+        }
+
+        // A deadline that is already due removes the key, as in Redis
+        "EXPIRE" => {
+            let [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(seconds))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'expire' command");
+            };
+            let Some(seconds) = std::str::from_utf8(seconds)
+                .ok()
+                .and_then(|n| n.parse::<i64>().ok())
+            else {
+                return Frame::error(NOT_AN_INTEGER_ERROR);
+            };
+            let key = String::from_utf8_lossy(key);
+            if seconds <= 0 {
+                return Frame::integer(
+                    matches!(storage.delete(&key), StorageResult::Deleted) as i64
+                );
+            }
+            let Some(expires_at) = Instant::now().checked_add(Duration::from_secs(seconds as u64))
+            else {
+                return Frame::error("ERR invalid expire time in 'expire' command");
+            };
+            Frame::integer(storage.set_expiry(&key, Some(expires_at)).is_some() as i64)
+        }
+
+        // 1 only if the key had a deadline to drop
+        "PERSIST" => {
+            let [_, Frame::Bulk(Some(key))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'persist' command");
+            };
+            let previous = storage.set_expiry(&String::from_utf8_lossy(key), None);
+            Frame::integer(matches!(previous, Some(Some(_))) as i64)
+        }
+
         "FLUSHALL" | "FLUSHDB" => {
             storage.flush_all();
             Frame::simple("OK")
@@ -662,52 +1904,2845 @@ fn execute_resp_command(frame: &resp_parser::Frame, storage: &Arc<Storage>) -> r
 
         "DBSIZE" => Frame::integer(storage.stats().item_count as i64),
 
-        "QUIT" => Frame::simple("OK"),
+        "CONFIG" => execute_resp_config(args, storage, config),
 
-        _ => Frame::error(format!("ERR unknown command '{cmd}'")),
-    }
-}
+        // The server and stats sections; every worker runs the whole
+        // pipeline, so the worker count stands in for io_threads. The
+        // deployment's server id, if any, is its run_id
+        "INFO" => {
+            let stats = storage.stats();
+            let run_id = match &config.server_id {
+                Some(id) => format!("run_id:{id}\r\n"),
+                None => String::new(),
+            };
+            Frame::bulk(format!(
+                "# Server\r\nredis_version:{}\r\nredis_mode:standalone\r\n{}runtime:{}\r\n\
+                 io_threads:{}\r\n\r\n\
+                 # Stats\r\ntotal_items:{}\r\ncmd_set:{}\r\nkeyspace_hits:{}\r\n\
+                 keyspace_misses:{}\r\nevicted_keys:{}\r\nexpired_unfetched:{}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                run_id,
+                config.runtime,
+                config.workers,
+                stats.total_items,
+                stats.cmd_set,
+                stats.get_hits,
+                stats.get_misses,
+                stats.evictions,
+                stats.expired_unfetched,
+            ))
+        }
 
-fn handle_incr_decr(storage: &Arc<Storage>, key: &str, delta: u64, is_incr: bool) -> Vec<u8> {
-    match storage.get(key) {
-        None => Response::not_found().to_vec(),
-        Some(item) => {
-            let current_str = match std::str::from_utf8(&item.value) {
-                Ok(s) => s.trim(),
-                Err(_) => {
-                    return Response::client_error(
-                        "cannot increment or decrement non-numeric value",
-                    )
-                    .to_vec();
-                }
+        // No replication, so no replica ever acknowledges
+        "WAIT" => {
+            if args.len() != 3 {
+                return Frame::error("ERR wrong number of arguments for 'wait' command");
+            }
+            let is_integer = |arg: &Frame| match arg {
+                Frame::Bulk(Some(n)) => std::str::from_utf8(n)
+                    .ok()
+                    .and_then(|n| n.parse::<i64>().ok())
+                    .is_some(),
+                _ => false,
             };
+            if !args[1..].iter().all(is_integer) {
+                return Frame::error(NOT_AN_INTEGER_ERROR);
+            }
+            Frame::integer(0)
+        }
 
-            let current: u64 = match current_str.parse() {
-                Ok(n) => n,
-                Err(_) => {
-                    return Response::client_error(
-                        "cannot increment or decrement non-numeric value",
-                    )
-                    .to_vec();
+        // Replica bootstrap: a point-in-time binary snapshot as one bulk
+        // string. The snapshot is taken now; `process_resp` streams the
+        // reply in its place.
+        "SYNC" => {
+            session.sync_snapshot = Some(storage.snapshot());
+            Frame::null()
+        }
+
+        // Pairs of key and estimated reads, hottest first
+        "HOTKEYS" => {
+            let count = match args.as_slice() {
+                [_] => HOT_KEYS_REPORTED,
+                [_, Frame::Bulk(Some(count))] => {
+                    match std::str::from_utf8(count).ok().and_then(|n| n.parse().ok()) {
+                        Some(count) => count,
+                        None => return Frame::error(NOT_AN_INTEGER_ERROR),
+                    }
                 }
+                _ => return Frame::error("ERR wrong number of arguments for 'hotkeys' command"),
+            };
+            match storage.hot_keys(count) {
+                Some(hot_keys) => Frame::array(
+                    hot_keys
+                        .into_iter()
+                        .map(|(key, reads)| {
+                            Frame::array(vec![Frame::bulk(key), Frame::integer(reads as i64)])
+                        })
+                        .collect(),
+                ),
+                None => Frame::error(format!("ERR {HOTKEYS_DISABLED_ERROR}")),
+            }
+        }
+
+        // One page of keys: the next cursor, then the keys
+        "SCAN" => {
+            let scan = match ScanArgs::parse(args) {
+                Ok(scan) => scan,
+                Err(error) => return Frame::error(error),
             };
+            let (next, keys) = storage.scan(scan.cursor, scan.count);
+            let keys = keys
+                .into_iter()
+                .filter(|key| {
+                    scan.pattern
+                        .is_none_or(|p| glob::matches(p, key.as_bytes()))
+                })
+                .map(Frame::bulk)
+                .collect();
+            Frame::array(vec![Frame::bulk(next.to_string()), Frame::array(keys)])
+        }
 
-            let new_value = if is_incr {
-                current.wrapping_add(delta)
-            } else {
-                current.saturating_sub(delta)
+        "TYPE" => {
+            let [_, Frame::Bulk(Some(key))] = args.as_slice() else {
+                return Frame::error("ERR wrong number of arguments for 'type' command");
             };
+            match storage.value_type(&String::from_utf8_lossy(key)) {
+                Some(value_type) => Frame::simple(value_type.name()),
+                None => Frame::simple("none"),
+            }
+        }
+
+        "OBJECT" => execute_resp_object(args, storage),
+
+        "DEBUG" => execute_resp_debug(args, storage),
+
+        "QUIT" => Frame::simple("OK"),
+
+        "HELLO" => execute_resp_hello(args, session),
+
+        "CLIENT" => execute_resp_client(args, session),
 
-            let new_value_str = new_value.to_string();
-            storage.set(key, new_value_str.as_bytes().to_vec(), item.flags, 0);
+        _ => Frame::error(format!("ERR unknown command '{cmd}'")),
+    }
+}
 
-            Response::numeric(new_value).to_vec()
+/// The WRONGTYPE error if `key` holds something other than a string.
+///
+/// For string commands whose storage call doesn't hand back the item.
+fn expect_string(storage: &Storage, key: &str) -> Option<resp_parser::Frame> {
+    match storage.value_type(key) {
+        Some(value_type) if value_type != ValueType::String => {
+            Some(resp_parser::Frame::error(WRONGTYPE_ERROR))
         }
+        _ => None,
     }
 }
 
-fn copy_response(response: &[u8], output: &mut [u8]) -> usize {
-    let len = response.len().min(output.len());
-    output[..len].copy_from_slice(&response[..len]);
-    len
+/// A Redis expire time in seconds as a storage TTL, `None` unless positive.
+///
+/// Storage takes a TTL past 30 days as a Unix timestamp, as memcached does,
+/// so a longer one is given as the timestamp it ends at.
+fn relative_ttl(seconds: i64) -> Option<u64> {
+    let seconds = u64::try_from(seconds).ok().filter(|&s| s > 0)?;
+    if seconds <= MAX_RELATIVE_TTL {
+        return Some(seconds);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs().checked_add(seconds)
+}
+
+/// Execute `INCR`/`DECR key` and `INCRBY`/`DECRBY key amount`.
+///
+/// A missing key counts from 0, and the value is a signed 64-bit integer
+/// that errors rather than wraps on overflow, as in Redis.
+fn execute_resp_counter(
+    cmd: &str,
+    args: &[resp_parser::Frame],
+    storage: &Arc<Storage>,
+) -> resp_parser::Frame {
+    use resp_parser::Frame;
+
+    let by = cmd.ends_with("BY");
+    let (key, amount) = match (by, args) {
+        (false, [_, Frame::Bulk(Some(key))]) => (key, 1),
+        (true, [_, Frame::Bulk(Some(key)), Frame::Bulk(Some(amount))]) => {
+            match std::str::from_utf8(amount)
+                .ok()
+                .and_then(|n| n.parse().ok())
+            {
+                Some(amount) => (key, amount),
+                None => return Frame::error(NOT_AN_INTEGER_ERROR),
+            }
+        }
+        _ => {
+            return Frame::error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                cmd.to_lowercase()
+            ))
+        }
+    };
+    let delta = if cmd.starts_with("DECR") {
+        match i64::checked_neg(amount) {
+            Some(delta) => delta,
+            None => return Frame::error("ERR decrement would overflow"),
+        }
+    } else {
+        amount
+    };
+
+    let key = String::from_utf8_lossy(key);
+    if let Some(wrong_type) = expect_string(storage, &key) {
+        return wrong_type;
+    }
+    match storage.incr_by_signed(&key, delta) {
+        SignedCounterResult::Value(n) => Frame::integer(n),
+        SignedCounterResult::NotNumeric => Frame::error(NOT_AN_INTEGER_ERROR),
+        SignedCounterResult::Overflow => Frame::error("ERR increment or decrement would overflow"),
+        SignedCounterResult::OutOfMemory => Frame::error(OOM_ERROR),
+    }
+}
+
+/// Execute `CONFIG <subcommand>`.
+///
+/// Settings are fixed at startup, so only `RESETSTAT` is supported: it
+/// restarts the same counters as memcached's `stats reset`.
+fn execute_resp_config(
+    args: &[resp_parser::Frame],
+    storage: &Arc<Storage>,
+    config: &RequestConfig,
+) -> resp_parser::Frame {
+    use resp_parser::Frame;
+
+    let subcommand = match args {
+        [_, Frame::Bulk(Some(sub)), ..] => String::from_utf8_lossy(sub).to_uppercase(),
+        _ => return Frame::error("ERR wrong number of arguments for 'config' command"),
+    };
+    match (subcommand.as_str(), args.len()) {
+        ("RESETSTAT", 2) => {
+            storage.reset_stats();
+            config.connection_stats.reset();
+            config.response_chain_stats.reset();
+            Frame::simple("OK")
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{subcommand}'"
+        )),
+    }
+}
+
+/// Execute `OBJECT <subcommand> <key>` introspection.
+///
+/// Lookups don't touch the key's LRU position, so inspecting a key doesn't
+/// change what gets evicted.
+fn execute_resp_object(args: &[resp_parser::Frame], storage: &Arc<Storage>) -> resp_parser::Frame {
+    use resp_parser::Frame;
+
+    if args.len() != 3 {
+        return Frame::error("ERR wrong number of arguments for 'object' command");
+    }
+    let (subcommand, key) = match (&args[1], &args[2]) {
+        (Frame::Bulk(Some(sub)), Frame::Bulk(Some(key))) => (
+            String::from_utf8_lossy(sub).to_uppercase(),
+            String::from_utf8_lossy(key),
+        ),
+        _ => return Frame::error("ERR invalid key"),
+    };
+
+    match subcommand.as_str() {
+        "IDLETIME" => match storage.idle_time(&key) {
+            Some(idle) => Frame::integer(idle.as_secs() as i64),
+            None => Frame::null(),
+        },
+        // Counted under every policy, but only meaningful under LFU
+        "FREQ" if storage.eviction_policy() != EvictionPolicy::Lfu => Frame::error(
+            "ERR An LFU maxmemory policy is not selected, access frequency not tracked.",
+        ),
+        "FREQ" => match storage.access_frequency(&key) {
+            Some(frequency) => Frame::integer(frequency as i64),
+            None => Frame::null(),
+        },
+        "ENCODING" => match storage.entry_stats(&key) {
+            Some(entry) if entry.value_type != ValueType::String => Frame::error(WRONGTYPE_ERROR),
+            Some(entry) => Frame::bulk(entry.encoding.name()),
+            None => Frame::null(),
+        },
+        _ => Frame::error(format!(
+            "ERR unknown subcommand '{subcommand}'. Try OBJECT HELP."
+        )),
+    }
+}
+
+/// Arguments of `SCAN cursor [MATCH pattern] [COUNT count]`.
+struct ScanArgs<'a> {
+    cursor: u64,
+    /// Keys must match this glob, checked once a page is taken, so a page
+    /// may come back with fewer than `count` keys, or none
+    pattern: Option<&'a [u8]>,
+    /// Keys to visit for the page
+    count: usize,
+}
+
+impl<'a> ScanArgs<'a> {
+    fn parse(args: &'a [resp_parser::Frame]) -> Result<Self, &'static str> {
+        use resp_parser::Frame;
+
+        let Some(Frame::Bulk(Some(cursor))) = args.get(1) else {
+            return Err("ERR wrong number of arguments for 'scan' command");
+        };
+        let cursor = std::str::from_utf8(cursor)
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .ok_or("ERR invalid cursor")?;
+        let mut scan = ScanArgs {
+            cursor,
+            pattern: None,
+            count: SCAN_DEFAULT_COUNT,
+        };
+        for option in args[2..].chunks(2) {
+            let [Frame::Bulk(Some(name)), Frame::Bulk(Some(value))] = option else {
+                return Err("ERR syntax error");
+            };
+            if name.eq_ignore_ascii_case(b"MATCH") {
+                scan.pattern = Some(value);
+            } else if name.eq_ignore_ascii_case(b"COUNT") {
+                scan.count = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or(NOT_AN_INTEGER_ERROR)?;
+                if scan.count == 0 {
+                    return Err("ERR syntax error");
+                }
+            } else {
+                return Err("ERR syntax error");
+            }
+        }
+        Ok(scan)
+    }
+}
+
+/// Options of `SET key value [NX|XX] [EX seconds|PX milliseconds]`.
+struct SetOptions {
+    condition: SetCondition,
+    /// Set by `EX`/`PX`; `None` leaves the default TTL
+    expires_at: Option<Instant>,
+}
+
+impl SetOptions {
+    fn parse(args: &[resp_parser::Frame]) -> Result<Self, &'static str> {
+        use resp_parser::Frame;
+
+        let mut options = SetOptions {
+            condition: SetCondition::Always,
+            expires_at: None,
+        };
+        let mut args = args.iter();
+        while let Some(option) = args.next() {
+            let Frame::Bulk(Some(name)) = option else {
+                return Err("ERR syntax error");
+            };
+            let condition = if name.eq_ignore_ascii_case(b"NX") {
+                SetCondition::IfAbsent
+            } else if name.eq_ignore_ascii_case(b"XX") {
+                SetCondition::IfPresent
+            } else {
+                let duration: fn(u64) -> Duration = if name.eq_ignore_ascii_case(b"EX") {
+                    Duration::from_secs
+                } else if name.eq_ignore_ascii_case(b"PX") {
+                    Duration::from_millis
+                } else {
+                    return Err("ERR syntax error");
+                };
+                let (None, Some(Frame::Bulk(Some(value)))) = (options.expires_at, args.next())
+                else {
+                    return Err("ERR syntax error");
+                };
+                let count = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|n| n.parse::<i64>().ok())
+                    .ok_or(NOT_AN_INTEGER_ERROR)?;
+                let expires_at = u64::try_from(count)
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .and_then(|n| Instant::now().checked_add(duration(n)))
+                    .ok_or("ERR invalid expire time in 'set' command")?;
+                options.expires_at = Some(expires_at);
+                continue;
+            };
+            if options.condition != SetCondition::Always {
+                return Err("ERR syntax error");
+            }
+            options.condition = condition;
+        }
+        Ok(options)
+    }
+}
+
+/// Execute `DEBUG <subcommand>` server controls.
+///
+/// Only `DEBUG READONLY ON|OFF` is supported, toggling read-only mode.
+fn execute_resp_debug(args: &[resp_parser::Frame], storage: &Arc<Storage>) -> resp_parser::Frame {
+    use resp_parser::Frame;
+
+    let arg = |i: usize| match args.get(i) {
+        Some(Frame::Bulk(Some(s))) => Some(String::from_utf8_lossy(s).to_uppercase()),
+        _ => None,
+    };
+
+    match arg(1).as_deref() {
+        Some("READONLY") => {
+            let read_only = match (arg(2).as_deref(), args.len()) {
+                (Some("ON"), 3) => true,
+                (Some("OFF"), 3) => false,
+                _ => return Frame::error("ERR syntax error, expected DEBUG READONLY ON|OFF"),
+            };
+            storage.set_read_only(read_only);
+            Frame::simple("OK")
+        }
+        Some("OBJECT") => {
+            let key = match (args.get(2), args.len()) {
+                (Some(Frame::Bulk(Some(key))), 3) => String::from_utf8_lossy(key),
+                _ => return Frame::error("ERR wrong number of arguments for 'debug' command"),
+            };
+            match storage.entry_stats(&key) {
+                // Items have no stable address, so `Value at` is a placeholder
+                Some(entry) => Frame::simple(format!(
+                    "Value at:0x0 refcount:1 encoding:{} serializedlength:{} lru:{} lru_seconds_idle:{}",
+                    entry.encoding.name(),
+                    entry.value_len,
+                    entry.lru_seq,
+                    entry.idle.as_secs()
+                )),
+                None => Frame::error("ERR no such key"),
+            }
+        }
+        Some(subcommand) => Frame::error(format!(
+            "ERR unknown subcommand '{subcommand}'. Try DEBUG HELP."
+        )),
+        None => Frame::error("ERR wrong number of arguments for 'debug' command"),
+    }
+}
+
+/// HELLO [protover [SETNAME name]]: switch the connection's protocol
+/// version (and name), then describe the server and connection, as a map in
+/// the new version.
+///
+/// There is no authentication to do, so AUTH is a syntax error.
+fn execute_resp_hello(args: &[resp_parser::Frame], session: &mut Session) -> resp_parser::Frame {
+    use resp_parser::{Frame, RespVersion};
+
+    let mut args = args[1..].iter();
+    let version = match args.next() {
+        None => session.resp_version,
+        Some(Frame::Bulk(Some(protover))) => {
+            match std::str::from_utf8(protover)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+            {
+                Some(2) => RespVersion::Resp2,
+                Some(3) => RespVersion::Resp3,
+                Some(_) => return Frame::error("NOPROTO unsupported protocol version"),
+                None => {
+                    return Frame::error("ERR Protocol version is not an integer or out of range")
+                }
+            }
+        }
+        Some(_) => return Frame::error("ERR syntax error"),
+    };
+    let mut name = None;
+    while let Some(option) = args.next() {
+        match (option, args.next()) {
+            (Frame::Bulk(Some(option)), Some(Frame::Bulk(Some(new_name))))
+                if option.eq_ignore_ascii_case(b"SETNAME") =>
+            {
+                match client_name(new_name) {
+                    Ok(new_name) => name = Some(new_name),
+                    Err(error) => return error,
+                }
+            }
+            _ => return Frame::error("ERR syntax error"),
+        }
+    }
+
+    session.resp_version = version;
+    if let Some(name) = name {
+        session.client_name = name;
+    }
+    let proto = match version {
+        RespVersion::Resp2 => 2,
+        RespVersion::Resp3 => 3,
+    };
+    Frame::Map(vec![
+        (Frame::bulk("server"), Frame::bulk(env!("CARGO_PKG_NAME"))),
+        (
+            Frame::bulk("version"),
+            Frame::bulk(env!("CARGO_PKG_VERSION")),
+        ),
+        (Frame::bulk("proto"), Frame::integer(proto)),
+        (Frame::bulk("id"), Frame::integer(session.client_id as i64)),
+        (Frame::bulk("mode"), Frame::bulk("standalone")),
+        (Frame::bulk("role"), Frame::bulk("master")),
+        (Frame::bulk("modules"), Frame::array(vec![])),
+    ])
+}
+
+/// CLIENT ID/GETNAME/SETNAME/INFO, about the calling connection only.
+fn execute_resp_client(args: &[resp_parser::Frame], session: &mut Session) -> resp_parser::Frame {
+    use resp_parser::Frame;
+
+    let subcommand = match args.get(1) {
+        Some(Frame::Bulk(Some(sub))) => String::from_utf8_lossy(sub).to_uppercase(),
+        _ => return Frame::error("ERR wrong number of arguments for 'client' command"),
+    };
+    let arity = match subcommand.as_str() {
+        "ID" | "GETNAME" | "INFO" => 2,
+        "SETNAME" => 3,
+        _ => {
+            return Frame::error(format!(
+                "ERR unknown subcommand '{subcommand}'. Try CLIENT HELP."
+            ))
+        }
+    };
+    if args.len() != arity {
+        return Frame::error(format!(
+            "ERR wrong number of arguments for 'client|{}' command",
+            subcommand.to_lowercase()
+        ));
+    }
+
+    match subcommand.as_str() {
+        "ID" => Frame::integer(session.client_id as i64),
+        "GETNAME" => match &session.client_name {
+            Some(name) => Frame::bulk(name.clone()),
+            None => Frame::null(),
+        },
+        "SETNAME" => {
+            let Frame::Bulk(Some(name)) = &args[2] else {
+                return Frame::error("ERR syntax error");
+            };
+            match client_name(name) {
+                Ok(name) => {
+                    session.client_name = name;
+                    Frame::simple("OK")
+                }
+                Err(error) => error,
+            }
+        }
+        // One line in Redis's `key=value` format, fields it doesn't track left out
+        "INFO" => {
+            let addr = session.peer_addr.map(|a| a.to_string()).unwrap_or_default();
+            let resp = match session.resp_version {
+                resp_parser::RespVersion::Resp2 => 2,
+                resp_parser::RespVersion::Resp3 => 3,
+            };
+            Frame::bulk(format!(
+                "id={} addr={addr} name={} resp={resp}\n",
+                session.client_id,
+                session.client_name.as_deref().unwrap_or_default(),
+            ))
+        }
+        _ => unreachable!("subcommand checked above"),
+    }
+}
+
+/// Validate a connection name given to CLIENT SETNAME or HELLO: `None` (no
+/// name) if empty, as names are shown space-separated, never with spaces.
+fn client_name(name: &[u8]) -> Result<Option<String>, resp_parser::Frame> {
+    if !name.iter().all(|b| (b'!'..=b'~').contains(b)) {
+        return Err(resp_parser::Frame::error(
+            "ERR Client names cannot contain spaces, newlines or special characters.",
+        ));
+    }
+    // All ASCII, so valid UTF-8
+    Ok((!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned()))
+}
+
+/// Handle WATCH/UNWATCH/MULTI/EXEC/DISCARD and queueing inside MULTI.
+///
+/// Returns `None` if the command should execute normally. Only SET, DEL and
+/// GET can be queued; anything else fails to queue and makes EXEC abort, as
+/// Redis does for commands rejected while queueing.
+fn execute_resp_transaction(
+    cmd: &str,
+    args: &[resp_parser::Frame],
+    storage: &Arc<Storage>,
+    default_flags: u32,
+    session: &mut Session,
+) -> Option<resp_parser::Frame> {
+    use resp_parser::Frame;
+
+    let in_multi = session.transaction.in_multi();
+    let response = match cmd {
+        "MULTI" => {
+            if !session.transaction.multi() {
+                return Some(Frame::error("ERR MULTI calls can not be nested"));
+            }
+            Frame::simple("OK")
+        }
+        "EXEC" => {
+            if !in_multi {
+                return Some(Frame::error("ERR EXEC without MULTI"));
+            }
+            let replies = std::mem::take(&mut session.queued_replies);
+            if std::mem::take(&mut session.multi_error) {
+                session.transaction.discard();
+                return Some(Frame::error(
+                    "EXECABORT Transaction discarded because of previous errors.",
+                ));
+            }
+            // Read-only mode was switched on after the writes were queued
+            if storage.is_read_only()
+                && replies
+                    .iter()
+                    .any(|reply| !matches!(reply, QueuedReply::Get))
+            {
+                session.transaction.discard();
+                return Some(Frame::error(READONLY_ERROR));
+            }
+            match session.transaction.exec(storage) {
+                Some(results) => {
+                    let mut results = results.into_iter();
+                    let frames = replies
+                        .into_iter()
+                        .map(|reply| match reply {
+                            QueuedReply::Set => match results.next() {
+                                Some(StorageResult::OutOfMemory) => Frame::error(OOM_ERROR),
+                                Some(StorageResult::NotStored) => Frame::null(),
+                                _ => Frame::simple("OK"),
+                            },
+                            QueuedReply::Deleted(keys) => {
+                                let deleted = results
+                                    .by_ref()
+                                    .take(keys)
+                                    .filter(|r| *r == StorageResult::Deleted)
+                                    .count();
+                                Frame::integer(deleted as i64)
+                            }
+                            QueuedReply::Get => match results.next() {
+                                Some(StorageResult::Value(value)) => Frame::bulk(value),
+                                Some(StorageResult::OutOfMemory) => Frame::error(OOM_ERROR),
+                                _ => Frame::null(),
+                            },
+                        })
+                        .collect();
+                    Frame::array(frames)
+                }
+                // A watched key changed: null array
+                None => Frame::Array(None),
+            }
+        }
+        "DISCARD" => {
+            if !in_multi {
+                return Some(Frame::error("ERR DISCARD without MULTI"));
+            }
+            session.transaction.discard();
+            session.queued_replies.clear();
+            session.multi_error = false;
+            Frame::simple("OK")
+        }
+        "WATCH" => {
+            if in_multi {
+                return Some(Frame::error("ERR WATCH inside MULTI is not allowed"));
+            }
+            if args.len() < 2 {
+                return Some(Frame::error(
+                    "ERR wrong number of arguments for 'watch' command",
+                ));
+            }
+            for arg in &args[1..] {
+                if let Frame::Bulk(Some(key)) = arg {
+                    session
+                        .transaction
+                        .watch(storage, &String::from_utf8_lossy(key));
+                }
+            }
+            Frame::simple("OK")
+        }
+        "UNWATCH" => {
+            session.transaction.unwatch();
+            Frame::simple("OK")
+        }
+        _ if in_multi => match queue_resp_command(cmd, args, storage, default_flags) {
+            Ok((ops, reply)) => {
+                for op in ops {
+                    session.transaction.queue(op);
+                }
+                session.queued_replies.push(reply);
+                Frame::simple("QUEUED")
+            }
+            Err(error) => {
+                session.multi_error = true;
+                error
+            }
+        },
+        _ => return None,
+    };
+    Some(response)
+}
+
+/// Translate a queueable RESP command into transaction ops, or the error
+/// that refuses to queue it.
+fn queue_resp_command(
+    cmd: &str,
+    args: &[resp_parser::Frame],
+    storage: &Storage,
+    default_flags: u32,
+) -> Result<(Vec<TxnOp>, QueuedReply), resp_parser::Frame> {
+    use resp_parser::Frame;
+
+    let key = |key: &[u8]| String::from_utf8_lossy(key).to_string();
+    match (cmd, args) {
+        ("SET", [_, Frame::Bulk(Some(k)), Frame::Bulk(Some(value)), options @ ..]) => {
+            let options = SetOptions::parse(options).map_err(Frame::error)?;
+            Ok((
+                vec![TxnOp::Set {
+                    key: key(k),
+                    value: value.to_vec(),
+                    flags: default_flags,
+                    // Without EX/PX the default TTL applies, as outside MULTI
+                    expires_at: options.expires_at.or_else(|| storage.calculate_expiry(0)),
+                    condition: options.condition,
+                }],
+                QueuedReply::Set,
+            ))
+        }
+        ("DEL", [_, keys @ ..]) if !keys.is_empty() => {
+            let ops: Vec<TxnOp> = keys
+                .iter()
+                .filter_map(|k| match k {
+                    Frame::Bulk(Some(k)) => Some(TxnOp::Delete { key: key(k) }),
+                    _ => None,
+                })
+                .collect();
+            let count = ops.len();
+            Ok((ops, QueuedReply::Deleted(count)))
+        }
+        ("GET", [_, Frame::Bulk(Some(k))]) => {
+            Ok((vec![TxnOp::Get { key: key(k) }], QueuedReply::Get))
+        }
+        _ => Err(Frame::error(format!(
+            "ERR '{cmd}' is not supported inside MULTI (only SET, DEL and GET are)"
+        ))),
+    }
+}
+
+/// Memcached reply to a set-style command.
+fn store_response(result: StorageResult) -> Vec<u8> {
+    match result {
+        StorageResult::Stored => Response::stored().to_vec(),
+        StorageResult::OutOfMemory => Response::server_error(OUT_OF_MEMORY_ERROR).to_vec(),
+        _ => Response::not_stored().to_vec(),
+    }
+}
+
+fn handle_incr_decr(
+    storage: &Arc<Storage>,
+    key: &str,
+    delta: u64,
+    is_incr: bool,
+    config: &RequestConfig,
+) -> Vec<u8> {
+    let result = match (is_incr, config.incr_autocreate) {
+        (true, false) => storage.incr(key, delta),
+        (false, false) => storage.decr(key, delta),
+        (true, true) => storage.incr_or_create(key, delta),
+        (false, true) => storage.decr_or_create(key, delta),
+    };
+
+    match result {
+        CounterResult::Value(n) => Response::numeric(n).to_vec(),
+        CounterResult::NotFound => Response::not_found().to_vec(),
+        CounterResult::OutOfMemory => Response::server_error(OUT_OF_MEMORY_ERROR).to_vec(),
+        CounterResult::NotNumeric => {
+            Response::client_error("cannot increment or decrement non-numeric value").to_vec()
+        }
+    }
+}
+
+/// Reply standing in for a response over `max_response_chain_buffers`.
+fn response_too_large(protocol: Protocol) -> &'static [u8] {
+    match protocol {
+        Protocol::Memcached => b"SERVER_ERROR response too large\r\n",
+        Protocol::Resp => b"-ERR response too large\r\n",
+        Protocol::Ping | Protocol::Echo | Protocol::Auto => b"ERROR response too large\r\n",
+    }
+}
+
+fn copy_response(response: &[u8], output: &mut [u8]) -> usize {
+    let len = response.len().min(output.len());
+    output[..len].copy_from_slice(&response[..len]);
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SnapshotFormat;
+
+    fn request_config() -> RequestConfig {
+        RequestConfig {
+            max_value_size: 1024 * 1024,
+            ..Default::default()
+        }
+    }
+
+    /// Run one memcached command and return the response bytes.
+    fn memcached(storage: &Arc<Storage>, input: &[u8]) -> Vec<u8> {
+        memcached_with(storage, input, &request_config())
+    }
+
+    fn memcached_with(storage: &Arc<Storage>, input: &[u8], config: &RequestConfig) -> Vec<u8> {
+        let mut output = vec![0u8; 4096];
+        match process_memcached(input, &mut output, storage, config, &mut Session::default()) {
+            ProcessResult::Response {
+                consumed,
+                response_len,
+            } => {
+                assert_eq!(consumed, input.len());
+                output[..response_len].to_vec()
+            }
+            _ => panic!("Expected a response"),
+        }
+    }
+
+    fn echo_config(binary: bool) -> RequestConfig {
+        RequestConfig {
+            echo_binary_framing: binary,
+            ..request_config()
+        }
+    }
+
+    #[test]
+    fn test_echo_ascii_framing() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let mut output = vec![0u8; 64];
+        match process_echo(
+            b"5\r\nhello5\r\n",
+            &mut output,
+            &storage,
+            &echo_config(false),
+        ) {
+            ProcessResult::Response {
+                consumed,
+                response_len,
+            } => {
+                assert_eq!(consumed, 8);
+                assert_eq!(&output[..response_len], b"5\r\nhello");
+            }
+            _ => panic!("Expected a response"),
+        }
+    }
+
+    #[test]
+    fn test_echo_binary_framing() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = echo_config(true);
+        let mut output = vec![0u8; 64];
+
+        let mut frame = 5u32.to_be_bytes().to_vec();
+        frame.extend_from_slice(b"hello");
+        match process_echo(&frame, &mut output, &storage, &config) {
+            ProcessResult::Response {
+                consumed,
+                response_len,
+            } => {
+                assert_eq!(consumed, 9);
+                assert_eq!(&output[..response_len], &frame[..]);
+            }
+            _ => panic!("Expected a response"),
+        }
+
+        // A partial header or payload waits for more data
+        assert!(matches!(
+            process_echo(&frame[..3], &mut output, &storage, &config),
+            ProcessResult::NeedData
+        ));
+        assert!(matches!(
+            process_echo(&frame[..6], &mut output, &storage, &config),
+            ProcessResult::NeedData
+        ));
+    }
+
+    #[test]
+    fn test_echo_binary_framing_multi_buffer() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = echo_config(true);
+        // Payload spans several output buffers
+        let mut output = vec![0u8; 64];
+        let payload: Vec<u8> = (0..200u8).collect();
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&payload);
+
+        match process_echo(&frame[..100], &mut output, &storage, &config) {
+            ProcessResult::NeedChain {
+                command_len,
+                value_len,
+            } => {
+                assert_eq!(command_len, 4);
+                assert_eq!(value_len, 200);
+            }
+            _ => panic!("Expected NeedChain"),
+        }
+
+        match process_echo(&frame, &mut output, &storage, &config) {
+            ProcessResult::LargeResponse {
+                consumed,
+                response_data,
+            } => {
+                assert_eq!(consumed, frame.len());
+                assert_eq!(response_data, frame);
+            }
+            _ => panic!("Expected a large response"),
+        }
+    }
+
+    #[test]
+    fn test_streamed_value_parses_header_once() {
+        use crate::protocols::memcached::parser::HEADER_PARSES;
+
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut output = vec![0u8; 4096];
+        let request = b"set k 5 0 10\r\n0123456789\r\nget k\r\n";
+        let set_len = request.len() - b"get k\r\n".len();
+
+        HEADER_PARSES.with(|n| n.set(0));
+        // Deliver one byte at a time; the runtime re-submits the whole
+        // accumulated buffer on each read
+        let mut response = None;
+        for end in 1..=request.len() {
+            match process_memcached(
+                &request[..end],
+                &mut output,
+                &storage,
+                &config,
+                &mut session,
+            ) {
+                ProcessResult::NeedData => {}
+                ProcessResult::Response {
+                    consumed,
+                    response_len,
+                } => {
+                    assert_eq!(end, set_len);
+                    assert_eq!(consumed, set_len);
+                    response = Some(output[..response_len].to_vec());
+                    break;
+                }
+                _ => panic!("Unexpected result at {end} bytes"),
+            }
+        }
+
+        assert_eq!(response.as_deref(), Some(&b"STORED\r\n"[..]));
+        assert_eq!(HEADER_PARSES.with(|n| n.get()), 1);
+        assert!(session.pending_storage.is_none());
+        assert_eq!(storage.get("k").unwrap().value, b"0123456789");
+    }
+
+    #[test]
+    fn test_bad_data_chunk() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(
+            memcached(&storage, b"set k 0 0 3\r\nabcd\r"),
+            b"CLIENT_ERROR bad data chunk\r\n"
+        );
+        assert!(storage.get("k").is_none());
+    }
+
+    #[test]
+    fn test_bad_trailing_token_skips_data_block() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let bad = b"CLIENT_ERROR bad command line format\r\n";
+        assert_eq!(memcached(&storage, b"set k 0 0 5 quiet\r\nhello\r\n"), bad);
+        assert_eq!(
+            memcached(&storage, b"cas k 0 0 5 1 noreply extra\r\nhello\r\n"),
+            bad
+        );
+        assert!(storage.get("k").is_none());
+
+        // The data block is consumed along with the command line
+        let mut output = vec![0u8; 1024];
+        let input = b"set k 0 0 5 x\r\nhello\r\nget k\r\n";
+        match process_memcached(
+            input,
+            &mut output,
+            &storage,
+            &request_config(),
+            &mut Session::default(),
+        ) {
+            ProcessResult::Response { consumed, .. } => assert_eq!(consumed, input.len() - 7),
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_flags_skip_data_block() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let mut output = vec![0u8; 1024];
+        let input = b"set k 99999999999 0 5\r\nhello\r\nget k\r\n";
+        match process_memcached(
+            input,
+            &mut output,
+            &storage,
+            &request_config(),
+            &mut Session::default(),
+        ) {
+            ProcessResult::Response {
+                consumed,
+                response_len,
+            } => {
+                assert_eq!(
+                    &output[..response_len],
+                    b"CLIENT_ERROR bad command line format\r\n"
+                );
+                // The next command starts right after the data block
+                assert_eq!(&input[consumed..], b"get k\r\n");
+            }
+            _ => panic!("expected a response"),
+        }
+        assert!(storage.get("k").is_none());
+    }
+
+    #[test]
+    fn test_noreply_ignored_when_not_honored() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(memcached(&storage, b"set k 0 0 1 noreply\r\nv\r\n"), b"");
+
+        let config = RequestConfig {
+            honor_noreply: false,
+            ..request_config()
+        };
+        assert_eq!(
+            memcached_with(&storage, b"set k 0 0 1 noreply\r\nv\r\n", &config),
+            b"STORED\r\n"
+        );
+        assert_eq!(
+            memcached_with(&storage, b"delete k noreply\r\n", &config),
+            b"DELETED\r\n"
+        );
+    }
+
+    #[test]
+    fn test_touch() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(memcached(&storage, b"touch k 10\r\n"), b"NOT_FOUND\r\n");
+
+        storage.set("k", b"v".to_vec(), 0, 0);
+        assert_eq!(memcached(&storage, b"touch k 10\r\n"), b"TOUCHED\r\n");
+        assert!(storage.ttl("k").unwrap().is_some());
+        assert_eq!(memcached(&storage, b"touch k 0 noreply\r\n"), b"");
+        assert_eq!(storage.ttl("k"), Some(None));
+
+        storage.set_read_only(true);
+        assert_eq!(
+            memcached(&storage, b"touch k 10\r\n"),
+            b"SERVER_ERROR read only\r\n"
+        );
+    }
+
+    #[test]
+    fn test_incr_on_missing_key() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(memcached(&storage, b"incr n 5\r\n"), b"NOT_FOUND\r\n");
+        assert_eq!(memcached(&storage, b"decr n 5\r\n"), b"NOT_FOUND\r\n");
+        assert!(storage.get("n").is_none());
+
+        let config = RequestConfig {
+            incr_autocreate: true,
+            ..request_config()
+        };
+        assert_eq!(memcached_with(&storage, b"incr n 5\r\n", &config), b"5\r\n");
+        assert_eq!(
+            memcached_with(&storage, b"incr n 5\r\n", &config),
+            b"10\r\n"
+        );
+        assert_eq!(memcached_with(&storage, b"decr d 5\r\n", &config), b"0\r\n");
+        assert_eq!(
+            memcached_with(&storage, b"get n d\r\n", &config),
+            b"VALUE n 0 2\r\n10\r\nVALUE d 0 1\r\n0\r\nEND\r\n"
+        );
+    }
+
+    #[test]
+    fn test_negative_exptime_stores_expired() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(memcached(&storage, b"set k 0 -1 1\r\nv\r\n"), b"STORED\r\n");
+        assert_eq!(memcached(&storage, b"get k\r\n"), b"END\r\n");
+    }
+
+    #[test]
+    fn test_gat_and_gats() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("a", b"va".to_vec(), 1, 0);
+        let cas = storage.cas_of("a").unwrap();
+
+        assert_eq!(
+            memcached(&storage, b"gat 100 a missing\r\n"),
+            b"VALUE a 1 2\r\nva\r\nEND\r\n"
+        );
+        assert!(storage.ttl("a").unwrap().is_some());
+
+        assert_eq!(
+            memcached(&storage, b"gats 0 a\r\n"),
+            format!("VALUE a 1 2 {cas}\r\nva\r\nEND\r\n").as_bytes()
+        );
+        assert_eq!(storage.ttl("a"), Some(None));
+    }
+
+    #[test]
+    fn test_meta_get_metadata_only() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("foo", b"bar".to_vec(), 30, 0);
+
+        assert_eq!(memcached(&storage, b"mg foo\r\n"), b"HD\r\n");
+        assert_eq!(memcached(&storage, b"mg foo f s\r\n"), b"HD f30 s3\r\n");
+    }
+
+    #[test]
+    fn test_meta_get_with_value() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("foo", b"bar".to_vec(), 30, 0);
+
+        assert_eq!(
+            memcached(&storage, b"mg foo v f\r\n"),
+            b"VA 3 f30\r\nbar\r\n"
+        );
+    }
+
+    #[test]
+    fn test_meta_get_value_flags_and_ttl() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("foo", b"bar".to_vec(), 30, 100);
+
+        let reply = String::from_utf8(memcached(&storage, b"mg foo v f t\r\n")).unwrap();
+        let ttl = reply
+            .strip_prefix("VA 3 f30 t")
+            .and_then(|rest| rest.strip_suffix("\r\nbar\r\n"))
+            .unwrap_or_else(|| panic!("{reply}"));
+        assert!(matches!(ttl, "99" | "100"), "{reply}");
+
+        // `T` touches the item before `t` reads the TTL back
+        assert_eq!(memcached(&storage, b"mg foo T0 t\r\n"), b"HD t-1\r\n");
+    }
+
+    #[test]
+    fn test_meta_set_and_delete() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        assert_eq!(
+            memcached(&storage, b"ms foo 3 F5 T0 k Oa1\r\nbar\r\n"),
+            b"HD kfoo Oa1\r\n"
+        );
+        assert_eq!(
+            memcached(&storage, b"mg foo v f t\r\n"),
+            b"VA 3 f5 t-1\r\nbar\r\n"
+        );
+
+        // `c` returns the new item's CAS value
+        let stored = String::from_utf8(memcached(&storage, b"ms foo 1 c\r\nx\r\n")).unwrap();
+        let cas = storage.get("foo").unwrap().cas_unique;
+        assert_eq!(stored, format!("HD c{cas}\r\n"));
+        assert_eq!(memcached(&storage, b"ms foo 1 q\r\ny\r\n"), b"");
+        assert_eq!(storage.get("foo").unwrap().value, b"y");
+
+        // A bad flag still skips the data block
+        assert_eq!(
+            memcached(&storage, b"ms foo 3 Fx\r\nbar\r\n"),
+            b"CLIENT_ERROR bad command line format\r\n"
+        );
+
+        assert_eq!(memcached(&storage, b"md foo k\r\n"), b"HD kfoo\r\n");
+        assert_eq!(memcached(&storage, b"mg foo v\r\n"), b"EN\r\n");
+        assert_eq!(memcached(&storage, b"md foo\r\n"), b"NF\r\n");
+        // Quiet mode only hides success
+        assert_eq!(memcached(&storage, b"md foo q Ox\r\n"), b"NF Ox\r\n");
+
+        storage.set("bar", b"v".to_vec(), 0, 0);
+        storage.set_read_only(true);
+        assert_eq!(
+            memcached(&storage, b"md bar\r\n"),
+            b"SERVER_ERROR read only\r\n"
+        );
+        assert_eq!(
+            memcached(&storage, b"ms bar 1\r\nw\r\n"),
+            b"SERVER_ERROR read only\r\n"
+        );
+    }
+
+    #[test]
+    fn test_meta_get_miss() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        assert_eq!(memcached(&storage, b"mg missing v\r\n"), b"EN\r\n");
+        assert_eq!(memcached(&storage, b"mg missing v q\r\n"), b"");
+    }
+
+    #[test]
+    fn test_meta_get_recache_after_delete() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("before", b"v".to_vec(), 0, 0);
+        memcached(&storage, b"delete before\r\n");
+        storage.set_tombstone_ttl(Duration::from_secs(60));
+        storage.set("key", b"v".to_vec(), 0, 0);
+
+        // Never-set keys and deletes from before tombstones are plain misses
+        assert_eq!(memcached(&storage, b"mg never v\r\n"), b"EN\r\n");
+        assert_eq!(memcached(&storage, b"mg before v\r\n"), b"EN\r\n");
+
+        assert_eq!(memcached(&storage, b"delete key\r\n"), b"DELETED\r\n");
+        assert_eq!(memcached(&storage, b"mg key v\r\n"), b"EN W\r\n");
+        assert_eq!(memcached(&storage, b"mg key v q\r\n"), b"EN Z\r\n");
+        assert_eq!(memcached(&storage, b"mg key v Oa\r\n"), b"EN Z Oa\r\n");
+
+        // Once the winner recaches, the tombstone is gone
+        memcached(&storage, b"set key 0 0 1\r\nw\r\n");
+        assert_eq!(storage.claim_recache("key"), None);
+    }
+
+    #[test]
+    fn test_concurrent_misses_elect_one_recacher() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set_recache_lease(Duration::from_secs(60));
+
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let storage = Arc::clone(&storage);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    memcached(&storage, b"mg key v\r\n")
+                })
+            })
+            .collect();
+        let replies: Vec<Vec<u8>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let won = replies.iter().filter(|r| *r == b"EN W\r\n").count();
+        let waiting = replies.iter().filter(|r| *r == b"EN Z\r\n").count();
+        assert_eq!((won, waiting), (1, 7), "{replies:?}");
+
+        // Recaching ends the lease, so the next miss elects afresh
+        memcached(&storage, b"set key 0 0 1\r\nv\r\n");
+        assert_eq!(memcached(&storage, b"mg key v\r\n"), b"VA 1\r\nv\r\n");
+        memcached(&storage, b"delete key\r\n");
+        assert_eq!(memcached(&storage, b"mg key v\r\n"), b"EN W\r\n");
+
+        // Leases off: a plain miss again
+        storage.set_recache_lease(Duration::ZERO);
+        assert_eq!(memcached(&storage, b"mg other v\r\n"), b"EN\r\n");
+    }
+
+    #[test]
+    fn test_meta_get_opaque() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("key", b"data".to_vec(), 0, 0);
+
+        assert_eq!(
+            memcached(&storage, b"mg key Oabc v\r\n"),
+            b"VA 4 Oabc\r\ndata\r\n"
+        );
+        assert_eq!(memcached(&storage, b"mg key O1 s\r\n"), b"HD s4 O1\r\n");
+        assert_eq!(memcached(&storage, b"mg nope Oabc v\r\n"), b"EN Oabc\r\n");
+    }
+
+    /// Run one RESP command and return the encoded response.
+    fn resp(storage: &Arc<Storage>, input: &[u8], config: &RequestConfig) -> Vec<u8> {
+        resp_session(storage, input, config, &mut Session::default())
+    }
+
+    fn resp_session(
+        storage: &Arc<Storage>,
+        input: &[u8],
+        config: &RequestConfig,
+        session: &mut Session,
+    ) -> Vec<u8> {
+        let mut output = vec![0u8; 4096];
+        match process_resp(input, &mut output, storage, config, session) {
+            ProcessResult::Response { response_len, .. } => output[..response_len].to_vec(),
+            ProcessResult::StreamResponse { mut response, .. } => {
+                let mut data = Vec::new();
+                response.read_to_end(&mut data).unwrap();
+                data
+            }
+            _ => panic!("Expected a response"),
+        }
+    }
+
+    #[test]
+    fn test_commands_over_cost_limit_are_rejected() {
+        let storage = Storage::new(1024 * 1024, 0);
+        for key in ["a", "b", "c"] {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        let config = RequestConfig {
+            max_command_cost: 2,
+            ..request_config()
+        };
+
+        // A multi-get of more keys than the limit looks none of them up
+        assert_eq!(
+            memcached_with(&storage, b"get a b c\r\n", &config),
+            b"CLIENT_ERROR command cost 3 exceeds max_command_cost 2\r\n"
+        );
+        assert!(memcached_with(&storage, b"gats 0 a b c\r\n", &config)
+            .starts_with(b"CLIENT_ERROR command cost 3"));
+        assert_eq!(storage.stats().get_hits, 0);
+        assert_eq!(
+            memcached_with(&storage, b"get a b\r\n", &config),
+            b"VALUE a 0 1\r\nv\r\nVALUE b 0 1\r\nv\r\nEND\r\n"
+        );
+
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+        assert_eq!(
+            run(&["DEL", "a", "b", "c"]),
+            b"-ERR command cost 3 exceeds max_command_cost 2\r\n"
+        );
+        assert_eq!(storage.stats().item_count, 3);
+        assert_eq!(run(&["EXISTS", "a", "b"]), b":2\r\n");
+        // A snapshot costs every item stored
+        assert!(run(&["SYNC"]).starts_with(b"-ERR command cost 3"));
+        storage.delete("c");
+        assert!(run(&["SYNC"]).starts_with(b"$"));
+
+        // Rejected while queueing, it aborts the transaction
+        let mut session = Session::default();
+        let mut run_in_session =
+            |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+        run_in_session(&["MULTI"]);
+        assert!(run_in_session(&["DEL", "a", "b", "x"]).starts_with(b"-ERR command cost"));
+        assert!(run_in_session(&["EXEC"]).starts_with(b"-EXECABORT"));
+        assert_eq!(storage.stats().item_count, 2);
+
+        // No limit by default
+        let unlimited = request_config();
+        assert_eq!(
+            resp(&storage, &command(&["DEL", "a", "b", "x"]), &unlimited),
+            b":2\r\n"
+        );
+    }
+
+    #[test]
+    fn test_disabled_commands_per_protocol() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("foo", b"bar".to_vec(), 0, 0);
+
+        let mut config = request_config();
+        config.disabled_commands.resp.insert("FLUSHALL".to_string());
+
+        // FLUSHALL is rejected as unknown and does nothing
+        assert_eq!(
+            resp(&storage, b"*1\r\n$8\r\nflushall\r\n", &config),
+            b"-ERR unknown command 'FLUSHALL'\r\n"
+        );
+        assert!(storage.get("foo").is_some());
+
+        // memcached flush_all is controlled independently
+        assert_eq!(
+            memcached_with(&storage, b"flush_all\r\n", &config),
+            b"OK\r\n"
+        );
+        assert!(storage.get("foo").is_none());
+
+        config
+            .disabled_commands
+            .memcached
+            .insert("flush_all".to_string());
+        assert_eq!(
+            memcached_with(&storage, b"flush_all\r\n", &config),
+            b"ERROR\r\n"
+        );
+    }
+
+    #[test]
+    fn test_disabled_storage_command_skips_data() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let mut config = request_config();
+        config.disabled_commands.memcached.insert("set".to_string());
+
+        assert_eq!(
+            memcached_with(&storage, b"set foo 0 0 3\r\nbar\r\n", &config),
+            b"ERROR\r\n"
+        );
+        assert!(storage.get("foo").is_none());
+    }
+
+    #[test]
+    fn test_unskippable_data_block_is_never_run() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("foo", b"bar".to_vec(), 0, 0);
+        let mut config = request_config();
+        config.disabled_commands.memcached.insert("set".to_string());
+
+        // Blocks too large for the buffer, carrying a command of their own
+        for line in ["set big 0 0 10000\r\n", "set big x 0 10000\r\n"] {
+            let input = format!("{line}flush_all\r\n");
+            let mut output = vec![0u8; 4096];
+            match process_pipeline(
+                Protocol::Memcached,
+                input.as_bytes(),
+                &mut output,
+                &storage,
+                &config,
+                &mut Session::default(),
+            ) {
+                ProcessResult::ErrorAfterResponse { response_len } => {
+                    assert!(output[..response_len].ends_with(b"\r\n"), "{line}");
+                }
+                _ => panic!("expected the connection to close: {line}"),
+            }
+            assert!(storage.get("foo").is_some(), "{line}");
+        }
+    }
+
+    /// Encode a RESP command from its arguments.
+    fn command(args: &[&str]) -> Vec<u8> {
+        resp_parser::Frame::array(
+            args.iter()
+                .map(|arg| resp_parser::Frame::bulk(arg.as_bytes().to_vec()))
+                .collect(),
+        )
+        .encode()
+        .to_vec()
+    }
+
+    #[test]
+    fn test_value_limit_per_protocol() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            memcached_max_value_size: Some(100),
+            resp_max_value_size: Some(300),
+            ..request_config()
+        };
+        let value = "v".repeat(200);
+
+        // Rejected at the header, before the data arrives
+        let mut output = vec![0u8; 4096];
+        let input = b"set small 0 0 200\r\n";
+        match process_memcached(
+            input,
+            &mut output,
+            &storage,
+            &config,
+            &mut Session::default(),
+        ) {
+            ProcessResult::ErrorAfterResponse { response_len } => {
+                assert_eq!(&output[..response_len], b"CLIENT_ERROR value too large\r\n");
+            }
+            _ => panic!("Expected an error before closing"),
+        }
+        let set = format!("set small 0 0 50\r\n{}\r\n", &value[..50]);
+        assert_eq!(
+            memcached_with(&storage, set.as_bytes(), &config),
+            b"STORED\r\n"
+        );
+
+        assert_eq!(
+            resp(&storage, &command(&["SET", "big", &value]), &config),
+            b"+OK\r\n"
+        );
+        assert_eq!(
+            resp(
+                &storage,
+                &command(&["SET", "big", &"v".repeat(400)]),
+                &config
+            ),
+            b"-ERR value too large\r\n"
+        );
+        assert_eq!(storage.get("big").unwrap().value.len(), 200);
+
+        // Echo has no override and keeps the global limit
+        assert_eq!(config.max_value_size_for(Protocol::Echo), 1024 * 1024);
+    }
+
+    #[test]
+    fn test_detect_protocol() {
+        for input in [
+            &command(&["GET", "k"])[..],
+            b"$4\r\nPING\r\n",
+            b"+OK\r\n",
+            b"-ERR\r\n",
+            b":1\r\n",
+            // A single byte is enough
+            b"*",
+        ] {
+            assert_eq!(detect_protocol(input), Protocol::Resp, "{input:?}");
+        }
+        for input in [
+            &b"get k\r\n"[..],
+            b"set k 0 0 1\r\nv\r\n",
+            b"mg k v\r\n",
+            b"version\r\n",
+            // Inline RESP isn't told apart from memcached text
+            b"PING\r\n",
+            b"g",
+        ] {
+            assert_eq!(detect_protocol(input), Protocol::Memcached, "{input:?}");
+        }
+
+        // Each parser gets the detecting bytes as part of its command
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut output = vec![0u8; 64];
+        let mut session = Session::default();
+        for (input, expected) in [
+            (&command(&["SET", "k", "v"])[..], &b"+OK\r\n"[..]),
+            (b"get k\r\n", b"VALUE k 0 1\r\nv\r\nEND\r\n"),
+        ] {
+            match process_pipeline(
+                Protocol::Auto,
+                input,
+                &mut output,
+                &storage,
+                &config,
+                &mut session,
+            ) {
+                ProcessResult::Response {
+                    consumed,
+                    response_len,
+                } => {
+                    assert_eq!(consumed, input.len());
+                    assert_eq!(&output[..response_len], expected);
+                }
+                _ => panic!("Expected a response to {input:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resp_on_memcached_port_gets_hint() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let mut output = vec![0u8; 64];
+        match process_memcached(
+            &command(&["PING"]),
+            &mut output,
+            &storage,
+            &request_config(),
+            &mut Session::default(),
+        ) {
+            ProcessResult::Reject { response } => {
+                assert_eq!(response, b"ERROR this port speaks memcached, not RESP\r\n");
+            }
+            _ => panic!("Expected a protocol mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_memcached_on_resp_port_gets_hint() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut output = vec![0u8; 64];
+
+        for input in [
+            &b"set k 0 0 1\r\nv\r\n"[..],
+            b"gets k\r\n",
+            b"get a b\r\n",
+            b"version\r\n",
+        ] {
+            match process_resp(
+                input,
+                &mut output,
+                &storage,
+                &config,
+                &mut Session::default(),
+            ) {
+                ProcessResult::Reject { response } => {
+                    assert_eq!(response, b"ERROR this port speaks RESP, not memcached\r\n");
+                }
+                _ => panic!("Expected a protocol mismatch for {input:?}"),
+            }
+        }
+
+        // Commands valid in both protocols are served as RESP
+        assert_eq!(resp(&storage, b"get k\r\n", &config), b"$-1\r\n");
+        assert_eq!(resp(&storage, b"incr n\r\n", &config), b":1\r\n");
+    }
+
+    #[test]
+    fn test_resp_mixed_inline_and_multibulk_pipeline() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut output = vec![0u8; 4096];
+
+        let mut input = b"PING\r\n".to_vec();
+        input.extend_from_slice(&command(&["PING"]));
+        input.extend_from_slice(b"SET k v\r\n");
+        input.extend_from_slice(&command(&["GET", "k"]));
+        // Trailing partial command stays unconsumed
+        input.extend_from_slice(b"*2\r\n$3\r\nGET");
+
+        match process_resp(&input, &mut output, &storage, &config, &mut session) {
+            ProcessResult::Response {
+                consumed,
+                response_len,
+            } => {
+                assert_eq!(consumed, input.len() - b"*2\r\n$3\r\nGET".len());
+                assert_eq!(
+                    &output[..response_len],
+                    b"+PONG\r\n+PONG\r\n+OK\r\n$1\r\nv\r\n"
+                );
+            }
+            _ => panic!("Expected a response"),
+        }
+
+        assert!(matches!(
+            process_resp(
+                b"*2\r\n$3\r\nGET",
+                &mut output,
+                &storage,
+                &config,
+                &mut session
+            ),
+            ProcessResult::NeedData
+        ));
+    }
+
+    #[test]
+    fn test_responses_over_chain_limit_are_refused() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("big", vec![b'b'; 5000], 0, 0);
+        storage.set("mid", vec![b'm'; 3000], 0, 0);
+        // Two 2KB buffers: enough for `mid`, not for `big`
+        let config = RequestConfig {
+            max_response_chain_buffers: 2,
+            ..request_config()
+        };
+        let run = |protocol: Protocol, input: &[u8]| {
+            let mut output = vec![0u8; 2048];
+            match process_pipeline(
+                protocol,
+                input,
+                &mut output,
+                &storage,
+                &config,
+                &mut Session::default(),
+            ) {
+                ProcessResult::Response {
+                    consumed,
+                    response_len,
+                } => (consumed, output[..response_len].to_vec()),
+                ProcessResult::LargeResponse {
+                    consumed,
+                    response_data,
+                } => (consumed, response_data),
+                _ => panic!("Expected a response"),
+            }
+        };
+
+        // The pipeline goes on past the refused response
+        let input = b"get big\r\nget mid\r\n";
+        let (consumed, reply) = run(Protocol::Memcached, input);
+        assert_eq!(consumed, input.len());
+        let mut expected = b"SERVER_ERROR response too large\r\nVALUE mid 0 3000\r\n".to_vec();
+        expected.extend_from_slice(&[b'm'; 3000]);
+        expected.extend_from_slice(b"\r\nEND\r\n");
+        assert!(reply == expected, "{}", String::from_utf8_lossy(&reply));
+
+        let input = command(&["GET", "big"]);
+        assert_eq!(
+            run(Protocol::Resp, &input),
+            (input.len(), b"-ERR response too large\r\n".to_vec())
+        );
+        assert_eq!(config.response_chain_stats.rejections(), 2);
+
+        // The event loops record the chains they write
+        config.response_chain_stats.record(2);
+        config.response_chain_stats.record(1);
+        let stats = String::from_utf8(memcached_with(&storage, b"stats\r\n", &config)).unwrap();
+        assert!(stats.contains("STAT response_chain_peak 2\r\n"), "{stats}");
+        assert!(
+            stats.contains("STAT response_chain_rejections 2\r\n"),
+            "{stats}"
+        );
+        memcached_with(&storage, b"stats reset\r\n", &config);
+        assert_eq!(config.response_chain_stats.peak(), 0);
+        assert_eq!(config.response_chain_stats.rejections(), 0);
+    }
+
+    #[test]
+    fn test_memcached_pipeline_answers_every_command() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |input: &[u8], output_len: usize| {
+            let mut output = vec![0u8; output_len];
+            let mut session = Session::default();
+            match process_pipeline(
+                Protocol::Memcached,
+                input,
+                &mut output,
+                &storage,
+                &config,
+                &mut session,
+            ) {
+                ProcessResult::Response {
+                    consumed,
+                    response_len,
+                } => (consumed, output[..response_len].to_vec()),
+                ProcessResult::LargeResponse {
+                    consumed,
+                    response_data,
+                } => (consumed, response_data),
+                _ => panic!("Expected a response"),
+            }
+        };
+
+        let input = b"set a 0 0 1\r\n1\r\nget a\r\nset b 0 0 1\r\n2\r\nget b\r\nget";
+        let (consumed, reply) = run(input, 4096);
+        // The trailing partial command waits for the rest of its line
+        assert_eq!(consumed, input.len() - 3);
+        assert_eq!(
+            reply,
+            b"STORED\r\nVALUE a 0 1\r\n1\r\nEND\r\nSTORED\r\nVALUE b 0 1\r\n2\r\nEND\r\n"
+        );
+
+        // Silent commands consume input without a reply
+        let input = b"set c 0 0 1 noreply\r\n3\r\ndelete c noreply\r\n";
+        assert_eq!(run(input, 4096), (input.len(), Vec::new()));
+
+        // Past the output buffer, the batch so far goes out with the big reply
+        storage.set("big", vec![b'x'; 3000], 0, 0);
+        let input = b"get a\r\nget big\r\n";
+        let (consumed, reply) = run(input, 2048);
+        assert_eq!(consumed, input.len());
+        assert!(reply.starts_with(b"VALUE a 0 1\r\n1\r\nEND\r\nVALUE big 0 3000\r\n"));
+        assert!(reply.ends_with(b"\r\nEND\r\n"));
+
+        // A quit behind replies waits for them to be sent
+        assert_eq!(run(b"get a\r\nquit\r\n", 4096).0, 7);
+        assert!(matches!(
+            process_pipeline(
+                Protocol::Memcached,
+                b"quit\r\n",
+                &mut [0u8; 4096],
+                &storage,
+                &config,
+                &mut Session::default(),
+            ),
+            ProcessResult::Quit
+        ));
+    }
+
+    #[test]
+    fn test_resp_pipeline_overflowing_output() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut output = vec![0u8; 16];
+
+        let input = b"PING\r\nPING\r\nPING\r\n";
+        match process_resp(
+            input,
+            &mut output,
+            &storage,
+            &config,
+            &mut Session::default(),
+        ) {
+            ProcessResult::LargeResponse {
+                consumed,
+                response_data,
+            } => {
+                assert_eq!(consumed, input.len());
+                assert_eq!(response_data, b"+PONG\r\n+PONG\r\n+PONG\r\n");
+            }
+            _ => panic!("Expected a large response"),
+        }
+    }
+
+    #[test]
+    fn test_fair_share_limits_each_turn() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            fair_share_bytes: 64,
+            ..request_config()
+        };
+        let turn = |protocol, input: &[u8], session: &mut Session| {
+            let mut output = vec![0u8; 4096];
+            match process_pipeline(protocol, input, &mut output, &storage, &config, session) {
+                ProcessResult::Response { consumed, .. } => consumed,
+                _ => panic!("Expected a response"),
+            }
+        };
+
+        // A turn ends on the command reaching 64 bytes, and what it
+        // overdrew comes off the next one
+        let mut session = Session::default();
+        let input = b"get k\r\n".repeat(20);
+        assert_eq!(turn(Protocol::Memcached, &input, &mut session), 70);
+        assert_eq!(session.fair_share.debt, 6);
+        assert_eq!(turn(Protocol::Memcached, &input[70..], &mut session), 63);
+        // Draining the input clears the debt
+        assert_eq!(turn(Protocol::Memcached, &input[133..], &mut session), 7);
+        assert_eq!(session.fair_share.debt, 0);
+
+        // A large value leaves the next turns one command each until repaid
+        let mut input = format!("set big 0 0 200\r\n{}\r\n", "v".repeat(200)).into_bytes();
+        input.extend(b"get k\r\n".repeat(20));
+        let mut offset = 0;
+        let turns: Vec<usize> = (0..4)
+            .map(|_| {
+                let consumed = turn(Protocol::Memcached, &input[offset..], &mut session);
+                offset += consumed;
+                consumed
+            })
+            .collect();
+        assert_eq!(turns, [219, 7, 7, 28]);
+        assert_eq!(session.fair_share.debt, 5);
+
+        // RESP answers a turn's commands in one processor call
+        let mut session = Session::default();
+        let input = command(&["GET", "k"]).repeat(10);
+        assert_eq!(turn(Protocol::Resp, &input, &mut session), 80);
+        assert_eq!(session.fair_share.debt, 16);
+        // RESET leaves the debt in place
+        assert_eq!(
+            resp_session(&storage, &command(&["RESET"]), &config, &mut session),
+            b"+RESET\r\n"
+        );
+        assert_eq!(session.fair_share.debt, 16);
+    }
+
+    #[test]
+    fn test_stats_sizes_marks_sampled_histograms() {
+        let storage = Storage::new(1024 * 1024, 0);
+        for i in 0..10 {
+            storage.set(&format!("k{i}"), b"v".to_vec(), 0, 0);
+        }
+
+        let exact = String::from_utf8(memcached(&storage, b"stats sizes\r\n")).unwrap();
+        assert!(exact.contains(" 10\r\n"), "{exact}");
+        assert!(exact.contains("STAT oldest_age 0\r\n"), "{exact}");
+        assert!(exact.ends_with("STAT approximate 0\r\nEND\r\n"), "{exact}");
+
+        let config = RequestConfig {
+            stats_sample_size: 4,
+            ..request_config()
+        };
+        let sampled =
+            String::from_utf8(memcached_with(&storage, b"stats sizes\r\n", &config)).unwrap();
+        assert!(sampled.contains(" 10\r\n"), "{sampled}");
+        assert!(sampled.contains("STAT approximate 1\r\n"), "{sampled}");
+
+        assert_eq!(memcached(&storage, b"stats bogus\r\n"), b"ERROR\r\n");
+    }
+
+    #[test]
+    fn test_stats_pressure() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(
+            memcached(&storage, b"stats pressure\r\n"),
+            b"STAT eviction_rate 0.00\r\nSTAT memory_pressure 0.0000\r\nEND\r\n"
+        );
+
+        // Past the limit, so some of these are evicted
+        for i in 0..1100 {
+            storage.set(&format!("k{i}"), vec![b'x'; 1024], 0, 0);
+        }
+        let reply = String::from_utf8(memcached(&storage, b"stats pressure\r\n")).unwrap();
+        let stat = |name: &str| -> f64 {
+            reply
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("STAT {name} ")))
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        assert!(stat("eviction_rate") > 0.0, "{reply}");
+        let expected = storage.stats().memory_used as f64 / (1024 * 1024) as f64;
+        assert!(
+            (stat("memory_pressure") - expected).abs() < 0.0001,
+            "{reply}"
+        );
+    }
+
+    #[test]
+    fn test_stats_count_hits_and_misses() {
+        let storage = Storage::new(1024 * 1024, 0);
+        memcached(&storage, b"set a 0 0 1\r\n1\r\n");
+        memcached(&storage, b"get a\r\n");
+        memcached(&storage, b"get a b c\r\n");
+        memcached(&storage, b"gets b\r\n");
+
+        let stats = String::from_utf8(memcached(&storage, b"stats\r\n")).unwrap();
+        assert!(stats.contains("STAT cmd_set 1\r\n"), "{stats}");
+        assert!(stats.contains("STAT get_hits 2\r\n"), "{stats}");
+        assert!(stats.contains("STAT get_misses 3\r\n"), "{stats}");
+        assert!(stats.contains("STAT evictions 0\r\n"), "{stats}");
+        assert!(stats.contains("STAT expired_unfetched 0\r\n"), "{stats}");
+
+        let config = request_config();
+        resp(&storage, &command(&["GET", "a"]), &config);
+        let info = String::from_utf8(resp(&storage, &command(&["INFO"]), &config)).unwrap();
+        assert!(info.contains("\r\n# Stats\r\n"), "{info}");
+        assert!(info.contains("keyspace_hits:3\r\n"), "{info}");
+        assert!(info.contains("keyspace_misses:3\r\n"), "{info}");
+    }
+
+    #[test]
+    fn test_stats_reset_restarts_counters() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        memcached_with(&storage, b"set a 0 0 1\r\n1\r\n", &config);
+        memcached_with(&storage, b"get a b\r\n", &config);
+        config
+            .connection_stats
+            .clean_disconnects
+            .fetch_add(2, Ordering::Relaxed);
+
+        assert_eq!(
+            memcached_with(&storage, b"stats reset\r\n", &config),
+            b"RESET\r\n"
+        );
+        memcached_with(&storage, b"get a\r\n", &config);
+        let stats = String::from_utf8(memcached_with(&storage, b"stats\r\n", &config)).unwrap();
+        assert!(stats.contains("STAT get_hits 1\r\n"), "{stats}");
+        assert!(stats.contains("STAT get_misses 0\r\n"), "{stats}");
+        assert!(stats.contains("STAT cmd_set 0\r\n"), "{stats}");
+        assert!(stats.contains("STAT total_items 0\r\n"), "{stats}");
+        assert!(stats.contains("STAT clean_disconnects 0\r\n"), "{stats}");
+        // Gauges describe the cache as it is
+        assert!(stats.contains("STAT curr_items 1\r\n"), "{stats}");
+
+        assert_eq!(
+            resp(&storage, &command(&["config", "resetstat"]), &config),
+            b"+OK\r\n"
+        );
+        let info = String::from_utf8(resp(&storage, &command(&["INFO"]), &config)).unwrap();
+        assert!(info.contains("keyspace_hits:0\r\n"), "{info}");
+        assert!(
+            resp(&storage, &command(&["CONFIG", "GET", "maxmemory"]), &config)
+                .starts_with(b"-ERR unknown subcommand")
+        );
+        assert!(resp(&storage, &command(&["CONFIG"]), &config).starts_with(b"-ERR wrong number"));
+    }
+
+    #[test]
+    fn test_version_identifies_deployment() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            memcached(&storage, b"version\r\n"),
+            format!("VERSION grow-a-cache {version}\r\n").into_bytes()
+        );
+        let config = request_config();
+        let info = String::from_utf8(resp(&storage, &command(&["INFO"]), &config)).unwrap();
+        assert!(
+            info.contains(&format!("redis_version:{version}\r\n")),
+            "{info}"
+        );
+        assert!(!info.contains("run_id:"), "{info}");
+
+        let config = RequestConfig {
+            server_id: Some("cache-eu-1".to_string()),
+            ..config
+        };
+        assert_eq!(
+            memcached_with(&storage, b"version\r\n", &config),
+            format!("VERSION grow-a-cache {version} cache-eu-1\r\n").into_bytes()
+        );
+        let info = String::from_utf8(resp(&storage, &command(&["INFO"]), &config)).unwrap();
+        assert!(
+            info.contains(&format!("redis_version:{version}\r\n")),
+            "{info}"
+        );
+        assert!(info.contains("run_id:cache-eu-1\r\n"), "{info}");
+    }
+
+    #[test]
+    fn test_hot_keys_report() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        assert_eq!(
+            memcached(&storage, b"stats hotkeys\r\n"),
+            format!("SERVER_ERROR {HOTKEYS_DISABLED_ERROR}\r\n").into_bytes()
+        );
+        assert_eq!(
+            resp(&storage, &command(&["HOTKEYS"]), &config),
+            format!("-ERR {HOTKEYS_DISABLED_ERROR}\r\n").into_bytes()
+        );
+
+        storage.set_hotkey_tracking(true);
+        let hot = ["hot-a", "hot-b", "hot-c"];
+        for key in hot {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        for round in 0..3000 {
+            for key in hot {
+                storage.get(key);
+            }
+            storage.get(&format!("cold-{}", round % 500));
+        }
+
+        let stats = String::from_utf8(memcached(&storage, b"stats hotkeys\r\n")).unwrap();
+        let mut top: Vec<&str> = stats
+            .lines()
+            .take(3)
+            .map(|line| line.split(' ').nth(1).unwrap())
+            .collect();
+        top.sort_unstable();
+        assert_eq!(top, hot, "{stats}");
+        assert_eq!(stats.lines().count(), HOT_KEYS_REPORTED + 1, "{stats}");
+        assert!(stats.ends_with("END\r\n"));
+
+        // Two [key, reads] pairs, both hot keys
+        let reply = resp(&storage, &command(&["HOTKEYS", "2"]), &config);
+        let reply = String::from_utf8(reply).unwrap();
+        assert!(reply.starts_with("*2\r\n*2\r\n$5\r\nhot-"), "{reply}");
+        assert_eq!(reply.matches("*2\r\n$5\r\nhot-").count(), 2, "{reply}");
+        assert_eq!(reply.matches("\r\n:").count(), 2, "{reply}");
+    }
+
+    #[test]
+    fn test_wait_reports_no_replicas() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+
+        assert_eq!(
+            resp(&storage, &command(&["WAIT", "0", "0"]), &config),
+            b":0\r\n"
+        );
+        assert_eq!(resp(&storage, b"WAIT 1 100\r\n", &config), b":0\r\n");
+        assert!(resp(&storage, &command(&["WAIT", "x", "0"]), &config).starts_with(b"-ERR"));
+        assert!(resp(&storage, &command(&["WAIT", "0"]), &config).starts_with(b"-ERR"));
+    }
+
+    #[test]
+    fn test_sync_streams_a_loadable_snapshot() {
+        let storage = Storage::new(1024 * 1024, 0);
+        for i in 0..200 {
+            storage.set(&format!("key{i}"), vec![b'x'; 100], i, 0);
+        }
+        storage.set("ttl", b"v".to_vec(), 7, 3600);
+
+        // Answered with the reply to a command pipelined ahead of it
+        let mut output = vec![0u8; 4096];
+        let input = [command(&["PING"]), command(&["SYNC"])].concat();
+        let config = request_config();
+        let mut reply = Vec::new();
+        match process_pipeline(
+            Protocol::Resp,
+            &input,
+            &mut output,
+            &storage,
+            &config,
+            &mut Session::default(),
+        ) {
+            ProcessResult::StreamResponse {
+                consumed,
+                mut response,
+            } => {
+                assert_eq!(consumed, input.len());
+                // Written while the reply is on its way, so not in it
+                storage.set("late", b"v".to_vec(), 0, 0);
+                response.read_to_end(&mut reply).unwrap();
+            }
+            _ => panic!("expected a streamed response"),
+        };
+        let reply = reply.strip_prefix(b"+PONG\r\n").unwrap();
+        let snapshot = match resp_parser::parse(reply, 1, usize::MAX) {
+            resp_parser::ParseResult::Complete(resp_parser::Frame::Bulk(Some(data)), len) => {
+                assert_eq!(len, reply.len());
+                data
+            }
+            _ => panic!("expected one bulk string"),
+        };
+
+        let replica = Storage::new(1024 * 1024, 0);
+        let loaded = replica
+            .load(&mut &snapshot[..], SnapshotFormat::Binary)
+            .unwrap();
+        assert_eq!(loaded, 201);
+        for i in 0..200 {
+            let item = replica.get(&format!("key{i}")).unwrap();
+            assert_eq!((item.value, item.flags), (vec![b'x'; 100], i));
+        }
+        let item = replica.get("ttl").unwrap();
+        assert_eq!((item.value, item.flags), (b"v".to_vec(), 7));
+        assert!(replica.ttl("ttl").unwrap().is_some());
+        assert!(replica.get("late").is_none());
+    }
+
+    #[test]
+    fn test_object_idletime() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        storage.set("k", b"v".to_vec(), 0, 0);
+
+        let idletime =
+            |storage: &Arc<Storage>| resp(storage, &command(&["OBJECT", "IDLETIME", "k"]), &config);
+        assert_eq!(idletime(&storage), b":0\r\n");
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(idletime(&storage), b":1\r\n");
+        // Inspecting the key is not an access
+        assert_eq!(idletime(&storage), b":1\r\n");
+
+        // A read resets it
+        resp(&storage, &command(&["GET", "k"]), &config);
+        assert_eq!(idletime(&storage), b":0\r\n");
+
+        assert_eq!(
+            resp(
+                &storage,
+                &command(&["OBJECT", "IDLETIME", "missing"]),
+                &config
+            ),
+            b"$-1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_object() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        storage.set("k", b"hello".to_vec(), 0, 0);
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+
+        let debug_object = |storage: &Arc<Storage>, key: &str| {
+            String::from_utf8(resp(storage, &command(&["DEBUG", "OBJECT", key]), &config)).unwrap()
+        };
+        let reply = debug_object(&storage, "k");
+        assert!(reply.starts_with("+Value at:"), "{reply}");
+        assert!(reply.contains(" encoding:raw "), "{reply}");
+        assert!(reply.contains(" serializedlength:5 "), "{reply}");
+        assert!(reply.ends_with(" lru_seconds_idle:1\r\n"), "{reply}");
+
+        // Counters are stored as 8-byte integers
+        storage.set("n", b"41".to_vec(), 0, 0);
+        storage.incr("n", 1);
+        let reply = debug_object(&storage, "n");
+        assert!(
+            reply.contains(" encoding:int serializedlength:8 "),
+            "{reply}"
+        );
+        assert!(reply.ends_with(" lru_seconds_idle:0\r\n"), "{reply}");
+
+        assert_eq!(debug_object(&storage, "missing"), "-ERR no such key\r\n");
+    }
+
+    /// Keys of a full `SCAN` with `options`, and how many pages it took.
+    fn resp_scan_all(storage: &Arc<Storage>, options: &[&str]) -> (Vec<String>, usize) {
+        use resp_parser::{Frame, ParseResult};
+
+        let config = request_config();
+        let (mut keys, mut pages, mut cursor) = (Vec::new(), 0, "0".to_string());
+        loop {
+            let mut args = vec!["SCAN", cursor.as_str()];
+            args.extend(options);
+            let reply = resp(storage, &command(&args), &config);
+            let ParseResult::Complete(Frame::Array(Some(reply)), _) =
+                resp_parser::parse(&reply, 2, usize::MAX)
+            else {
+                panic!("not an array: {}", String::from_utf8_lossy(&reply));
+            };
+            let [Frame::Bulk(Some(next)), Frame::Array(Some(page))] = reply.as_slice() else {
+                panic!("unexpected reply {reply:?}");
+            };
+            pages += 1;
+            for key in page {
+                let Frame::Bulk(Some(key)) = key else {
+                    panic!("unexpected key {key:?}");
+                };
+                keys.push(String::from_utf8(key.to_vec()).unwrap());
+            }
+            cursor = String::from_utf8(next.to_vec()).unwrap();
+            if cursor == "0" {
+                keys.sort();
+                return (keys, pages);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+        assert_eq!(run(&["SCAN", "0"]), b"*2\r\n$1\r\n0\r\n*0\r\n");
+
+        let mut expected: Vec<_> = (0..25).map(|i| format!("user:{i}")).collect();
+        for key in &expected {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        storage.set("other", b"v".to_vec(), 0, 0);
+
+        let (keys, pages) = resp_scan_all(&storage, &[]);
+        assert_eq!(keys.len(), 26);
+        // Ten keys a page by default
+        assert_eq!(pages, 3);
+        let (_, pages) = resp_scan_all(&storage, &["COUNT", "100"]);
+        assert_eq!(pages, 1);
+
+        expected.sort();
+        let (keys, _) = resp_scan_all(&storage, &["match", "user:*", "count", "4"]);
+        assert_eq!(keys, expected);
+        let (keys, _) = resp_scan_all(&storage, &["MATCH", "user:1?"]);
+        assert_eq!(keys.len(), 10);
+
+        for (args, error) in [
+            (
+                &["SCAN"][..],
+                "ERR wrong number of arguments for 'scan' command",
+            ),
+            (&["SCAN", "-1"], "ERR invalid cursor"),
+            (&["SCAN", "abc"], "ERR invalid cursor"),
+            (&["SCAN", "0", "COUNT"], "ERR syntax error"),
+            (&["SCAN", "0", "COUNT", "0"], "ERR syntax error"),
+            (&["SCAN", "0", "COUNT", "x"], NOT_AN_INTEGER_ERROR),
+            (&["SCAN", "0", "TYPE", "string"], "ERR syntax error"),
+        ] {
+            assert_eq!(run(args), format!("-{error}\r\n").into_bytes(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_scan_count_is_its_cost() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            max_command_cost: 100,
+            ..request_config()
+        };
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+
+        assert_eq!(
+            run(&["SCAN", "0", "COUNT", "100"]),
+            b"*2\r\n$1\r\n0\r\n*0\r\n"
+        );
+        let reply = String::from_utf8(run(&["SCAN", "0", "COUNT", "101"])).unwrap();
+        assert!(
+            reply.starts_with("-ERR command cost 101 exceeds"),
+            "{reply}"
+        );
+    }
+
+    #[test]
+    fn test_hello_negotiates_resp3() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+        let version = env!("CARGO_PKG_VERSION");
+        let server_info = |prefix: &str, proto: u8| {
+            format!(
+                "{prefix}$6\r\nserver\r\n$12\r\ngrow-a-cache\r\n\
+                 $7\r\nversion\r\n${}\r\n{version}\r\n$5\r\nproto\r\n:{proto}\r\n\
+                 $2\r\nid\r\n:0\r\n\
+                 $4\r\nmode\r\n$10\r\nstandalone\r\n$4\r\nrole\r\n$6\r\nmaster\r\n\
+                 $7\r\nmodules\r\n*0\r\n",
+                version.len()
+            )
+            .into_bytes()
+        };
+
+        // Without a version, describes the server in the current one
+        assert_eq!(run(&["HELLO"]), server_info("*14\r\n", 2));
+        assert_eq!(run(&["GET", "missing"]), b"$-1\r\n");
+
+        // The reply to HELLO 3 is already RESP3, as is everything after
+        assert_eq!(run(&["HELLO", "3"]), server_info("%7\r\n", 3));
+        assert_eq!(run(&["GET", "missing"]), b"_\r\n");
+        assert_eq!(
+            run(&["HELLO", "3", "SETNAME", "app"]),
+            server_info("%7\r\n", 3)
+        );
+
+        // Errors leave the version alone
+        assert_eq!(
+            run(&["HELLO", "4"]),
+            b"-NOPROTO unsupported protocol version\r\n"
+        );
+        assert_eq!(
+            run(&["HELLO", "three"]),
+            b"-ERR Protocol version is not an integer or out of range\r\n"
+        );
+        assert_eq!(
+            run(&["HELLO", "2", "AUTH", "user", "pass"]),
+            b"-ERR syntax error\r\n"
+        );
+        assert_eq!(run(&["HELLO", "2", "SETNAME"]), b"-ERR syntax error\r\n");
+        assert_eq!(run(&["GET", "missing"]), b"_\r\n");
+
+        // Back to RESP2, by HELLO 2 or by RESET
+        assert_eq!(run(&["HELLO", "2"]), server_info("*14\r\n", 2));
+        assert_eq!(run(&["GET", "missing"]), b"$-1\r\n");
+        run(&["HELLO", "3"]);
+        run(&["RESET"]);
+        assert_eq!(run(&["GET", "missing"]), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_client_commands() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let addr: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+        let mut session = Session::new(7, Some(addr));
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        assert_eq!(run(&["CLIENT", "ID"]), b":7\r\n");
+        assert_eq!(run(&["client", "id"]), b":7\r\n");
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$-1\r\n");
+        assert_eq!(
+            run(&["CLIENT", "INFO"]),
+            b"$38\r\nid=7 addr=10.0.0.1:50000 name= resp=2\n\r\n"
+        );
+
+        assert_eq!(run(&["CLIENT", "SETNAME", "worker-1"]), b"+OK\r\n");
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$8\r\nworker-1\r\n");
+        assert_eq!(
+            run(&["CLIENT", "INFO"]),
+            b"$46\r\nid=7 addr=10.0.0.1:50000 name=worker-1 resp=2\n\r\n"
+        );
+
+        // A bad name leaves the old one
+        assert_eq!(
+            run(&["CLIENT", "SETNAME", "has space"]),
+            b"-ERR Client names cannot contain spaces, newlines or special characters.\r\n"
+        );
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$8\r\nworker-1\r\n");
+        // HELLO can name the connection too; an empty name clears it
+        run(&["HELLO", "2", "SETNAME", "worker-2"]);
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$8\r\nworker-2\r\n");
+        assert_eq!(run(&["CLIENT", "SETNAME", ""]), b"+OK\r\n");
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$-1\r\n");
+
+        assert_eq!(
+            run(&["CLIENT", "SETNAME"]),
+            b"-ERR wrong number of arguments for 'client|setname' command\r\n"
+        );
+        assert_eq!(
+            run(&["CLIENT", "ID", "extra"]),
+            b"-ERR wrong number of arguments for 'client|id' command\r\n"
+        );
+        assert_eq!(
+            run(&["CLIENT", "KILL"]),
+            b"-ERR unknown subcommand 'KILL'. Try CLIENT HELP.\r\n"
+        );
+
+        // RESET forgets the name, not who the client is
+        run(&["CLIENT", "SETNAME", "worker-3"]);
+        run(&["RESET"]);
+        assert_eq!(run(&["CLIENT", "GETNAME"]), b"$-1\r\n");
+        assert_eq!(run(&["CLIENT", "ID"]), b":7\r\n");
+        assert_eq!(
+            run(&["CLIENT", "INFO"]),
+            b"$38\r\nid=7 addr=10.0.0.1:50000 name= resp=2\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_connection_ids_are_unique() {
+        let stats = Arc::<ConnectionStats>::default();
+        let first = stats.opened();
+        let second = stats.opened();
+        assert_eq!((first.id(), second.id()), (1, 2));
+        // Not reused once closed
+        drop(first);
+        assert_eq!(stats.opened().id(), 3);
+    }
+
+    #[test]
+    fn test_set_options() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+        let pttl = |key: &str| storage.ttl(key).unwrap().unwrap().as_millis();
+
+        assert_eq!(run(&["SET", "k", "a", "EX", "10"]), b"+OK\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":10\r\n");
+        assert_eq!(run(&["SET", "k", "a", "px", "1500"]), b"+OK\r\n");
+        assert!((1000..=1500).contains(&pttl("k")), "pttl {}", pttl("k"));
+        // A plain SET drops the expiry again
+        assert_eq!(run(&["SET", "k", "a"]), b"+OK\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":-1\r\n");
+
+        assert_eq!(run(&["SET", "k", "b", "NX"]), b"$-1\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\na\r\n");
+        assert_eq!(run(&["SET", "k", "b", "XX", "EX", "20"]), b"+OK\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nb\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":20\r\n");
+        assert_eq!(run(&["SET", "new", "c", "xx"]), b"$-1\r\n");
+        assert_eq!(run(&["EXISTS", "new"]), b":0\r\n");
+        assert_eq!(run(&["SET", "new", "c", "EX", "30", "nx"]), b"+OK\r\n");
+        assert_eq!(run(&["TTL", "new"]), b":30\r\n");
+
+        for args in [
+            &["SET", "k", "v", "EX"][..],
+            &["SET", "k", "v", "NX", "XX"],
+            &["SET", "k", "v", "NX", "NX"],
+            &["SET", "k", "v", "EX", "1", "PX", "1000"],
+            &["SET", "k", "v", "KEEPTTL"],
+            &["SET", "k", "v", "10"],
+        ] {
+            assert_eq!(run(args), b"-ERR syntax error\r\n", "{args:?}");
+        }
+        for expiry in [&["EX", "0"][..], &["PX", "-5"]] {
+            let args = [&["SET", "k", "v"][..], expiry].concat();
+            assert_eq!(run(&args), b"-ERR invalid expire time in 'set' command\r\n");
+        }
+        assert_eq!(
+            run(&["SET", "k", "v", "EX", "soon"]),
+            format!("-{NOT_AN_INTEGER_ERROR}\r\n").into_bytes()
+        );
+        // None of the rejected commands wrote anything
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nb\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":20\r\n");
+    }
+
+    #[test]
+    fn test_compare_value_and_set() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+
+        assert_eq!(run(&["CVS", "k", "a", "b"]), b"$-1\r\n");
+        assert_eq!(run(&["SET", "k", "a"]), b"+OK\r\n");
+        assert_eq!(run(&["CVS", "k", "x", "b"]), b":0\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\na\r\n");
+        assert_eq!(run(&["cvs", "k", "a", "b"]), b":1\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nb\r\n");
+        assert_eq!(
+            run(&["CVS", "k", "b"]),
+            b"-ERR wrong number of arguments for 'cvs' command\r\n"
+        );
+
+        storage.set_read_only(true);
+        assert_eq!(
+            run(&["CVS", "k", "b", "c"]),
+            format!("-{READONLY_ERROR}\r\n").into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_getset_setnx_setex_getdel() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+
+        assert_eq!(run(&["GETSET", "k", "a"]), b"$-1\r\n");
+        assert_eq!(run(&["getset", "k", "b"]), b"$1\r\na\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nb\r\n");
+
+        assert_eq!(run(&["SETNX", "k", "c"]), b":0\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nb\r\n");
+        assert_eq!(run(&["SETNX", "new", "c"]), b":1\r\n");
+        assert_eq!(run(&["GET", "new"]), b"$1\r\nc\r\n");
+
+        assert_eq!(run(&["GETDEL", "k"]), b"$1\r\nb\r\n");
+        assert_eq!(run(&["GETDEL", "k"]), b"$-1\r\n");
+        assert_eq!(run(&["EXISTS", "k"]), b":0\r\n");
+
+        assert_eq!(run(&["SETEX", "t", "100", "v"]), b"+OK\r\n");
+        assert_eq!(run(&["GET", "t"]), b"$1\r\nv\r\n");
+        assert_eq!(run(&["TTL", "t"]), b":100\r\n");
+        // Past memcached's 30 day cutoff, still relative
+        let year = 365 * 24 * 60 * 60;
+        run(&["SETEX", "t", &year.to_string(), "v"]);
+        let ttl = storage.ttl("t").unwrap().unwrap().as_secs();
+        assert!((year - 5..=year).contains(&ttl), "ttl {ttl}");
+
+        for seconds in ["0", "-1"] {
+            assert_eq!(
+                run(&["SETEX", "t", seconds, "v"]),
+                b"-ERR invalid expire time in 'setex' command\r\n"
+            );
+        }
+        assert_eq!(
+            run(&["SETEX", "t", "soon", "v"]),
+            format!("-{NOT_AN_INTEGER_ERROR}\r\n").into_bytes()
+        );
+        for (args, name) in [
+            (&["GETSET", "k"][..], "getset"),
+            (&["SETNX", "k"], "setnx"),
+            (&["SETEX", "k", "1"], "setex"),
+            (&["GETDEL", "k", "extra"], "getdel"),
+        ] {
+            assert_eq!(
+                run(args),
+                format!("-ERR wrong number of arguments for '{name}' command\r\n").into_bytes()
+            );
+        }
+
+        storage.set_read_only(true);
+        for args in [
+            &["GETSET", "k", "v"][..],
+            &["SETNX", "k", "v"],
+            &["SETEX", "k", "1", "v"],
+            &["GETDEL", "new"],
+        ] {
+            assert_eq!(run(args), format!("-{READONLY_ERROR}\r\n").into_bytes());
+        }
+        assert_eq!(run(&["GET", "new"]), b"$1\r\nc\r\n");
+    }
+
+    #[test]
+    fn test_string_commands_check_value_type() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+
+        assert_eq!(run(&["SET", "k", "v"]), b"+OK\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nv\r\n");
+        assert_eq!(run(&["CVS", "k", "v", "w"]), b":1\r\n");
+        assert_eq!(run(&["INCR", "n"]), b":1\r\n");
+        assert_eq!(run(&["DECRBY", "n", "3"]), b":-2\r\n");
+
+        assert_eq!(run(&["TYPE", "k"]), b"+string\r\n");
+        assert_eq!(run(&["type", "n"]), b"+string\r\n");
+        assert_eq!(run(&["TYPE", "missing"]), b"+none\r\n");
+        assert!(run(&["TYPE"]).starts_with(b"-ERR wrong number of arguments"));
+        // Values written by memcached are strings too
+        storage.set("mc", b"1".to_vec(), 0, 0);
+        assert_eq!(run(&["TYPE", "mc"]), b"+string\r\n");
+        assert_eq!(storage.value_type("mc"), Some(ValueType::String));
+
+        assert_eq!(run(&["OBJECT", "ENCODING", "k"]), b"$3\r\nraw\r\n");
+        assert_eq!(run(&["OBJECT", "ENCODING", "missing"]), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_resp_counters() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+
+        // A missing key counts from 0, and may go negative
+        assert_eq!(run(&["INCR", "n"]), b":1\r\n");
+        assert_eq!(run(&["incrby", "n", "10"]), b":11\r\n");
+        assert_eq!(run(&["DECRBY", "n", "20"]), b":-9\r\n");
+        assert_eq!(run(&["DECR", "n"]), b":-10\r\n");
+        assert_eq!(run(&["GET", "n"]), b"$3\r\n-10\r\n");
+        assert_eq!(run(&["DECR", "fresh"]), b":-1\r\n");
+
+        // Pipelined as one raw buffer
+        assert_eq!(
+            resp(
+                &storage,
+                b"*2\r\n$4\r\nINCR\r\n$1\r\np\r\n*3\r\n$6\r\nINCRBY\r\n$1\r\np\r\n$2\r\n-5\r\n",
+                &config
+            ),
+            b":1\r\n:-4\r\n"
+        );
+
+        let not_an_integer = format!("-{NOT_AN_INTEGER_ERROR}\r\n").into_bytes();
+        assert_eq!(run(&["SET", "word", "abc"]), b"+OK\r\n");
+        assert_eq!(run(&["INCR", "word"]), not_an_integer);
+        assert_eq!(run(&["INCRBY", "n", "1.5"]), not_an_integer);
+        assert_eq!(run(&["SET", "max", &i64::MAX.to_string()]), b"+OK\r\n");
+        assert_eq!(
+            run(&["INCR", "max"]),
+            b"-ERR increment or decrement would overflow\r\n"
+        );
+        assert_eq!(
+            run(&["DECRBY", "n", &i64::MIN.to_string()]),
+            b"-ERR decrement would overflow\r\n"
+        );
+        assert_eq!(
+            run(&["INCR", "n", "1"]),
+            b"-ERR wrong number of arguments for 'incr' command\r\n"
+        );
+        assert_eq!(
+            run(&["DECRBY", "n"]),
+            b"-ERR wrong number of arguments for 'decrby' command\r\n"
+        );
+        assert_eq!(run(&["GET", "n"]), b"$3\r\n-10\r\n");
+
+        storage.set_read_only(true);
+        assert_eq!(
+            run(&["INCR", "n"]),
+            format!("-{READONLY_ERROR}\r\n").into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_ttl_and_pttl() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        storage.set("fresh", b"v".to_vec(), 0, 100);
+        storage.set("forever", b"v".to_vec(), 0, 0);
+        storage.set("short", b"v".to_vec(), 0, 1);
+
+        assert_eq!(
+            resp(&storage, &command(&["TTL", "fresh"]), &config),
+            b":100\r\n"
+        );
+        let pttl = resp(&storage, &command(&["PTTL", "fresh"]), &config);
+        let pttl: i64 = std::str::from_utf8(&pttl[1..pttl.len() - 2])
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((99_000..=100_000).contains(&pttl), "{pttl}");
+
+        assert_eq!(
+            resp(&storage, &command(&["TTL", "forever"]), &config),
+            b":-1\r\n"
+        );
+        assert_eq!(
+            resp(&storage, &command(&["PTTL", "missing"]), &config),
+            b":-2\r\n"
+        );
+
+        // Past its expiry the key reads as missing
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(
+            resp(&storage, &command(&["TTL", "short"]), &config),
+            b":-2\r\n"
+        );
+        assert!(resp(&storage, &command(&["TTL"]), &config).starts_with(b"-ERR wrong number"));
+    }
+
+    #[test]
+    fn test_expire_and_persist() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let run = |args: &[&str]| resp(&storage, &command(args), &config);
+        storage.set("k", b"v".to_vec(), 0, 0);
+
+        assert_eq!(run(&["EXPIRE", "missing", "100"]), b":0\r\n");
+        assert_eq!(run(&["PERSIST", "k"]), b":0\r\n");
+        assert_eq!(run(&["EXPIRE", "k", "100"]), b":1\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":100\r\n");
+        assert_eq!(run(&["persist", "k"]), b":1\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":-1\r\n");
+        assert_eq!(run(&["PERSIST", "missing"]), b":0\r\n");
+
+        // Already expired, so both treat it as missing
+        assert_eq!(run(&["EXPIRE", "k", "1"]), b":1\r\n");
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(run(&["EXPIRE", "k", "100"]), b":0\r\n");
+        storage.set("short", b"v".to_vec(), 0, 1);
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(run(&["PERSIST", "short"]), b":0\r\n");
+        assert_eq!(run(&["TTL", "short"]), b":-2\r\n");
+
+        // A deadline already due deletes
+        storage.set("k", b"v".to_vec(), 0, 0);
+        assert_eq!(run(&["EXPIRE", "k", "0"]), b":1\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$-1\r\n");
+        assert_eq!(run(&["EXPIRE", "k", "-5"]), b":0\r\n");
+
+        assert_eq!(
+            run(&["EXPIRE", "k", "soon"]),
+            format!("-{NOT_AN_INTEGER_ERROR}\r\n").into_bytes()
+        );
+        assert_eq!(
+            run(&["EXPIRE", "k"]),
+            b"-ERR wrong number of arguments for 'expire' command\r\n"
+        );
+        assert_eq!(
+            run(&["EXPIRE", "k", &i64::MAX.to_string()]),
+            b"-ERR invalid expire time in 'expire' command\r\n"
+        );
+    }
+
+    #[test]
+    fn test_object_freq_requires_lfu() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        storage.set("k", b"v".to_vec(), 0, 0);
+
+        let reply = resp(&storage, &command(&["OBJECT", "FREQ", "k"]), &config);
+        assert!(reply.starts_with(b"-ERR An LFU maxmemory policy is not selected"));
+
+        let reply = resp(&storage, &command(&["OBJECT", "SIZE", "k"]), &config);
+        assert!(reply.starts_with(b"-ERR unknown subcommand 'SIZE'"));
+    }
+
+    #[test]
+    fn test_object_freq_under_lfu() {
+        let storage = Storage::with_limits(1024 * 1024, None, 0, EvictionPolicy::Lfu);
+        let config = request_config();
+        storage.set("k", b"v".to_vec(), 0, 0);
+        storage.get("k");
+        storage.get("k");
+
+        let freq = |key: &str| resp(&storage, &command(&["OBJECT", "FREQ", key]), &config);
+        assert_eq!(freq("k"), b":2\r\n");
+        assert_eq!(freq("missing"), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_noeviction_write_errors() {
+        let storage = Storage::with_limits(200, None, 0, EvictionPolicy::NoEviction);
+        let config = request_config();
+        let value = "v".repeat(150);
+
+        let set = format!("set k 0 0 150\r\n{value}\r\n");
+        assert_eq!(
+            memcached(&storage, set.as_bytes()),
+            b"SERVER_ERROR out of memory storing object\r\n"
+        );
+        assert_eq!(memcached(&storage, b"set n 0 0 1\r\n1\r\n"), b"STORED\r\n");
+        assert_eq!(
+            resp(&storage, &command(&["SET", "k", &value]), &config),
+            format!("-{OOM_ERROR}\r\n").into_bytes()
+        );
+        assert!(storage.get("k").is_none());
+    }
+
+    #[test]
+    fn test_exec_aborts_when_watched_key_modified() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut conn_a = Session::default();
+        let mut conn_b = Session::default();
+        let run = |session: &mut Session, args: &[&str]| {
+            resp_session(&storage, &command(args), &config, session)
+        };
+
+        run(&mut conn_a, &["SET", "counter", "1"]);
+        assert_eq!(run(&mut conn_a, &["WATCH", "counter"]), b"+OK\r\n");
+        assert_eq!(run(&mut conn_a, &["MULTI"]), b"+OK\r\n");
+        assert_eq!(run(&mut conn_a, &["SET", "counter", "2"]), b"+QUEUED\r\n");
+
+        // Another connection modifies the watched key
+        assert_eq!(run(&mut conn_b, &["SET", "counter", "10"]), b"+OK\r\n");
+
+        assert_eq!(run(&mut conn_a, &["EXEC"]), b"*-1\r\n");
+        assert_eq!(run(&mut conn_a, &["GET", "counter"]), b"$2\r\n10\r\n");
+    }
+
+    #[test]
+    fn test_exec_applies_queued_writes() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        run(&["SET", "old", "x"]);
+        run(&["WATCH", "old"]);
+        run(&["MULTI"]);
+        assert_eq!(run(&["SET", "new", "y"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["DEL", "old", "missing"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["EXEC"]), b"*2\r\n+OK\r\n:1\r\n");
+        assert_eq!(run(&["GET", "new"]), b"$1\r\ny\r\n");
+        assert_eq!(run(&["EXISTS", "old"]), b":0\r\n");
+
+        // Unsupported commands fail to queue and abort EXEC
+        run(&["MULTI"]);
+        assert!(run(&["EXISTS", "new"]).starts_with(b"-ERR"));
+        assert!(run(&["EXEC"]).starts_with(b"-EXECABORT"));
+        assert_eq!(run(&["EXEC"]), b"-ERR EXEC without MULTI\r\n");
+    }
+
+    #[test]
+    fn test_exec_queues_set_options_and_reads() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        run(&["SET", "taken", "x"]);
+        run(&["MULTI"]);
+        assert_eq!(run(&["SET", "k", "v", "EX", "10"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["SET", "taken", "y", "NX"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["GET", "k"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["GET", "missing"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["EXEC"]), b"*4\r\n+OK\r\n$-1\r\n$1\r\nv\r\n$-1\r\n");
+        assert_eq!(run(&["GET", "taken"]), b"$1\r\nx\r\n");
+        assert_eq!(run(&["TTL", "k"]), b":10\r\n");
+
+        // A bad option fails to queue, with the error SET gives
+        run(&["MULTI"]);
+        assert_eq!(
+            run(&["SET", "k", "v", "EX", "0"]),
+            b"-ERR invalid expire time in 'set' command\r\n"
+        );
+        assert!(run(&["EXEC"]).starts_with(b"-EXECABORT"));
+    }
+
+    #[test]
+    fn test_resp_stores_carry_default_flags() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            default_flags: 42,
+            ..request_config()
+        };
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        assert_eq!(run(&["SET", "plain", "v"]), b"+OK\r\n");
+        run(&["MULTI"]);
+        run(&["SET", "queued", "v"]);
+        run(&["EXEC"]);
+
+        assert_eq!(
+            memcached(&storage, b"get plain queued\r\n"),
+            b"VALUE plain 42 1\r\nv\r\nVALUE queued 42 1\r\nv\r\nEND\r\n"
+        );
+        assert_eq!(memcached(&storage, b"mg plain f\r\n"), b"HD f42\r\n");
+
+        // Memcached stores keep the flags the client sent
+        memcached_with(&storage, b"set mc 7 0 1\r\nv\r\n", &config);
+        assert_eq!(memcached(&storage, b"mg mc f\r\n"), b"HD f7\r\n");
+    }
+
+    #[test]
+    fn test_reset_clears_connection_state() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        run(&["WATCH", "k"]);
+        run(&["MULTI"]);
+        assert_eq!(run(&["SET", "k", "queued"]), b"+QUEUED\r\n");
+        assert_eq!(run(&["RESET"]), b"+RESET\r\n");
+
+        // The transaction is gone and its write never ran
+        assert_eq!(run(&["EXEC"]), b"-ERR EXEC without MULTI\r\n");
+        assert_eq!(run(&["GET", "k"]), b"$-1\r\n");
+
+        // Neither is the watch: a changed key no longer aborts EXEC
+        run(&["WATCH", "k"]);
+        run(&["RESET"]);
+        storage.set("k", b"other".to_vec(), 0, 0);
+        run(&["MULTI"]);
+        run(&["SET", "k", "v"]);
+        assert_eq!(run(&["EXEC"]), b"*1\r\n+OK\r\n");
+    }
+
+    #[test]
+    fn test_read_only_mode_rejects_writes() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = request_config();
+        let mut session = Session::default();
+        let mut run = |args: &[&str]| resp_session(&storage, &command(args), &config, &mut session);
+
+        assert_eq!(run(&["SET", "k", "v"]), b"+OK\r\n");
+        memcached(&storage, b"set n 0 0 1\r\n5\r\n");
+        assert_eq!(run(&["DEBUG", "READONLY", "on"]), b"+OK\r\n");
+        assert!(storage.is_read_only());
+
+        let readonly = format!("-{READONLY_ERROR}\r\n").into_bytes();
+        let read_only = b"SERVER_ERROR read only\r\n";
+        assert_eq!(run(&["SET", "k", "other"]), readonly);
+        assert_eq!(run(&["DEL", "k"]), readonly);
+        assert_eq!(run(&["FLUSHALL"]), readonly);
+        for write in [
+            &b"set k 0 0 1\r\nx\r\n"[..],
+            b"add a 0 0 1\r\nx\r\n",
+            b"replace k 0 0 1\r\nx\r\n",
+            b"cas k 0 0 1 1\r\nx\r\n",
+            b"append k 0 0 1\r\nx\r\n",
+            b"delete k\r\n",
+            b"incr n 1\r\n",
+        ] {
+            assert_eq!(memcached(&storage, write), read_only);
+        }
+
+        // Reads and stats still work
+        assert_eq!(run(&["GET", "k"]), b"$1\r\nv\r\n");
+        assert_eq!(
+            memcached(&storage, b"get n\r\n"),
+            b"VALUE n 0 1\r\n5\r\nEND\r\n"
+        );
+        assert!(memcached(&storage, b"stats\r\n").starts_with(b"STAT curr_items 2\r\n"));
+
+        // Writes queued in MULTI abort EXEC
+        run(&["MULTI"]);
+        assert_eq!(run(&["SET", "k", "other"]), readonly);
+        assert!(run(&["EXEC"]).starts_with(b"-EXECABORT"));
+
+        assert_eq!(run(&["DEBUG", "READONLY", "off"]), b"+OK\r\n");
+        assert_eq!(run(&["SET", "k", "other"]), b"+OK\r\n");
+        assert_eq!(memcached(&storage, b"incr n 1\r\n"), b"6\r\n");
+    }
 }
@@ -12,18 +12,28 @@
 
 #![allow(dead_code)] // Some methods will be used when features are wired in
 
+use crate::request::Protocol;
 use std::borrow::Cow;
-use std::io::IoSlice;
+use std::cell::Cell;
+use std::io::{self, IoSlice, Read};
+use std::rc::Rc;
 
 /// Per-worker buffer pool with fixed-size buffers.
 ///
 /// Buffers are pre-allocated and reused to avoid allocation overhead
 /// on the hot path. The pool tracks which buffers are in use via a free list.
+///
+/// The pool can be resized; a shrink drops free buffers only, so indices
+/// held by callers stay valid.
 pub struct BufferPool {
-    /// Actual buffer storage.
+    /// Actual buffer storage (a dropped buffer leaves an empty slot).
     buffers: Vec<Vec<u8>>,
     /// Stack of available buffer indices (LIFO for cache locality).
     free_list: Vec<usize>,
+    /// Slots whose buffer was dropped by a shrink, reused by a grow.
+    retired: Vec<usize>,
+    /// Buffers still to drop as they are freed, for a deferred shrink.
+    pending_shrink: usize,
     /// Size of each buffer.
     buffer_size: usize,
 }
@@ -46,10 +56,54 @@ impl BufferPool {
         Self {
             buffers,
             free_list,
+            retired: Vec::new(),
+            pending_shrink: 0,
             buffer_size: size,
         }
     }
 
+    /// Grow or shrink the pool to `new_count` buffers.
+    ///
+    /// Growing allocates right away. Shrinking drops free buffers now and
+    /// in-use ones only once they are freed, so `capacity()` may take a
+    /// while to reach `new_count`. Buffer indices are never reused for a
+    /// different live buffer, but any io_uring registration of the old set
+    /// is stale afterwards.
+    pub fn resize(&mut self, new_count: usize) {
+        let current = self.capacity();
+        if new_count >= current {
+            self.pending_shrink = 0;
+            for _ in current..new_count {
+                let idx = match self.retired.pop() {
+                    Some(idx) => {
+                        self.buffers[idx] = vec![0u8; self.buffer_size];
+                        idx
+                    }
+                    None => {
+                        self.buffers.push(vec![0u8; self.buffer_size]);
+                        self.buffers.len() - 1
+                    }
+                };
+                self.free_list.push(idx);
+            }
+        } else {
+            self.pending_shrink = current - new_count;
+            while self.pending_shrink > 0 {
+                match self.free_list.pop() {
+                    Some(idx) => self.retire(idx),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Drop the buffer at `idx`, toward a pending shrink.
+    fn retire(&mut self, idx: usize) {
+        self.buffers[idx] = Vec::new();
+        self.retired.push(idx);
+        self.pending_shrink -= 1;
+    }
+
     /// Allocate a buffer from the pool.
     ///
     /// Returns `None` if no buffers are available.
@@ -63,7 +117,11 @@ impl BufferPool {
     /// Panics if `idx` is out of bounds (debug builds only).
     pub fn free(&mut self, idx: usize) {
         debug_assert!(idx < self.buffers.len(), "buffer index out of bounds");
-        self.free_list.push(idx);
+        if self.pending_shrink > 0 {
+            self.retire(idx);
+        } else {
+            self.free_list.push(idx);
+        }
     }
 
     /// Get an immutable reference to a buffer.
@@ -82,6 +140,21 @@ impl BufferPool {
         &mut self.buffers[idx]
     }
 
+    /// Borrow one buffer for reading and a different one for writing.
+    ///
+    /// # Panics
+    /// Panics if the indices are equal or out of bounds.
+    pub fn split(&mut self, src: usize, dst: usize) -> (&[u8], &mut [u8]) {
+        assert_ne!(src, dst, "split needs two distinct buffers");
+        if src < dst {
+            let (head, tail) = self.buffers.split_at_mut(dst);
+            (&head[src], &mut tail[0])
+        } else {
+            let (head, tail) = self.buffers.split_at_mut(src);
+            (&tail[0], &mut head[dst])
+        }
+    }
+
     /// Get a mutable pointer to a buffer for FFI.
     ///
     /// # Safety
@@ -96,9 +169,9 @@ impl BufferPool {
         self.buffer_size
     }
 
-    /// Get the total number of buffers.
+    /// Get the total number of buffers, free or in use.
     pub fn capacity(&self) -> usize {
-        self.buffers.len()
+        self.buffers.len() - self.retired.len()
     }
 
     /// Get the number of available buffers.
@@ -130,8 +203,7 @@ impl BufferPool {
     /// Free multiple buffers at once.
     pub fn free_many(&mut self, indices: impl IntoIterator<Item = usize>) {
         for idx in indices {
-            debug_assert!(idx < self.buffers.len(), "buffer index out of bounds");
-            self.free_list.push(idx);
+            self.free(idx);
         }
     }
 }
@@ -240,6 +312,65 @@ impl BufferChain {
         Ok(())
     }
 
+    /// Replace the chain's contents with the next bytes of `source`, up to
+    /// `max_buffers` buffers' worth. Returns the bytes read, 0 once the
+    /// source is drained.
+    ///
+    /// Fills fewer buffers if the pool runs short, as long as one is filled.
+    pub fn refill(
+        &mut self,
+        source: &mut (impl Read + ?Sized),
+        max_buffers: usize,
+        pool: &mut BufferPool,
+    ) -> io::Result<usize> {
+        self.release(pool);
+        while self.len < max_buffers * self.buffer_size {
+            let (buf_idx, offset) = match self.current_buffer_and_offset(pool) {
+                Ok(at) => at,
+                Err(_) if self.len > 0 => break,
+                Err(_) => return Err(io::Error::other("buffer pool exhausted")),
+            };
+            let n = source.read(&mut pool.get_mut(buf_idx)[offset..self.buffer_size])?;
+            if n == 0 {
+                // Taken for bytes that never came
+                if offset == 0 {
+                    self.buffers.pop();
+                    pool.free(buf_idx);
+                }
+                break;
+            }
+            self.len += n;
+        }
+        Ok(self.len)
+    }
+
+    /// Read once from `source` into the space after the chain's contents,
+    /// allocating a buffer if the last is full. Returns the bytes read, 0 at
+    /// the end of the input.
+    pub fn read_from(
+        &mut self,
+        source: &mut (impl Read + ?Sized),
+        pool: &mut BufferPool,
+    ) -> io::Result<usize> {
+        let (buf_idx, offset) = self
+            .current_buffer_and_offset(pool)
+            .map_err(|_| io::Error::other("buffer pool exhausted"))?;
+        match source.read(&mut pool.get_mut(buf_idx)[offset..self.buffer_size]) {
+            Ok(n) if n > 0 => {
+                self.len += n;
+                Ok(n)
+            }
+            unfilled => {
+                // Taken for bytes that never came
+                if offset == 0 {
+                    self.buffers.pop();
+                    pool.free(buf_idx);
+                }
+                unfilled
+            }
+        }
+    }
+
     /// Get or allocate the current buffer for writing.
     ///
     /// Returns (buffer_index, offset_in_buffer).
@@ -314,34 +445,19 @@ impl BufferChain {
     /// Create IoSlice views for scatter-gather I/O.
     ///
     /// Returns slices starting from the given byte offset (for resuming partial writes).
-    pub fn io_slices<'a>(
-        &'a self,
-        pool: &'a BufferPool,
-        start_offset: usize,
-    ) -> Vec<IoSlice<'a>> {
-        if start_offset >= self.len {
-            return Vec::new();
-        }
-
+    /// Each slice covers the stored bytes of one buffer; buffers wholly before
+    /// the offset, or past the end of the data, are left out.
+    pub fn io_slices<'a>(&'a self, pool: &'a BufferPool, start_offset: usize) -> Vec<IoSlice<'a>> {
         let mut slices = Vec::with_capacity(self.buffers.len());
         let mut skip = start_offset;
-        let mut remaining = self.len - start_offset;
 
-        for &buf_idx in &self.buffers {
-            let buf = pool.get(buf_idx);
-            let chunk_len = remaining.min(self.buffer_size);
-
-            if skip >= chunk_len {
-                skip -= chunk_len;
+        for chunk in self.chunks(pool) {
+            if skip >= chunk.len() {
+                skip -= chunk.len();
                 continue;
             }
-
-            let slice = &buf[skip..skip + chunk_len - skip.min(chunk_len)];
-            if !slice.is_empty() {
-                slices.push(IoSlice::new(&buf[skip..chunk_len]));
-            }
+            slices.push(IoSlice::new(&chunk[skip..]));
             skip = 0;
-            remaining -= chunk_len;
         }
 
         slices
@@ -401,6 +517,59 @@ impl BufferChain {
     }
 }
 
+/// Per-worker cap on connections accumulating a large value in chain buffers.
+pub struct LargeValueSlots {
+    active: Rc<Cell<usize>>,
+    /// Most concurrent large values (0 = unlimited)
+    limit: usize,
+}
+
+impl LargeValueSlots {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            active: Rc::new(Cell::new(0)),
+            limit,
+        }
+    }
+
+    /// Take a slot, or `None` if the worker is at its limit.
+    pub fn acquire(&self) -> Option<LargeValueSlot> {
+        if self.limit != 0 && self.active.get() >= self.limit {
+            return None;
+        }
+        self.active.set(self.active.get() + 1);
+        Some(LargeValueSlot {
+            active: Rc::clone(&self.active),
+        })
+    }
+
+    /// Large values accumulating now.
+    pub fn active(&self) -> usize {
+        self.active.get()
+    }
+}
+
+/// A connection's claim on a large-value slot, returned on drop.
+#[derive(Debug)]
+pub struct LargeValueSlot {
+    active: Rc<Cell<usize>>,
+}
+
+impl Drop for LargeValueSlot {
+    fn drop(&mut self) {
+        self.active.set(self.active.get() - 1);
+    }
+}
+
+/// Error sent when a worker has no large-value slot left.
+pub fn large_value_busy_response(protocol: Protocol) -> &'static [u8] {
+    match protocol {
+        Protocol::Memcached => b"SERVER_ERROR temporarily out of large buffers\r\n",
+        Protocol::Resp => b"-ERR busy\r\n",
+        Protocol::Ping | Protocol::Echo | Protocol::Auto => b"ERROR busy\r\n",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -440,6 +609,47 @@ mod tests {
         assert_eq!(pool.available(), 4);
     }
 
+    #[test]
+    fn test_buffer_pool_resize() {
+        let mut pool = BufferPool::new(4, 64);
+        let held = pool.alloc_many(3).unwrap();
+        for (i, &idx) in held.iter().enumerate() {
+            pool.get_mut(idx)[0] = i as u8 + 1;
+        }
+
+        pool.resize(8);
+        assert_eq!(pool.capacity(), 8);
+        assert_eq!(pool.available(), 5);
+
+        // Only the five free buffers can go right away
+        pool.resize(2);
+        assert_eq!(pool.capacity(), 3);
+        assert_eq!(pool.available(), 0);
+        for (i, &idx) in held.iter().enumerate() {
+            assert_eq!(pool.get(idx).len(), 64);
+            assert_eq!(pool.get(idx)[0], i as u8 + 1);
+        }
+
+        // The rest of the shrink happens as buffers come back
+        pool.free(held[0]);
+        assert_eq!(pool.capacity(), 2);
+        assert_eq!(pool.available(), 0);
+        pool.free(held[1]);
+        assert_eq!(pool.capacity(), 2);
+        assert_eq!(pool.available(), 1);
+
+        // Growing refills dropped slots rather than adding new ones
+        pool.resize(5);
+        assert_eq!(pool.capacity(), 5);
+        assert_eq!(pool.available(), 4);
+        assert_eq!(pool.get(held[2])[0], 3);
+        let fresh = pool.alloc_many(4).unwrap();
+        assert!(fresh
+            .iter()
+            .all(|&idx| idx < 8 && pool.get(idx).len() == 64));
+        assert!(!fresh.contains(&held[2]));
+    }
+
     #[test]
     fn test_buffer_chain_single_buffer() {
         let mut pool = BufferPool::new(4, 1024);
@@ -498,6 +708,37 @@ mod tests {
         assert_eq!(pool.available(), 10);
     }
 
+    #[test]
+    fn test_buffer_chain_io_slices_resume() {
+        let mut pool = BufferPool::new(10, 100);
+        let mut chain = BufferChain::new(pool.buffer_size());
+        let data: Vec<u8> = (0..250u8).collect();
+        chain.append(&data, &mut pool).unwrap();
+
+        // First, middle, and last buffer, and each buffer boundary
+        for (offset, lens) in [
+            (0, &[100, 100, 50][..]),
+            (30, &[70, 100, 50]),
+            (100, &[100, 50]),
+            (130, &[70, 50]),
+            (200, &[50]),
+            (249, &[1]),
+            (250, &[]),
+        ] {
+            let slices = chain.io_slices(&pool, offset);
+            let slice_lens: Vec<usize> = slices.iter().map(|slice| slice.len()).collect();
+            assert_eq!(slice_lens, lens, "offset {offset}");
+            let resumed: Vec<u8> = slices
+                .iter()
+                .flat_map(|slice| slice.iter())
+                .copied()
+                .collect();
+            assert_eq!(resumed, data[offset..], "offset {offset}");
+        }
+
+        chain.release(&mut pool);
+    }
+
     #[test]
     fn test_buffer_chain_append_incremental() {
         let mut pool = BufferPool::new(10, 100);
@@ -533,6 +774,37 @@ mod tests {
         chain.release(&mut pool);
     }
 
+    #[test]
+    fn test_buffer_chain_refill() {
+        let mut pool = BufferPool::new(4, 100);
+        let mut chain = BufferChain::new(pool.buffer_size());
+        let data: Vec<u8> = (0..=255).collect();
+        let mut source = &data[..];
+
+        // At most two buffers at a time, whatever the source holds
+        let mut read = Vec::new();
+        let mut rounds = Vec::new();
+        loop {
+            let n = chain.refill(&mut source, 2, &mut pool).unwrap();
+            if n == 0 {
+                break;
+            }
+            rounds.push((n, chain.buffer_count()));
+            read.extend_from_slice(&chain.as_contiguous(&pool));
+        }
+        assert_eq!(rounds, [(200, 2), (56, 1)]);
+        assert_eq!(read, data);
+        assert_eq!(chain.buffer_count(), 0);
+        assert_eq!(pool.available(), 4);
+
+        // Short of buffers, it fills what it gets
+        let held = pool.alloc_many(3).unwrap();
+        let mut source = &data[..];
+        assert_eq!(chain.refill(&mut source, 2, &mut pool).unwrap(), 100);
+        pool.free_many(held);
+        chain.release(&mut pool);
+    }
+
     #[test]
     fn test_buffer_chain_first_n_bytes() {
         let mut pool = BufferPool::new(4, 100);
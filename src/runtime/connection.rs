@@ -7,9 +7,11 @@
 //! This separation enables future worker specialization (dedicated accept threads)
 //! and TLS handshake support.
 
-use crate::request::Protocol;
+use crate::request::{OpenConnection, Protocol, ResponseStream, Session};
+use crate::runtime::{BufferChain, LargeValueSlot, QuotaPermit};
 use slab::Slab;
 use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
 
 /// Data plane state: request processing on an established connection.
 ///
@@ -108,6 +110,50 @@ impl ConnPhase {
     }
 }
 
+/// How long the command at the front of the read buffer has been arriving.
+///
+/// Bounds clients that trickle a command in slowly enough to hold a
+/// connection and its partial buffer forever while still looking active.
+#[derive(Debug, Default)]
+pub struct CommandAssembly {
+    started: Option<Instant>,
+}
+
+impl CommandAssembly {
+    /// Note that a read left an incomplete command buffered.
+    ///
+    /// The clock starts at the first such read and keeps running until
+    /// the command completes.
+    pub fn pending(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Note that a command completed, with `leftover` bytes of the next one
+    /// already buffered.
+    pub fn completed(&mut self, leftover: bool) {
+        self.started = leftover.then(Instant::now);
+    }
+
+    /// Whether the pending command has been arriving for longer than `limit`.
+    pub fn expired(&self, limit: Option<Duration>) -> bool {
+        match (self.started, limit) {
+            (Some(started), Some(limit)) => started.elapsed() > limit,
+            _ => false,
+        }
+    }
+}
+
+/// iovecs of an in-flight vectored write (io_uring), kept with the
+/// connection so they outlive the submission pointing at them.
+#[derive(Default)]
+pub struct IoVecs(pub Vec<libc::iovec>);
+
+impl std::fmt::Debug for IoVecs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IoVecs({} entries)", self.0.len())
+    }
+}
+
 /// A single client connection.
 #[derive(Debug)]
 pub struct Connection {
@@ -123,6 +169,29 @@ pub struct Connection {
     pub read_buf_idx: Option<usize>,
     /// Number of bytes accumulated in read_buf_idx.
     pub read_accumulated: usize,
+    /// Input of a value too large for read_buf_idx, accumulated in place
+    /// of it (io_uring).
+    pub read_chain: Option<BufferChain>,
+    /// Input length at which the chained command is parsed again.
+    pub read_chain_wanted: usize,
+    /// Held while `read_chain` accumulates a large value.
+    pub large_value: Option<LargeValueSlot>,
+    /// Response too large for one buffer, written with writev (io_uring).
+    pub write_chain: Option<BufferChain>,
+    /// iovecs for the `write_chain` write in flight.
+    pub write_iovecs: IoVecs,
+    /// Rest of a streamed response, refilling `write_chain` as it drains.
+    pub write_stream: Option<ResponseStream>,
+    /// Per-IP quota slot, released when the connection is dropped.
+    #[allow(dead_code)] // Held only for its Drop side effect
+    pub quota_permit: Option<QuotaPermit>,
+    /// Counts toward the open connections gauge until dropped.
+    #[allow(dead_code)] // Held only for its Drop side effect
+    pub open: Option<OpenConnection>,
+    /// Protocol state carried across requests (e.g. RESP transactions).
+    pub session: Session,
+    /// Arrival time of the partial command in the read buffer.
+    pub assembly: CommandAssembly,
 }
 
 impl Connection {
@@ -136,6 +205,16 @@ impl Connection {
             protocol,
             read_buf_idx: None,
             read_accumulated: 0,
+            read_chain: None,
+            read_chain_wanted: 0,
+            large_value: None,
+            write_chain: None,
+            write_iovecs: IoVecs::default(),
+            write_stream: None,
+            quota_permit: None,
+            open: None,
+            session: Session::default(),
+            assembly: CommandAssembly::default(),
         }
     }
 
@@ -150,6 +229,16 @@ impl Connection {
             protocol,
             read_buf_idx: None,
             read_accumulated: 0,
+            read_chain: None,
+            read_chain_wanted: 0,
+            large_value: None,
+            write_chain: None,
+            write_iovecs: IoVecs::default(),
+            write_stream: None,
+            quota_permit: None,
+            open: None,
+            session: Session::default(),
+            assembly: CommandAssembly::default(),
         }
     }
 
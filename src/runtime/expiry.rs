@@ -0,0 +1,71 @@
+//! Background reclaiming of expired items.
+//!
+//! Reads skip expired items but leave them in place, and eviction only
+//! sweeps once memory runs out, so a key that is never read again would
+//! hold its memory indefinitely. The sweeper thread calls
+//! `Storage::cleanup_expired` every `cleanup_interval` seconds, and stops
+//! with the workers.
+
+use super::Shutdown;
+use crate::storage::Storage;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Longest the sweeper sleeps before checking for shutdown.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Start the sweeper, reclaiming expired items every `interval` until
+/// `shutdown` is requested.
+pub(crate) fn spawn_sweeper(
+    storage: Arc<Storage>,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("expiry-sweeper".to_string())
+        .spawn(move || {
+            let mut next_sweep = Instant::now() + interval;
+            while !shutdown.is_requested() {
+                let now = Instant::now();
+                if now < next_sweep {
+                    thread::sleep((next_sweep - now).min(SHUTDOWN_CHECK_INTERVAL));
+                    continue;
+                }
+                let reclaimed = storage.cleanup_expired();
+                debug!(reclaimed, "Expiry sweep finished");
+                next_sweep = Instant::now() + interval;
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweeper_reclaims_unread_expired_items() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("short", b"lived".to_vec(), 0, 1);
+        assert!(storage.stats().memory_used > 0);
+
+        let shutdown = Shutdown::new();
+        let sweeper = spawn_sweeper(
+            Arc::clone(&storage),
+            Duration::from_millis(50),
+            shutdown.clone(),
+        )
+        .unwrap();
+
+        // Past the TTL and several sweeps, without reading the key
+        thread::sleep(Duration::from_millis(1500));
+        let stats = storage.stats();
+        assert_eq!(stats.item_count, 0);
+        assert_eq!(stats.memory_used, 0);
+
+        shutdown.trigger();
+        sweeper.join().unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Listener setup shared by the runtime backends.
+//!
+//! Every worker has its own `SO_REUSEPORT` listener so the kernel spreads
+//! connections across workers. During a rolling restart the bind can fail
+//! transiently with `EADDRINUSE` while the old process is still closing its
+//! sockets, so binding is retried with exponential backoff and jitter.
+//! Options on the connections a listener accepts are set here too.
+//!
+//! A Unix domain socket listener is bound once and shared by the workers.
+//! A socket file left by a server that died is replaced, and the file is
+//! removed again when the listener is dropped.
+
+use crate::config::BindRetry;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Create a TCP listener with SO_REUSEPORT for kernel load balancing.
+pub fn create_listener_with_reuseport(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+    let socket = socket2::Socket::new(
+        match addr {
+            SocketAddr::V4(_) => socket2::Domain::IPV4,
+            SocketAddr::V6(_) => socket2::Domain::IPV6,
+        },
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+
+    socket.set_reuse_port(true)?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+/// Set or clear `TCP_NODELAY` on an accepted connection.
+#[allow(dead_code)] // Only used by the io_uring accept path (Linux)
+pub fn set_nodelay(fd: RawFd, nodelay: bool) -> io::Result<()> {
+    // SAFETY: the caller owns `fd` for the duration of this call.
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    socket2::SockRef::from(&fd).set_nodelay(nodelay)
+}
+
+/// Create a listener, retrying `EADDRINUSE` within the `retry` budget.
+///
+/// Other errors are returned immediately.
+pub fn bind_with_retry(addr: SocketAddr, retry: &BindRetry) -> io::Result<std::net::TcpListener> {
+    let mut backoff = Duration::from_millis(retry.initial_backoff_ms);
+    let max_backoff = Duration::from_millis(retry.max_backoff_ms);
+    let mut rng = jitter_seed();
+
+    for attempt in 1.. {
+        match create_listener_with_reuseport(addr) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && attempt <= retry.attempts => {
+                // Equal jitter: sleep between half and all of the backoff
+                let half = backoff / 2;
+                let jitter = half.mul_f64(next_unit(&mut rng));
+                let delay = half + jitter;
+                warn!(
+                    addr = %addr,
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    "Address in use, retrying bind"
+                );
+                thread::sleep(delay);
+                backoff = (backoff * 2).min(max_backoff);
+            }
+            result => return result,
+        }
+    }
+    unreachable!("retry loop only exits by returning")
+}
+
+/// A Unix domain socket listener that owns its socket file.
+#[derive(Debug)]
+pub struct UnixSocketListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocketListener {
+    /// Bind a non-blocking listener at `path`, replacing a stale socket.
+    ///
+    /// A socket file nothing accepts on is left over from a server that
+    /// didn't shut down cleanly. One that still accepts belongs to a running
+    /// server, and a path that isn't a socket is never removed.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if !meta.file_type().is_socket() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            Ok(_) => match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is in use by a running server", path.display()),
+                    ));
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    warn!(path = %path.display(), "Removing stale Unix socket");
+                    fs::remove_file(path)?;
+                }
+                Err(e) => return Err(e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Another handle on the listener, for one worker.
+    pub fn try_clone(&self) -> io::Result<UnixListener> {
+        self.listener.try_clone()
+    }
+
+    /// The socket file's path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), error = %e, "Failed to remove Unix socket");
+        }
+    }
+}
+
+/// Seed for the jitter generator, distinct per call and per thread.
+fn jitter_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let local = 0u8;
+    // Mix in a stack address so workers starting together diverge
+    (nanos ^ (&local as *const u8 as u64)) | 1
+}
+
+/// Next value in [0, 1) from a xorshift64 generator.
+fn next_unit(state: &mut u64) -> f64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    #[test]
+    fn test_bind_retries_until_address_frees() {
+        // A plain listener (no SO_REUSEPORT) blocks our bind until dropped
+        let blocker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = blocker.local_addr().unwrap();
+
+        let retry = BindRetry {
+            attempts: 10,
+            initial_backoff_ms: 20,
+            max_backoff_ms: 100,
+        };
+        assert_eq!(
+            create_listener_with_reuseport(addr).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            drop(blocker);
+        });
+
+        let start = Instant::now();
+        let listener = bind_with_retry(addr, &retry).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        release.join().unwrap();
+    }
+
+    #[test]
+    fn test_bind_gives_up_after_budget() {
+        let blocker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = blocker.local_addr().unwrap();
+
+        let retry = BindRetry {
+            attempts: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+        };
+        let err = bind_with_retry(addr, &retry).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn test_unix_socket_replaces_only_stale_files() {
+        let path =
+            std::env::temp_dir().join(format!("grow-a-cache-listener-{}.sock", std::process::id()));
+        // A listener dropped without cleanup leaves its file behind
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = UnixSocketListener::bind(&path).unwrap();
+        let err = UnixSocketListener::bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+        assert!(!path.exists());
+
+        fs::write(&path, b"not a socket").unwrap();
+        let err = UnixSocketListener::bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_jitter_stays_in_unit_range() {
+        let mut rng = jitter_seed();
+        for _ in 0..1000 {
+            let x = next_unit(&mut rng);
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+}
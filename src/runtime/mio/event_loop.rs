@@ -8,108 +8,212 @@
 //!
 //! For values larger than the buffer size, we use `BufferChain` to accumulate
 //! data across multiple pool buffers. This keeps memory bounded while supporting
-//! values up to `max_value_size`.
+//! values up to `max_value_size`. Once a command announces a large value, its
+//! input moves to a chain and later reads go straight into it; the chain is
+//! only parsed, from a contiguous copy, once the value can be complete.
+//!
+//! Each worker bounds how many connections may accumulate a large value at
+//! once (`max_concurrent_large_values`), so chain buffers can't starve
+//! small requests; a large value over the limit is refused and its
+//! connection closed.
+//!
+//! Requests are parsed in place in the connection's read buffer. Bytes are
+//! only moved when a read leaves a partial command behind the ones answered.
+//!
+//! With `idle_timeout_secs` set, a worker sweeps its connections about once
+//! a second and closes those that have neither read nor written for longer.
+//!
+//! On shutdown a worker drops its listener and closes every connection that
+//! is reading, so only responses already being written are let finish.
 
+use super::socket::{Endpoint, Listener, Stream};
+use crate::admin::Readiness;
 use crate::config::Config;
-use crate::request::{process_echo, process_memcached, process_ping, process_resp};
-use crate::runtime::{BufferChain, BufferPool, ChainError, DataState, ProcessResult, Protocol};
+use crate::privileges::drop_privileges;
+use crate::request::{
+    detect_protocol, process_pipeline, ConnectionStats, OpenConnection, RequestConfig,
+    ResponseStream, Session,
+};
+use crate::runtime::{
+    large_value_busy_response, quota_exceeded_response, spawn_worker, BufferChain, BufferPool,
+    ChainError, CommandAssembly, ConnectionQuota, DataState, LargeValueSlot, LargeValueSlots,
+    ProcessResult, Protocol, QuotaPermit, Shutdown, UnixSocketListener,
+};
 use crate::storage::Storage;
-use mio::net::{TcpListener, TcpStream};
 use mio::{Events, Interest, Poll, Token};
 use slab::Slab;
 use std::io::{self, Read, Write};
-use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::thread;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
 const LISTENER_TOKEN: Token = Token(usize::MAX);
 
+/// Longest a worker blocks in `poll` before checking for shutdown.
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How often a worker looks for connections past the idle timeout.
+const IDLE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Readiness API mio drives on this platform, reported by `stats`.
+#[cfg(target_os = "linux")]
+const BACKEND: &str = "epoll";
+#[cfg(not(target_os = "linux"))]
+const BACKEND: &str = "kqueue";
+
+#[cfg(test)]
+thread_local! {
+    /// Unconsumed input moved to the front of a read buffer on this thread.
+    static INPUT_SHIFTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 /// Per-worker connection state for mio backend.
 ///
 /// Uses shared `DataState` for read/write state tracking,
-/// but wraps the mio `TcpStream` directly.
+/// but wraps the mio stream (TCP or Unix) directly.
 ///
 /// For large values, `read_chain` accumulates data beyond the primary buffer,
 /// and `write_chain` holds multi-buffer responses for scatter-gather writes.
 struct MioConnection {
-    stream: TcpStream,
+    stream: Stream,
     /// Data plane state (reading/writing)
     data_state: DataState,
     /// Primary read buffer (always allocated)
     read_buf_idx: usize,
     /// Primary write buffer (always allocated)
     write_buf_idx: usize,
-    /// Chain for accumulating large reads (beyond primary buffer)
+    /// Input of a value too large for the read buffer, read into a chain
+    /// in place of it
     read_chain: Option<BufferChain>,
+    /// Input length at which the chained command is parsed again
+    read_chain_wanted: usize,
     /// Chain for large writes (populated from response data)
     write_chain: Option<BufferChain>,
+    /// Rest of a streamed response, refilling `write_chain` as it drains
+    write_stream: Option<ResponseStream>,
+    /// Held while `read_chain` accumulates a large value
+    large_value: Option<LargeValueSlot>,
+    /// Unparsed input left behind the response being written
+    pipelined: usize,
     protocol: Protocol,
+    /// Per-IP quota slot, released when the connection is dropped
+    #[allow(dead_code)] // Held only for its Drop side effect
+    quota_permit: Option<QuotaPermit>,
+    /// Counts toward the open connections gauge until dropped
+    #[allow(dead_code)] // Held only for its Drop side effect
+    open: Option<OpenConnection>,
+    /// Protocol state carried across requests (e.g. RESP transactions)
+    session: Session,
+    /// Arrival time of the partial command in the read buffer
+    assembly: CommandAssembly,
+    /// Last read or write that moved bytes, for the idle timeout
+    last_activity: Instant,
 }
 
 /// Run the mio-based server.
-pub fn run(config: Config, storage: Arc<Storage>, protocol: Protocol) -> io::Result<()> {
-    let num_workers = if config.workers == 0 {
-        num_cpus()
-    } else {
-        config.workers
+pub fn run(
+    config: Config,
+    storage: Arc<Storage>,
+    protocol: Protocol,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
+    connection_stats: Arc<ConnectionStats>,
+) -> io::Result<()> {
+    let num_workers = config.worker_count();
+
+    // Unix sockets have no SO_REUSEPORT to spread connections, so one
+    // listener is bound here and shared by the workers
+    let endpoint = match &config.unix_socket {
+        Some(path) => Endpoint::Unix(Arc::new(UnixSocketListener::bind(path)?)),
+        None => Endpoint::Tcp(
+            format!("{}:{}", config.host, config.port)
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        ),
     };
 
-    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
-        .parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-
     info!(
         workers = num_workers,
-        addr = %addr,
+        addr = %endpoint,
         protocol = ?protocol,
         "Starting mio runtime"
     );
 
+    // Each worker gets its own listener, all bound before root is given up
+    let listeners = (0..num_workers)
+        .map(|_| endpoint.bind(&config.bind_retry))
+        .collect::<io::Result<Vec<_>>>()?;
+    drop_privileges(config.user.as_deref(), config.group.as_deref())?;
+
     let mut handles = Vec::with_capacity(num_workers);
+    let quota = ConnectionQuota::from_limit(config.max_connections_per_ip);
+    // Shared with the admin endpoint's connections gauge
+    let request_config = RequestConfig {
+        connection_stats,
+        ..RequestConfig::from_config(&config, BACKEND)
+    };
 
-    for worker_id in 0..num_workers {
-        let config = config.clone();
+    for (worker_id, listener) in listeners.into_iter().enumerate() {
+        let worker_config = config.clone();
+        let request_config = request_config.clone();
         let storage = Arc::clone(&storage);
-
-        let handle = thread::Builder::new()
-            .name(format!("worker-{worker_id}"))
-            .spawn(move || {
-                if let Err(e) = worker_loop(worker_id, addr, &config, storage, protocol) {
-                    error!(worker = worker_id, error = %e, "Worker failed");
-                }
-            })?;
+        let quota = quota.clone();
+        let readiness = Arc::clone(&readiness);
+        let shutdown = shutdown.clone();
+
+        let handle = spawn_worker(&config, worker_id, move || {
+            if let Err(e) = worker_loop(
+                worker_id,
+                listener,
+                &worker_config,
+                request_config,
+                storage,
+                protocol,
+                quota,
+                readiness,
+                shutdown,
+            ) {
+                error!(worker = worker_id, error = %e, "Worker failed");
+            }
+        })?;
 
         handles.push(handle);
     }
 
-    // Wait for all workers
+    // Wait for all workers, which return once drained
     for handle in handles {
         let _ = handle.join();
     }
+    info!("mio runtime stopped");
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn worker_loop(
     worker_id: usize,
-    addr: SocketAddr,
+    mut listener: Listener,
     config: &Config,
+    request_config: RequestConfig,
     storage: Arc<Storage>,
     protocol: Protocol,
+    quota: Option<Arc<ConnectionQuota>>,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
 ) -> io::Result<()> {
     let mut poll = Poll::new()?;
     let mut events = Events::with_capacity(config.batch_size);
 
-    // Create listener with SO_REUSEPORT for kernel load balancing
-    let listener = create_listener_with_reuseport(addr)?;
-    let mut listener = TcpListener::from_std(listener);
     poll.registry()
         .register(&mut listener, LISTENER_TOKEN, Interest::READABLE)?;
+    // Dropped when shutdown begins, so new connections are refused
+    let mut listener = Some(listener);
 
     let max_connections = config.max_connections;
+    let nodelay = config.nodelay_by_protocol.get(config.protocol);
     let buffer_size = config.buffer_size;
-    let max_value_size = config.max_value_size;
+    let max_value_size = request_config.max_value_size_for(protocol);
 
     // Buffer pool sizing:
     // - 2 buffers per connection (read + write)
@@ -118,7 +222,8 @@ fn worker_loop(
     // Add 50% more for chains: ~1.9GB total per worker
     let pool_size = max_connections * 3;
     let mut buffers = BufferPool::new(pool_size, buffer_size);
-    let mut connections: Slab<MioConnection> = Slab::with_capacity(max_connections);
+    let mut connections = connection_slab(max_connections);
+    let large_values = LargeValueSlots::new(config.max_concurrent_large_values);
 
     info!(
         worker = worker_id,
@@ -128,20 +233,42 @@ fn worker_loop(
         "Worker started"
     );
 
+    // Listener is bound and the loop is about to run
+    readiness.count_down();
+
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let mut drain_deadline = None;
+    let idle_timeout = match config.idle_timeout_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let mut next_idle_sweep = Instant::now() + IDLE_SWEEP_INTERVAL;
+
     loop {
-        poll.poll(&mut events, None)?;
+        match poll.poll(&mut events, Some(POLL_TIMEOUT)) {
+            Ok(()) => {}
+            // The shutdown signal itself
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
 
         for event in events.iter() {
             match event.token() {
                 LISTENER_TOKEN => {
+                    let Some(listener) = &listener else {
+                        continue;
+                    };
                     accept_connections(
-                        &listener,
+                        listener,
                         &mut poll,
                         &mut connections,
                         &mut buffers,
                         max_connections,
                         worker_id,
                         protocol,
+                        quota.as_ref(),
+                        nodelay,
+                        &request_config.connection_stats,
                     )?;
                 }
                 Token(conn_id) => {
@@ -152,7 +279,8 @@ fn worker_loop(
                         &mut connections,
                         &mut buffers,
                         &storage,
-                        max_value_size,
+                        &request_config,
+                        &large_values,
                     ) {
                         debug!(conn_id, error = %e, "Connection error");
                         close_connection(&mut poll, &mut connections, &mut buffers, conn_id);
@@ -160,26 +288,138 @@ fn worker_loop(
                 }
             }
         }
+
+        if let Some(idle_timeout) = idle_timeout {
+            if Instant::now() >= next_idle_sweep {
+                close_idle_connections(
+                    &mut poll,
+                    &mut connections,
+                    &mut buffers,
+                    idle_timeout,
+                    &request_config,
+                );
+                next_idle_sweep = Instant::now() + IDLE_SWEEP_INTERVAL;
+            }
+        }
+
+        if drain_deadline.is_none() && shutdown.is_requested() {
+            if let Some(mut listener) = listener.take() {
+                let _ = poll.registry().deregister(&mut listener);
+            }
+            drain_deadline = Some(Instant::now() + shutdown_timeout);
+            info!(
+                worker = worker_id,
+                connections = connections.len(),
+                "Shutting down, draining connections"
+            );
+        }
+
+        if let Some(deadline) = drain_deadline {
+            // Including ones whose response finished since the last pass
+            close_reading_connections(&mut poll, &mut connections, &mut buffers);
+            if !connections.is_empty() && Instant::now() >= deadline {
+                warn!(
+                    worker = worker_id,
+                    connections = connections.len(),
+                    "Shutdown timeout, closing connections still writing"
+                );
+                let conn_ids: Vec<usize> = connections.iter().map(|(id, _)| id).collect();
+                for conn_id in conn_ids {
+                    close_connection(&mut poll, &mut connections, &mut buffers, conn_id);
+                }
+            }
+            if connections.is_empty() {
+                info!(worker = worker_id, "Worker stopped");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Close connections with no reads or writes for longer than `idle_timeout`.
+fn close_idle_connections(
+    poll: &mut Poll,
+    connections: &mut Slab<MioConnection>,
+    buffers: &mut BufferPool,
+    idle_timeout: Duration,
+    request_config: &RequestConfig,
+) {
+    let idle: Vec<usize> = connections
+        .iter()
+        .filter(|(_, conn)| conn.last_activity.elapsed() > idle_timeout)
+        .map(|(id, _)| id)
+        .collect();
+    for conn_id in idle {
+        debug!(conn_id, "Idle timeout, closing connection");
+        let stats = &request_config.connection_stats;
+        stats.idle_kicks.fetch_add(1, Ordering::Relaxed);
+        close_connection(poll, connections, buffers, conn_id);
     }
 }
 
+/// Close every connection waiting for a request, leaving those writing.
+fn close_reading_connections(
+    poll: &mut Poll,
+    connections: &mut Slab<MioConnection>,
+    buffers: &mut BufferPool,
+) {
+    let reading: Vec<usize> = connections
+        .iter()
+        .filter(|(_, conn)| matches!(conn.data_state, DataState::Reading { .. }))
+        .map(|(id, _)| id)
+        .collect();
+    for conn_id in reading {
+        close_connection(poll, connections, buffers, conn_id);
+    }
+}
+
+/// Connection table with a slot for every connection a worker accepts.
+///
+/// A slab stores its entries inline, so reserving all `max_connections`
+/// up front (accept refuses any beyond) means a connection burst never
+/// reallocates the table and stalls the worker mid-accept.
+fn connection_slab(max_connections: usize) -> Slab<MioConnection> {
+    Slab::with_capacity(max_connections)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn accept_connections(
-    listener: &TcpListener,
+    listener: &Listener,
     poll: &mut Poll,
     connections: &mut Slab<MioConnection>,
     buffers: &mut BufferPool,
     max_connections: usize,
     worker_id: usize,
     protocol: Protocol,
+    quota: Option<&Arc<ConnectionQuota>>,
+    nodelay: bool,
+    connection_stats: &Arc<ConnectionStats>,
 ) -> io::Result<()> {
     loop {
         match listener.accept() {
-            Ok((stream, peer_addr)) => {
+            Ok((mut stream, peer_addr)) => {
                 if connections.len() >= max_connections {
                     warn!("Connection limit reached");
                     continue;
                 }
 
+                // Unix peers have no IP to count against
+                let quota_permit = match (quota, peer_addr) {
+                    (Some(quota), Some(peer_addr)) => match quota.try_acquire(peer_addr.ip()) {
+                        Some(permit) => Some(permit),
+                        None => {
+                            warn!(peer = %peer_addr, "Per-IP connection limit reached, rejecting");
+                            let _ = stream.write(quota_exceeded_response(protocol));
+                            continue;
+                        }
+                    },
+                    _ => None,
+                };
+
+                if let Err(e) = stream.set_nodelay(nodelay) {
+                    warn!(error = %e, "Could not set TCP_NODELAY");
+                }
+
                 // Allocate read buffer
                 let read_buf_idx = match buffers.alloc() {
                     Some(idx) => idx,
@@ -199,14 +439,25 @@ fn accept_connections(
                     }
                 };
 
+                let open = connection_stats.opened();
+                let session = Session::new(open.id(), peer_addr);
                 let conn_id = connections.insert(MioConnection {
                     stream,
                     data_state: DataState::reading(),
                     read_buf_idx,
                     write_buf_idx,
                     read_chain: None,
+                    read_chain_wanted: 0,
                     write_chain: None,
+                    write_stream: None,
+                    large_value: None,
+                    pipelined: 0,
                     protocol,
+                    quota_permit,
+                    open: Some(open),
+                    session,
+                    assembly: CommandAssembly::default(),
+                    last_activity: Instant::now(),
                 });
 
                 // Re-borrow after insert
@@ -217,7 +468,7 @@ fn accept_connections(
                 debug!(
                     worker = worker_id,
                     conn_id,
-                    peer = %peer_addr,
+                    peer = ?peer_addr,
                     "Accepted connection"
                 );
             }
@@ -231,6 +482,7 @@ fn accept_connections(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_connection_event(
     conn_id: usize,
     event: &mio::event::Event,
@@ -238,23 +490,44 @@ fn handle_connection_event(
     connections: &mut Slab<MioConnection>,
     buffers: &mut BufferPool,
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
 ) -> io::Result<()> {
     if !connections.contains(conn_id) {
         return Ok(());
     }
 
     if event.is_readable() {
-        handle_readable(conn_id, poll, connections, buffers, storage, max_value_size)?;
+        handle_readable(
+            conn_id,
+            poll,
+            connections,
+            buffers,
+            storage,
+            request_config,
+            large_values,
+        )?;
     }
 
-    // Re-check connection exists (may have been removed)
+    // Connections are only ever registered for one of the two, but an event
+    // carrying both is still handled in order: a reply the readable half
+    // produced may be flushed right away, while a connection left reading
+    // is ignored by the writable half. Errors return above, so a connection
+    // is closed (by the caller) before anything else touches it.
     if !connections.contains(conn_id) {
         return Ok(());
     }
 
     if event.is_writable() {
-        handle_writable(conn_id, poll, connections, buffers)?;
+        handle_writable(
+            conn_id,
+            poll,
+            connections,
+            buffers,
+            storage,
+            request_config,
+            large_values,
+        )?;
     }
 
     Ok(())
@@ -266,7 +539,8 @@ fn handle_readable(
     connections: &mut Slab<MioConnection>,
     buffers: &mut BufferPool,
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
 ) -> io::Result<()> {
     let conn = connections
         .get_mut(conn_id)
@@ -279,49 +553,151 @@ fn handle_readable(
 
     let read_buf_idx = conn.read_buf_idx;
     let write_buf_idx = conn.write_buf_idx;
-    let protocol = conn.protocol;
     let buffer_size = buffers.buffer_size();
 
-    // Read into read buffer
-    let read_buf = buffers.get_mut(read_buf_idx);
-    let n = match conn.stream.read(&mut read_buf[filled..]) {
-        Ok(0) => {
-            // EOF
-            return Err(io::Error::new(io::ErrorKind::ConnectionReset, "EOF"));
+    // Read into read buffer, or a large value's chain until it can be
+    // complete (readiness is edge-triggered, so until the socket runs dry);
+    // when input left behind the last response fills the buffer, parse that
+    // first, as a zero-length read would look like EOF
+    let mut n = 0;
+    loop {
+        let read = match &mut conn.read_chain {
+            Some(chain) if chain.len() < conn.read_chain_wanted => {
+                chain.read_from(&mut conn.stream, buffers)
+            }
+            Some(_) => break,
+            None if filled == buffer_size => break,
+            None => conn
+                .stream
+                .read(&mut buffers.get_mut(read_buf_idx)[filled..]),
+        };
+        match read {
+            Ok(0) => {
+                // EOF
+                let stats = &request_config.connection_stats;
+                stats.clean_disconnects.fetch_add(1, Ordering::Relaxed);
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "EOF"));
+            }
+            Ok(read) => {
+                conn.last_activity = Instant::now();
+                n = read;
+                if conn.read_chain.is_none() {
+                    break;
+                }
+            }
+            // Input left behind the last response still needs parsing
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock && conn.pipelined > 0 => break,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if conn
+                    .assembly
+                    .expired(request_config.command_assembly_timeout)
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "command assembly timed out",
+                    ));
+                }
+                return Ok(());
+            }
+            Err(e) => {
+                let stats = &request_config.connection_stats;
+                stats.read_errors.fetch_add(1, Ordering::Relaxed);
+                return Err(e);
+            }
         }
-        Ok(n) => n,
-        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
-        Err(e) => return Err(e),
+    }
+    conn.pipelined = 0;
+
+    // Chained input is parsed from a contiguous copy, as on io_uring
+    let chained_input = conn
+        .read_chain
+        .as_ref()
+        .map(|chain| chain.assemble(buffers));
+    let total_filled = chained_input.as_ref().map_or(filled + n, Vec::len);
+
+    // Parse straight from the read buffer, responding into the write buffer
+    let mut session = std::mem::take(&mut conn.session);
+    let (read_buf, write_buf) = buffers.split(read_buf_idx, write_buf_idx);
+    let input = match &chained_input {
+        Some(input) => input,
+        None => &read_buf[..total_filled],
     };
 
-    let total_filled = filled + n;
-
-    // Process command(s) in the read buffer
-    // We need to split borrows: get read data, then write buffer separately
-    let input = &buffers.get(read_buf_idx)[..total_filled];
-    let input_copy: Vec<u8> = input.to_vec(); // Copy to avoid borrow conflict
+    // The first bytes settle which protocol an `Auto` connection speaks
+    if conn.protocol == Protocol::Auto && total_filled > 0 {
+        conn.protocol = detect_protocol(input);
+    }
+    let protocol = conn.protocol;
 
-    let write_buf = buffers.get_mut(write_buf_idx);
-    let result = match protocol {
-        Protocol::Memcached => process_memcached(&input_copy, write_buf, storage, max_value_size),
-        Protocol::Resp => process_resp(&input_copy, write_buf, storage, max_value_size),
-        Protocol::Ping => process_ping(&input_copy, write_buf, storage),
-        Protocol::Echo => process_echo(&input_copy, write_buf, storage, max_value_size),
-    };
+    let result = process_pipeline(
+        protocol,
+        input,
+        write_buf,
+        storage,
+        request_config,
+        &mut session,
+    );
 
     // Re-borrow connection after buffer operations
     let conn = connections
         .get_mut(conn_id)
         .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
+    conn.session = session;
+
+    if matches!(
+        result,
+        ProcessResult::NeedData | ProcessResult::NeedChain { .. }
+    ) {
+        conn.assembly.pending();
+        if conn
+            .assembly
+            .expired(request_config.command_assembly_timeout)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "command assembly timed out",
+            ));
+        }
+        // No read can complete a command that already fills the buffer;
+        // only a large value may outgrow it
+        let outgrown = match chained_input {
+            Some(_) => conn.large_value.is_none(),
+            None => total_filled == buffer_size,
+        };
+        if outgrown && matches!(result, ProcessResult::NeedData) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "command exceeds read buffer",
+            ));
+        }
+    }
 
     match result {
+        ProcessResult::NeedData if chained_input.is_some() => {
+            // The value is in, but not all that follows it
+            conn.read_chain_wanted = total_filled + 1;
+            conn.data_state = DataState::reading();
+            return handle_readable(
+                conn_id,
+                poll,
+                connections,
+                buffers,
+                storage,
+                request_config,
+                large_values,
+            );
+        }
         ProcessResult::NeedData => {
             // Need more data, stay in reading state with updated fill level
             conn.data_state = DataState::reading_with(total_filled);
             // Already registered for readable
         }
-        ProcessResult::NeedChain { command_len, value_len } => {
+        ProcessResult::NeedChain {
+            command_len,
+            value_len,
+        } => {
             // Large value detected - need to accumulate into chain
+            let max_value_size = request_config.max_value_size_for(protocol);
             if value_len > max_value_size {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -329,59 +705,77 @@ fn handle_readable(
                 ));
             }
 
-            // Calculate how many chain buffers we need
-            let total_needed = command_len + value_len + 2; // +2 for \r\n
-            let chain_bytes_needed = total_needed.saturating_sub(buffer_size);
-            let chain_buffers_needed = (chain_bytes_needed + buffer_size - 1) / buffer_size;
-
-            // Initialize read chain if needed
-            let chain = conn.read_chain.get_or_insert_with(|| BufferChain::new(buffer_size));
-
-            // Allocate chain buffers
-            if chain.buffer_count() < chain_buffers_needed {
-                let to_alloc = chain_buffers_needed - chain.buffer_count();
-                match buffers.alloc_many(to_alloc) {
-                    Some(indices) => {
-                        // Re-borrow conn to access chain
-                        let conn = connections.get_mut(conn_id).unwrap();
-                        if let Some(chain) = &mut conn.read_chain {
-                            for idx in indices {
-                                chain.push_buffer(idx);
-                            }
-                        }
-                    }
+            // A new large value needs one of the worker's slots
+            if conn.large_value.is_none() {
+                match large_values.acquire() {
+                    Some(slot) => conn.large_value = Some(slot),
                     None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::Other,
-                            "buffer pool exhausted for large value",
-                        ));
+                        // Its body is still arriving, so close rather than skip it
+                        let _ = conn.stream.write(large_value_busy_response(protocol));
+                        return Err(io::Error::other("out of large-value slots"));
                     }
                 }
             }
 
-            // Stay in reading state with current fill level
-            let conn = connections.get_mut(conn_id).unwrap();
-            conn.data_state = DataState::reading_with(total_filled);
+            // The input so far moves to a chain, which reads go into from
+            // now on
+            if conn.read_chain.is_none() {
+                let pending = buffers.get(read_buf_idx)[..total_filled].to_vec();
+                let mut chain = BufferChain::new(buffer_size);
+                let moved = chain.append(&pending, buffers);
+                conn.read_chain = Some(chain);
+                if moved.is_err() {
+                    return Err(io::Error::other("buffer pool exhausted for large value"));
+                }
+            }
+            conn.read_chain_wanted = (command_len + value_len).max(total_filled + 1);
+            conn.data_state = DataState::reading();
+
+            // The rest of the value may be waiting already, with no further
+            // readable edge coming
+            return handle_readable(
+                conn_id,
+                poll,
+                connections,
+                buffers,
+                storage,
+                request_config,
+                large_values,
+            );
         }
         ProcessResult::Response {
             consumed,
             response_len,
         } => {
-            // Move unconsumed data to start of read buffer if needed
-            if consumed < total_filled {
-                let read_buf = buffers.get_mut(read_buf_idx);
-                read_buf.copy_within(consumed..total_filled, 0);
-            }
-
-            // Re-borrow conn after buffer op
-            let conn = connections
-                .get_mut(conn_id)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
-
-            // Release any read chain buffers
-            if let Some(mut chain) = conn.read_chain.take() {
-                chain.release(buffers);
+            keep_unconsumed(
+                conn,
+                buffers,
+                chained_input.as_deref(),
+                consumed,
+                total_filled,
+            )?;
+            conn.assembly.completed(consumed < total_filled);
+
+            // Only silent commands: keep reading, any partial one stays
+            if response_len == 0 {
+                conn.data_state = DataState::reading_with(total_filled - consumed);
+                if chained_input.is_none() {
+                    return Ok(());
+                }
+                // A chained read may have stopped short of what the socket
+                // holds, with no further readable edge coming
+                conn.pipelined = total_filled - consumed;
+                return handle_readable(
+                    conn_id,
+                    poll,
+                    connections,
+                    buffers,
+                    storage,
+                    request_config,
+                    large_values,
+                );
             }
+            conn.pipelined = total_filled - consumed;
 
             // Transition to writing
             conn.data_state = DataState::writing(write_buf_idx, response_len);
@@ -390,33 +784,30 @@ fn handle_readable(
             poll.registry()
                 .reregister(&mut conn.stream, Token(conn_id), Interest::WRITABLE)?;
         }
-        ProcessResult::LargeResponse { consumed, response_data } => {
+        ProcessResult::LargeResponse {
+            consumed,
+            response_data,
+        } => {
             // Response is too large for single buffer - use write chain
-            // Move unconsumed data to start of read buffer if needed
-            if consumed < total_filled {
-                let read_buf = buffers.get_mut(read_buf_idx);
-                read_buf.copy_within(consumed..total_filled, 0);
-            }
-
-            // Re-borrow conn after buffer op
-            let conn = connections
-                .get_mut(conn_id)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
-
-            // Release any read chain buffers
-            if let Some(mut chain) = conn.read_chain.take() {
-                chain.release(buffers);
-            }
+            keep_unconsumed(
+                conn,
+                buffers,
+                chained_input.as_deref(),
+                consumed,
+                total_filled,
+            )?;
+            conn.assembly.completed(consumed < total_filled);
+            conn.pipelined = total_filled - consumed;
 
             // Create write chain and populate with response data
             let mut write_chain = BufferChain::new(buffer_size);
             if let Err(ChainError::PoolExhausted) = write_chain.append(&response_data, buffers) {
                 write_chain.release(buffers);
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "buffer pool exhausted for large response",
-                ));
+                return Err(io::Error::other("buffer pool exhausted for large response"));
             }
+            request_config
+                .response_chain_stats
+                .record(write_chain.buffer_count());
 
             let response_len = write_chain.len();
             conn.write_chain = Some(write_chain);
@@ -433,6 +824,47 @@ fn handle_readable(
             poll.registry()
                 .reregister(&mut conn.stream, Token(conn_id), Interest::WRITABLE)?;
         }
+        ProcessResult::StreamResponse {
+            consumed,
+            mut response,
+        } => {
+            keep_unconsumed(
+                conn,
+                buffers,
+                chained_input.as_deref(),
+                consumed,
+                total_filled,
+            )?;
+            conn.assembly.completed(consumed < total_filled);
+            conn.pipelined = total_filled - consumed;
+
+            // Written a few buffers at a time, refilled by `handle_writable`
+            let mut write_chain = BufferChain::new(buffer_size);
+            let response_len = match write_chain.refill(
+                &mut response,
+                request_config.stream_chain_buffers(),
+                buffers,
+            ) {
+                Ok(len) => len,
+                Err(e) => {
+                    write_chain.release(buffers);
+                    return Err(e);
+                }
+            };
+            request_config
+                .response_chain_stats
+                .record(write_chain.buffer_count());
+            conn.write_chain = Some(write_chain);
+            conn.write_stream = Some(response);
+
+            conn.data_state = DataState::Writing {
+                buf_idx: usize::MAX,
+                written: 0,
+                total: response_len,
+            };
+            poll.registry()
+                .reregister(&mut conn.stream, Token(conn_id), Interest::WRITABLE)?;
+        }
         ProcessResult::Quit => {
             // Client quit, close connection
             return Err(io::Error::new(
@@ -440,6 +872,21 @@ fn handle_readable(
                 "client quit",
             ));
         }
+        ProcessResult::Reject { response } => {
+            // Wrong protocol: best-effort hint, then close
+            let _ = conn.stream.write(response);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "protocol mismatch",
+            ));
+        }
+        ProcessResult::ErrorAfterResponse { response_len } => {
+            // Input can't be followed past this: best-effort reply, then close
+            let _ = conn
+                .stream
+                .write(&buffers.get(write_buf_idx)[..response_len]);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "protocol error"));
+        }
         ProcessResult::Error => {
             // Protocol error
             return Err(io::Error::new(io::ErrorKind::InvalidData, "protocol error"));
@@ -449,37 +896,105 @@ fn handle_readable(
     Ok(())
 }
 
+/// Keep input left over after a response for the next parse, releasing
+/// the chain any large value was read into.
+///
+/// Left input too long for the read buffer stays chained.
+fn keep_unconsumed(
+    conn: &mut MioConnection,
+    buffers: &mut BufferPool,
+    chained_input: Option<&[u8]>,
+    consumed: usize,
+    filled: usize,
+) -> io::Result<()> {
+    if let Some(mut chain) = conn.read_chain.take() {
+        chain.release(buffers);
+    }
+    conn.large_value = None;
+    let Some(input) = chained_input else {
+        shift_unconsumed(buffers, conn.read_buf_idx, consumed, filled);
+        return Ok(());
+    };
+
+    let rest = &input[consumed..];
+    if rest.len() <= buffers.buffer_size() {
+        buffers.get_mut(conn.read_buf_idx)[..rest.len()].copy_from_slice(rest);
+        return Ok(());
+    }
+    let mut chain = BufferChain::new(buffers.buffer_size());
+    let kept = chain.append(rest, buffers);
+    conn.read_chain = Some(chain);
+    conn.read_chain_wanted = 0;
+    kept.map_err(|_| io::Error::other("buffer pool exhausted for large input"))
+}
+
+/// Move input left over after a response to the start of the read buffer.
+///
+/// A read holding exactly the commands answered needs no move.
+fn shift_unconsumed(buffers: &mut BufferPool, read_buf_idx: usize, consumed: usize, filled: usize) {
+    if consumed < filled {
+        #[cfg(test)]
+        INPUT_SHIFTS.with(|n| n.set(n.get() + 1));
+        buffers
+            .get_mut(read_buf_idx)
+            .copy_within(consumed..filled, 0);
+    }
+}
+
+/// Flush the pending response, then go straight back to reading.
+///
+/// Input pipelined during the write may already sit in the socket (or the
+/// read buffer) with no further readable edge coming, so it is read right
+/// after the response completes rather than waiting on the next event. A
+/// streamed response is refilled and written on the same way.
 fn handle_writable(
     conn_id: usize,
     poll: &mut Poll,
     connections: &mut Slab<MioConnection>,
     buffers: &mut BufferPool,
+    storage: &Arc<Storage>,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
 ) -> io::Result<()> {
-    let conn = connections
-        .get_mut(conn_id)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
-
-    let (write_buf_idx, written, total) = match conn.data_state {
-        DataState::Writing {
-            buf_idx,
-            written,
-            total,
-        } => (buf_idx, written, total),
-        _ => return Ok(()), // Not in writing state
-    };
-
-    // Check if we're writing from a chain (buf_idx == usize::MAX) or single buffer
-    let n = if write_buf_idx == usize::MAX {
-        // Chain write using writev
-        let chain = conn.write_chain.as_ref().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidData, "missing write chain")
-        })?;
+    loop {
+        let conn = connections
+            .get_mut(conn_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
+
+        let (write_buf_idx, written, total) = match conn.data_state {
+            DataState::Writing {
+                buf_idx,
+                written,
+                total,
+            } => (buf_idx, written, total),
+            _ => return Ok(()), // Not in writing state
+        };
 
-        let io_slices = chain.io_slices(buffers, written);
-        if io_slices.is_empty() {
-            0
+        // Check if we're writing from a chain (buf_idx == usize::MAX) or single buffer
+        let n = if write_buf_idx == usize::MAX {
+            // Chain write using writev
+            let chain = conn
+                .write_chain
+                .as_ref()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing write chain"))?;
+
+            let io_slices = chain.io_slices(buffers, written);
+            if io_slices.is_empty() {
+                0
+            } else {
+                match conn.stream.write_vectored(&io_slices) {
+                    Ok(0) => {
+                        return Err(io::Error::new(io::ErrorKind::WriteZero, "write returned 0"));
+                    }
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) => return Err(e),
+                }
+            }
         } else {
-            match conn.stream.write_vectored(&io_slices) {
+            // Single buffer write
+            let buf = buffers.get(write_buf_idx);
+            match conn.stream.write(&buf[written..total]) {
                 Ok(0) => {
                     return Err(io::Error::new(io::ErrorKind::WriteZero, "write returned 0"));
                 }
@@ -487,46 +1002,62 @@ fn handle_writable(
                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
                 Err(e) => return Err(e),
             }
+        };
+
+        // Re-borrow after buffer access
+        let conn = connections
+            .get_mut(conn_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
+
+        conn.last_activity = Instant::now();
+        let new_written = written + n;
+        if new_written < total {
+            // Partial write, continue
+            conn.data_state = DataState::Writing {
+                buf_idx: write_buf_idx,
+                written: new_written,
+                total,
+            };
+            return Ok(());
         }
-    } else {
-        // Single buffer write
-        let buf = buffers.get(write_buf_idx);
-        match conn.stream.write(&buf[written..total]) {
-            Ok(0) => {
-                return Err(io::Error::new(io::ErrorKind::WriteZero, "write returned 0"));
+
+        // The chain went out; a streamed response refills it with the
+        // next buffers' worth, in the same buffers' place
+        if let (Some(stream), Some(chain)) = (&mut conn.write_stream, &mut conn.write_chain) {
+            let len = chain.refill(stream, request_config.stream_chain_buffers(), buffers)?;
+            if len > 0 {
+                request_config
+                    .response_chain_stats
+                    .record(chain.buffer_count());
+                conn.data_state = DataState::Writing {
+                    buf_idx: usize::MAX,
+                    written: 0,
+                    total: len,
+                };
+                continue;
             }
-            Ok(n) => n,
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
-            Err(e) => return Err(e),
         }
-    };
-
-    // Re-borrow after buffer access
-    let conn = connections
-        .get_mut(conn_id)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
+        conn.write_stream = None;
 
-    let new_written = written + n;
-    if new_written >= total {
         // Write complete - release chain if used
         if let Some(mut chain) = conn.write_chain.take() {
             chain.release(buffers);
         }
 
-        // Go back to reading
-        conn.data_state = DataState::reading();
+        // Go back to reading, keeping input that arrived with the request
+        conn.data_state = DataState::reading_with(conn.pipelined);
         poll.registry()
             .reregister(&mut conn.stream, Token(conn_id), Interest::READABLE)?;
-    } else {
-        // Partial write, continue
-        conn.data_state = DataState::Writing {
-            buf_idx: write_buf_idx,
-            written: new_written,
-            total,
-        };
+        return handle_readable(
+            conn_id,
+            poll,
+            connections,
+            buffers,
+            storage,
+            request_config,
+            large_values,
+        );
     }
-
-    Ok(())
 }
 
 fn close_connection(
@@ -552,28 +1083,673 @@ fn close_connection(
     }
 }
 
-/// Create a TCP listener with SO_REUSEPORT for kernel load balancing.
-fn create_listener_with_reuseport(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
-    let socket = socket2::Socket::new(
-        match addr {
-            SocketAddr::V4(_) => socket2::Domain::IPV4,
-            SocketAddr::V6(_) => socket2::Domain::IPV6,
-        },
-        socket2::Type::STREAM,
-        Some(socket2::Protocol::TCP),
-    )?;
-
-    socket.set_reuse_port(true)?;
-    socket.set_reuse_address(true)?;
-    socket.set_nonblocking(true)?;
-    socket.bind(&addr.into())?;
-    socket.listen(1024)?;
-
-    Ok(socket.into())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CliArgs, NodelayByProtocol, ProtocolType};
+    use clap::Parser;
+    use mio::net::{TcpListener, TcpStream};
+
+    /// Register the server side of a fresh loopback connection.
+    fn connect(
+        poll: &Poll,
+        connections: &mut Slab<MioConnection>,
+        buffers: &mut BufferPool,
+    ) -> (usize, std::net::TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        server.set_nonblocking(true).unwrap();
+
+        let conn_id = connections.insert(MioConnection {
+            stream: Stream::Tcp(TcpStream::from_std(server)),
+            data_state: DataState::reading(),
+            read_buf_idx: buffers.alloc().unwrap(),
+            write_buf_idx: buffers.alloc().unwrap(),
+            read_chain: None,
+            read_chain_wanted: 0,
+            write_chain: None,
+            write_stream: None,
+            large_value: None,
+            pipelined: 0,
+            protocol: Protocol::Memcached,
+            quota_permit: None,
+            open: None,
+            session: Session::default(),
+            assembly: CommandAssembly::default(),
+            last_activity: Instant::now(),
+        });
+        let conn = &mut connections[conn_id];
+        poll.registry()
+            .register(&mut conn.stream, Token(conn_id), Interest::READABLE)
+            .unwrap();
+        (conn_id, client)
+    }
 
-fn num_cpus() -> usize {
-    std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(1)
+    /// Dispatch the next event for `conn_id` that is readable and writable at
+    /// once, registering it for both to get one.
+    fn dispatch_combined_event(
+        poll: &mut Poll,
+        connections: &mut Slab<MioConnection>,
+        buffers: &mut BufferPool,
+        storage: &Arc<Storage>,
+        conn_id: usize,
+    ) -> io::Result<()> {
+        poll.registry()
+            .reregister(
+                &mut connections[conn_id].stream,
+                Token(conn_id),
+                Interest::READABLE | Interest::WRITABLE,
+            )
+            .unwrap();
+        let mut events = Events::with_capacity(8);
+        for _ in 0..100 {
+            poll.poll(&mut events, Some(std::time::Duration::from_millis(10)))
+                .unwrap();
+            let combined = events.iter().find(|event| {
+                event.token() == Token(conn_id) && event.is_readable() && event.is_writable()
+            });
+            if let Some(event) = combined {
+                return handle_connection_event(
+                    conn_id,
+                    event,
+                    poll,
+                    connections,
+                    buffers,
+                    storage,
+                    &RequestConfig::default(),
+                    &LargeValueSlots::new(0),
+                );
+            }
+        }
+        panic!("no readable and writable event");
+    }
+
+    /// Feed `request` to a new connection and return the shifts it caused.
+    fn shifts_for(request: &[u8]) -> usize {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig::default();
+
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+        client.write_all(request).unwrap();
+
+        let before = INPUT_SHIFTS.with(|n| n.get());
+        // The bytes may not be readable yet; retry until a response is built
+        for _ in 0..1000 {
+            handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &LargeValueSlots::new(0),
+            )
+            .unwrap();
+            if matches!(connections[conn_id].data_state, DataState::Writing { .. }) {
+                return INPUT_SHIFTS.with(|n| n.get()) - before;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("no response was produced");
+    }
+
+    #[test]
+    fn test_single_command_read_is_not_copied() {
+        assert_eq!(shifts_for(b"get k\r\n"), 0);
+
+        // Commands in one read are answered together, leaving nothing to move
+        assert_eq!(shifts_for(b"get a\r\nget b\r\n"), 0);
+        // A partial command behind them must survive for the next read
+        assert_eq!(shifts_for(b"get k\r\nget"), 1);
+    }
+
+    #[test]
+    fn test_nodelay_follows_protocol() {
+        let nodelay = NodelayByProtocol::default();
+        for (protocol, config_protocol, expected) in [
+            (Protocol::Memcached, ProtocolType::Memcached, true),
+            (Protocol::Echo, ProtocolType::Echo, false),
+        ] {
+            let mut poll = Poll::new().unwrap();
+            let mut connections = Slab::new();
+            let mut buffers = BufferPool::new(4, 4096);
+            let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let _client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let listener = Listener::Tcp(listener);
+
+            // The connection may not be queued for accept yet
+            for _ in 0..1000 {
+                accept_connections(
+                    &listener,
+                    &mut poll,
+                    &mut connections,
+                    &mut buffers,
+                    16,
+                    0,
+                    protocol,
+                    None,
+                    nodelay.get(config_protocol),
+                    &Arc::default(),
+                )
+                .unwrap();
+                if !connections.is_empty() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+
+            let (_, conn) = connections.iter().next().expect("never accepted");
+            assert_eq!(conn.stream.nodelay().unwrap(), expected, "{protocol:?}");
+        }
+    }
+
+    #[test]
+    fn test_input_pipelined_during_write_is_served() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("k", b"v".to_vec(), 0, 0);
+        let config = RequestConfig::default();
+        let slots = LargeValueSlots::new(0);
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+
+        // Two commands answered as one batch, then the start of a third
+        client.write_all(b"get k\r\nget x\r\nget").unwrap();
+        for _ in 0..1000 {
+            handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &slots,
+            )
+            .unwrap();
+            if matches!(connections[conn_id].data_state, DataState::Writing { .. }) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        // The rest arrives while the server is writing, with no readable
+        // event to follow
+        client.write_all(b" k\r\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        // Each completed write goes on to the next buffered command
+        for _ in 0..2 {
+            assert!(matches!(
+                connections[conn_id].data_state,
+                DataState::Writing { .. }
+            ));
+            handle_writable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &slots,
+            )
+            .unwrap();
+        }
+        assert!(matches!(
+            connections[conn_id].data_state,
+            DataState::Reading { filled: 0 }
+        ));
+
+        let expected: &[u8] = b"VALUE k 0 1\r\nv\r\nEND\r\nEND\r\nVALUE k 0 1\r\nv\r\nEND\r\n";
+        let mut reply = vec![0u8; expected.len()];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn test_auto_protocol_follows_first_bytes() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("k", b"v".to_vec(), 0, 0);
+        let config = RequestConfig::default();
+        let slots = LargeValueSlots::new(0);
+
+        for (request, protocol, expected) in [
+            (
+                &b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"[..],
+                Protocol::Resp,
+                &b"$1\r\nv\r\n"[..],
+            ),
+            (
+                b"get k\r\n",
+                Protocol::Memcached,
+                b"VALUE k 0 1\r\nv\r\nEND\r\n",
+            ),
+        ] {
+            let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+            connections[conn_id].protocol = Protocol::Auto;
+            client.write_all(request).unwrap();
+            for _ in 0..1000 {
+                handle_readable(
+                    conn_id,
+                    &mut poll,
+                    &mut connections,
+                    &mut buffers,
+                    &storage,
+                    &config,
+                    &slots,
+                )
+                .unwrap();
+                if matches!(connections[conn_id].data_state, DataState::Writing { .. }) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            assert_eq!(connections[conn_id].protocol, protocol);
+            handle_writable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &slots,
+            )
+            .unwrap();
+
+            let mut reply = vec![0u8; expected.len()];
+            client.read_exact(&mut reply).unwrap();
+            assert_eq!(reply, expected);
+        }
+    }
+
+    #[test]
+    fn test_connection_slab_never_reallocates() {
+        let mut poll = Poll::new().unwrap();
+        let mut buffers = BufferPool::new(64, 1024);
+        let max_connections = 8;
+        let mut connections = connection_slab(max_connections);
+        let capacity = connections.capacity();
+        assert!(capacity >= max_connections);
+
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = Listener::Tcp(listener);
+        let accept_burst = |poll: &mut Poll,
+                            connections: &mut Slab<MioConnection>,
+                            buffers: &mut BufferPool,
+                            count: usize| {
+            let clients: Vec<_> = (0..count)
+                .map(|_| std::net::TcpStream::connect(addr).unwrap())
+                .collect();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            accept_connections(
+                &listener,
+                poll,
+                connections,
+                buffers,
+                max_connections,
+                0,
+                Protocol::Memcached,
+                None,
+                true,
+                &Arc::default(),
+            )
+            .unwrap();
+            clients
+        };
+
+        // More than fit: the table fills and the rest are refused
+        let _clients = accept_burst(&mut poll, &mut connections, &mut buffers, 12);
+        assert_eq!(connections.len(), max_connections);
+        assert_eq!(connections.capacity(), capacity);
+
+        // Churn reuses freed slots
+        for conn_id in [2, 5] {
+            close_connection(&mut poll, &mut connections, &mut buffers, conn_id);
+        }
+        let _more = accept_burst(&mut poll, &mut connections, &mut buffers, 4);
+        assert_eq!(connections.len(), max_connections);
+        assert_eq!(connections.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_combined_readable_writable_event() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("k", b"v".to_vec(), 0, 0);
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+
+        // Answered once, flushed in the same event, then back to reading
+        client.write_all(b"get k\r\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        dispatch_combined_event(&mut poll, &mut connections, &mut buffers, &storage, conn_id)
+            .unwrap();
+        assert!(matches!(
+            connections[conn_id].data_state,
+            DataState::Reading { filled: 0 }
+        ));
+        let expected = b"VALUE k 0 1\r\nv\r\nEND\r\n";
+        let mut reply = [0u8; 21];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, expected);
+        client.set_nonblocking(true).unwrap();
+        let extra = client.read(&mut reply);
+        assert_eq!(extra.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(storage.stats().get_hits, 1);
+
+        // A close from the readable half ends the event there
+        client.write_all(b"quit\r\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let err =
+            dispatch_combined_event(&mut poll, &mut connections, &mut buffers, &storage, conn_id)
+                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        assert!(matches!(
+            connections[conn_id].data_state,
+            DataState::Reading { .. }
+        ));
+        close_connection(&mut poll, &mut connections, &mut buffers, conn_id);
+        assert!(!connections.contains(conn_id));
+        assert_eq!(buffers.available(), 4);
+    }
+
+    #[test]
+    fn test_oversized_multibulk_header_closes_connection() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig::default();
+
+        for header in [
+            &b"*10000000\r\n"[..],
+            b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1000000000\r\n",
+        ] {
+            let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+            connections[conn_id].protocol = Protocol::Resp;
+            // Only the header is sent; none of the body it announces
+            client.write_all(header).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+
+            let err = handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &LargeValueSlots::new(0),
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_slow_command_assembly_closes_connection() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            command_assembly_timeout: Some(std::time::Duration::from_millis(50)),
+            ..RequestConfig::default()
+        };
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+
+        let mut dribble = |bytes: &[u8], pause_ms: u64| {
+            client.write_all(bytes).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(pause_ms));
+            handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &LargeValueSlots::new(0),
+            )
+        };
+
+        assert!(dribble(b"g", 10).is_ok());
+        assert!(dribble(b"e", 10).is_ok());
+        let err = dribble(b"t", 60).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_crlf_split_at_buffer_boundary() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 64);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            max_value_size: 1024,
+            ..RequestConfig::default()
+        };
+        let slots = LargeValueSlots::new(0);
+
+        // A set filling the buffer exactly, its final \n in a read of its own
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+        let mut request = b"set k 0 0 48\r\n".to_vec();
+        request.extend_from_slice(&[b'v'; 48]);
+        request.extend_from_slice(b"\r\n");
+        assert_eq!(request.len(), 64);
+        for part in [&request[..63], &request[63..]] {
+            client.write_all(part).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &slots,
+            )
+            .unwrap();
+        }
+        handle_writable(
+            conn_id,
+            &mut poll,
+            &mut connections,
+            &mut buffers,
+            &storage,
+            &config,
+            &slots,
+        )
+        .unwrap();
+        let mut reply = [0u8; 8];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"STORED\r\n");
+        assert_eq!(storage.get("k").unwrap().value, [b'v'; 48]);
+
+        // One byte longer, the \r is the last byte the buffer holds: the
+        // command can never complete, which is an error rather than an EOF
+        let (conn_id, mut client) = connect(&poll, &mut connections, &mut buffers);
+        let mut request = b"set k 0 0 49\r\n".to_vec();
+        request.extend_from_slice(&[b'v'; 49]);
+        request.push(b'\r');
+        assert_eq!(request.len(), 64);
+        client.write_all(&request).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let err = handle_readable(
+            conn_id,
+            &mut poll,
+            &mut connections,
+            &mut buffers,
+            &storage,
+            &config,
+            &slots,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let stats = &config.connection_stats;
+        assert_eq!(stats.clean_disconnects.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_large_values_beyond_limit_are_refused() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(16, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig {
+            max_value_size: 1024 * 1024,
+            ..RequestConfig::default()
+        };
+        let slots = LargeValueSlots::new(1);
+        let (a, mut client_a) = connect(&poll, &mut connections, &mut buffers);
+        let (b, mut client_b) = connect(&poll, &mut connections, &mut buffers);
+        let (c, mut client_c) = connect(&poll, &mut connections, &mut buffers);
+        let (d, mut client_d) = connect(&poll, &mut connections, &mut buffers);
+
+        // Returns whether a response is ready, or the connection's error
+        let mut send = |conn_id: usize, client: &mut std::net::TcpStream, bytes: &[u8]| {
+            client.write_all(bytes).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle_readable(
+                conn_id,
+                &mut poll,
+                &mut connections,
+                &mut buffers,
+                &storage,
+                &config,
+                &slots,
+            )
+            .map(|()| matches!(connections[conn_id].data_state, DataState::Writing { .. }))
+        };
+
+        // The first large set takes the only slot
+        assert!(!send(a, &mut client_a, b"set a 0 0 10000\r\n").unwrap());
+        assert_eq!(slots.active(), 1);
+
+        // A second is refused with a temporary error
+        assert!(send(b, &mut client_b, b"set b 0 0 10000\r\n").is_err());
+        let mut reply = [0u8; 64];
+        let n = client_b.read(&mut reply).unwrap();
+        assert_eq!(
+            &reply[..n],
+            b"SERVER_ERROR temporarily out of large buffers\r\n"
+        );
+
+        // Small requests are unaffected
+        assert!(send(c, &mut client_c, b"set c 0 0 1\r\nv\r\n").unwrap());
+
+        // The first completes, read into its chain, which frees its slot
+        let mut value = vec![b'v'; 10000];
+        value.extend_from_slice(b"\r\n");
+        assert!(send(a, &mut client_a, &value).unwrap());
+        assert_eq!(slots.active(), 0);
+        assert_eq!(storage.get("a").unwrap().value, [b'v'; 10000]);
+
+        // Closing the connection mid-value frees its slot
+        assert!(!send(d, &mut client_d, b"set d 0 0 10000\r\n").unwrap());
+        assert_eq!(slots.active(), 1);
+        close_connection(&mut poll, &mut connections, &mut buffers, d);
+        assert_eq!(slots.active(), 0);
+    }
+
+    #[test]
+    fn test_idle_connection_is_closed() {
+        let mut poll = Poll::new().unwrap();
+        let mut connections = Slab::new();
+        let mut buffers = BufferPool::new(4, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let config = RequestConfig::default();
+        let slots = LargeValueSlots::new(0);
+        let idle_timeout = Duration::from_millis(50);
+        let (idle, mut idle_client) = connect(&poll, &mut connections, &mut buffers);
+        let (active, mut active_client) = connect(&poll, &mut connections, &mut buffers);
+
+        // Both start out active
+        close_idle_connections(
+            &mut poll,
+            &mut connections,
+            &mut buffers,
+            idle_timeout,
+            &config,
+        );
+        assert_eq!(connections.len(), 2);
+
+        std::thread::sleep(Duration::from_millis(60));
+        active_client.write_all(b"version\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        handle_readable(
+            active,
+            &mut poll,
+            &mut connections,
+            &mut buffers,
+            &storage,
+            &config,
+            &slots,
+        )
+        .unwrap();
+
+        close_idle_connections(
+            &mut poll,
+            &mut connections,
+            &mut buffers,
+            idle_timeout,
+            &config,
+        );
+        assert!(!connections.contains(idle));
+        assert!(connections.contains(active));
+        assert_eq!(buffers.available(), 2);
+        assert_eq!(idle_client.read(&mut [0u8; 1]).unwrap(), 0);
+        let stats = config.connection_stats.counts();
+        assert_eq!(stats.idle_kicks, 1);
+        assert_eq!(stats.read_errors, 0);
+    }
+
+    #[test]
+    fn test_run_returns_after_shutdown() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let listen = format!("127.0.0.1:{port}");
+        let cli = CliArgs::parse_from(["grow-a-cache", "--listen", &listen, "--workers", "1"]);
+        let mut config = Config::from_cli(cli).unwrap();
+        config.max_connections = 16;
+        let readiness = Readiness::new(1);
+        let shutdown = Shutdown::new();
+
+        let server = {
+            let readiness = Arc::clone(&readiness);
+            let shutdown = shutdown.clone();
+            let storage = Storage::new(1024 * 1024, 0);
+            std::thread::spawn(move || {
+                run(
+                    config,
+                    storage,
+                    Protocol::Memcached,
+                    readiness,
+                    shutdown,
+                    Arc::default(),
+                )
+            })
+        };
+        while !readiness.is_ready() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mut client = std::net::TcpStream::connect(&listen).unwrap();
+        client.write_all(b"version\r\n").unwrap();
+        assert!(client.read(&mut [0u8; 64]).unwrap() > 0);
+
+        // The idle connection is closed, and the worker with it
+        shutdown.trigger();
+        assert_eq!(client.read(&mut [0u8; 1]).unwrap(), 0);
+        server.join().unwrap().unwrap();
+    }
 }
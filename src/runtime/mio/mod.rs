@@ -4,13 +4,30 @@
 //! This module can be used on both Linux and macOS for comparison.
 
 mod event_loop;
+mod socket;
 
+use crate::admin::Readiness;
 use crate::config::Config;
-use crate::runtime::Protocol;
+use crate::request::ConnectionStats;
+use crate::runtime::{Protocol, Shutdown};
 use crate::storage::Storage;
 use std::sync::Arc;
 
 /// Run the server using mio backend.
-pub fn run(config: Config, storage: Arc<Storage>, protocol: Protocol) -> std::io::Result<()> {
-    event_loop::run(config, storage, protocol)
+pub fn run(
+    config: Config,
+    storage: Arc<Storage>,
+    protocol: Protocol,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
+    connection_stats: Arc<ConnectionStats>,
+) -> std::io::Result<()> {
+    event_loop::run(
+        config,
+        storage,
+        protocol,
+        readiness,
+        shutdown,
+        connection_stats,
+    )
 }
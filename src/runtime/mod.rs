@@ -6,17 +6,40 @@
 //!
 //! Shared abstractions:
 //! - `BufferPool`: Per-worker buffer management
+//! - `LargeValueSlots`: Per-worker cap on values accumulating in buffer chains
 //! - `Connection`: Connection state machine with control/data plane separation
 //! - `ConnPhase`: Control plane state (Accepting, Handshaking, Established, Closing)
 //! - `DataState`: Data plane state (Reading, Writing)
+//! - `ConnectionQuota`: Per-IP connection limit shared across workers
+//! - `Shutdown`: SIGTERM/SIGINT trigger that drains and stops the workers
+//! - `spawn_sweeper`: background thread reclaiming expired items
+//! - `bind_with_retry`: SO_REUSEPORT listener setup with bounded bind retry
+//! - `UnixSocketListener`: shared Unix domain socket listener owning its file
+//! - `spawn_worker`: worker thread startup with best-effort CPU pinning
 
 mod buffer;
 mod connection;
+mod expiry;
+mod listener;
+mod quota;
+mod shutdown;
 
 // Re-export shared types for use by platform-specific implementations
-pub(crate) use buffer::{BufferChain, BufferPool, ChainError};
-pub(crate) use connection::{ConnPhase, Connection, ConnectionRegistry, DataState};
 pub(crate) use crate::request::{ProcessResult, Protocol};
+pub(crate) use buffer::{
+    large_value_busy_response, BufferChain, BufferPool, ChainError, LargeValueSlot, LargeValueSlots,
+};
+pub(crate) use connection::{
+    CommandAssembly, ConnPhase, Connection, ConnectionRegistry, DataState,
+};
+pub(crate) use expiry::spawn_sweeper;
+#[cfg(target_os = "linux")]
+pub(crate) use listener::set_nodelay;
+pub(crate) use listener::{bind_with_retry, UnixSocketListener};
+#[cfg(target_os = "linux")]
+pub(crate) use quota::peer_addr;
+pub(crate) use quota::{quota_exceeded_response, ConnectionQuota, QuotaPermit};
+pub(crate) use shutdown::Shutdown;
 
 // io_uring backend (Linux only)
 #[cfg(target_os = "linux")]
@@ -30,8 +53,15 @@ pub(crate) use uring::{OpType, TokenAllocator};
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 mod mio;
 
+use crate::admin::{self, Readiness};
 use crate::config::{Config, ProtocolType};
+use crate::request::ConnectionStats;
 use crate::storage::Storage;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, warn};
 
 /// Map config protocol to runtime protocol.
 fn map_protocol(config_protocol: ProtocolType) -> Protocol {
@@ -40,15 +70,181 @@ fn map_protocol(config_protocol: ProtocolType) -> Protocol {
         ProtocolType::Resp => Protocol::Resp,
         ProtocolType::Ping => Protocol::Ping,
         ProtocolType::Echo => Protocol::Echo,
+        ProtocolType::Auto => Protocol::Auto,
+    }
+}
+
+/// Thread builder for a worker, applying the configured stack size.
+pub(crate) fn worker_thread(config: &Config, worker_id: usize) -> thread::Builder {
+    let builder = thread::Builder::new().name(format!("worker-{worker_id}"));
+    match config.worker_stack_size {
+        Some(size) => builder.stack_size(size),
+        None => builder,
+    }
+}
+
+/// Spawn a worker thread running `body`, pinned to a CPU if configured.
+///
+/// Naming and pinning are best-effort: a sandbox that refuses either gets an
+/// unnamed or unpinned worker (with a warning for pinning), not a failed
+/// startup. The OS thread name is already set this way by the standard
+/// library, which ignores `pthread_setname_np` errors.
+pub(crate) fn spawn_worker<F>(
+    config: &Config,
+    worker_id: usize,
+    body: F,
+) -> io::Result<JoinHandle<()>>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let pin = config.pin_workers;
+    worker_thread(config, worker_id).spawn(move || {
+        if pin {
+            if let Err(e) = pin_to_cpu(worker_id) {
+                warn!(worker = worker_id, error = %e, "Could not pin worker to a CPU, running unpinned");
+            }
+        }
+        body()
+    })
+}
+
+/// Makes `pin_to_cpu` fail, as under a seccomp profile that denies it.
+#[cfg(test)]
+static DENY_AFFINITY: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Restrict the calling thread to CPU `worker_id` (mod the CPU count).
+#[cfg(target_os = "linux")]
+fn pin_to_cpu(worker_id: usize) -> io::Result<()> {
+    #[cfg(test)]
+    if DENY_AFFINITY.load(std::sync::atomic::Ordering::SeqCst) {
+        return Err(io::Error::from_raw_os_error(libc::EPERM));
+    }
+
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    // SAFETY: the set is zero-initialized and sized for the call
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(worker_id % cpus, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_cpu(_worker_id: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "CPU pinning is only available on Linux",
+    ))
+}
+
+/// Shared startup for both backends.
+///
+/// Starts the admin endpoint (if configured) before loading the startup
+/// snapshot, so probes see "not ready" for the whole warmup. The returned
+/// gate still waits for every worker to enter its event loop.
+fn prepare(
+    config: &Config,
+    connection_stats: &Arc<ConnectionStats>,
+) -> io::Result<(Arc<Storage>, Arc<Readiness>)> {
+    let storage = Storage::with_limits(
+        config.max_memory,
+        config.max_value_bytes,
+        config.default_ttl,
+        config.eviction_policy,
+    );
+    let readiness = Readiness::new(config.worker_count() + 1);
+
+    if let Some(addr) = config.admin_listen {
+        admin::spawn(
+            addr,
+            Arc::clone(&readiness),
+            Arc::clone(&storage),
+            Arc::clone(connection_stats),
+        )?;
+    }
+
+    if let Some(ref path) = config.snapshot_path {
+        match storage.load_snapshot(path, config.snapshot_format) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!(path = %path.display(), "No snapshot found, starting empty");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    storage.set_tombstone_ttl(Duration::from_secs(config.tombstone_ttl_secs));
+    storage.set_recache_lease(Duration::from_millis(config.recache_lease_ms));
+    storage.set_hotkey_tracking(config.hotkey_tracking);
+    // After the snapshot, which loads regardless
+    storage.set_read_only(config.read_only);
+    readiness.count_down();
+
+    Ok((storage, readiness))
+}
+
+/// A backend's `run`: serves until its workers return.
+type Backend = fn(
+    Config,
+    Arc<Storage>,
+    Protocol,
+    Arc<Readiness>,
+    Shutdown,
+    Arc<ConnectionStats>,
+) -> io::Result<()>;
+
+/// Run a backend's workers on prepared storage, with the expiry sweeper
+/// alongside until they return.
+///
+/// After a graceful shutdown, saves the snapshot for the next start.
+fn serve(config: Config, backend: Backend) -> io::Result<()> {
+    let connection_stats = Arc::<ConnectionStats>::default();
+    let (storage, readiness) = prepare(&config, &connection_stats)?;
+    let protocol = map_protocol(config.protocol);
+    let shutdown = Shutdown::on_signals();
+    let sweeper = match config.cleanup_interval {
+        0 => None,
+        secs => Some(spawn_sweeper(
+            Arc::clone(&storage),
+            Duration::from_secs(secs),
+            shutdown.clone(),
+        )?),
+    };
+
+    let snapshot = config
+        .snapshot_path
+        .clone()
+        .map(|path| (path, config.snapshot_format));
+
+    let result = backend(
+        config,
+        Arc::clone(&storage),
+        protocol,
+        readiness,
+        shutdown.clone(),
+        connection_stats,
+    );
+    // Workers may also return on an error, so stop the sweeper either way
+    shutdown.trigger();
+    if let Some(sweeper) = sweeper {
+        let _ = sweeper.join();
     }
+    result?;
+
+    if let Some((path, format)) = snapshot {
+        storage.save_snapshot(&path, format)?;
+    }
+    Ok(())
 }
 
 /// Run the server with io_uring backend (Linux only).
+///
+/// Returns once `SIGTERM` or `SIGINT` has drained every worker.
 #[cfg(target_os = "linux")]
 pub fn run_uring(config: Config) -> std::io::Result<()> {
-    let storage = Storage::new(config.max_memory, config.default_ttl);
-    let protocol = map_protocol(config.protocol);
-    uring::run(config, storage, protocol)
+    serve(config, uring::run)
 }
 
 #[cfg(not(target_os = "linux"))]
@@ -61,11 +257,11 @@ pub fn run_uring(_config: Config) -> std::io::Result<()> {
 
 /// Run the server with mio backend (epoll on Linux, kqueue on macOS).
 /// This allows comparison with io_uring on Linux.
+///
+/// Returns once `SIGTERM` or `SIGINT` has drained every worker.
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn run_mio(config: Config) -> std::io::Result<()> {
-    let storage = Storage::new(config.max_memory, config.default_ttl);
-    let protocol = map_protocol(config.protocol);
-    mio::run(config, storage, protocol)
+    serve(config, mio::run)
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "macos")))]
@@ -75,3 +271,53 @@ pub fn run_mio(_config: Config) -> std::io::Result<()> {
         "Unsupported platform: only Linux and macOS are supported",
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CliArgs, MIN_WORKER_STACK_SIZE};
+    use clap::Parser;
+
+    #[test]
+    fn test_worker_with_custom_stack_size() {
+        let cli = CliArgs::parse_from(["grow-a-cache", "--worker-stack-size", "8388608"]);
+        let config = Config::from_cli(cli).unwrap();
+        assert!(config.validate().is_ok());
+
+        // A 3MB stack frame overflows the 2MB default but fits in 8MB
+        let handle = worker_thread(&config, 7)
+            .spawn(|| {
+                let mut buf = [0u8; 3 * 1024 * 1024];
+                buf[buf.len() - 1] = 1;
+                let buf = std::hint::black_box(&buf);
+                (thread::current().name().map(String::from), buf.len())
+            })
+            .unwrap();
+        let (name, len) = handle.join().unwrap();
+        assert_eq!(name.as_deref(), Some("worker-7"));
+        assert_eq!(len, 3 * 1024 * 1024);
+
+        let cli = CliArgs::parse_from(["grow-a-cache", "--worker-stack-size", "4096"]);
+        let config = Config::from_cli(cli).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains(&format!("minimum of {MIN_WORKER_STACK_SIZE}")));
+    }
+
+    #[test]
+    fn test_worker_starts_when_pinning_is_denied() {
+        let cli = CliArgs::parse_from(["grow-a-cache"]);
+        let mut config = Config::from_cli(cli).unwrap();
+        config.pin_workers = true;
+
+        DENY_AFFINITY.store(true, std::sync::atomic::Ordering::SeqCst);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = spawn_worker(&config, 3, move || {
+            tx.send(thread::current().name().map(String::from)).unwrap();
+        });
+
+        // The worker still ran its body, under its name
+        handle.unwrap().join().unwrap();
+        DENY_AFFINITY.store(false, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(rx.recv().unwrap().as_deref(), Some("worker-3"));
+    }
+}
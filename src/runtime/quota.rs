@@ -0,0 +1,189 @@
+//! Per-IP connection quota shared across workers.
+//!
+//! Each accepted connection takes a `QuotaPermit` for its peer IP. The permit
+//! is stored on the connection and releases its slot when dropped, so every
+//! close path decrements the counter without extra bookkeeping.
+
+use crate::request::Protocol;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::io::{BorrowedFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+/// Shared per-IP connection counter.
+pub struct ConnectionQuota {
+    /// Active connection count per peer IP.
+    counts: Mutex<HashMap<IpAddr, usize>>,
+    /// Maximum connections allowed from a single IP.
+    max_per_ip: usize,
+}
+
+impl ConnectionQuota {
+    /// Create a quota allowing `max_per_ip` connections from each peer IP.
+    pub fn new(max_per_ip: usize) -> Arc<Self> {
+        Arc::new(Self {
+            counts: Mutex::new(HashMap::new()),
+            max_per_ip,
+        })
+    }
+
+    /// Create a quota only if a limit is configured (0 = unlimited).
+    pub fn from_limit(max_per_ip: usize) -> Option<Arc<Self>> {
+        (max_per_ip > 0).then(|| Self::new(max_per_ip))
+    }
+
+    /// Try to take a connection slot for `ip`.
+    ///
+    /// Returns `None` if the IP is already at its quota.
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<QuotaPermit> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(ip).or_insert(0);
+        if *count >= self.max_per_ip {
+            return None;
+        }
+        *count += 1;
+        Some(QuotaPermit {
+            quota: Arc::clone(self),
+            ip,
+        })
+    }
+
+    /// Number of active connections from `ip`.
+    #[cfg(test)]
+    pub fn active(&self, ip: IpAddr) -> usize {
+        self.counts.lock().unwrap().get(&ip).copied().unwrap_or(0)
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&ip);
+            }
+        }
+    }
+}
+
+/// A connection slot held against a peer IP's quota.
+///
+/// Dropping the permit returns the slot.
+pub struct QuotaPermit {
+    quota: Arc<ConnectionQuota>,
+    ip: IpAddr,
+}
+
+impl fmt::Debug for QuotaPermit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaPermit").field("ip", &self.ip).finish()
+    }
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        self.quota.release(self.ip);
+    }
+}
+
+/// Error sent to a client rejected for exceeding its per-IP quota.
+pub fn quota_exceeded_response(protocol: Protocol) -> &'static [u8] {
+    match protocol {
+        Protocol::Memcached => b"SERVER_ERROR too many connections from your address\r\n",
+        Protocol::Resp => b"-ERR too many connections from your address\r\n",
+        Protocol::Ping | Protocol::Echo | Protocol::Auto => {
+            b"ERROR too many connections from your address\r\n"
+        }
+    }
+}
+
+/// Look up the peer address of a connected socket (`None` for a Unix
+/// socket).
+#[allow(dead_code)] // Only used by the io_uring accept path (Linux)
+pub fn peer_addr(fd: RawFd) -> Option<SocketAddr> {
+    // SAFETY: the caller owns `fd` for the duration of this call.
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    socket2::SockRef::from(&fd)
+        .peer_addr()
+        .ok()
+        .and_then(|addr| addr.as_socket())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn test_quota_caps_per_ip() {
+        let quota = ConnectionQuota::new(2);
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        let p1 = quota.try_acquire(a).unwrap();
+        let _p2 = quota.try_acquire(a).unwrap();
+        assert!(quota.try_acquire(a).is_none());
+
+        // Another IP is unaffected
+        let _p3 = quota.try_acquire(b).unwrap();
+        assert_eq!(quota.active(b), 1);
+
+        // Dropping a permit frees a slot
+        drop(p1);
+        assert_eq!(quota.active(a), 1);
+        assert!(quota.try_acquire(a).is_some());
+    }
+
+    #[test]
+    fn test_from_limit_zero_is_unlimited() {
+        assert!(ConnectionQuota::from_limit(0).is_none());
+        assert!(ConnectionQuota::from_limit(5).is_some());
+    }
+
+    #[test]
+    fn test_quota_on_loopback_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let quota = ConnectionQuota::new(3);
+
+        let mut clients = Vec::new();
+        let mut permits = Vec::new();
+        let mut rejected = 0;
+        for _ in 0..10 {
+            clients.push(TcpStream::connect(addr).unwrap());
+            let (stream, _) = listener.accept().unwrap();
+            let ip = peer_addr(stream.as_raw_fd()).unwrap().ip();
+            match quota.try_acquire(ip) {
+                Some(permit) => permits.push((stream, permit)),
+                None => rejected += 1,
+            }
+        }
+
+        assert_eq!(permits.len(), 3);
+        assert_eq!(rejected, 7);
+        assert_eq!(quota.active(IpAddr::V4(Ipv4Addr::LOCALHOST)), 3);
+
+        // A different source address on the loopback network is unaffected
+        #[cfg(target_os = "linux")]
+        {
+            let socket = socket2::Socket::new(
+                socket2::Domain::IPV4,
+                socket2::Type::STREAM,
+                Some(socket2::Protocol::TCP),
+            )
+            .unwrap();
+            let source: SocketAddr = "127.0.0.2:0".parse().unwrap();
+            socket.bind(&source.into()).unwrap();
+            socket.connect(&addr.into()).unwrap();
+            let (stream, peer) = listener.accept().unwrap();
+            assert_eq!(peer.ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)));
+            let ip = peer_addr(stream.as_raw_fd()).unwrap().ip();
+            assert!(quota.try_acquire(ip).is_some());
+        }
+
+        // Closing connections releases their slots
+        permits.clear();
+        assert_eq!(quota.active(IpAddr::V4(Ipv4Addr::LOCALHOST)), 0);
+    }
+}
@@ -0,0 +1,89 @@
+//! Graceful shutdown on `SIGTERM` and `SIGINT`.
+//!
+//! The signal handler only raises a flag. Each worker checks it whenever it
+//! wakes (the mio poll and the io_uring wait are both bounded), then stops
+//! accepting, closes idle connections, and lets responses it is still
+//! writing finish. A worker returns once it has no connections left, or
+//! after `shutdown_timeout_secs`, so `run` returns once all have drained.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Set by the first `SIGTERM` or `SIGINT`.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_shutdown_signal(_signal: libc::c_int) {
+    // Only async-signal-safe work here: a single atomic store
+    SIGNALLED.store(true, Ordering::Relaxed);
+}
+
+/// Install the handlers that turn `SIGTERM` and `SIGINT` into a shutdown.
+fn install_shutdown_signals() -> io::Result<()> {
+    let handler = on_shutdown_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only touches an atomic
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Shutdown trigger shared by a server's workers.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    /// Also follows `SIGTERM`/`SIGINT`
+    signals: bool,
+}
+
+impl Shutdown {
+    /// A trigger pulled only by `trigger`.
+    #[allow(dead_code)] // Servers shut down on signals; tests trigger directly
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A trigger that `SIGTERM` and `SIGINT` also pull.
+    ///
+    /// If the handlers can't be installed, the signals keep their default
+    /// action of killing the process.
+    pub fn on_signals() -> Self {
+        if let Err(e) = install_shutdown_signals() {
+            warn!(error = %e, "Failed to install SIGTERM/SIGINT shutdown handlers");
+        }
+        Self {
+            requested: Arc::new(AtomicBool::new(false)),
+            signals: true,
+        }
+    }
+
+    /// Ask every worker sharing this trigger to drain and stop.
+    pub fn trigger(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether shutdown was requested.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+            || (self.signals && SIGNALLED.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger_reaches_every_clone() {
+        let shutdown = Shutdown::new();
+        let worker = shutdown.clone();
+        assert!(!worker.is_requested());
+
+        shutdown.trigger();
+        assert!(worker.is_requested());
+        assert!(!Shutdown::new().is_requested());
+    }
+}
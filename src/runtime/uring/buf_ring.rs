@@ -78,7 +78,7 @@ impl BufRing {
         let buffers_layout = Layout::from_size_align(buffers_size, 4096)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
-        let buffers_ptr = unsafe { alloc_zeroed(buffers_layout) as *mut u8 };
+        let buffers_ptr = unsafe { alloc_zeroed(buffers_layout) };
         if buffers_ptr.is_null() {
             unsafe { dealloc(ring_ptr as *mut u8, ring_layout) };
             return Err(io::Error::new(
@@ -4,32 +4,66 @@
 //! then process completions in batches.
 //!
 //! Uses provided buffer rings for kernel-managed buffer selection on reads.
+//! When the ring runs dry, reads fail with `ENOBUFS`; those connections are
+//! parked and their reads re-armed once the batch has recycled buffers.
+//!
+//! Reads are copied into a per-connection accumulation buffer. A value too
+//! large for it (or input that outgrows it) continues in a `BufferChain`,
+//! parsed again only once the announced value can be complete.
+//! Responses too large for one write buffer are likewise copied into a
+//! chain and written with writev, resuming where a partial write stopped.
+//!
+//! With `idle_timeout_secs` set, every read carries a linked timeout. A
+//! connection that sends nothing for that long has its read cancelled and
+//! is closed.
+//!
+//! On shutdown a worker shuts its listener and every reading connection
+//! down, so their pending operations complete and close them, while writes
+//! already submitted run to completion. The worker returns once no
+//! operation is left in flight.
 
 use super::buf_ring::{BufRing, READ_BGID};
+use super::op_dump::{self, DumpRequests};
+use crate::admin::Readiness;
 use crate::config::Config;
-use crate::request::{process_echo, process_memcached, process_ping, process_resp, ProcessResult};
+use crate::privileges::drop_privileges;
+use crate::request::{
+    detect_protocol, process_pipeline, ConnectionStats, ProcessResult, RequestConfig, Session,
+};
 use crate::runtime::{
-    BufferPool, ConnPhase, Connection, ConnectionRegistry, DataState, OpType, Protocol,
-    TokenAllocator,
+    bind_with_retry, large_value_busy_response, peer_addr, quota_exceeded_response, set_nodelay,
+    spawn_worker, BufferChain, BufferPool, ChainError, ConnPhase, Connection, ConnectionQuota,
+    ConnectionRegistry, DataState, LargeValueSlots, OpType, Protocol, Shutdown, TokenAllocator,
 };
 use crate::storage::Storage;
 use io_uring::cqueue::buffer_select;
 use io_uring::squeue::Flags;
 use io_uring::{opcode, types, IoUring};
 use std::io;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, TcpListener};
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::thread;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+/// Longest a worker blocks waiting for completions before running its
+/// periodic checks.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Write buffer index standing for the connection's `write_chain`.
+const CHAIN_WRITE: usize = usize::MAX;
+
 /// Run the io_uring-based server.
-pub fn run(config: Config, storage: Arc<Storage>, protocol: Protocol) -> io::Result<()> {
-    let num_workers = if config.workers == 0 {
-        num_cpus()
-    } else {
-        config.workers
-    };
+pub fn run(
+    config: Config,
+    storage: Arc<Storage>,
+    protocol: Protocol,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
+    connection_stats: Arc<ConnectionStats>,
+) -> io::Result<()> {
+    let num_workers = config.worker_count();
 
     let addr: SocketAddr = format!("{}:{}", config.host, config.port)
         .parse()
@@ -43,56 +77,101 @@ pub fn run(config: Config, storage: Arc<Storage>, protocol: Protocol) -> io::Res
         "Starting io_uring runtime"
     );
 
+    if let Err(e) = op_dump::install_dump_signal() {
+        warn!(error = %e, "Failed to install SIGUSR1 op-dump handler");
+    }
+
+    // Each worker gets its own SO_REUSEPORT listener, all bound before root
+    // is given up
+    let listeners = (0..num_workers)
+        .map(|_| bind_with_retry(addr, &config.bind_retry))
+        .collect::<io::Result<Vec<_>>>()?;
+    drop_privileges(config.user.as_deref(), config.group.as_deref())?;
+
     let mut handles = Vec::with_capacity(num_workers);
+    let quota = ConnectionQuota::from_limit(config.max_connections_per_ip);
+    // Shared with the admin endpoint's connections gauge
+    let request_config = RequestConfig {
+        connection_stats,
+        ..RequestConfig::from_config(&config, "io_uring")
+    };
 
-    for worker_id in 0..num_workers {
-        let config = config.clone();
+    for (worker_id, listener) in listeners.into_iter().enumerate() {
+        let worker_config = config.clone();
+        let request_config = request_config.clone();
         let storage = Arc::clone(&storage);
-
-        let handle = thread::Builder::new()
-            .name(format!("worker-{}", worker_id))
-            .spawn(move || {
-                if let Err(e) = worker_loop(worker_id, addr, &config, storage, protocol) {
-                    error!(worker = worker_id, error = %e, "Worker failed");
-                }
-            })?;
+        let quota = quota.clone();
+        let readiness = Arc::clone(&readiness);
+        let shutdown = shutdown.clone();
+
+        let handle = spawn_worker(&config, worker_id, move || {
+            if let Err(e) = worker_loop(
+                worker_id,
+                listener,
+                &worker_config,
+                request_config,
+                storage,
+                protocol,
+                quota,
+                readiness,
+                shutdown,
+            ) {
+                error!(worker = worker_id, error = %e, "Worker failed");
+            }
+        })?;
 
         handles.push(handle);
     }
 
-    // Wait for all workers
+    // Wait for all workers, which return once drained
     for handle in handles {
         let _ = handle.join();
     }
+    info!("io_uring runtime stopped");
 
     Ok(())
 }
 
+/// Submit pending SQEs and wait for a completion, or until `WAIT_TIMEOUT`.
+///
+/// Timeouts and signal interruptions are not errors: they let the loop run
+/// its periodic checks (e.g. op-dump requests) even when idle.
+fn submit_and_wait(ring: &IoUring) -> io::Result<()> {
+    let timeout = types::Timespec::from(WAIT_TIMEOUT);
+    let args = types::SubmitArgs::new().timespec(&timeout);
+    match ring.submitter().submit_with_args(1, &args) {
+        Ok(_) => Ok(()),
+        Err(e) if matches!(e.raw_os_error(), Some(libc::ETIME) | Some(libc::EINTR)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn worker_loop(
     worker_id: usize,
-    addr: SocketAddr,
+    listener: TcpListener,
     config: &Config,
+    request_config: RequestConfig,
     storage: Arc<Storage>,
     protocol: Protocol,
+    quota: Option<Arc<ConnectionQuota>>,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
 ) -> io::Result<()> {
     // Create io_uring instance
     let mut ring: IoUring = IoUring::new(config.ring_size as u32)?;
 
-    // Create listener with SO_REUSEPORT
-    let listener = create_listener_with_reuseport(addr)?;
     let listener_fd = listener.as_raw_fd();
 
     let max_connections = config.max_connections;
+    let nodelay = config.nodelay_by_protocol.get(config.protocol);
     let buffer_size = config.buffer_size;
     let batch_size = config.batch_size;
-    let max_value_size = config.max_value_size;
+    let max_value_size = request_config.max_value_size_for(protocol);
 
     // Calculate ring entries - cap at 4096 to limit memory usage
     // With 64KB buffers: 4096 * 64KB = 256MB per worker for the read ring
-    let ring_entries = std::cmp::min(
-        (max_connections as u16).next_power_of_two(),
-        4096,
-    );
+    let ring_entries = std::cmp::min((max_connections as u16).next_power_of_two(), 4096);
 
     // Create provided buffer ring for reads (kernel selects buffers)
     let read_buf_ring = BufRing::new(&ring, ring_entries, buffer_size, READ_BGID)?;
@@ -101,6 +180,7 @@ fn worker_loop(
     // Base: write buffer per connection + extra for chains
     let write_pool_size = std::cmp::min(max_connections * 2, 8192);
     let mut write_buffers = BufferPool::new(write_pool_size, buffer_size);
+    let large_values = LargeValueSlots::new(config.max_concurrent_large_values);
 
     let mut connections = ConnectionRegistry::new(max_connections);
     let mut tokens = TokenAllocator::new(max_connections * 2);
@@ -115,9 +195,29 @@ fn worker_loop(
         "Worker started with buffer ring"
     );
 
+    // Listener is bound and the loop is about to run
+    readiness.count_down();
+
+    let mut dump_requests = DumpRequests::new();
+    // Connections whose read found the buffer ring empty
+    let mut starved_reads = Vec::new();
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let mut drain_deadline = None;
+    let mut drain_expired = false;
+    // Read by the kernel when each linked timeout is submitted
+    let idle_timeout = match config.idle_timeout_secs {
+        0 => None,
+        secs => Some(types::Timespec::from(Duration::from_secs(secs))),
+    };
+    let idle_timeout = idle_timeout.as_ref();
+
     loop {
         // Submit pending operations and wait for at least one completion
-        ring.submit_and_wait(1)?;
+        submit_and_wait(&ring)?;
+
+        if dump_requests.take() {
+            op_dump::log_outstanding(worker_id, &tokens);
+        }
 
         // Process completions in batch
         let mut processed = 0;
@@ -137,12 +237,19 @@ fn worker_loop(
             let op = match tokens.free(token) {
                 Some(op) => op,
                 None => {
+                    // Never issued, or a stale/duplicate completion for a reused slot
                     warn!("Unknown token in completion: {}", token);
                     continue;
                 }
             };
 
             match op {
+                OpType::Accept if drain_deadline.is_some() => {
+                    // Accepted just before the listener was shut down
+                    if result >= 0 {
+                        unsafe { libc::close(result) };
+                    }
+                }
                 OpType::Accept => {
                     handle_accept(
                         result,
@@ -152,6 +259,10 @@ fn worker_loop(
                         listener_fd,
                         worker_id,
                         protocol,
+                        quota.as_ref(),
+                        nodelay,
+                        idle_timeout,
+                        &request_config.connection_stats,
                     )?;
                 }
                 OpType::Read { conn_id } => {
@@ -169,9 +280,15 @@ fn worker_loop(
                         &read_buf_ring,
                         &mut write_buffers,
                         &storage,
-                        max_value_size,
+                        &request_config,
+                        &large_values,
+                        &mut starved_reads,
+                        idle_timeout,
                     )?;
                 }
+                // Fired, failing its read with ECANCELED, or cancelled by
+                // the read completing first: nothing left to do either way
+                OpType::ReadTimeout { .. } => {}
                 OpType::Write { conn_id, buf_idx } => {
                     handle_write(
                         result,
@@ -181,13 +298,61 @@ fn worker_loop(
                         &mut tokens,
                         &mut connections,
                         &mut write_buffers,
+                        &storage,
+                        &request_config,
+                        &large_values,
+                        idle_timeout,
                     )?;
                 }
             }
         }
+
+        // The batch has recycled the buffers it consumed
+        rearm_starved_reads(
+            &mut ring,
+            &mut tokens,
+            &connections,
+            &mut starved_reads,
+            idle_timeout,
+        )?;
+
+        if drain_deadline.is_none() && shutdown.is_requested() {
+            // Fails the pending accept, which the loop then leaves unarmed
+            unsafe { libc::shutdown(listener_fd, libc::SHUT_RDWR) };
+            drain_deadline = Some(Instant::now() + shutdown_timeout);
+            info!(
+                worker = worker_id,
+                connections = connections.len(),
+                "Shutting down, draining connections"
+            );
+        }
+
+        if let Some(deadline) = drain_deadline {
+            if !drain_expired && !connections.is_empty() && Instant::now() >= deadline {
+                warn!(
+                    worker = worker_id,
+                    connections = connections.len(),
+                    "Shutdown timeout, closing connections still writing"
+                );
+                drain_expired = true;
+            }
+            // A shut down socket completes its pending read (or, once the
+            // drain expired, write) right away, and that closes it. Repeated
+            // for connections whose response finished since the last pass.
+            for (_, conn) in connections.iter() {
+                if drain_expired || conn.is_reading() {
+                    unsafe { libc::shutdown(conn.fd, libc::SHUT_RDWR) };
+                }
+            }
+            if tokens.is_empty() {
+                info!(worker = worker_id, "Worker stopped");
+                return Ok(());
+            }
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_accept(
     result: i32,
     ring: &mut IoUring,
@@ -196,6 +361,10 @@ fn handle_accept(
     listener_fd: RawFd,
     worker_id: usize,
     protocol: Protocol,
+    quota: Option<&Arc<ConnectionQuota>>,
+    nodelay: bool,
+    idle_timeout: Option<&types::Timespec>,
+    connection_stats: &Arc<ConnectionStats>,
 ) -> io::Result<()> {
     // Always re-arm accept
     submit_accept(ring, tokens, listener_fd)?;
@@ -207,8 +376,38 @@ fn handle_accept(
     }
 
     let client_fd = result;
+    let peer = peer_addr(client_fd);
+
+    let quota_permit = match (quota, peer.map(|addr| addr.ip())) {
+        (Some(quota), Some(ip)) => match quota.try_acquire(ip) {
+            Some(permit) => Some(permit),
+            None => {
+                warn!(peer = %ip, "Per-IP connection limit reached, rejecting");
+                let response = quota_exceeded_response(protocol);
+                unsafe {
+                    libc::send(
+                        client_fd,
+                        response.as_ptr() as *const libc::c_void,
+                        response.len(),
+                        libc::MSG_DONTWAIT,
+                    );
+                    libc::close(client_fd);
+                }
+                return Ok(());
+            }
+        },
+        _ => None,
+    };
+
+    if let Err(e) = set_nodelay(client_fd, nodelay) {
+        warn!(error = %e, "Could not set TCP_NODELAY");
+    }
 
-    let conn = Connection::new(client_fd, protocol);
+    let mut conn = Connection::new(client_fd, protocol);
+    conn.quota_permit = quota_permit;
+    let open = connection_stats.opened();
+    conn.session = Session::new(open.id(), peer);
+    conn.open = Some(open);
 
     let conn_id = match connections.insert(conn) {
         Some(id) => id,
@@ -227,11 +426,12 @@ fn handle_accept(
     );
 
     // Submit read for the new connection (kernel will select buffer)
-    submit_read(ring, tokens, connections, conn_id)?;
+    submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_read(
     result: i32,
     conn_id: usize,
@@ -243,17 +443,35 @@ fn handle_read(
     read_buf_ring: &BufRing,
     write_buffers: &mut BufferPool,
     storage: &Arc<Storage>,
-    max_value_size: usize,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
+    starved_reads: &mut Vec<usize>,
+    idle_timeout: Option<&types::Timespec>,
 ) -> io::Result<()> {
+    if result == -libc::ENOBUFS {
+        // Buffer ring exhausted: backpressure, not a broken connection.
+        // The data is still in the socket, so just read again later.
+        debug!(conn_id, "Read found no provided buffer, deferring");
+        starved_reads.push(conn_id);
+        return Ok(());
+    }
+
     if result <= 0 {
         // EOF or error: close connection
-        if result < 0 {
+        let stats = &request_config.connection_stats;
+        if result == -libc::ECANCELED {
+            // Only the linked idle timeout cancels reads
+            debug!(conn_id, "Idle timeout, closing connection");
+            stats.idle_kicks.fetch_add(1, Ordering::Relaxed);
+        } else if result < 0 {
             let err = io::Error::from_raw_os_error(-result);
             debug!(conn_id, "Read error: {}", err);
+            stats.read_errors.fetch_add(1, Ordering::Relaxed);
         } else {
             debug!(conn_id, "Connection closed by peer");
+            stats.clean_disconnects.fetch_add(1, Ordering::Relaxed);
         }
-        // Recycle buffer if we got one
+        // Recycle buffer if we got one (an EOF can still select one)
         if let Some(bid) = buf_id {
             read_buf_ring.recycle_buffer(bid);
         }
@@ -283,7 +501,6 @@ fn handle_read(
         }
     };
 
-    let protocol = conn.protocol;
     let accumulated = conn.read_accumulated;
 
     // Get or allocate accumulation buffer
@@ -306,33 +523,102 @@ fn handle_read(
         }
     };
 
-    // Copy new data from provided buffer to accumulation buffer
     let new_data = &read_buf_ring.get_buffer_slice(bid)[..n];
-    let total_len = accumulated + n;
 
-    if total_len > buffer_size {
-        // Data exceeds buffer size - would need chained buffers
-        warn!(conn_id, "Read data exceeds buffer size");
-        read_buf_ring.recycle_buffer(bid);
-        close_connection(connections, write_buffers, conn_id);
-        return Ok(());
+    // Input outgrowing the accumulation buffer continues in a chain
+    if conn.read_chain.is_none() && accumulated + n > buffer_size {
+        let pending = write_buffers.get(accum_buf_idx)[..accumulated].to_vec();
+        let mut chain = BufferChain::new(buffer_size);
+        let spilled = chain.append(&pending, write_buffers);
+        conn.read_chain = Some(chain);
+        conn.read_chain_wanted = 0;
+        conn.read_accumulated = 0;
+        if spilled.is_err() {
+            warn!(conn_id, "Buffer pool exhausted for large input");
+            read_buf_ring.recycle_buffer(bid);
+            close_connection(connections, write_buffers, conn_id);
+            return Ok(());
+        }
     }
 
-    // Copy to accumulation buffer
-    {
+    if let Some(chain) = &mut conn.read_chain {
+        let appended = chain.append(new_data, write_buffers);
+        read_buf_ring.recycle_buffer(bid);
+        if appended.is_err() {
+            warn!(conn_id, "Buffer pool exhausted for large input");
+            close_connection(connections, write_buffers, conn_id);
+            return Ok(());
+        }
+        // Nothing to parse until the value can be complete
+        if chain.len() < conn.read_chain_wanted {
+            if conn
+                .assembly
+                .expired(request_config.command_assembly_timeout)
+            {
+                debug!(conn_id, "Command assembly timed out");
+                close_connection(connections, write_buffers, conn_id);
+                return Ok(());
+            }
+            return submit_read(ring, tokens, connections, conn_id, idle_timeout);
+        }
+    } else {
+        // Copy to accumulation buffer
+        let total_len = accumulated + n;
         let accum_buf = write_buffers.get_mut(accum_buf_idx);
         accum_buf[accumulated..total_len].copy_from_slice(new_data);
+        conn.read_accumulated = total_len;
+
+        // Recycle provided buffer now that we've copied the data
+        read_buf_ring.recycle_buffer(bid);
     }
 
-    // Recycle provided buffer now that we've copied the data
-    read_buf_ring.recycle_buffer(bid);
+    process_input(
+        conn_id,
+        ring,
+        tokens,
+        connections,
+        write_buffers,
+        storage,
+        request_config,
+        large_values,
+        idle_timeout,
+    )
+}
 
-    // Update accumulated count
-    let conn = connections.get_mut(conn_id).unwrap();
-    conn.read_accumulated = total_len;
+/// Answer the commands in a connection's buffered input, then write the
+/// replies or read on.
+#[allow(clippy::too_many_arguments)]
+fn process_input(
+    conn_id: usize,
+    ring: &mut IoUring,
+    tokens: &mut TokenAllocator,
+    connections: &mut ConnectionRegistry,
+    write_buffers: &mut BufferPool,
+    storage: &Arc<Storage>,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
+    idle_timeout: Option<&types::Timespec>,
+) -> io::Result<()> {
+    let buffer_size = write_buffers.buffer_size();
+    let conn = connections
+        .get_mut(conn_id)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
+    let Some(accum_buf_idx) = conn.read_buf_idx else {
+        return submit_read(ring, tokens, connections, conn_id, idle_timeout);
+    };
 
     // Copy input data to avoid borrow conflict with write buffer allocation
-    let input_copy: Vec<u8> = write_buffers.get(accum_buf_idx)[..total_len].to_vec();
+    let input_copy: Vec<u8> = match &conn.read_chain {
+        Some(chain) => chain.assemble(write_buffers),
+        None => write_buffers.get(accum_buf_idx)[..conn.read_accumulated].to_vec(),
+    };
+    let total_len = input_copy.len();
+
+    // The first bytes settle which protocol an `Auto` connection speaks
+    if conn.protocol == Protocol::Auto && total_len > 0 {
+        conn.protocol = detect_protocol(&input_copy);
+    }
+    let protocol = conn.protocol;
 
     // Allocate a write buffer for the response
     let write_buf_idx = match write_buffers.alloc() {
@@ -344,13 +630,16 @@ fn handle_read(
         }
     };
 
+    let mut session = std::mem::take(&mut connections.get_mut(conn_id).unwrap().session);
     let write_buf = write_buffers.get_mut(write_buf_idx);
-    let result = match protocol {
-        Protocol::Memcached => process_memcached(&input_copy, write_buf, storage, max_value_size),
-        Protocol::Resp => process_resp(&input_copy, write_buf, storage, max_value_size),
-        Protocol::Ping => process_ping(&input_copy, write_buf, storage),
-        Protocol::Echo => process_echo(&input_copy, write_buf, storage, max_value_size),
-    };
+    let result = process_pipeline(
+        protocol,
+        &input_copy,
+        write_buf,
+        storage,
+        request_config,
+        &mut session,
+    );
 
     // Re-borrow connection after buffer operations
     let conn = match connections.get_mut(conn_id) {
@@ -360,31 +649,101 @@ fn handle_read(
             return Ok(());
         }
     };
+    conn.session = session;
+
+    if matches!(
+        result,
+        ProcessResult::NeedData | ProcessResult::NeedChain { .. }
+    ) {
+        write_buffers.free(write_buf_idx);
+        conn.assembly.pending();
+        if conn
+            .assembly
+            .expired(request_config.command_assembly_timeout)
+        {
+            debug!(conn_id, "Command assembly timed out");
+            close_connection(connections, write_buffers, conn_id);
+            return Ok(());
+        }
+    }
 
     match result {
         ProcessResult::NeedData => {
+            // Only a large value may outgrow the buffer it was announced in
+            if conn.read_chain.is_some() && total_len > 2 * buffer_size {
+                warn!(conn_id, "Command exceeds read buffer");
+                close_connection(connections, write_buffers, conn_id);
+                return Ok(());
+            }
             // Need more data - keep accumulated data and resubmit read
-            write_buffers.free(write_buf_idx);
-            submit_read(ring, tokens, connections, conn_id)?;
+            conn.read_chain_wanted = total_len + 1;
+            submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
         }
-        ProcessResult::NeedChain { .. } => {
-            // Large value support for io_uring will be added in a follow-up
-            // For now, reject as not implemented
-            warn!(conn_id, "Large value support not yet implemented for io_uring");
-            write_buffers.free(write_buf_idx);
-            close_connection(connections, write_buffers, conn_id);
+        ProcessResult::NeedChain {
+            command_len,
+            value_len,
+        } => {
+            // Large value detected - need to accumulate into chain
+            if value_len > request_config.max_value_size_for(protocol) {
+                warn!(conn_id, value_len, "Value too large");
+                close_connection(connections, write_buffers, conn_id);
+                return Ok(());
+            }
+
+            // A new large value needs one of the worker's slots
+            if conn.large_value.is_none() {
+                match large_values.acquire() {
+                    Some(slot) => conn.large_value = Some(slot),
+                    None => {
+                        // Its body is still arriving, so close rather than skip it
+                        let response = large_value_busy_response(protocol);
+                        unsafe {
+                            libc::send(
+                                conn.fd,
+                                response.as_ptr() as *const libc::c_void,
+                                response.len(),
+                                libc::MSG_DONTWAIT,
+                            );
+                        }
+                        close_connection(connections, write_buffers, conn_id);
+                        return Ok(());
+                    }
+                }
+            }
+
+            if conn.read_chain.is_none() {
+                let mut chain = BufferChain::new(buffer_size);
+                let moved = chain.append(&input_copy, write_buffers);
+                conn.read_chain = Some(chain);
+                conn.read_accumulated = 0;
+                if moved.is_err() {
+                    warn!(conn_id, "Buffer pool exhausted for large value");
+                    close_connection(connections, write_buffers, conn_id);
+                    return Ok(());
+                }
+            }
+            conn.read_chain_wanted = (command_len + value_len).max(total_len + 1);
+            submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
         }
         ProcessResult::Response {
             consumed,
             response_len,
         } => {
-            // Clear accumulated data (or shift unconsumed data to start)
-            if consumed < total_len {
-                let accum_buf = write_buffers.get_mut(accum_buf_idx);
-                accum_buf.copy_within(consumed..total_len, 0);
-                conn.read_accumulated = total_len - consumed;
-            } else {
-                conn.read_accumulated = 0;
+            // Keep only the unconsumed input, at the start of the buffer
+            if keep_unconsumed(conn, write_buffers, accum_buf_idx, &input_copy[consumed..]).is_err()
+            {
+                write_buffers.free(write_buf_idx);
+                close_connection(connections, write_buffers, conn_id);
+                return Ok(());
+            }
+            let conn = connections.get_mut(conn_id).unwrap();
+            conn.assembly.completed(consumed < total_len);
+
+            // Only silent commands: nothing to send, read on
+            if response_len == 0 {
+                write_buffers.free(write_buf_idx);
+                submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
+                return Ok(());
             }
 
             // Transition to writing
@@ -398,18 +757,21 @@ fn handle_read(
                 response_len,
             )?;
         }
-        ProcessResult::LargeResponse { consumed, response_data } => {
-            // Clear accumulated data
-            if consumed < total_len {
-                let accum_buf = write_buffers.get_mut(accum_buf_idx);
-                accum_buf.copy_within(consumed..total_len, 0);
-                conn.read_accumulated = total_len - consumed;
-            } else {
-                conn.read_accumulated = 0;
+        ProcessResult::LargeResponse {
+            consumed,
+            response_data,
+        } => {
+            // Keep only the unconsumed input, at the start of the buffer
+            if keep_unconsumed(conn, write_buffers, accum_buf_idx, &input_copy[consumed..]).is_err()
+            {
+                write_buffers.free(write_buf_idx);
+                close_connection(connections, write_buffers, conn_id);
+                return Ok(());
             }
+            let conn = connections.get_mut(conn_id).unwrap();
+            conn.assembly.completed(consumed < total_len);
 
-            // Large response - need to use multiple buffers
-            // For now, copy to write buffer if it fits, otherwise reject
+            // A response that fits goes out like any other
             if response_data.len() <= write_buffers.buffer_size() {
                 let write_buf = write_buffers.get_mut(write_buf_idx);
                 write_buf[..response_data.len()].copy_from_slice(&response_data);
@@ -423,16 +785,109 @@ fn handle_read(
                     response_data.len(),
                 )?;
             } else {
-                // TODO: Implement multi-buffer write for io_uring
-                warn!(conn_id, "Large response support not yet implemented for io_uring");
+                // Otherwise it is written from a chain, with writev
+                write_buffers.free(write_buf_idx);
+                let mut write_chain = BufferChain::new(write_buffers.buffer_size());
+                if let Err(ChainError::PoolExhausted) =
+                    write_chain.append(&response_data, write_buffers)
+                {
+                    warn!(conn_id, "Buffer pool exhausted for large response");
+                    write_chain.release(write_buffers);
+                    close_connection(connections, write_buffers, conn_id);
+                    return Ok(());
+                }
+                request_config
+                    .response_chain_stats
+                    .record(write_chain.buffer_count());
+                let response_len = write_chain.len();
+                conn.write_chain = Some(write_chain);
+                conn.start_writing(CHAIN_WRITE, response_len);
+                submit_write(
+                    ring,
+                    tokens,
+                    connections,
+                    write_buffers,
+                    conn_id,
+                    response_len,
+                )?;
+            }
+        }
+        ProcessResult::StreamResponse {
+            consumed,
+            mut response,
+        } => {
+            // Keep only the unconsumed input, at the start of the buffer
+            if keep_unconsumed(conn, write_buffers, accum_buf_idx, &input_copy[consumed..]).is_err()
+            {
                 write_buffers.free(write_buf_idx);
                 close_connection(connections, write_buffers, conn_id);
+                return Ok(());
             }
+            let conn = connections.get_mut(conn_id).unwrap();
+            conn.assembly.completed(consumed < total_len);
+
+            // Written a few buffers at a time, refilled by `handle_write`
+            write_buffers.free(write_buf_idx);
+            let mut write_chain = BufferChain::new(write_buffers.buffer_size());
+            let response_len = match write_chain.refill(
+                &mut response,
+                request_config.stream_chain_buffers(),
+                write_buffers,
+            ) {
+                Ok(len) => len,
+                Err(e) => {
+                    warn!(conn_id, error = %e, "Failed to start streamed response");
+                    write_chain.release(write_buffers);
+                    close_connection(connections, write_buffers, conn_id);
+                    return Ok(());
+                }
+            };
+            request_config
+                .response_chain_stats
+                .record(write_chain.buffer_count());
+            conn.write_chain = Some(write_chain);
+            conn.write_stream = Some(response);
+            conn.start_writing(CHAIN_WRITE, response_len);
+            submit_write(
+                ring,
+                tokens,
+                connections,
+                write_buffers,
+                conn_id,
+                response_len,
+            )?;
         }
         ProcessResult::Quit => {
             write_buffers.free(write_buf_idx);
             close_connection(connections, write_buffers, conn_id);
         }
+        ProcessResult::Reject { response } => {
+            // Wrong protocol: best-effort hint, then close
+            unsafe {
+                libc::send(
+                    conn.fd,
+                    response.as_ptr() as *const libc::c_void,
+                    response.len(),
+                    libc::MSG_DONTWAIT,
+                );
+            }
+            write_buffers.free(write_buf_idx);
+            close_connection(connections, write_buffers, conn_id);
+        }
+        ProcessResult::ErrorAfterResponse { response_len } => {
+            // Input can't be followed past this: best-effort reply, then close
+            let response = &write_buffers.get(write_buf_idx)[..response_len];
+            unsafe {
+                libc::send(
+                    conn.fd,
+                    response.as_ptr() as *const libc::c_void,
+                    response.len(),
+                    libc::MSG_DONTWAIT,
+                );
+            }
+            write_buffers.free(write_buf_idx);
+            close_connection(connections, write_buffers, conn_id);
+        }
         ProcessResult::Error => {
             write_buffers.free(write_buf_idx);
             close_connection(connections, write_buffers, conn_id);
@@ -442,6 +897,36 @@ fn handle_read(
     Ok(())
 }
 
+/// Put input left after a response back in the accumulation buffer,
+/// releasing any chain it was read into.
+///
+/// Left input too long for the buffer stays chained, to be parsed on the
+/// next read.
+fn keep_unconsumed(
+    conn: &mut Connection,
+    write_buffers: &mut BufferPool,
+    accum_buf_idx: usize,
+    rest: &[u8],
+) -> Result<(), ChainError> {
+    if let Some(mut chain) = conn.read_chain.take() {
+        chain.release(write_buffers);
+    }
+    conn.large_value = None;
+    conn.read_accumulated = 0;
+
+    if rest.len() <= write_buffers.buffer_size() {
+        write_buffers.get_mut(accum_buf_idx)[..rest.len()].copy_from_slice(rest);
+        conn.read_accumulated = rest.len();
+        return Ok(());
+    }
+    let mut chain = BufferChain::new(write_buffers.buffer_size());
+    let kept = chain.append(rest, write_buffers);
+    conn.read_chain = Some(chain);
+    conn.read_chain_wanted = 0;
+    kept
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_write(
     result: i32,
     conn_id: usize,
@@ -450,13 +935,17 @@ fn handle_write(
     tokens: &mut TokenAllocator,
     connections: &mut ConnectionRegistry,
     write_buffers: &mut BufferPool,
+    storage: &Arc<Storage>,
+    request_config: &RequestConfig,
+    large_values: &LargeValueSlots,
+    idle_timeout: Option<&types::Timespec>,
 ) -> io::Result<()> {
     if result <= 0 {
         if result < 0 {
             let err = io::Error::from_raw_os_error(-result);
             debug!(conn_id, "Write error: {}", err);
         }
-        write_buffers.free(buf_idx);
+        // Closing frees the write buffer (or chain)
         close_connection(connections, write_buffers, conn_id);
         return Ok(());
     }
@@ -465,7 +954,9 @@ fn handle_write(
     let conn = match connections.get_mut(conn_id) {
         Some(c) => c,
         None => {
-            write_buffers.free(buf_idx);
+            if buf_idx != CHAIN_WRITE {
+                write_buffers.free(buf_idx);
+            }
             return Ok(());
         }
     };
@@ -474,10 +965,60 @@ fn handle_write(
         *written += n;
 
         if *written >= *total {
+            // The chain went out; a streamed response refills it with the
+            // next buffers' worth, in the same buffers' place
+            if let (Some(stream), Some(chain)) = (&mut conn.write_stream, &mut conn.write_chain) {
+                match chain.refill(stream, request_config.stream_chain_buffers(), write_buffers) {
+                    Ok(0) => {}
+                    Ok(len) => {
+                        request_config
+                            .response_chain_stats
+                            .record(chain.buffer_count());
+                        conn.start_writing(CHAIN_WRITE, len);
+                        return submit_write(
+                            ring,
+                            tokens,
+                            connections,
+                            write_buffers,
+                            conn_id,
+                            len,
+                        );
+                    }
+                    Err(e) => {
+                        debug!(conn_id, "Streamed response failed: {}", e);
+                        close_connection(connections, write_buffers, conn_id);
+                        return Ok(());
+                    }
+                }
+            }
+            conn.write_stream = None;
+
             // Write complete, free write buffer and go back to reading
-            write_buffers.free(buf_idx);
+            if buf_idx == CHAIN_WRITE {
+                if let Some(mut chain) = conn.write_chain.take() {
+                    chain.release(write_buffers);
+                }
+                conn.write_iovecs.0.clear();
+            } else {
+                write_buffers.free(buf_idx);
+            }
             conn.start_reading();
-            submit_read(ring, tokens, connections, conn_id)?;
+            // Input left behind the replies is this connection's next turn.
+            // It may hold whole commands, which no read would wake up.
+            if conn.read_accumulated > 0 || conn.read_chain.is_some() {
+                return process_input(
+                    conn_id,
+                    ring,
+                    tokens,
+                    connections,
+                    write_buffers,
+                    storage,
+                    request_config,
+                    large_values,
+                    idle_timeout,
+                );
+            }
+            submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
         } else {
             // Partial write, continue
             let remaining = *total - *written;
@@ -506,52 +1047,110 @@ fn submit_accept(
     unsafe {
         ring.submission().push(&accept).map_err(|_| {
             tokens.free(token);
-            io::Error::new(io::ErrorKind::Other, "submission queue full")
+            io::Error::other("submission queue full")
         })?;
     }
 
     Ok(())
 }
 
+/// Submit a read, linked to `idle_timeout` if set.
+///
+/// The timeout cancels the read if no data arrives in time; the read then
+/// completes with `ECANCELED`. The timespec only has to outlive the next
+/// submit, which is when the kernel reads it.
 fn submit_read(
     ring: &mut IoUring,
     tokens: &mut TokenAllocator,
     connections: &ConnectionRegistry,
     conn_id: usize,
+    idle_timeout: Option<&types::Timespec>,
 ) -> io::Result<()> {
     let conn = connections
         .get(conn_id)
         .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
 
+    // A read without its timeout right behind it would never time out
+    let mut submission = ring.submission();
+    let entries = 1 + usize::from(idle_timeout.is_some());
+    if submission.capacity() - submission.len() < entries {
+        return Err(io::Error::other("submission queue full"));
+    }
+
     let token = tokens.alloc(OpType::Read { conn_id });
 
     // Use Recv with BUFFER_SELECT - kernel will pick a buffer from our ring
+    let flags = match idle_timeout {
+        Some(_) => Flags::BUFFER_SELECT | Flags::IO_LINK,
+        None => Flags::BUFFER_SELECT,
+    };
     let recv = opcode::Recv::new(types::Fd(conn.fd), std::ptr::null_mut(), 0)
         .buf_group(READ_BGID)
         .build()
-        .flags(Flags::BUFFER_SELECT)
+        .flags(flags)
         .user_data(token);
 
     unsafe {
-        ring.submission().push(&recv).map_err(|_| {
+        submission.push(&recv).map_err(|_| {
             tokens.free(token);
-            io::Error::new(io::ErrorKind::Other, "submission queue full")
+            io::Error::other("submission queue full")
         })?;
     }
 
+    if let Some(timeout) = idle_timeout {
+        let token = tokens.alloc(OpType::ReadTimeout { conn_id });
+        let link = opcode::LinkTimeout::new(timeout).build().user_data(token);
+        unsafe {
+            submission.push(&link).map_err(|_| {
+                tokens.free(token);
+                io::Error::other("submission queue full")
+            })?;
+        }
+    }
+
     Ok(())
 }
 
-fn submit_write(
+/// Re-submit reads that failed with `ENOBUFS`, skipping closed connections.
+fn rearm_starved_reads(
     ring: &mut IoUring,
     tokens: &mut TokenAllocator,
     connections: &ConnectionRegistry,
+    starved_reads: &mut Vec<usize>,
+    idle_timeout: Option<&types::Timespec>,
+) -> io::Result<()> {
+    for conn_id in starved_reads.drain(..) {
+        if connections.get(conn_id).is_some() {
+            submit_read(ring, tokens, connections, conn_id, idle_timeout)?;
+        }
+    }
+    Ok(())
+}
+
+/// iovecs covering a chain's bytes from `offset` on, as many as one
+/// writev takes; a longer chain finishes in the writes that follow.
+fn chain_iovecs(chain: &BufferChain, pool: &BufferPool, offset: usize) -> Vec<libc::iovec> {
+    chain
+        .io_slices(pool, offset)
+        .iter()
+        .take(libc::UIO_MAXIOV as usize)
+        .map(|slice| libc::iovec {
+            iov_base: slice.as_ptr() as *mut libc::c_void,
+            iov_len: slice.len(),
+        })
+        .collect()
+}
+
+fn submit_write(
+    ring: &mut IoUring,
+    tokens: &mut TokenAllocator,
+    connections: &mut ConnectionRegistry,
     buffers: &mut BufferPool,
     conn_id: usize,
     len: usize,
 ) -> io::Result<()> {
     let conn = connections
-        .get(conn_id)
+        .get_mut(conn_id)
         .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "connection not found"))?;
 
     let (buf_idx, offset) = match conn.phase {
@@ -566,22 +1165,38 @@ fn submit_write(
         }
     };
 
-    let buf_ptr = buffers.get_ptr(buf_idx);
-
     let token = tokens.alloc(OpType::Write { conn_id, buf_idx });
 
-    let write = opcode::Write::new(
-        types::Fd(conn.fd),
-        unsafe { buf_ptr.add(offset) },
-        len as u32,
-    )
-    .build()
-    .user_data(token);
+    let write = if buf_idx == CHAIN_WRITE {
+        // The rest of the chain, left where the kernel can read it until
+        // the write completes
+        let chain = conn
+            .write_chain
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing write chain"))?;
+        conn.write_iovecs.0 = chain_iovecs(chain, buffers, offset);
+        opcode::Writev::new(
+            types::Fd(conn.fd),
+            conn.write_iovecs.0.as_ptr(),
+            conn.write_iovecs.0.len() as u32,
+        )
+        .build()
+        .user_data(token)
+    } else {
+        let buf_ptr = buffers.get_ptr(buf_idx);
+        opcode::Write::new(
+            types::Fd(conn.fd),
+            unsafe { buf_ptr.add(offset) },
+            len as u32,
+        )
+        .build()
+        .user_data(token)
+    };
 
     unsafe {
         ring.submission().push(&write).map_err(|_| {
             tokens.free(token);
-            io::Error::new(io::ErrorKind::Other, "submission queue full")
+            io::Error::other("submission queue full")
         })?;
     }
 
@@ -596,7 +1211,12 @@ fn close_connection(
     if let Some(conn) = connections.remove(conn_id) {
         // Return write buffer to pool if we have one
         if let ConnPhase::Established(DataState::Writing { buf_idx, .. }) = conn.phase {
-            write_buffers.free(buf_idx);
+            if buf_idx != CHAIN_WRITE {
+                write_buffers.free(buf_idx);
+            }
+        }
+        if let Some(mut chain) = conn.write_chain {
+            chain.release(write_buffers);
         }
 
         // Return read accumulation buffer to pool if we have one
@@ -604,6 +1224,11 @@ fn close_connection(
             write_buffers.free(buf_idx);
         }
 
+        // And any chain a large value was accumulating in
+        if let Some(mut chain) = conn.read_chain {
+            chain.release(write_buffers);
+        }
+
         // Close the file descriptor
         unsafe { libc::close(conn.fd) };
 
@@ -611,28 +1236,345 @@ fn close_connection(
     }
 }
 
-/// Create a TCP listener with SO_REUSEPORT for kernel load balancing.
-fn create_listener_with_reuseport(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
-    let socket = socket2::Socket::new(
-        match addr {
-            SocketAddr::V4(_) => socket2::Domain::IPV4,
-            SocketAddr::V6(_) => socket2::Domain::IPV6,
-        },
-        socket2::Type::STREAM,
-        Some(socket2::Protocol::TCP),
-    )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    socket.set_reuse_port(true)?;
-    socket.set_reuse_address(true)?;
-    socket.set_nonblocking(true)?;
-    socket.bind(&addr.into())?;
-    socket.listen(1024)?;
+    /// Connected AF_UNIX stream pair: (server side, client side).
+    fn socket_pair() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        let rc = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) };
+        assert_eq!(rc, 0, "socketpair failed");
+        (fds[0], fds[1])
+    }
 
-    Ok(socket.into())
-}
+    fn send(fd: RawFd, data: &[u8]) {
+        let n = unsafe { libc::send(fd, data.as_ptr() as *const libc::c_void, data.len(), 0) };
+        assert_eq!(n, data.len() as isize);
+    }
 
-fn num_cpus() -> usize {
-    std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(1)
+    /// Wait for one completion: (connection, result, selected buffer).
+    fn next_read(ring: &mut IoUring, tokens: &mut TokenAllocator) -> (usize, i32, Option<u16>) {
+        ring.submit_and_wait(1).unwrap();
+        let cqe = ring.completion().next().unwrap();
+        match tokens.free(cqe.user_data()) {
+            Some(OpType::Read { conn_id }) => (conn_id, cqe.result(), buffer_select(cqe.flags())),
+            other => panic!("unexpected completion for {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_exhausted_buffer_ring_defers_read() {
+        let mut ring = IoUring::new(8).unwrap();
+        // A single provided buffer, so a second concurrent read starves
+        let read_buf_ring = BufRing::new(&ring, 1, 64, READ_BGID).unwrap();
+        let mut tokens = TokenAllocator::new(8);
+        let mut connections = ConnectionRegistry::new(4);
+        let mut write_buffers = BufferPool::new(4, 64);
+        let storage = Storage::new(1024 * 1024, 0);
+        let request_config = RequestConfig::default();
+        let mut starved_reads = Vec::new();
+
+        let (server_a, client_a) = socket_pair();
+        let (server_b, client_b) = socket_pair();
+        let conn_a = connections
+            .insert(Connection::new(server_a, Protocol::Memcached))
+            .unwrap();
+        let conn_b = connections
+            .insert(Connection::new(server_b, Protocol::Memcached))
+            .unwrap();
+        send(client_a, b"version\r\n");
+        send(client_b, b"version\r\n");
+
+        submit_read(&mut ring, &mut tokens, &connections, conn_a, None).unwrap();
+        submit_read(&mut ring, &mut tokens, &connections, conn_b, None).unwrap();
+
+        let mut holder = None;
+        for _ in 0..2 {
+            let (conn_id, result, buf_id) = next_read(&mut ring, &mut tokens);
+            if result == -libc::ENOBUFS {
+                handle_read(
+                    result,
+                    conn_id,
+                    buf_id,
+                    0,
+                    &mut ring,
+                    &mut tokens,
+                    &mut connections,
+                    &read_buf_ring,
+                    &mut write_buffers,
+                    &storage,
+                    &request_config,
+                    &LargeValueSlots::new(0),
+                    &mut starved_reads,
+                    None,
+                )
+                .unwrap();
+            } else {
+                assert_eq!(result, 9);
+                holder = Some(buf_id.unwrap());
+            }
+        }
+
+        // The starved connection is parked, not closed
+        assert_eq!(starved_reads.len(), 1);
+        let starved = starved_reads[0];
+        assert!(connections.get(starved).is_some());
+
+        // Once the buffer comes back, the re-armed read sees the same bytes
+        read_buf_ring.recycle_buffer(holder.unwrap());
+        rearm_starved_reads(
+            &mut ring,
+            &mut tokens,
+            &connections,
+            &mut starved_reads,
+            None,
+        )
+        .unwrap();
+        assert!(starved_reads.is_empty());
+
+        let (conn_id, result, buf_id) = next_read(&mut ring, &mut tokens);
+        assert_eq!(conn_id, starved);
+        assert_eq!(result, 9);
+        assert_eq!(
+            &read_buf_ring.get_buffer_slice(buf_id.unwrap())[..9],
+            b"version\r\n"
+        );
+
+        for fd in [server_a, client_a, server_b, client_b] {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    #[test]
+    fn test_idle_read_times_out() {
+        let mut ring = IoUring::new(8).unwrap();
+        let read_buf_ring = BufRing::new(&ring, 4, 64, READ_BGID).unwrap();
+        let mut tokens = TokenAllocator::new(8);
+        let mut connections = ConnectionRegistry::new(4);
+        let mut write_buffers = BufferPool::new(4, 64);
+        let storage = Storage::new(1024 * 1024, 0);
+        let request_config = RequestConfig::default();
+        let idle_timeout = types::Timespec::from(Duration::from_millis(50));
+
+        let (server, client) = socket_pair();
+        let conn_id = connections
+            .insert(Connection::new(server, Protocol::Memcached))
+            .unwrap();
+        submit_read(
+            &mut ring,
+            &mut tokens,
+            &connections,
+            conn_id,
+            Some(&idle_timeout),
+        )
+        .unwrap();
+
+        // The timeout fires and cancels the read it is linked to
+        ring.submit_and_wait(2).unwrap();
+        let completions: Vec<_> = ring
+            .completion()
+            .map(|cqe| (tokens.free(cqe.user_data()).unwrap(), cqe.result()))
+            .collect();
+        assert_eq!(completions.len(), 2);
+        let mut read_result = None;
+        for (op, result) in completions {
+            match op {
+                OpType::Read { .. } => read_result = Some(result),
+                OpType::ReadTimeout { .. } => assert_eq!(result, -libc::ETIME),
+                other => panic!("unexpected completion for {other:?}"),
+            }
+        }
+        assert_eq!(read_result, Some(-libc::ECANCELED));
+
+        handle_read(
+            -libc::ECANCELED,
+            conn_id,
+            None,
+            0,
+            &mut ring,
+            &mut tokens,
+            &mut connections,
+            &read_buf_ring,
+            &mut write_buffers,
+            &storage,
+            &request_config,
+            &LargeValueSlots::new(0),
+            &mut Vec::new(),
+            Some(&idle_timeout),
+        )
+        .unwrap();
+        assert!(connections.get(conn_id).is_none());
+        assert!(tokens.is_empty());
+        let stats = request_config.connection_stats.counts();
+        assert_eq!(stats.idle_kicks, 1);
+        assert_eq!(stats.read_errors, 0);
+
+        unsafe { libc::close(client) };
+    }
+
+    #[test]
+    fn test_accept_sets_nodelay() {
+        use std::os::unix::io::{FromRawFd, IntoRawFd, OwnedFd};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        for nodelay in [true, false] {
+            let mut ring = IoUring::new(8).unwrap();
+            let mut tokens = TokenAllocator::new(8);
+            let mut connections = ConnectionRegistry::new(4);
+
+            let _client = std::net::TcpStream::connect(addr).unwrap();
+            let (accepted, _) = listener.accept().unwrap();
+            handle_accept(
+                accepted.into_raw_fd(),
+                &mut ring,
+                &mut tokens,
+                &mut connections,
+                listener.as_raw_fd(),
+                0,
+                Protocol::Memcached,
+                None,
+                nodelay,
+                None,
+                &Arc::default(),
+            )
+            .unwrap();
+
+            let (_, conn) = connections.iter().next().unwrap();
+            // Closed on drop
+            let socket = unsafe { OwnedFd::from_raw_fd(conn.fd) };
+            assert_eq!(socket2::SockRef::from(&socket).nodelay().unwrap(), nodelay);
+        }
+    }
+
+    /// Shrink a socket's send buffer and make it nonblocking, so a write
+    /// larger than the buffer comes back short instead of waiting.
+    fn make_writes_short(fd: RawFd) {
+        let size: libc::c_int = 4096;
+        let rc = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_SNDBUF,
+                &size as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        assert_eq!(rc, 0, "setsockopt failed");
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        assert_eq!(
+            unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) },
+            0
+        );
+    }
+
+    #[test]
+    fn test_short_writes_resume_mid_chain() {
+        let mut ring = IoUring::new(8).unwrap();
+        let mut tokens = TokenAllocator::new(8);
+        let mut connections = ConnectionRegistry::new(4);
+        let mut write_buffers = BufferPool::new(64, 4096);
+        let storage = Storage::new(1024 * 1024, 0);
+        let request_config = RequestConfig::default();
+
+        let (server, client) = socket_pair();
+        make_writes_short(server);
+        let conn_id = connections
+            .insert(Connection::new(server, Protocol::Memcached))
+            .unwrap();
+        // Not a whole number of buffers, nor of anything the socket takes
+        let data: Vec<u8> = (0..100_003u32).map(|i| (i % 251) as u8).collect();
+        let mut chain = BufferChain::new(write_buffers.buffer_size());
+        chain.append(&data, &mut write_buffers).unwrap();
+        let conn = connections.get_mut(conn_id).unwrap();
+        conn.write_chain = Some(chain);
+        conn.start_writing(CHAIN_WRITE, data.len());
+        submit_write(
+            &mut ring,
+            &mut tokens,
+            &mut connections,
+            &mut write_buffers,
+            conn_id,
+            data.len(),
+        )
+        .unwrap();
+
+        // Drain the client side while the server's writes complete short
+        let mut received = Vec::new();
+        let mut short_writes = 0;
+        let mut buf = [0u8; 8192];
+        while received.len() < data.len() {
+            ring.submit_and_wait(1).unwrap();
+            let cqe = ring.completion().next().unwrap();
+            let (id, buf_idx) = match tokens.free(cqe.user_data()) {
+                Some(OpType::Write { conn_id, buf_idx }) => (conn_id, buf_idx),
+                other => panic!("unexpected completion for {other:?}"),
+            };
+            assert!(cqe.result() > 0, "write failed: {}", cqe.result());
+            let written = cqe.result() as usize;
+            if received.len() + written < data.len() {
+                short_writes += 1;
+            }
+            // Everything written so far, before the write resumes
+            let target = received.len() + written;
+            while received.len() < target {
+                let n = unsafe {
+                    libc::recv(client, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+                };
+                assert!(n > 0, "recv failed");
+                received.extend_from_slice(&buf[..n as usize]);
+            }
+            handle_write(
+                cqe.result(),
+                id,
+                buf_idx,
+                &mut ring,
+                &mut tokens,
+                &mut connections,
+                &mut write_buffers,
+                &storage,
+                &request_config,
+                &LargeValueSlots::new(0),
+                None,
+            )
+            .unwrap();
+        }
+
+        assert!(short_writes > 1, "only {short_writes} short writes");
+        assert!(received == data, "resumed writes reordered the reply");
+        // Done writing: the chain's buffers are back and it reads again
+        let conn = connections.get(conn_id).unwrap();
+        assert!(conn.write_chain.is_none());
+        assert!(matches!(
+            conn.phase,
+            ConnPhase::Established(DataState::Reading { .. })
+        ));
+        assert_eq!(write_buffers.available(), 64);
+
+        for fd in [server, client] {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    #[test]
+    fn test_chain_iovecs_resume_mid_chain() {
+        let mut pool = BufferPool::new(4, 64);
+        let mut chain = BufferChain::new(64);
+        let data: Vec<u8> = (0..150u8).collect();
+        chain.append(&data, &mut pool).unwrap();
+
+        for offset in [0, 10, 64, 70, 149] {
+            let iovecs = chain_iovecs(&chain, &pool, offset);
+            let resumed: Vec<u8> = iovecs
+                .iter()
+                .flat_map(|iov| unsafe {
+                    std::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len)
+                })
+                .copied()
+                .collect();
+            assert_eq!(resumed, data[offset..], "offset {offset}");
+        }
+        assert!(chain_iovecs(&chain, &pool, 150).is_empty());
+    }
 }
@@ -5,16 +5,33 @@
 
 mod buf_ring;
 mod event_loop;
+mod op_dump;
 mod token;
 
 pub(crate) use token::{OpType, TokenAllocator};
 
+use crate::admin::Readiness;
 use crate::config::Config;
-use crate::runtime::Protocol;
+use crate::request::ConnectionStats;
+use crate::runtime::{Protocol, Shutdown};
 use crate::storage::Storage;
 use std::sync::Arc;
 
 /// Run the server using io_uring backend.
-pub fn run(config: Config, storage: Arc<Storage>, protocol: Protocol) -> std::io::Result<()> {
-    event_loop::run(config, storage, protocol)
+pub fn run(
+    config: Config,
+    storage: Arc<Storage>,
+    protocol: Protocol,
+    readiness: Arc<Readiness>,
+    shutdown: Shutdown,
+    connection_stats: Arc<ConnectionStats>,
+) -> std::io::Result<()> {
+    event_loop::run(
+        config,
+        storage,
+        protocol,
+        readiness,
+        shutdown,
+        connection_stats,
+    )
 }
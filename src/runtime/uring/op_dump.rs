@@ -0,0 +1,84 @@
+//! On-demand dump of in-flight io_uring operations.
+//!
+//! Sending `SIGUSR1` to the process asks every io_uring worker to log its
+//! outstanding `TokenAllocator` entries (token, operation, connection). A
+//! token that never leaves the list points at a leaked or stuck submission.
+//!
+//! Workers wake at least once per `WAIT_TIMEOUT` (see the event loop), so
+//! even a worker with no traffic answers the request promptly.
+
+use super::TokenAllocator;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+
+/// Bumped once per dump request; each worker dumps when it sees a new value.
+static DUMP_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn on_dump_signal(_signal: libc::c_int) {
+    // Only async-signal-safe work here: a single atomic increment
+    DUMP_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Install the `SIGUSR1` handler that requests a dump.
+pub fn install_dump_signal() -> io::Result<()> {
+    let handler = on_dump_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic
+    if unsafe { libc::signal(libc::SIGUSR1, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Per-worker view of dump requests.
+pub struct DumpRequests {
+    seen: u64,
+}
+
+impl DumpRequests {
+    /// Start watching from the current generation.
+    pub fn new() -> Self {
+        Self {
+            seen: DUMP_GENERATION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether a dump was requested since the last call.
+    pub fn take(&mut self) -> bool {
+        let current = DUMP_GENERATION.load(Ordering::Relaxed);
+        if current == self.seen {
+            return false;
+        }
+        self.seen = current;
+        true
+    }
+}
+
+/// Log every outstanding operation of one worker.
+pub fn log_outstanding(worker_id: usize, tokens: &TokenAllocator) {
+    let outstanding = tokens.outstanding();
+    info!(
+        worker = worker_id,
+        count = outstanding.len(),
+        "Outstanding io_uring operations"
+    );
+    for (token, op) in outstanding {
+        info!(worker = worker_id, token, conn_id = ?op.conn_id(), op = ?op, "In-flight op");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_requests_dump() {
+        install_dump_signal().unwrap();
+        let mut requests = DumpRequests::new();
+        assert!(!requests.take());
+
+        unsafe { libc::raise(libc::SIGUSR1) };
+        assert!(requests.take());
+        assert!(!requests.take());
+    }
+}
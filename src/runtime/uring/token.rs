@@ -2,6 +2,11 @@
 //!
 //! Each submitted operation gets a unique token (user_data) that identifies
 //! the operation type and associated resources when the completion arrives.
+//!
+//! A token is a slab slot in the low 32 bits and that slot's generation in
+//! the high 32 bits. Freeing a slot bumps its generation, so a stale or
+//! duplicate completion for a reused slot no longer matches and is reported
+//! as unknown instead of being dispatched to the slot's new operation.
 
 #![allow(dead_code)] // Will be used when io_uring is wired in
 
@@ -18,6 +23,12 @@ pub enum OpType {
         /// Connection identifier in the registry.
         conn_id: usize,
     },
+    /// Timeout linked to a connection's read, cancelling it when the
+    /// connection stays idle.
+    ReadTimeout {
+        /// Connection identifier in the registry.
+        conn_id: usize,
+    },
     /// Write operation on a connection.
     Write {
         /// Connection identifier in the registry.
@@ -27,12 +38,26 @@ pub enum OpType {
     },
 }
 
+impl OpType {
+    /// Connection this operation belongs to, if any.
+    pub fn conn_id(&self) -> Option<usize> {
+        match *self {
+            OpType::Accept => None,
+            OpType::Read { conn_id }
+            | OpType::ReadTimeout { conn_id }
+            | OpType::Write { conn_id, .. } => Some(conn_id),
+        }
+    }
+}
+
 /// Allocator for operation tokens with O(1) lookup.
 ///
 /// Uses a slab to efficiently allocate and deallocate tokens,
 /// providing stable identifiers for in-flight operations.
 pub struct TokenAllocator {
     ops: Slab<OpType>,
+    /// Current generation of each slot ever allocated
+    generations: Vec<u32>,
 }
 
 impl TokenAllocator {
@@ -40,6 +65,7 @@ impl TokenAllocator {
     pub fn new(capacity: usize) -> Self {
         Self {
             ops: Slab::with_capacity(capacity),
+            generations: Vec::with_capacity(capacity),
         }
     }
 
@@ -47,28 +73,47 @@ impl TokenAllocator {
     ///
     /// Returns the token (user_data value for io_uring).
     pub fn alloc(&mut self, op: OpType) -> u64 {
-        self.ops.insert(op) as u64
+        let idx = self.ops.insert(op);
+        if idx == self.generations.len() {
+            self.generations.push(0);
+        }
+        self.token(idx)
     }
 
     /// Get the operation type for a token.
     ///
-    /// Returns None if the token is invalid or already freed.
+    /// Returns None if the token is invalid, already freed, or stale.
     pub fn get(&self, token: u64) -> Option<OpType> {
-        self.ops.get(token as usize).copied()
+        let idx = self.slot(token)?;
+        self.ops.get(idx).copied()
     }
 
     /// Free a token, making it available for reuse.
     ///
-    /// Returns the operation type that was associated with the token.
+    /// Returns the operation type that was associated with the token, or
+    /// None if the token is invalid, already freed, or stale.
     pub fn free(&mut self, token: u64) -> Option<OpType> {
-        let idx = token as usize;
+        let idx = self.slot(token)?;
         if self.ops.contains(idx) {
+            self.generations[idx] = self.generations[idx].wrapping_add(1);
             Some(self.ops.remove(idx))
         } else {
             None
         }
     }
 
+    /// Token for the current generation of a slot.
+    fn token(&self, idx: usize) -> u64 {
+        (u64::from(self.generations[idx]) << 32) | idx as u64
+    }
+
+    /// Slot named by a token, if the token is from the slot's current generation.
+    fn slot(&self, token: u64) -> Option<usize> {
+        let idx = (token & u64::from(u32::MAX)) as usize;
+        let generation = (token >> 32) as u32;
+        (self.generations.get(idx) == Some(&generation)).then_some(idx)
+    }
+
     /// Number of currently allocated tokens.
     pub fn len(&self) -> usize {
         self.ops.len()
@@ -78,6 +123,17 @@ impl TokenAllocator {
     pub fn is_empty(&self) -> bool {
         self.ops.is_empty()
     }
+
+    /// Snapshot of all in-flight operations, ordered by token.
+    ///
+    /// Used to diagnose hangs: a token that stays here without a matching
+    /// completion points at a leaked or never-completed submission.
+    pub fn outstanding(&self) -> Vec<(u64, OpType)> {
+        self.ops
+            .iter()
+            .map(|(idx, op)| (self.token(idx), *op))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -102,8 +158,71 @@ mod tests {
         assert!(alloc.get(t1).is_none());
         assert_eq!(alloc.len(), 1);
 
-        // Allocate reuses slot
+        // Allocate reuses the slot under a new generation
         let t3 = alloc.alloc(OpType::Accept);
-        assert_eq!(t3, t1); // Slab reuses slots
+        assert_eq!(t3 as u32, t1 as u32);
+        assert_ne!(t3, t1);
+    }
+
+    #[test]
+    fn test_stale_token_is_rejected() {
+        let mut alloc = TokenAllocator::new(16);
+
+        let stale = alloc.alloc(OpType::Read { conn_id: 1 });
+        assert!(alloc.free(stale).is_some());
+        let fresh = alloc.alloc(OpType::Write {
+            conn_id: 2,
+            buf_idx: 3,
+        });
+
+        // A late completion for the old read must not reach the new write
+        assert!(alloc.get(stale).is_none());
+        assert!(alloc.free(stale).is_none());
+        assert!(matches!(
+            alloc.get(fresh),
+            Some(OpType::Write { conn_id: 2, .. })
+        ));
+
+        // Freeing twice is caught too
+        assert!(alloc.free(fresh).is_some());
+        assert!(alloc.free(fresh).is_none());
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn test_outstanding_tracks_in_flight_ops() {
+        use io_uring::{opcode, IoUring};
+
+        let mut ring = IoUring::new(8).unwrap();
+        let mut alloc = TokenAllocator::new(16);
+
+        let ops = [
+            OpType::Accept,
+            OpType::Read { conn_id: 4 },
+            OpType::Write {
+                conn_id: 4,
+                buf_idx: 9,
+            },
+        ];
+        for op in ops {
+            let token = alloc.alloc(op);
+            let nop = opcode::Nop::new().build().user_data(token);
+            unsafe { ring.submission().push(&nop).unwrap() };
+        }
+        ring.submit().unwrap();
+
+        // Submitted but not yet reaped: all three are outstanding
+        let outstanding = alloc.outstanding();
+        assert_eq!(outstanding.len(), 3);
+        assert!(matches!(outstanding[0], (0, OpType::Accept)));
+        let conns: Vec<_> = outstanding.iter().map(|(_, op)| op.conn_id()).collect();
+        assert_eq!(conns, vec![None, Some(4), Some(4)]);
+
+        // Draining completions frees every token
+        ring.submit_and_wait(3).unwrap();
+        for cqe in ring.completion() {
+            assert!(alloc.free(cqe.user_data()).is_some());
+        }
+        assert!(alloc.outstanding().is_empty());
     }
 }
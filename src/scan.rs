@@ -0,0 +1,147 @@
+//! Keys in a fixed order for cursor scans.
+//!
+//! A `HashMap` reorders its keys whenever it grows, so a cursor into it
+//! would skip or repeat keys. Instead, each key is placed by a hash of its
+//! own, fixed for the life of the index, and the cursor is simply the next
+//! hash to visit. Inserting or removing other keys never moves a key, so a
+//! scan that runs until the cursor comes back 0 returns every key present
+//! for its whole duration exactly once. A key added or removed mid-scan may
+//! or may not be returned, but never twice.
+//!
+//! The few keys sharing a hash are always returned in the same page, so a
+//! cursor never has to point between them.
+//!
+//! Like the LRU list, the index keeps its own copy of each key, not charged
+//! to `max_memory`.
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::BuildHasher;
+
+/// Keys ordered by a per-index hash.
+pub(crate) struct ScanIndex {
+    /// Seeded once, so a key keeps its place while the index lives
+    hasher: RandomState,
+    /// Keys by hash; almost every bucket holds one
+    buckets: BTreeMap<u64, Vec<String>>,
+}
+
+impl ScanIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    /// Add `key`, if not already present.
+    pub(crate) fn insert(&mut self, key: &str) {
+        let bucket = self.buckets.entry(self.hasher.hash_one(key)).or_default();
+        if !bucket.iter().any(|k| k == key) {
+            bucket.push(key.to_string());
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        let hash = self.hasher.hash_one(key);
+        if let Some(bucket) = self.buckets.get_mut(&hash) {
+            bucket.retain(|k| k != key);
+            if bucket.is_empty() {
+                self.buckets.remove(&hash);
+            }
+        }
+    }
+
+    /// The hash `key` is placed by, whether or not it is present: a page
+    /// from `cursor` on holds it if this is at least `cursor`.
+    pub(crate) fn position(&self, key: &str) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    /// About `count` keys from `cursor` on (at least one if any are left),
+    /// and the cursor to continue from, 0 once the scan is complete.
+    pub(crate) fn page(&self, cursor: u64, count: usize) -> (u64, Vec<&str>) {
+        let mut keys = Vec::new();
+        for (&hash, bucket) in self.buckets.range(cursor..) {
+            if keys.len() >= count.max(1) {
+                return (hash, keys);
+            }
+            keys.extend(bucket.iter().map(String::as_str));
+        }
+        (0, keys)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.values().map(Vec::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Every key of a full scan in pages of `count`.
+    fn scan_all(index: &ScanIndex, count: usize) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, page) = index.page(cursor, count);
+            keys.extend(page.into_iter().map(str::to_string));
+            if next == 0 {
+                return keys;
+            }
+            assert!(next > cursor, "cursor went backwards");
+            cursor = next;
+        }
+    }
+
+    #[test]
+    fn test_full_scan_returns_each_key_once() {
+        let mut index = ScanIndex::new();
+        for i in 0..1000 {
+            index.insert(&format!("key{i}"));
+        }
+        // Inserting again changes nothing
+        index.insert("key7");
+        assert_eq!(index.len(), 1000);
+
+        for count in [1, 10, 999, 5000] {
+            let keys = scan_all(&index, count);
+            assert_eq!(keys.len(), 1000, "count {count}");
+            let unique: HashSet<_> = keys.iter().collect();
+            assert_eq!(unique.len(), 1000, "count {count}");
+        }
+    }
+
+    #[test]
+    fn test_remove_and_clear() {
+        let mut index = ScanIndex::new();
+        index.insert("a");
+        index.insert("b");
+        index.remove("a");
+        index.remove("missing");
+        assert_eq!(scan_all(&index, 10), ["b"]);
+        assert!(index.buckets.values().all(|bucket| !bucket.is_empty()));
+
+        index.clear();
+        assert_eq!(index.page(0, 10), (0, vec![]));
+    }
+
+    #[test]
+    fn test_page_keeps_a_bucket_together() {
+        let mut index = ScanIndex::new();
+        // Force a collision
+        index.buckets.insert(5, vec!["x".into(), "y".into()]);
+        index.buckets.insert(9, vec!["z".into()]);
+
+        assert_eq!(index.page(0, 1), (9, vec!["x", "y"]));
+        assert_eq!(index.page(9, 1), (0, vec!["z"]));
+        assert_eq!(index.page(6, 0), (0, vec!["z"]));
+    }
+}
@@ -2,16 +2,75 @@
 //!
 //! Provides a thread-safe key-value store with:
 //! - Automatic expiration of items
-//! - Memory usage tracking and capping
-//! - LRU eviction when memory limit is reached
+//! - Memory usage tracking and capping, with an optional separate cap on
+//!   stored value bytes
+//! - Eviction when memory limit is reached, by LRU, LFU, or random choice,
+//!   or refusing writes instead
 //! - CAS (compare-and-swap) support
+//! - Atomic multi-key commits guarded by watched CAS tokens
+//! - Snapshot dump/load in binary or JSON format
+//! - A read-only flag for maintenance windows
+//! - A callback told about every eviction and expiry
+//! - Optional short-lived tombstones for deleted keys, so a miss right after
+//!   a delete can hand one client the job of recaching it
+//! - Optional recache leases, doing the same for any miss so a stampede on
+//!   a missing key is answered by one client
+//! - Item size histograms, sampled on large caches to bound the lock hold
+//! - A pressure signal for autoscaling: recent eviction rate and the
+//!   fraction of memory in use
 
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use crate::hotkeys::HotKeys;
+use crate::lru::LruList;
+use crate::scan::ScanIndex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::hash::BuildHasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::{debug, info, trace};
 
+/// Largest TTL read as relative seconds (30 days); larger TTLs are absolute
+/// Unix timestamps, as in memcached.
+pub const MAX_RELATIVE_TTL: u64 = 60 * 60 * 24 * 30;
+
+/// A TTL that is always in the past (a timestamp in 1970), for storing an
+/// item already expired.
+pub const EXPIRED_TTL: u64 = MAX_RELATIVE_TTL + 1;
+
+/// Minimum time between full expiry sweeps made on behalf of eviction.
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Least recently used keys LFU eviction compares the read counts of.
+const LFU_SAMPLE: usize = 16;
+
+/// Span the reported eviction rate is averaged over.
+const EVICTION_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Minimum time between the eviction counter readings the rate is taken
+/// from, bounding how many are kept.
+const EVICTION_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Which item to drop when a write needs room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// Least recently used
+    #[default]
+    Lru,
+    /// Least frequently read, among the least recently used few
+    Lfu,
+    /// Any item
+    Random,
+    /// None: writes that need room fail with `OutOfMemory`
+    NoEviction,
+}
+
 /// A single cached item
 #[derive(Debug, Clone)]
 pub struct CacheItem {
@@ -25,6 +84,77 @@ pub struct CacheItem {
     pub cas_unique: u64,
     /// Last access time for LRU eviction
     pub last_accessed: Instant,
+    /// The kind of value stored
+    pub value_type: ValueType,
+    /// How `value` is encoded internally
+    pub encoding: ValueEncoding,
+    /// Reads of this value, for LFU eviction
+    pub frequency: AccessFrequency,
+}
+
+/// Count of reads of an item, saturating at `u32::MAX`.
+///
+/// Atomic because reads only hold the data read lock.
+#[derive(Debug, Default)]
+pub struct AccessFrequency(AtomicU32);
+
+impl AccessFrequency {
+    fn hit(&self) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1));
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for AccessFrequency {
+    fn clone(&self) -> Self {
+        Self(AtomicU32::new(self.get()))
+    }
+}
+
+/// The kind of value an item holds, as `TYPE` reports it.
+///
+/// Commands for one kind refuse keys holding another, so new kinds can be
+/// added alongside strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueType {
+    /// Bytes, possibly a counter; everything memcached stores
+    #[default]
+    String,
+}
+
+impl ValueType {
+    /// The name `TYPE` replies with.
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+        }
+    }
+}
+
+/// Internal encoding of an item's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueEncoding {
+    /// Bytes exactly as the client stored them
+    #[default]
+    Raw,
+    /// A counter written by incr/decr, stored as an 8-byte little-endian
+    /// u64 so repeated updates skip the decimal parse/format round trip
+    Integer,
+}
+
+impl ValueEncoding {
+    /// The name `OBJECT ENCODING` and `DEBUG OBJECT` report.
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueEncoding::Raw => "raw",
+            ValueEncoding::Integer => "int",
+        }
+    }
 }
 
 impl CacheItem {
@@ -41,6 +171,91 @@ impl CacheItem {
             false
         }
     }
+
+    /// Remaining time until this item expires (None = never expires)
+    pub fn remaining_ttl(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|expires_at| expires_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// The value as clients see it (counters formatted as decimal text).
+    pub fn client_value(&self) -> Cow<'_, [u8]> {
+        match self.integer() {
+            Some(n) => Cow::Owned(n.to_string().into_bytes()),
+            None => Cow::Borrowed(&self.value),
+        }
+    }
+
+    /// The counter value of an integer-encoded item.
+    fn integer(&self) -> Option<u64> {
+        match self.encoding {
+            ValueEncoding::Integer => Some(u64::from_le_bytes(self.value[..8].try_into().ok()?)),
+            ValueEncoding::Raw => None,
+        }
+    }
+
+    /// The value as a signed counter, if it reads as a decimal i64.
+    fn signed(&self) -> Option<i64> {
+        match self.integer() {
+            Some(n) => i64::try_from(n).ok(),
+            None => std::str::from_utf8(&self.value).ok()?.parse().ok(),
+        }
+    }
+
+    /// Clone with the value in its client-visible form.
+    fn to_client(&self) -> CacheItem {
+        match self.encoding {
+            ValueEncoding::Raw => self.clone(),
+            ValueEncoding::Integer => CacheItem {
+                value: self.client_value().into_owned(),
+                encoding: ValueEncoding::Raw,
+                ..self.clone()
+            },
+        }
+    }
+}
+
+/// Result of an incr/decr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterResult {
+    /// The counter's new value
+    Value(u64),
+    /// Key not found (or expired)
+    NotFound,
+    /// Existing value is not a decimal u64
+    NotNumeric,
+    /// No room to create the counter under `EvictionPolicy::NoEviction`
+    OutOfMemory,
+}
+
+/// Result of a signed (Redis-style) counter update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedCounterResult {
+    /// The counter's new value
+    Value(i64),
+    /// Existing value is not a decimal i64
+    NotNumeric,
+    /// The result would not fit in an i64
+    Overflow,
+    /// No room to create the counter under `EvictionPolicy::NoEviction`
+    OutOfMemory,
+}
+
+/// Whether a set created a key or overwrote a live one, or found no room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOutcome {
+    Created,
+    Replaced,
+    OutOfMemory,
+}
+
+impl From<SetOutcome> for StorageResult {
+    fn from(outcome: SetOutcome) -> Self {
+        match outcome {
+            SetOutcome::Created | SetOutcome::Replaced => StorageResult::Stored,
+            SetOutcome::OutOfMemory => StorageResult::OutOfMemory,
+        }
+    }
 }
 
 /// Result of a storage operation
@@ -59,8 +274,125 @@ pub enum StorageResult {
     CasMismatch,
     /// Successfully deleted
     Deleted,
+    /// Expiration updated
+    Touched,
+    /// No room for the write under `EvictionPolicy::NoEviction`
+    OutOfMemory,
+    /// Value read by a transaction
+    Value(Vec<u8>),
+}
+
+/// When a set writes: always, only if the key is absent, or only if present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    Always,
+    IfAbsent,
+    IfPresent,
+}
+
+impl SetCondition {
+    /// Whether the set goes ahead, given whether the key holds a live item.
+    pub fn allows(self, live: bool) -> bool {
+        match self {
+            SetCondition::Always => true,
+            SetCondition::IfAbsent => !live,
+            SetCondition::IfPresent => live,
+        }
+    }
+}
+
+/// A command queued in an optimistic transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxnOp {
+    /// Store a value (same semantics as `Storage::set_until`, or
+    /// `add_until`/`replace_until` under a condition)
+    Set {
+        key: String,
+        value: Vec<u8>,
+        flags: u32,
+        expires_at: Option<Instant>,
+        condition: SetCondition,
+    },
+    /// Remove a key
+    Delete { key: String },
+    /// Read a value as of the commit (`StorageResult::Value`, or `NotFound`)
+    Get { key: String },
+}
+
+impl TxnOp {
+    /// The key the op reads or writes.
+    pub fn key(&self) -> &str {
+        match self {
+            TxnOp::Set { key, .. } | TxnOp::Delete { key } | TxnOp::Get { key } => key,
+        }
+    }
+}
+
+/// Serialization format for storage snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    /// Compact length-prefixed binary encoding (fast path)
+    #[default]
+    Binary,
+    /// Human-readable JSON with base64-encoded values (for debugging)
+    Json,
+}
+
+/// Magic bytes at the start of a binary snapshot
+const SNAPSHOT_MAGIC: &[u8; 4] = b"GACS";
+
+/// Snapshot format version
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// A single item as recorded in a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotEntry {
+    key: String,
+    /// Value bytes (base64-encoded in JSON snapshots)
+    #[serde(with = "base64_bytes")]
+    value: Vec<u8>,
+    flags: u32,
+    /// Remaining TTL in milliseconds at dump time (None = never expires)
+    ttl_ms: Option<u64>,
+}
+
+/// JSON snapshot document
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonSnapshot {
+    version: u8,
+    items: Vec<SnapshotEntry>,
+}
+
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(encoded.as_bytes())
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Why an item left the cache without a client removing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// Dropped to make room under a memory limit
+    Evicted,
+    /// Removed after its TTL ran out, by the reaper or on access
+    Expired,
 }
 
+/// Hook for downstream invalidation, called with each removed key.
+pub type RemovalCallback = Box<dyn Fn(&str, RemovalReason) + Send + Sync>;
+
 /// Thread-safe in-memory cache storage
 pub struct Storage {
     /// The actual storage
@@ -69,56 +401,362 @@ pub struct Storage {
     memory_used: AtomicU64,
     /// Maximum memory allowed
     max_memory: usize,
+    /// How writes make room once a limit is reached
+    eviction_policy: EvictionPolicy,
+    /// Sum of stored value lengths (no per-item overhead)
+    value_bytes: AtomicU64,
+    /// Maximum value bytes allowed (`None` = only `max_memory` applies)
+    max_value_bytes: Option<usize>,
     /// Default TTL in seconds (0 = no expiration)
     default_ttl: u64,
     /// CAS unique counter
     cas_counter: AtomicU64,
-    /// Access order for LRU (key -> access sequence number)
-    access_order: RwLock<HashMap<String, u64>>,
+    /// Keys from most to least recently used
+    access_order: RwLock<LruList<AccessRecord>>,
+    /// Keys in the fixed order cursor scans walk
+    scan_order: RwLock<ScanIndex>,
+    /// Snapshots still being read out, saved items for before writes
+    /// change them
+    snapshots: Mutex<Vec<Weak<Mutex<SnapshotView>>>>,
     /// Access sequence counter
     access_counter: AtomicU64,
+    /// Keys created since startup (overwrites not included)
+    total_items: AtomicU64,
+    /// Keys looked up and found
+    get_hits: AtomicU64,
+    /// Keys looked up and missing or expired
+    get_misses: AtomicU64,
+    /// Store commands received (set, add, replace, cas, append, prepend)
+    cmd_set: AtomicU64,
+    /// Live items evicted under memory pressure
+    evictions: AtomicU64,
+    /// Expired items removed, read or not
+    expired: AtomicU64,
+    /// Items that expired without ever being read
+    expired_unfetched: AtomicU64,
+    /// Clients may only read (checked by the request layer)
+    read_only: AtomicBool,
+    /// Bytes freed by evicting live items under memory pressure
+    evicted_bytes: AtomicU64,
+    /// Bytes freed by removing expired items
+    expired_bytes: AtomicU64,
+    /// Counter values at the last `reset_stats`, subtracted when reported
+    stats_baseline: Mutex<Counters>,
+    /// Told about evictions and expiries, if installed
+    on_removal: OnceLock<RemovalCallback>,
+    /// Removals made under the data lock, reported once it is released
+    removals: Mutex<Vec<(String, RemovalReason)>>,
+    /// How long a delete leaves a tombstone, in milliseconds (0 = never)
+    tombstone_ttl_ms: AtomicU64,
+    /// How long a miss leases its recache to one client, in milliseconds
+    /// (0 = never)
+    recache_lease_ms: AtomicU64,
+    /// Recently deleted keys and leased misses (key -> tombstone)
+    tombstones: Mutex<HashMap<String, Tombstone>>,
+    /// When eviction last swept for expired items
+    last_expiry_sweep: Mutex<Option<Instant>>,
+    /// Most read keys, when tracking is on
+    hot_keys: HotKeys,
+    /// Readings of the eviction counter, oldest first, taken when the
+    /// pressure is reported
+    eviction_samples: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+/// Cumulative counters that `stats reset` restarts from zero.
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    total_items: u64,
+    get_hits: u64,
+    get_misses: u64,
+    cmd_set: u64,
+    evictions: u64,
+    expired: u64,
+    expired_unfetched: u64,
+    evicted_bytes: u64,
+    expired_bytes: u64,
+}
+
+/// Marks a key deleted on purpose, as opposed to evicted or never set, or
+/// a miss whose recache is leased to one client.
+#[derive(Debug, Clone, Copy)]
+struct Tombstone {
+    expires_at: Instant,
+    /// A client already claimed the recache
+    claimed: bool,
+}
+
+/// Most recent access to a key.
+///
+/// Reads only hold the data read lock, so they record the access time here
+/// rather than in `CacheItem::last_accessed`.
+#[derive(Debug, Clone, Copy)]
+struct AccessRecord {
+    /// Access sequence number, lowest is least recently used
+    seq: u64,
+    /// When the access happened
+    at: Instant,
+    /// The current value has been read
+    fetched: bool,
 }
 
 impl Storage {
     /// Create a new storage instance
+    #[allow(dead_code)] // The server builds storage via `with_limits`
     pub fn new(max_memory: usize, default_ttl: u64) -> Arc<Self> {
+        Self::with_limits(max_memory, None, default_ttl, EvictionPolicy::Lru)
+    }
+
+    /// Create a storage instance that also caps the sum of value lengths.
+    ///
+    /// Writes make room per `eviction_policy` when either limit would be
+    /// exceeded.
+    pub fn with_limits(
+        max_memory: usize,
+        max_value_bytes: Option<usize>,
+        default_ttl: u64,
+        eviction_policy: EvictionPolicy,
+    ) -> Arc<Self> {
         info!(
             max_memory_mb = max_memory / 1024 / 1024,
-            default_ttl, "Initializing storage"
+            ?max_value_bytes,
+            default_ttl,
+            ?eviction_policy,
+            "Initializing storage"
         );
         Arc::new(Self {
             data: RwLock::new(HashMap::new()),
             memory_used: AtomicU64::new(0),
             max_memory,
+            eviction_policy,
+            value_bytes: AtomicU64::new(0),
+            max_value_bytes,
             default_ttl,
             cas_counter: AtomicU64::new(1),
-            access_order: RwLock::new(HashMap::new()),
+            access_order: RwLock::new(LruList::new()),
+            scan_order: RwLock::new(ScanIndex::new()),
+            snapshots: Mutex::new(Vec::new()),
             access_counter: AtomicU64::new(0),
+            total_items: AtomicU64::new(0),
+            get_hits: AtomicU64::new(0),
+            get_misses: AtomicU64::new(0),
+            cmd_set: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            expired_unfetched: AtomicU64::new(0),
+            read_only: AtomicBool::new(false),
+            evicted_bytes: AtomicU64::new(0),
+            expired_bytes: AtomicU64::new(0),
+            stats_baseline: Mutex::new(Counters::default()),
+            on_removal: OnceLock::new(),
+            removals: Mutex::new(Vec::new()),
+            tombstone_ttl_ms: AtomicU64::new(0),
+            recache_lease_ms: AtomicU64::new(0),
+            tombstones: Mutex::new(HashMap::new()),
+            last_expiry_sweep: Mutex::new(None),
+            hot_keys: HotKeys::new(),
+            eviction_samples: Mutex::new(VecDeque::from([(Instant::now(), 0)])),
         })
     }
 
+    /// Enter or leave read-only mode.
+    ///
+    /// Client writes are rejected while set; internal writes such as
+    /// snapshot loading and eviction are unaffected.
+    pub fn set_read_only(&self, read_only: bool) {
+        if self.read_only.swap(read_only, Ordering::SeqCst) != read_only {
+            info!(read_only, "Storage read-only mode changed");
+        }
+    }
+
+    /// Track the most read keys; turning it off forgets them.
+    pub fn set_hotkey_tracking(&self, enabled: bool) {
+        self.hot_keys.set_enabled(enabled);
+    }
+
+    /// Up to `n` of the most read keys with their estimated reads, hottest
+    /// first, or `None` if tracking is off.
+    pub fn hot_keys(&self, n: usize) -> Option<Vec<(String, u64)>> {
+        self.hot_keys.is_enabled().then(|| self.hot_keys.top(n))
+    }
+
+    /// Leave a tombstone for `ttl` on every delete (zero turns them off).
+    pub fn set_tombstone_ttl(&self, ttl: Duration) {
+        self.tombstone_ttl_ms
+            .store(ttl.as_millis() as u64, Ordering::SeqCst);
+        if ttl.is_zero() {
+            self.tombstones.lock().unwrap().clear();
+        }
+    }
+
+    /// Lease the recache of every missing key to its first claimant for
+    /// `lease` (zero turns leases off).
+    pub fn set_recache_lease(&self, lease: Duration) {
+        self.recache_lease_ms
+            .store(lease.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Claim the recache of a missing key.
+    ///
+    /// Returns `Some(true)` to the first caller after a delete, which
+    /// should repopulate the key, and `Some(false)` to later ones until the
+    /// tombstone expires. With recache leases on, a key that was never
+    /// deleted works the same way: the first miss takes a lease and the
+    /// misses behind it wait on that client. `None` if neither applies.
+    pub fn claim_recache(&self, key: &str) -> Option<bool> {
+        let now = Instant::now();
+        let mut tombstones = self.tombstones.lock().unwrap();
+        if let Some(tombstone) = tombstones.get_mut(key) {
+            if tombstone.expires_at > now {
+                return Some(!std::mem::replace(&mut tombstone.claimed, true));
+            }
+            tombstones.remove(key);
+        }
+
+        let lease_ms = self.recache_lease_ms.load(Ordering::SeqCst);
+        if lease_ms == 0 {
+            return None;
+        }
+        tombstones.insert(
+            key.to_string(),
+            Tombstone {
+                expires_at: now + Duration::from_millis(lease_ms),
+                claimed: true,
+            },
+        );
+        Some(true)
+    }
+
+    /// Install the callback told about evictions and expiries.
+    ///
+    /// It runs after the data lock is released, so it may call back into
+    /// storage. Only the first callback installed takes effect.
+    #[allow(dead_code)] // For downstream invalidation hooks
+    pub fn set_removal_callback(&self, callback: RemovalCallback) -> bool {
+        self.on_removal.set(callback).is_ok()
+    }
+
+    /// Remember a removal for the callback, while holding the data lock.
+    fn queue_removal(&self, key: &str, reason: RemovalReason) {
+        if self.on_removal.get().is_some() {
+            self.removals
+                .lock()
+                .unwrap()
+                .push((key.to_string(), reason));
+        }
+    }
+
+    /// Report queued removals. Call with the data lock released.
+    fn notify_removals(&self) {
+        let Some(callback) = self.on_removal.get() else {
+            return;
+        };
+        let removals = std::mem::take(&mut *self.removals.lock().unwrap());
+        for (key, reason) in removals {
+            callback(&key, reason);
+        }
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Whether client writes are currently rejected.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
     /// Generate a new CAS unique token
+    ///
+    /// The counter wraps at `u64::MAX` but never hands out 0, which clients
+    /// treat as "no CAS".
     fn next_cas_unique(&self) -> u64 {
-        self.cas_counter.fetch_add(1, Ordering::SeqCst)
+        loop {
+            let cas = self.cas_counter.fetch_add(1, Ordering::SeqCst);
+            if cas != 0 {
+                return cas;
+            }
+        }
+    }
+
+    /// Move the CAS counter past a token assigned from outside.
+    fn reserve_cas_unique(&self, cas: u64) {
+        let _ = self
+            .cas_counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+                (current <= cas).then_some(cas.wrapping_add(1))
+            });
+    }
+
+    /// Seed the CAS counter, e.g. to exercise wraparound.
+    #[cfg(test)]
+    pub fn set_cas_counter(&self, value: u64) {
+        self.cas_counter.store(value, Ordering::SeqCst);
     }
 
-    /// Record an access to a key for LRU tracking
-    fn record_access(&self, key: &str) {
+    /// Record an access to a key for LRU tracking.
+    ///
+    /// `fetched` says whether the current value has now been read (`None`
+    /// leaves that unchanged), for counting items that expire unread.
+    fn record_access(&self, key: &str, fetched: Option<bool>) {
         let seq = self.access_counter.fetch_add(1, Ordering::SeqCst);
         if let Ok(mut order) = self.access_order.write() {
-            order.insert(key.to_string(), seq);
+            let fetched =
+                fetched.unwrap_or_else(|| order.get(key).is_some_and(|access| access.fetched));
+            order.touch(
+                key,
+                AccessRecord {
+                    seq,
+                    at: Instant::now(),
+                    fetched,
+                },
+            );
         }
     }
 
-    /// Calculate expiration time from TTL
-    fn calculate_expiry(&self, ttl: u64) -> Option<Instant> {
-        let effective_ttl = if ttl == 0 { self.default_ttl } else { ttl };
-        if effective_ttl == 0 {
-            None
+    /// Record a read of `key`, a hit if it was found.
+    fn record_lookup(&self, key: &str, hit: bool) {
+        self.hot_keys.record(key);
+        if hit {
+            self.get_hits.fetch_add(1, Ordering::Relaxed);
+            self.record_access(key, Some(true));
         } else {
-            // Memcached treats values > 30 days as Unix timestamps
-            // For simplicity, we treat all values as relative seconds
-            Some(Instant::now() + Duration::from_secs(effective_ttl))
+            self.get_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count an expired item being removed, before its LRU entry goes.
+    fn note_expired(&self, key: &str, item: &CacheItem) {
+        self.expired.fetch_add(1, Ordering::Relaxed);
+        self.expired_bytes
+            .fetch_add(footprint(key, item) as u64, Ordering::Relaxed);
+        let fetched = self
+            .access_order
+            .read()
+            .is_ok_and(|order| order.get(key).is_some_and(|access| access.fetched));
+        if !fetched {
+            self.expired_unfetched.fetch_add(1, Ordering::Relaxed);
+        }
+        self.queue_removal(key, RemovalReason::Expired);
+    }
+
+    /// Calculate expiration time from TTL.
+    ///
+    /// Like memcached, 0 means the default TTL, up to 30 days is relative,
+    /// and anything larger is an absolute Unix timestamp (one already past
+    /// expires at once). The configured default is always relative.
+    pub fn calculate_expiry(&self, ttl: u64) -> Option<Instant> {
+        let now = Instant::now();
+        match ttl {
+            0 if self.default_ttl == 0 => None,
+            0 => now.checked_add(Duration::from_secs(self.default_ttl)),
+            1..=MAX_RELATIVE_TTL => Some(now + Duration::from_secs(ttl)),
+            timestamp => {
+                let since_epoch = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                // Too far out to represent means never
+                now.checked_add(Duration::from_secs(timestamp).saturating_sub(since_epoch))
+            }
         }
     }
 
@@ -129,16 +767,107 @@ impl Storage {
             if item.is_expired() {
                 trace!(key, "Item expired on access");
                 drop(data);
-                self.delete(key);
+                self.remove_expired(key);
+                self.record_lookup(key, false);
+                return None;
+            }
+            item.frequency.hit();
+            self.record_lookup(key, true);
+            Some(item.to_client())
+        } else {
+            self.record_lookup(key, false);
+            None
+        }
+    }
+
+    /// Get an item together with its remaining TTL.
+    ///
+    /// The TTL is computed under the same read lock as the lookup, so callers
+    /// that need both (e.g. the meta `t` flag) avoid a second round trip.
+    pub fn get_with_ttl(&self, key: &str) -> Option<(CacheItem, Option<Duration>)> {
+        let data = self.data.read().ok()?;
+        if let Some(item) = data.get(key) {
+            if item.is_expired() {
+                trace!(key, "Item expired on access");
+                drop(data);
+                self.remove_expired(key);
+                self.record_lookup(key, false);
                 return None;
             }
-            self.record_access(key);
-            Some(item.clone())
+            item.frequency.hit();
+            self.record_lookup(key, true);
+            let ttl = item.remaining_ttl();
+            Some((item.to_client(), ttl))
         } else {
+            self.record_lookup(key, false);
             None
         }
     }
 
+    /// Get the remaining TTL of an item without touching its LRU position.
+    ///
+    /// Returns `None` if the key is missing or expired, `Some(None)` if the
+    /// item never expires.
+    pub fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        let data = self.data.read().ok()?;
+        match data.get(key) {
+            Some(item) if !item.is_expired() => Some(item.remaining_ttl()),
+            _ => None,
+        }
+    }
+
+    /// Time since an item was last read or written, without touching its
+    /// LRU position (`None` if missing or expired).
+    pub fn idle_time(&self, key: &str) -> Option<Duration> {
+        self.entry_stats(key).map(|entry| entry.idle)
+    }
+
+    /// The kind of value under a key, without counting a read or touching
+    /// its LRU position (`None` if missing or expired).
+    pub fn value_type(&self, key: &str) -> Option<ValueType> {
+        let data = self.data.read().ok()?;
+        data.get(key)
+            .filter(|item| !item.is_expired())
+            .map(|item| item.value_type)
+    }
+
+    /// Reads of an item's current value, without counting this one (`None`
+    /// if missing or expired).
+    pub fn access_frequency(&self, key: &str) -> Option<u32> {
+        let data = self.data.read().ok()?;
+        data.get(key)
+            .filter(|item| !item.is_expired())
+            .map(|item| item.frequency.get())
+    }
+
+    /// Internal details of one item for introspection, without touching its
+    /// LRU position (`None` if missing or expired).
+    pub fn entry_stats(&self, key: &str) -> Option<EntryStats> {
+        let (last_write, value_len, value_type, encoding) = {
+            let data = self.data.read().ok()?;
+            let item = data.get(key).filter(|item| !item.is_expired())?;
+            (
+                item.last_accessed,
+                item.value.len(),
+                item.value_type,
+                item.encoding,
+            )
+        };
+        let last_read = self
+            .access_order
+            .read()
+            .ok()
+            .and_then(|order| order.get(key).copied());
+        let last_access = last_read.map_or(last_write, |access| access.at.max(last_write));
+        Some(EntryStats {
+            value_len,
+            value_type,
+            encoding,
+            lru_seq: last_read.map_or(0, |access| access.seq),
+            idle: last_access.elapsed(),
+        })
+    }
+
     /// Get multiple items from storage
     pub fn get_multi(&self, keys: &[&str]) -> Vec<(String, CacheItem)> {
         let data = self.data.read().unwrap();
@@ -146,13 +875,14 @@ impl Storage {
         let mut expired_keys = Vec::new();
 
         for &key in keys {
-            if let Some(item) = data.get(key) {
-                if item.is_expired() {
-                    expired_keys.push(key.to_string());
-                } else {
-                    self.record_access(key);
-                    results.push((key.to_string(), item.clone()));
+            match data.get(key) {
+                Some(item) if item.is_expired() => expired_keys.push(key.to_string()),
+                Some(item) => {
+                    item.frequency.hit();
+                    self.record_lookup(key, true);
+                    results.push((key.to_string(), item.to_client()));
                 }
+                None => self.record_lookup(key, false),
             }
         }
 
@@ -160,7 +890,45 @@ impl Storage {
 
         // Clean up expired items
         for key in expired_keys {
-            self.delete(&key);
+            self.remove_expired(&key);
+            self.record_lookup(&key, false);
+        }
+
+        results
+    }
+
+    /// Get multiple items, giving each one found a new TTL (0 = the
+    /// default TTL, as for `set`).
+    ///
+    /// Values and CAS tokens are unchanged; expired items are skipped and
+    /// removed.
+    pub fn get_and_touch(&self, keys: &[&str], ttl: u64) -> Vec<(String, CacheItem)> {
+        let expires_at = self.calculate_expiry(ttl);
+        let mut data = self.data.write().unwrap();
+        let mut results = Vec::new();
+
+        for &key in keys {
+            self.preserve(&data, key);
+            match data.get_mut(key) {
+                Some(item) if item.is_expired() => {
+                    self.reclaim_expired(&mut data, key);
+                    self.get_misses.fetch_add(1, Ordering::Relaxed);
+                }
+                Some(item) => {
+                    item.expires_at = expires_at;
+                    item.frequency.hit();
+                    results.push((key.to_string(), item.to_client()));
+                }
+                None => {
+                    self.get_misses.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        drop(data);
+        self.notify_removals();
+        for (key, _) in &results {
+            self.record_lookup(key, true);
         }
 
         results
@@ -168,71 +936,246 @@ impl Storage {
 
     /// Set an item in storage
     pub fn set(&self, key: &str, value: Vec<u8>, flags: u32, ttl: u64) -> StorageResult {
+        self.store(key, value, flags, ttl).into()
+    }
+
+    /// Set an item under a CAS token chosen by the caller, e.g. a
+    /// replication leader's.
+    ///
+    /// Later tokens from this node are issued above `cas`. A `cas` of 0
+    /// means "no CAS" and gets a fresh token, as with `set`.
+    #[allow(dead_code)] // For the replication/import path
+    pub fn set_with_cas(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        ttl: u64,
+        cas: u64,
+    ) -> StorageResult {
+        let cas_unique = if cas == 0 {
+            self.next_cas_unique()
+        } else {
+            self.reserve_cas_unique(cas);
+            cas
+        };
+        self.store_item(key, value, flags, self.calculate_expiry(ttl), cas_unique)
+            .into()
+    }
+
+    /// Set an item, returning its new CAS token (`None` if there was no
+    /// room for it).
+    pub fn set_returning_cas(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        ttl: u64,
+    ) -> Option<u64> {
+        let cas_unique = self.next_cas_unique();
+        match self.store_item(key, value, flags, self.calculate_expiry(ttl), cas_unique) {
+            SetOutcome::OutOfMemory => None,
+            SetOutcome::Created | SetOutcome::Replaced => Some(cas_unique),
+        }
+    }
+
+    /// Set an item, reporting whether the key was created or overwritten.
+    pub fn store(&self, key: &str, value: Vec<u8>, flags: u32, ttl: u64) -> SetOutcome {
+        self.store_item(
+            key,
+            value,
+            flags,
+            self.calculate_expiry(ttl),
+            self.next_cas_unique(),
+        )
+    }
+
+    /// Set an item that expires at `expires_at` (never, if `None`) rather
+    /// than after a memcached-style TTL.
+    pub fn set_until(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        expires_at: Option<Instant>,
+    ) -> StorageResult {
+        self.store_item(key, value, flags, expires_at, self.next_cas_unique())
+            .into()
+    }
+
+    fn store_item(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        expires_at: Option<Instant>,
+        cas_unique: u64,
+    ) -> SetOutcome {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
         let item = CacheItem {
             value,
             flags,
-            expires_at: self.calculate_expiry(ttl),
-            cas_unique: self.next_cas_unique(),
+            expires_at,
+            cas_unique,
             last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
         };
 
-        let new_size = item.memory_size() + key.len();
-
-        // Check if we need to evict items
-        self.ensure_memory_available(new_size);
-
-        let mut data = self.data.write().unwrap();
-
-        // Account for old item's memory if replacing
-        if let Some(old_item) = data.get(key) {
-            let old_size = old_item.memory_size() + key.len();
-            self.memory_used
-                .fetch_sub(old_size as u64, Ordering::SeqCst);
+        // Only growth over the item being replaced needs room (it may be
+        // gone by the time the lock is retaken; `put` accounts for that)
+        let (old_size, old_len) = self
+            .data
+            .read()
+            .unwrap()
+            .get(key)
+            .map_or((0, 0), |old| (footprint(key, old), old.value.len()));
+        let needed = footprint(key, &item).saturating_sub(old_size);
+        if !self.ensure_memory_available(needed, item.value.len().saturating_sub(old_len)) {
+            return SetOutcome::OutOfMemory;
         }
 
-        self.memory_used
-            .fetch_add(new_size as u64, Ordering::SeqCst);
-        data.insert(key.to_string(), item);
-        self.record_access(key);
+        let mut data = self.data.write().unwrap();
+        let outcome = self.put(&mut data, key, item);
+        drop(data);
+        self.notify_removals();
 
         trace!(
             key,
+            ?outcome,
             memory_used = self.memory_used.load(Ordering::SeqCst),
             "Item stored"
         );
-        StorageResult::Stored
+        outcome
     }
 
     /// Add an item only if it doesn't exist
     pub fn add(&self, key: &str, value: Vec<u8>, flags: u32, ttl: u64) -> StorageResult {
-        // Check if key exists and is not expired
-        {
-            let data = self.data.read().unwrap();
-            if let Some(item) = data.get(key) {
-                if !item.is_expired() {
-                    return StorageResult::NotStored;
-                }
-            }
-        }
-
-        self.set(key, value, flags, ttl)
-    }
-
-    /// Replace an item only if it exists
-    pub fn replace(&self, key: &str, value: Vec<u8>, flags: u32, ttl: u64) -> StorageResult {
-        // Check if key exists and is not expired
-        {
-            let data = self.data.read().unwrap();
-            match data.get(key) {
-                Some(item) if !item.is_expired() => {}
-                _ => return StorageResult::NotStored,
-            }
-        }
-
-        self.set(key, value, flags, ttl)
+        self.add_until(key, value, flags, self.calculate_expiry(ttl))
     }
 
-    /// CAS (compare-and-swap) - update only if CAS token matches
+    /// `add`, expiring at `expires_at` as with `set_until`.
+    pub fn add_until(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        expires_at: Option<Instant>,
+    ) -> StorageResult {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
+        let is_live = |data: &HashMap<String, CacheItem>| {
+            data.get(key).is_some_and(|item| !item.is_expired())
+        };
+        // Most adds of a key that exists stop here, without making room
+        if is_live(&self.data.read().unwrap()) {
+            return StorageResult::NotStored;
+        }
+
+        let item = CacheItem {
+            value,
+            flags,
+            expires_at,
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
+        };
+        if !self.ensure_memory_available(footprint(key, &item), item.value.len()) {
+            return StorageResult::OutOfMemory;
+        }
+
+        // Checked again under the write lock, so of two racing adds only
+        // one stores
+        let mut data = self.data.write().unwrap();
+        let result = if is_live(&data) {
+            StorageResult::NotStored
+        } else {
+            self.put(&mut data, key, item);
+            StorageResult::Stored
+        };
+        drop(data);
+        self.notify_removals();
+        result
+    }
+
+    /// Set an item, returning the live item it replaced (`Some(None)` if
+    /// there was none, `None` if there was no room for the new one).
+    ///
+    /// The old item is read under the same hold of the lock as the write,
+    /// so no other write can land between them. Counts as a lookup.
+    pub fn get_and_set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        ttl: u64,
+    ) -> Option<Option<CacheItem>> {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
+        let item = CacheItem {
+            value,
+            flags,
+            expires_at: self.calculate_expiry(ttl),
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
+        };
+        // As in `store_item`, only growth over the old item needs room
+        let (old_size, old_len) = self
+            .data
+            .read()
+            .unwrap()
+            .get(key)
+            .map_or((0, 0), |old| (footprint(key, old), old.value.len()));
+        let needed = footprint(key, &item).saturating_sub(old_size);
+        if !self.ensure_memory_available(needed, item.value.len().saturating_sub(old_len)) {
+            return None;
+        }
+
+        let mut data = self.data.write().unwrap();
+        let old = data
+            .get(key)
+            .filter(|old| !old.is_expired())
+            .map(CacheItem::to_client);
+        self.put(&mut data, key, item);
+        drop(data);
+        self.notify_removals();
+        self.record_lookup(key, old.is_some());
+        Some(old)
+    }
+
+    /// Replace an item only if it exists
+    pub fn replace(&self, key: &str, value: Vec<u8>, flags: u32, ttl: u64) -> StorageResult {
+        self.replace_until(key, value, flags, self.calculate_expiry(ttl))
+    }
+
+    /// `replace`, expiring at `expires_at` as with `set_until`.
+    pub fn replace_until(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        flags: u32,
+        expires_at: Option<Instant>,
+    ) -> StorageResult {
+        // Check if key exists and is not expired
+        {
+            let data = self.data.read().unwrap();
+            match data.get(key) {
+                Some(item) if !item.is_expired() => {}
+                _ => {
+                    self.cmd_set.fetch_add(1, Ordering::Relaxed);
+                    return StorageResult::NotStored;
+                }
+            }
+        }
+
+        self.set_until(key, value, flags, expires_at)
+    }
+
+    /// CAS (compare-and-swap) - update only if CAS token matches
     pub fn cas(
         &self,
         key: &str,
@@ -241,88 +1184,218 @@ impl Storage {
         ttl: u64,
         cas_unique: u64,
     ) -> StorageResult {
-        let mut data = self.data.write().unwrap();
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
+        // Checked once up front, so a failing CAS never makes room
+        let (old_size, old_len) = {
+            let data = self.data.read().unwrap();
+            match data.get(key) {
+                None => return StorageResult::NotFound,
+                Some(item) if item.is_expired() => {
+                    drop(data);
+                    self.remove_expired(key);
+                    return StorageResult::NotFound;
+                }
+                Some(item) if item.cas_unique != cas_unique => {
+                    return StorageResult::CasMismatch;
+                }
+                Some(item) => (footprint(key, item), item.value.len()),
+            }
+        };
 
-        match data.get(key) {
+        let new_item = CacheItem {
+            value,
+            flags,
+            expires_at: self.calculate_expiry(ttl),
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
+        };
+        if !self.ensure_memory_available(
+            footprint(key, &new_item).saturating_sub(old_size),
+            new_item.value.len().saturating_sub(old_len),
+        ) {
+            return StorageResult::OutOfMemory;
+        }
+
+        // And again under the write lock: another write may have landed
+        // while no lock was held, and must not be overwritten
+        let mut data = self.data.write().unwrap();
+        let result = match data.get(key) {
             None => StorageResult::NotFound,
             Some(item) if item.is_expired() => {
-                // Treat expired items as not found
-                let old_size = item.memory_size() + key.len();
-                data.remove(key);
-                self.memory_used
-                    .fetch_sub(old_size as u64, Ordering::SeqCst);
+                self.reclaim_expired(&mut data, key);
                 StorageResult::NotFound
             }
             Some(item) if item.cas_unique != cas_unique => StorageResult::CasMismatch,
-            Some(old_item) => {
-                let old_size = old_item.memory_size() + key.len();
-
-                let new_item = CacheItem {
-                    value,
-                    flags,
-                    expires_at: self.calculate_expiry(ttl),
-                    cas_unique: self.next_cas_unique(),
-                    last_accessed: Instant::now(),
-                };
-                let new_size = new_item.memory_size() + key.len();
-
-                // Update memory tracking
-                self.memory_used
-                    .fetch_sub(old_size as u64, Ordering::SeqCst);
-
-                // Ensure we have memory (release lock temporarily)
-                drop(data);
-                self.ensure_memory_available(new_size);
-                data = self.data.write().unwrap();
+            Some(_) => {
+                self.put(&mut data, key, new_item);
+                StorageResult::Stored
+            }
+        };
+        drop(data);
+        self.notify_removals();
+        result
+    }
 
-                self.memory_used
-                    .fetch_add(new_size as u64, Ordering::SeqCst);
-                data.insert(key.to_string(), new_item);
-                self.record_access(key);
+    /// Set `key` to `new` only if its current value equals `expected`,
+    /// comparing the bytes a client would read rather than a CAS token.
+    ///
+    /// Returns `NotFound` for a missing or expired key and `CasMismatch` if
+    /// the value differs.
+    pub fn compare_value_and_set(
+        &self,
+        key: &str,
+        expected: &[u8],
+        new: Vec<u8>,
+        flags: u32,
+        ttl: u64,
+    ) -> StorageResult {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
+        let new_item = CacheItem {
+            value: new,
+            flags,
+            expires_at: self.calculate_expiry(ttl),
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
+        };
+        // Made room for up front, so the compare and the swap happen under
+        // one hold of the lock
+        if !self.ensure_memory_available(footprint(key, &new_item), new_item.value.len()) {
+            return StorageResult::OutOfMemory;
+        }
 
+        let mut data = self.data.write().unwrap();
+        let result = match data.get(key) {
+            None => StorageResult::NotFound,
+            Some(item) if item.is_expired() => {
+                self.reclaim_expired(&mut data, key);
+                StorageResult::NotFound
+            }
+            Some(item) if item.client_value().as_ref() != expected => StorageResult::CasMismatch,
+            Some(_) => {
+                self.put(&mut data, key, new_item);
                 StorageResult::Stored
             }
-        }
+        };
+        drop(data);
+        self.notify_removals();
+        result
     }
 
     /// Delete an item from storage
     pub fn delete(&self, key: &str) -> StorageResult {
         let mut data = self.data.write().unwrap();
-        if let Some(item) = data.remove(key) {
-            let size = item.memory_size() + key.len();
-            self.memory_used.fetch_sub(size as u64, Ordering::SeqCst);
-            if let Ok(mut order) = self.access_order.write() {
-                order.remove(key);
-            }
+        if self.unlink(&mut data, key).is_some() {
+            drop(data);
             trace!(key, "Item deleted");
+            self.leave_tombstone(key);
             StorageResult::Deleted
         } else {
             StorageResult::NotFound
         }
     }
 
+    /// Delete an item, returning it if it was live.
+    ///
+    /// Read and removed under one hold of the lock, so the item returned is
+    /// exactly the one deleted. Counts as a lookup.
+    pub fn get_and_delete(&self, key: &str) -> Option<CacheItem> {
+        let mut data = self.data.write().unwrap();
+        let item = match data.get(key) {
+            None => None,
+            Some(item) if item.is_expired() => {
+                self.reclaim_expired(&mut data, key);
+                None
+            }
+            Some(_) => self.unlink(&mut data, key),
+        };
+        drop(data);
+        self.notify_removals();
+        self.record_lookup(key, item.is_some());
+        let item = item?;
+        trace!(key, "Item deleted");
+        self.leave_tombstone(key);
+        Some(item.to_client())
+    }
+
+    /// Remember a deleted key for `tombstone_ttl`, if set.
+    fn leave_tombstone(&self, key: &str) {
+        let ttl_ms = self.tombstone_ttl_ms.load(Ordering::SeqCst);
+        if ttl_ms > 0 {
+            self.tombstones.lock().unwrap().insert(
+                key.to_string(),
+                Tombstone {
+                    expires_at: Instant::now() + Duration::from_millis(ttl_ms),
+                    claimed: false,
+                },
+            );
+        }
+    }
+
+    /// Give an existing item a new TTL, keeping its value and CAS token.
+    ///
+    /// An item already expired counts as missing and is removed.
+    pub fn touch(&self, key: &str, ttl: u64) -> StorageResult {
+        match self.set_expiry(key, self.calculate_expiry(ttl)) {
+            Some(_) => StorageResult::Touched,
+            None => StorageResult::NotFound,
+        }
+    }
+
+    /// Replace an item's deadline (`None` never expires), returning the one
+    /// it had, or `None` if the item is missing.
+    ///
+    /// An item already expired counts as missing and is removed.
+    pub fn set_expiry(&self, key: &str, expires_at: Option<Instant>) -> Option<Option<Instant>> {
+        let mut data = self.data.write().unwrap();
+        self.preserve(&data, key);
+        let previous = match data.get_mut(key) {
+            Some(item) if item.is_expired() => {
+                self.reclaim_expired(&mut data, key);
+                None
+            }
+            Some(item) => Some(std::mem::replace(&mut item.expires_at, expires_at)),
+            None => None,
+        };
+        drop(data);
+        self.notify_removals();
+        if previous.is_some() {
+            self.record_access(key, None);
+        }
+        previous
+    }
+
     /// Append data to an existing item
     pub fn append(&self, key: &str, data_to_append: &[u8]) -> StorageResult {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
         let mut data = self.data.write().unwrap();
 
+        self.preserve(&data, key);
         match data.get_mut(key) {
             None => StorageResult::NotStored,
             Some(item) if item.is_expired() => {
-                let old_size = item.memory_size() + key.len();
-                data.remove(key);
-                self.memory_used
-                    .fetch_sub(old_size as u64, Ordering::SeqCst);
+                self.reclaim_expired(&mut data, key);
+                drop(data);
+                self.notify_removals();
                 StorageResult::NotStored
             }
             Some(item) => {
+                self.decode_counter(item);
                 let additional_size = data_to_append.len();
 
-                // Check memory limit
-                let current_used = self.memory_used.load(Ordering::SeqCst) as usize;
-                if current_used + additional_size > self.max_memory {
+                // Check memory limits
+                if self.over_limits(additional_size, additional_size) {
                     drop(data);
-                    self.ensure_memory_available(additional_size);
+                    if !self.ensure_memory_available(additional_size, additional_size) {
+                        return StorageResult::OutOfMemory;
+                    }
                     data = self.data.write().unwrap();
+                    self.preserve(&data, key);
 
                     // Re-check if item still exists
                     match data.get_mut(key) {
@@ -330,9 +1403,8 @@ impl Storage {
                             item.value.extend_from_slice(data_to_append);
                             item.cas_unique = self.next_cas_unique();
                             item.last_accessed = Instant::now();
-                            self.memory_used
-                                .fetch_add(additional_size as u64, Ordering::SeqCst);
-                            self.record_access(key);
+                            self.resize_value(0, additional_size);
+                            self.record_access(key, None);
                             StorageResult::Stored
                         }
                         _ => StorageResult::NotStored,
@@ -341,9 +1413,8 @@ impl Storage {
                     item.value.extend_from_slice(data_to_append);
                     item.cas_unique = self.next_cas_unique();
                     item.last_accessed = Instant::now();
-                    self.memory_used
-                        .fetch_add(additional_size as u64, Ordering::SeqCst);
-                    self.record_access(key);
+                    self.resize_value(0, additional_size);
+                    self.record_access(key, None);
                     StorageResult::Stored
                 }
             }
@@ -352,26 +1423,30 @@ impl Storage {
 
     /// Prepend data to an existing item
     pub fn prepend(&self, key: &str, data_to_prepend: &[u8]) -> StorageResult {
+        self.cmd_set.fetch_add(1, Ordering::Relaxed);
         let mut data = self.data.write().unwrap();
 
+        self.preserve(&data, key);
         match data.get_mut(key) {
             None => StorageResult::NotStored,
             Some(item) if item.is_expired() => {
-                let old_size = item.memory_size() + key.len();
-                data.remove(key);
-                self.memory_used
-                    .fetch_sub(old_size as u64, Ordering::SeqCst);
+                self.reclaim_expired(&mut data, key);
+                drop(data);
+                self.notify_removals();
                 StorageResult::NotStored
             }
             Some(item) => {
+                self.decode_counter(item);
                 let additional_size = data_to_prepend.len();
 
-                // Check memory limit
-                let current_used = self.memory_used.load(Ordering::SeqCst) as usize;
-                if current_used + additional_size > self.max_memory {
+                // Check memory limits
+                if self.over_limits(additional_size, additional_size) {
                     drop(data);
-                    self.ensure_memory_available(additional_size);
+                    if !self.ensure_memory_available(additional_size, additional_size) {
+                        return StorageResult::OutOfMemory;
+                    }
                     data = self.data.write().unwrap();
+                    self.preserve(&data, key);
 
                     // Re-check if item still exists
                     match data.get_mut(key) {
@@ -381,9 +1456,8 @@ impl Storage {
                             item.value = new_value;
                             item.cas_unique = self.next_cas_unique();
                             item.last_accessed = Instant::now();
-                            self.memory_used
-                                .fetch_add(additional_size as u64, Ordering::SeqCst);
-                            self.record_access(key);
+                            self.resize_value(0, additional_size);
+                            self.record_access(key, None);
                             StorageResult::Stored
                         }
                         _ => StorageResult::NotStored,
@@ -394,334 +1468,2991 @@ impl Storage {
                     item.value = new_value;
                     item.cas_unique = self.next_cas_unique();
                     item.last_accessed = Instant::now();
-                    self.memory_used
-                        .fetch_add(additional_size as u64, Ordering::SeqCst);
-                    self.record_access(key);
+                    self.resize_value(0, additional_size);
+                    self.record_access(key, None);
                     StorageResult::Stored
                 }
             }
         }
     }
 
-    /// Ensure enough memory is available, evicting LRU items if necessary
-    fn ensure_memory_available(&self, needed: usize) {
-        let mut current = self.memory_used.load(Ordering::SeqCst) as usize;
+    /// Increment a counter, wrapping at 2^64 like memcached.
+    pub fn incr(&self, key: &str, delta: u64) -> CounterResult {
+        self.update_counter(key, |n| n.wrapping_add(delta))
+    }
 
-        while current + needed > self.max_memory {
-            if let Some(key_to_evict) = self.find_lru_key() {
-                debug!(key = %key_to_evict, "Evicting LRU item");
-                self.delete(&key_to_evict);
-                current = self.memory_used.load(Ordering::SeqCst) as usize;
-            } else {
-                // No items to evict
-                break;
-            }
-        }
+    /// Decrement a counter, saturating at zero like memcached.
+    pub fn decr(&self, key: &str, delta: u64) -> CounterResult {
+        self.update_counter(key, |n| n.saturating_sub(delta))
     }
 
-    /// Find the least recently used key
-    fn find_lru_key(&self) -> Option<String> {
-        let order = self.access_order.read().ok()?;
-        let data = self.data.read().ok()?;
+    /// Like `incr`, but a missing key is first created at 0 with the
+    /// default TTL, as in Redis.
+    pub fn incr_or_create(&self, key: &str, delta: u64) -> CounterResult {
+        self.update_or_create_counter(key, |n| n.wrapping_add(delta))
+    }
+
+    /// Like `decr`, but a missing key is first created at 0 with the
+    /// default TTL, as in Redis.
+    pub fn decr_or_create(&self, key: &str, delta: u64) -> CounterResult {
+        self.update_or_create_counter(key, |n| n.saturating_sub(delta))
+    }
 
-        // Find the key with lowest access sequence number
-        let mut min_seq = u64::MAX;
-        let mut lru_key = None;
+    /// Apply `op` to a numeric item, or to 0 for a new item if `key` is
+    /// missing or expired.
+    fn update_or_create_counter(&self, key: &str, op: impl Fn(u64) -> u64) -> CounterResult {
+        match self.update_counter(key, &op) {
+            CounterResult::NotFound => {}
+            result => return result,
+        }
 
-        for (key, &seq) in order.iter() {
-            // Only consider non-expired items that still exist
-            if let Some(item) = data.get(key) {
-                if !item.is_expired() && seq < min_seq {
-                    min_seq = seq;
-                    lru_key = Some(key.clone());
-                }
-            }
+        let new_value = op(0);
+        let item = CacheItem {
+            value: new_value.to_le_bytes().to_vec(),
+            flags: 0,
+            expires_at: self.calculate_expiry(0),
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Integer,
+            frequency: AccessFrequency::default(),
+        };
+        if !self.ensure_memory_available(footprint(key, &item), item.value.len()) {
+            return CounterResult::OutOfMemory;
         }
 
-        // If no valid key found in order, pick any key from data
-        if lru_key.is_none() {
-            lru_key = data.keys().next().cloned();
+        let mut data = self.data.write().unwrap();
+        if data.get(key).is_some_and(|existing| !existing.is_expired()) {
+            // Another client created it meanwhile, count on top of theirs
+            drop(data);
+            return self.update_counter(key, op);
         }
+        self.put(&mut data, key, item);
+        drop(data);
+        self.notify_removals();
 
-        lru_key
+        CounterResult::Value(new_value)
     }
 
-    /// Remove all expired items from storage.
-    /// Currently called lazily on access, but provided for future background cleanup.
-    #[allow(dead_code)]
-    pub fn cleanup_expired(&self) -> usize {
-        let mut expired_keys = Vec::new();
+    /// Apply `op` to a numeric item in place.
+    ///
+    /// The result is kept integer-encoded, so a hot counter is parsed from
+    /// text at most once and only formatted when read.
+    fn update_counter(&self, key: &str, op: impl FnOnce(u64) -> u64) -> CounterResult {
+        let mut data = self.data.write().unwrap();
 
-        // First pass: find expired keys
-        {
-            let data = self.data.read().unwrap();
-            for (key, item) in data.iter() {
-                if item.is_expired() {
-                    expired_keys.push(key.clone());
-                }
+        self.preserve(&data, key);
+        let item = match data.get_mut(key) {
+            None => return CounterResult::NotFound,
+            Some(item) if item.is_expired() => {
+                self.reclaim_expired(&mut data, key);
+                drop(data);
+                self.notify_removals();
+                return CounterResult::NotFound;
             }
-        }
+            Some(item) => item,
+        };
 
-        // Second pass: remove expired items
-        let count = expired_keys.len();
-        for key in expired_keys {
-            self.delete(&key);
-        }
+        let current = match item.integer() {
+            Some(n) => n,
+            None => match std::str::from_utf8(&item.value)
+                .ok()
+                .and_then(|s| s.trim().parse::<u64>().ok())
+            {
+                Some(n) => n,
+                None => return CounterResult::NotNumeric,
+            },
+        };
 
-        if count > 0 {
-            info!(count, "Cleaned up expired items");
-        }
+        let new_value = op(current);
+        let old_len = item.value.len();
+        item.value = new_value.to_le_bytes().to_vec();
+        item.encoding = ValueEncoding::Integer;
+        item.cas_unique = self.next_cas_unique();
+        item.last_accessed = Instant::now();
+        // `expires_at` is left alone: like memcached, counters keep their TTL
 
-        count
-    }
+        self.resize_value(old_len, item.value.len());
+        self.record_access(key, None);
 
-    /// Flush all items from storage
-    pub fn flush_all(&self) {
-        let mut data = self.data.write().unwrap();
-        let mut order = self.access_order.write().unwrap();
+        CounterResult::Value(new_value)
+    }
 
-        data.clear();
-        order.clear();
-        self.memory_used.store(0, Ordering::SeqCst);
+    /// Add `delta` to a signed counter, creating it at 0 with the default
+    /// TTL if missing, as Redis `INCRBY` does.
+    ///
+    /// Unlike `incr`/`decr` the value may go negative, and leaving the i64
+    /// range is an error rather than a wrap or clamp.
+    pub fn incr_by_signed(&self, key: &str, delta: i64) -> SignedCounterResult {
+        loop {
+            if let Some(result) = self.update_signed_counter(key, delta) {
+                return result;
+            }
 
-        info!("Flushed all items");
-    }
+            let (value, encoding) = signed_value(delta);
+            let item = CacheItem {
+                value,
+                flags: 0,
+                expires_at: self.calculate_expiry(0),
+                cas_unique: self.next_cas_unique(),
+                last_accessed: Instant::now(),
+                value_type: ValueType::String,
+                encoding,
+                frequency: AccessFrequency::default(),
+            };
+            if !self.ensure_memory_available(footprint(key, &item), item.value.len()) {
+                return SignedCounterResult::OutOfMemory;
+            }
 
-    /// Get statistics about the storage
-    pub fn stats(&self) -> StorageStats {
-        let data = self.data.read().unwrap();
-        StorageStats {
-            item_count: data.len(),
-            memory_used: self.memory_used.load(Ordering::SeqCst) as usize,
-            max_memory: self.max_memory,
-            cas_counter: self.cas_counter.load(Ordering::SeqCst),
+            let mut data = self.data.write().unwrap();
+            if data.get(key).is_some_and(|existing| !existing.is_expired()) {
+                // Another client created it meanwhile, count on top of theirs
+                continue;
+            }
+            self.put(&mut data, key, item);
+            drop(data);
+            self.notify_removals();
+            return SignedCounterResult::Value(delta);
         }
     }
-}
 
-/// Storage statistics
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct StorageStats {
-    pub item_count: usize,
-    pub memory_used: usize,
-    pub max_memory: usize,
-    pub cas_counter: u64,
-}
+    /// Add `delta` to a live signed counter in place, or `None` if `key` is
+    /// missing or expired.
+    fn update_signed_counter(&self, key: &str, delta: i64) -> Option<SignedCounterResult> {
+        let mut data = self.data.write().unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::thread;
+        self.preserve(&data, key);
+        let item = match data.get_mut(key) {
+            None => return None,
+            Some(item) if item.is_expired() => {
+                self.reclaim_expired(&mut data, key);
+                drop(data);
+                self.notify_removals();
+                return None;
+            }
+            Some(item) => item,
+        };
 
-    #[test]
-    fn test_basic_set_get() {
-        let storage = Storage::new(1024 * 1024, 0);
+        let Some(current) = item.signed() else {
+            return Some(SignedCounterResult::NotNumeric);
+        };
+        let Some(new_value) = current.checked_add(delta) else {
+            return Some(SignedCounterResult::Overflow);
+        };
 
-        let result = storage.set("key1", b"value1".to_vec(), 0, 0);
-        assert_eq!(result, StorageResult::Stored);
+        let old_len = item.value.len();
+        (item.value, item.encoding) = signed_value(new_value);
+        item.cas_unique = self.next_cas_unique();
+        item.last_accessed = Instant::now();
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value1");
-        assert_eq!(item.flags, 0);
+        self.resize_value(old_len, item.value.len());
+        self.record_access(key, None);
+
+        Some(SignedCounterResult::Value(new_value))
     }
 
-    #[test]
-    fn test_get_nonexistent() {
-        let storage = Storage::new(1024 * 1024, 0);
-        assert!(storage.get("nonexistent").is_none());
+    /// Turn an integer-encoded item back into text before a byte-level edit.
+    fn decode_counter(&self, item: &mut CacheItem) {
+        if item.encoding == ValueEncoding::Integer {
+            let old_len = item.value.len();
+            item.value = item.client_value().into_owned();
+            item.encoding = ValueEncoding::Raw;
+            self.resize_value(old_len, item.value.len());
+        }
     }
 
-    #[test]
-    fn test_delete() {
-        let storage = Storage::new(1024 * 1024, 0);
+    /// Insert `item` while holding the data write lock.
+    ///
+    /// Accounts memory for whatever the insert replaced and counts newly
+    /// created keys. Overwriting an expired item counts as a create, since
+    /// clients could no longer see it.
+    fn put(&self, data: &mut HashMap<String, CacheItem>, key: &str, item: CacheItem) -> SetOutcome {
+        self.preserve(data, key);
+        let new_size = footprint(key, &item);
+        let new_len = item.value.len();
+        if self.tombstone_ttl_ms.load(Ordering::Relaxed) > 0
+            || self.recache_lease_ms.load(Ordering::Relaxed) > 0
+        {
+            // Recached: a later miss is an eviction or expiry, not the delete
+            // or the miss the lease was for
+            self.tombstones.lock().unwrap().remove(key);
+        }
+        let (old_size, old_len, outcome) = match data.insert(key.to_string(), item) {
+            Some(old) => {
+                let outcome = if old.is_expired() {
+                    self.note_expired(key, &old);
+                    SetOutcome::Created
+                } else {
+                    SetOutcome::Replaced
+                };
+                (footprint(key, &old), old.value.len(), outcome)
+            }
+            None => {
+                self.scan_order.write().unwrap().insert(key);
+                (0, 0, SetOutcome::Created)
+            }
+        };
+        self.apply_size_delta(old_size, new_size);
+        apply_delta(&self.value_bytes, old_len, new_len);
+        // A new value, not yet read
+        self.record_access(key, Some(false));
+        if outcome == SetOutcome::Created {
+            self.total_items.fetch_add(1, Ordering::Relaxed);
+        }
+        outcome
+    }
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
-        assert!(storage.get("key1").is_some());
+    /// Account for an item changing from `old_size` to `new_size` bytes.
+    ///
+    /// Applied as one signed delta so a concurrent reader of `memory_used`
+    /// never sees the old size released before the new one is charged.
+    fn apply_size_delta(&self, old_size: usize, new_size: usize) {
+        apply_delta(&self.memory_used, old_size, new_size);
+    }
 
-        let result = storage.delete("key1");
-        assert_eq!(result, StorageResult::Deleted);
-        assert!(storage.get("key1").is_none());
+    /// Account for an in-place value edit from `old_len` to `new_len` bytes.
+    ///
+    /// The item's overhead is unchanged, so memory moves by the same amount.
+    fn resize_value(&self, old_len: usize, new_len: usize) {
+        self.apply_size_delta(old_len, new_len);
+        apply_delta(&self.value_bytes, old_len, new_len);
+    }
+
+    /// Release the accounting of an item already removed from the map.
+    fn release(&self, key: &str, item: &CacheItem) {
+        self.memory_used
+            .fetch_sub(footprint(key, item) as u64, Ordering::SeqCst);
+        self.value_bytes
+            .fetch_sub(item.value.len() as u64, Ordering::SeqCst);
+    }
+
+    /// Remove `key` while holding the data write lock, releasing its
+    /// accounting and LRU entry.
+    fn unlink(&self, data: &mut HashMap<String, CacheItem>, key: &str) -> Option<CacheItem> {
+        self.preserve(data, key);
+        let item = data.remove(key)?;
+        self.release(key, &item);
+        if let Ok(mut order) = self.access_order.write() {
+            order.remove(key);
+        }
+        self.scan_order.write().unwrap().remove(key);
+        Some(item)
+    }
+
+    /// Unlink an expired item, counting its bytes as reclaimed by expiry.
+    fn reclaim_expired(&self, data: &mut HashMap<String, CacheItem>, key: &str) -> bool {
+        let Some(item) = data.get(key) else {
+            return false;
+        };
+        self.note_expired(key, item);
+        self.unlink(data, key);
+        true
+    }
+
+    /// Remove `key` if it is still expired once the write lock is held (it
+    /// may have been rewritten since the caller saw it expire).
+    fn remove_expired(&self, key: &str) {
+        let mut data = self.data.write().unwrap();
+        if data.get(key).is_some_and(CacheItem::is_expired) {
+            self.reclaim_expired(&mut data, key);
+        }
+        drop(data);
+        self.notify_removals();
+    }
+
+    /// Remove an item to make room, counting its bytes as evicted (or as
+    /// expired, if it already was).
+    fn evict(&self, key: &str) {
+        let mut data = self.data.write().unwrap();
+        self.evict_locked(&mut data, key);
+        drop(data);
+        self.notify_removals();
+    }
+
+    /// `evict` for a caller already holding the data write lock.
+    fn evict_locked(&self, data: &mut HashMap<String, CacheItem>, key: &str) {
+        if data.get(key).is_some_and(CacheItem::is_expired) {
+            self.reclaim_expired(data, key);
+        } else if let Some(item) = self.unlink(data, key) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            self.evicted_bytes
+                .fetch_add(footprint(key, &item) as u64, Ordering::Relaxed);
+            self.queue_removal(key, RemovalReason::Evicted);
+        }
+    }
+
+    /// Whether adding `needed` bytes of memory, `value_needed` of them value
+    /// bytes, would exceed either limit.
+    fn over_limits(&self, needed: usize, value_needed: usize) -> bool {
+        let memory = self.memory_used.load(Ordering::SeqCst) as usize;
+        if memory + needed > self.max_memory {
+            return true;
+        }
+        self.max_value_bytes.is_some_and(|max| {
+            self.value_bytes.load(Ordering::SeqCst) as usize + value_needed > max
+        })
+    }
+
+    /// Ensure enough memory and value bytes are available, evicting items
+    /// chosen by the eviction policy if necessary.
+    ///
+    /// Returns false if the write must be refused, which only happens under
+    /// `NoEviction`; the other policies let a write through once nothing is
+    /// left to evict.
+    #[must_use]
+    fn ensure_memory_available(&self, needed: usize, value_needed: usize) -> bool {
+        if !self.over_limits(needed, value_needed) {
+            return true;
+        }
+
+        // Reclaim dead items before evicting anything live. The sweep is a
+        // full scan, so a sustained stream of evictions only pays for it
+        // once per interval; in between, expired items are reclaimed when
+        // they reach the back of the LRU.
+        if self.expiry_sweep_due() {
+            self.cleanup_expired();
+        }
+
+        while self.over_limits(needed, value_needed) {
+            if let Some(key_to_evict) = self.find_victim() {
+                debug!(key = %key_to_evict, policy = ?self.eviction_policy, "Evicting item");
+                self.evict(&key_to_evict);
+            } else {
+                // No items to evict
+                break;
+            }
+        }
+
+        self.eviction_policy != EvictionPolicy::NoEviction
+            || !self.over_limits(needed, value_needed)
+    }
+
+    /// Like `ensure_memory_available`, for a caller already holding the
+    /// data write lock. Neither expiry nor eviction removes a key in `spare`.
+    #[must_use]
+    fn make_room_locked(
+        &self,
+        data: &mut HashMap<String, CacheItem>,
+        needed: usize,
+        value_needed: usize,
+        spare: &HashSet<&str>,
+    ) -> bool {
+        if !self.over_limits(needed, value_needed) {
+            return true;
+        }
+
+        if self.expiry_sweep_due() {
+            let expired: Vec<String> = data
+                .iter()
+                .filter(|(key, item)| item.is_expired() && !spare.contains(key.as_str()))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in expired {
+                self.reclaim_expired(data, &key);
+            }
+        }
+
+        while self.over_limits(needed, value_needed) {
+            let Some(key_to_evict) = self.victim_among(data, spare) else {
+                break;
+            };
+            debug!(key = %key_to_evict, policy = ?self.eviction_policy, "Evicting item");
+            self.evict_locked(data, &key_to_evict);
+        }
+
+        self.eviction_policy != EvictionPolicy::NoEviction
+            || !self.over_limits(needed, value_needed)
+    }
+
+    /// The key the eviction policy drops next (`None` under `NoEviction`).
+    fn find_victim(&self) -> Option<String> {
+        let data = self.data.read().ok()?;
+        self.victim_among(&data, &HashSet::new())
+    }
+
+    /// The key in `data` the eviction policy drops next, passing over those
+    /// in `spare`.
+    ///
+    /// LFU takes the least frequently read of the `LFU_SAMPLE` least
+    /// recently used keys, oldest first on a tie: like Redis, it
+    /// approximates LFU by sampling rather than keeping every item ordered
+    /// by frequency.
+    fn victim_among(
+        &self,
+        data: &HashMap<String, CacheItem>,
+        spare: &HashSet<&str>,
+    ) -> Option<String> {
+        // Data before access order, as on the read path
+        let order = self.access_order.read().ok()?;
+        let mut oldest = order.iter_from_back().filter(|key| !spare.contains(key));
+        let key = match self.eviction_policy {
+            EvictionPolicy::Lru => oldest.next(),
+            EvictionPolicy::Lfu => oldest
+                .take(LFU_SAMPLE)
+                .min_by_key(|key| data.get(*key).map_or(0, |item| item.frequency.get())),
+            EvictionPolicy::Random => {
+                let seed = RandomState::new().hash_one(self.access_counter.load(Ordering::Relaxed));
+                order
+                    .pick(seed)
+                    .filter(|key| !spare.contains(key))
+                    .or_else(|| oldest.next())
+            }
+            EvictionPolicy::NoEviction => return None,
+        };
+        // Every stored key is in the LRU, but don't stall eviction if not
+        key.map(str::to_string).or_else(|| {
+            data.keys()
+                .find(|key| !spare.contains(key.as_str()))
+                .cloned()
+        })
+    }
+
+    /// Whether eviction should sweep for expired items first.
+    fn expiry_sweep_due(&self) -> bool {
+        let mut last = self.last_expiry_sweep.lock().unwrap();
+        let now = Instant::now();
+        if last.is_some_and(|at| now.duration_since(at) < EXPIRY_SWEEP_INTERVAL) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+
+    /// Remove all expired items from storage.
+    /// Called before LRU eviction so dead items are reclaimed first.
+    pub fn cleanup_expired(&self) -> usize {
+        let mut expired_keys = Vec::new();
+
+        // First pass: find expired keys
+        {
+            let data = self.data.read().unwrap();
+            for (key, item) in data.iter() {
+                if item.is_expired() {
+                    expired_keys.push(key.clone());
+                }
+            }
+        }
+
+        if expired_keys.is_empty() {
+            return 0;
+        }
+
+        // Second pass: remove items that are still expired (a key may have
+        // been rewritten since the scan)
+        let mut count = 0;
+        let mut data = self.data.write().unwrap();
+        for key in expired_keys {
+            if data.get(&key).is_some_and(CacheItem::is_expired)
+                && self.reclaim_expired(&mut data, &key)
+            {
+                count += 1;
+            }
+        }
+        drop(data);
+        self.notify_removals();
+
+        if count > 0 {
+            debug!(count, "Cleaned up expired items");
+        }
+        let now = Instant::now();
+        self.tombstones
+            .lock()
+            .unwrap()
+            .retain(|_, tombstone| tombstone.expires_at > now);
+
+        count
+    }
+
+    /// One page of a cursor scan over the live keys: about `count` keys
+    /// from `cursor` on, and the cursor to pass next, 0 once the scan is
+    /// complete. Start at 0.
+    ///
+    /// Keys present for the whole scan come back exactly once; see
+    /// [`crate::scan`]. Expired keys are skipped, so a page may be short or
+    /// even empty before the end.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<String>) {
+        let data = self.data.read().unwrap();
+        let order = self.scan_order.read().unwrap();
+        let (next, keys) = order.page(cursor, count);
+        let live = keys
+            .into_iter()
+            .filter(|key| data.get(*key).is_some_and(|item| !item.is_expired()))
+            .map(str::to_string)
+            .collect();
+        (next, live)
+    }
+
+    /// Flush all items from storage
+    pub fn flush_all(&self) {
+        let mut data = self.data.write().unwrap();
+        self.preserve_all(&data);
+        let mut order = self.access_order.write().unwrap();
+
+        data.clear();
+        order.clear();
+        self.scan_order.write().unwrap().clear();
+        self.tombstones.lock().unwrap().clear();
+        self.memory_used.store(0, Ordering::SeqCst);
+        self.value_bytes.store(0, Ordering::SeqCst);
+
+        info!("Flushed all items");
+    }
+
+    /// Write all live items to `writer` in the given snapshot format.
+    ///
+    /// TTLs are recorded as remaining time so they can be re-based on load.
+    /// Returns the number of items written.
+    pub fn dump<W: Write>(
+        self: &Arc<Self>,
+        writer: &mut W,
+        format: SnapshotFormat,
+    ) -> io::Result<usize> {
+        match format {
+            SnapshotFormat::Binary => {
+                let mut snapshot = self.snapshot();
+                let items = snapshot.items;
+                io::copy(&mut snapshot, writer)?;
+                writer.flush()?;
+                Ok(items)
+            }
+            SnapshotFormat::Json => {
+                let snapshot = JsonSnapshot {
+                    version: SNAPSHOT_VERSION,
+                    items: self.snapshot().into_entries(),
+                };
+                serde_json::to_writer_pretty(&mut *writer, &snapshot)?;
+                writer.write_all(b"\n")?;
+                Ok(snapshot.items.len())
+            }
+        }
+    }
+
+    /// A binary snapshot of the live items, serialized as it is read.
+    ///
+    /// Only the item count and length are taken under a single read lock.
+    /// The items are then read out a batch at a time, and a write to one
+    /// not yet read saves it as it was first, so the snapshot is a
+    /// consistent point-in-time view however slowly it is read, without
+    /// copying the whole dataset.
+    pub fn snapshot(self: &Arc<Self>) -> SnapshotStream {
+        let data = self.data.read().unwrap();
+        let view = SnapshotView {
+            taken_at: Instant::now(),
+            cursor: Some(0),
+            saved: BTreeMap::new(),
+        };
+        let (items, len) = data
+            .iter()
+            .filter_map(|(key, item)| view.entry_len(key, item))
+            .fold((0, 0), |(items, len), entry_len| {
+                (items + 1, len + entry_len)
+            });
+
+        let view = Arc::new(Mutex::new(view));
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|view| view.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&view));
+        drop(snapshots);
+        drop(data);
+
+        SnapshotStream::new(Arc::clone(self), view, items, len)
+    }
+
+    /// The next batch of entries `view` reads out, `None` once all have been.
+    fn snapshot_batch(&self, view: &Mutex<SnapshotView>) -> Option<Vec<SnapshotEntry>> {
+        // Writers save items under the data write lock, so while it is held
+        // for reading, the view and the items agree
+        let data = self.data.read().unwrap();
+        let order = self.scan_order.read().unwrap();
+        let mut view = view.lock().unwrap();
+        let cursor = view.cursor?;
+        let (next, keys) = order.page(cursor, SNAPSHOT_BATCH);
+        // Nothing is saved below the cursor, so this splits off the page's
+        let later = match next {
+            0 => BTreeMap::new(),
+            next => view.saved.split_off(&next),
+        };
+        let saved = std::mem::replace(&mut view.saved, later);
+        view.cursor = (next != 0).then_some(next);
+
+        let changed: HashSet<&str> = saved
+            .values()
+            .flatten()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        let mut entries: Vec<SnapshotEntry> = keys
+            .into_iter()
+            .filter(|key| !changed.contains(key))
+            .filter_map(|key| view.entry(key, data.get(key)?))
+            .collect();
+        entries.extend(saved.into_values().flatten().filter_map(|(_, entry)| entry));
+        Some(entries)
+    }
+
+    /// Save `key` as it stands for the snapshots yet to read it, before a
+    /// write changes it. Call with the data write lock held.
+    fn preserve(&self, data: &HashMap<String, CacheItem>, key: &str) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|view| view.strong_count() > 0);
+        if snapshots.is_empty() {
+            return;
+        }
+        let hash = self.scan_order.read().unwrap().position(key);
+        for view in snapshots.iter().filter_map(Weak::upgrade) {
+            view.lock().unwrap().save(hash, key, data.get(key));
+        }
+    }
+
+    /// `preserve` every key, before `flush_all`.
+    fn preserve_all(&self, data: &HashMap<String, CacheItem>) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|view| view.strong_count() > 0);
+        if snapshots.is_empty() {
+            return;
+        }
+        let order = self.scan_order.read().unwrap();
+        for view in snapshots.iter().filter_map(Weak::upgrade) {
+            let mut view = view.lock().unwrap();
+            for (key, item) in data {
+                view.save(order.position(key), key, Some(item));
+            }
+        }
+    }
+
+    /// Load items from a snapshot produced by [`Storage::dump`].
+    ///
+    /// Existing keys are overwritten. Returns the number of items loaded.
+    pub fn load<R: Read>(&self, reader: &mut R, format: SnapshotFormat) -> io::Result<usize> {
+        let entries = match format {
+            SnapshotFormat::Binary => read_binary_snapshot(reader)?,
+            SnapshotFormat::Json => {
+                let snapshot: JsonSnapshot = serde_json::from_reader(reader)?;
+                if snapshot.version != SNAPSHOT_VERSION {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported snapshot version {}", snapshot.version),
+                    ));
+                }
+                snapshot.items
+            }
+        };
+
+        let count = entries.len();
+        for entry in entries {
+            // Too far out to represent means never, as in `calculate_expiry`
+            let expires_at = entry
+                .ttl_ms
+                .and_then(|ms| Instant::now().checked_add(Duration::from_millis(ms)));
+            self.insert_item(&entry.key, entry.value, entry.flags, expires_at);
+        }
+
+        info!(count, "Loaded snapshot");
+        Ok(count)
+    }
+
+    /// Write all live items to the file at `path`, for a warm restart.
+    ///
+    /// The snapshot goes to a temporary file beside it first, renamed over
+    /// `path` once complete, so a crash mid-save keeps the previous one.
+    /// Returns the number of items written.
+    pub fn save_snapshot(
+        self: &Arc<Self>,
+        path: &Path,
+        format: SnapshotFormat,
+    ) -> io::Result<usize> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        let partial = Path::new(&partial);
+
+        let mut writer = BufWriter::new(File::create(partial)?);
+        let count = self.dump(&mut writer, format)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(partial, path)?;
+
+        info!(count, path = %path.display(), "Saved snapshot");
+        Ok(count)
+    }
+
+    /// Load the snapshot file at `path`, written by
+    /// [`Storage::save_snapshot`].
+    ///
+    /// TTLs count from now. Returns the number of items loaded.
+    pub fn load_snapshot(&self, path: &Path, format: SnapshotFormat) -> io::Result<usize> {
+        let mut reader = BufReader::new(File::open(path)?);
+        self.load(&mut reader, format)
+    }
+
+    /// Insert an item with an explicit expiration time.
+    fn insert_item(&self, key: &str, value: Vec<u8>, flags: u32, expires_at: Option<Instant>) {
+        let item = CacheItem {
+            value,
+            flags,
+            expires_at,
+            cas_unique: self.next_cas_unique(),
+            last_accessed: Instant::now(),
+            value_type: ValueType::String,
+            encoding: ValueEncoding::Raw,
+            frequency: AccessFrequency::default(),
+        };
+
+        if !self.ensure_memory_available(footprint(key, &item), item.value.len()) {
+            debug!(key, "No room to load item without evicting, skipped");
+            return;
+        }
+
+        let mut data = self.data.write().unwrap();
+        self.put(&mut data, key, item);
+    }
+
+    /// Current CAS token of a live item (`None` if missing or expired).
+    ///
+    /// Does not touch the item's LRU position.
+    pub fn cas_of(&self, key: &str) -> Option<u64> {
+        let data = self.data.read().ok()?;
+        data.get(key)
+            .filter(|item| !item.is_expired())
+            .map(|item| item.cas_unique)
+    }
+
+    /// Run `ops` atomically if every watched key still has the CAS token
+    /// observed when it was watched (`None` = key was absent).
+    ///
+    /// Validation, making room and the ops all happen under a single write
+    /// lock, so no other writer can interleave and an aborted transaction
+    /// evicts nothing. Returns `None` without running anything if a watched
+    /// key changed, otherwise the per-op results.
+    pub fn commit_if_unchanged(
+        &self,
+        watched: &HashMap<String, Option<u64>>,
+        ops: Vec<TxnOp>,
+    ) -> Option<Vec<StorageResult>> {
+        let mut data = self.data.write().unwrap();
+        let unchanged = watched.iter().all(|(key, observed)| {
+            let current = data
+                .get(key)
+                .filter(|item| !item.is_expired())
+                .map(|item| item.cas_unique);
+            current == *observed
+        });
+        if !unchanged {
+            debug!("Transaction aborted: watched key changed");
+            return None;
+        }
+
+        // The keys the ops name are spared: what they replace is already
+        // netted out of the room needed
+        let (needed, value_needed) = txn_growth(&data, &ops);
+        let spare: HashSet<&str> = ops.iter().map(TxnOp::key).collect();
+        if !self.make_room_locked(&mut data, needed, value_needed, &spare) {
+            drop(data);
+            self.notify_removals();
+            // Nothing is applied, so the transaction stays atomic
+            return Some(vec![StorageResult::OutOfMemory; ops.len()]);
+        }
+        drop(spare);
+
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            let result = match op {
+                TxnOp::Set {
+                    key,
+                    value,
+                    flags,
+                    expires_at,
+                    condition,
+                } => {
+                    let live = data.get(&key).is_some_and(|item| !item.is_expired());
+                    if condition.allows(live) {
+                        let item = CacheItem {
+                            value,
+                            flags,
+                            expires_at,
+                            cas_unique: self.next_cas_unique(),
+                            last_accessed: Instant::now(),
+                            value_type: ValueType::String,
+                            encoding: ValueEncoding::Raw,
+                            frequency: AccessFrequency::default(),
+                        };
+                        self.put(&mut data, &key, item).into()
+                    } else {
+                        StorageResult::NotStored
+                    }
+                }
+                TxnOp::Delete { key } => match self.unlink(&mut data, &key) {
+                    Some(_) => StorageResult::Deleted,
+                    None => StorageResult::NotFound,
+                },
+                TxnOp::Get { key } => {
+                    let value = data
+                        .get(&key)
+                        .filter(|item| !item.is_expired())
+                        .map(|item| {
+                            item.frequency.hit();
+                            item.client_value().into_owned()
+                        });
+                    self.record_lookup(&key, value.is_some());
+                    value.map_or(StorageResult::NotFound, StorageResult::Value)
+                }
+            };
+            results.push(result);
+        }
+        drop(data);
+        self.notify_removals();
+
+        Some(results)
+    }
+
+    /// Get statistics about the storage
+    ///
+    /// Counters are reported since the last `reset_stats`.
+    pub fn stats(&self) -> StorageStats {
+        let data = self.data.read().unwrap();
+        let baseline = self.stats_baseline.lock().unwrap();
+        let counters = self.counters();
+        StorageStats {
+            item_count: data.len(),
+            memory_used: self.memory_used.load(Ordering::SeqCst) as usize,
+            max_memory: self.max_memory,
+            value_bytes: self.value_bytes.load(Ordering::SeqCst) as usize,
+            max_value_bytes: self.max_value_bytes,
+            cas_counter: self.cas_counter.load(Ordering::SeqCst),
+            total_items: counters.total_items.saturating_sub(baseline.total_items),
+            get_hits: counters.get_hits.saturating_sub(baseline.get_hits),
+            get_misses: counters.get_misses.saturating_sub(baseline.get_misses),
+            cmd_set: counters.cmd_set.saturating_sub(baseline.cmd_set),
+            evictions: counters.evictions.saturating_sub(baseline.evictions),
+            expired: counters.expired.saturating_sub(baseline.expired),
+            expired_unfetched: counters
+                .expired_unfetched
+                .saturating_sub(baseline.expired_unfetched),
+            evicted_bytes: counters
+                .evicted_bytes
+                .saturating_sub(baseline.evicted_bytes),
+            expired_bytes: counters
+                .expired_bytes
+                .saturating_sub(baseline.expired_bytes),
+        }
+    }
+
+    /// Restart the cumulative counters (hits, misses, sets, evictions, ...)
+    /// from zero, as `stats reset` does. Gauges such as the item count and
+    /// memory used are unaffected.
+    ///
+    /// The counters keep counting; their values now become a baseline that
+    /// `stats` subtracts. Both hold the baseline lock while reading the
+    /// counters, so a report sees either every counter reset or none, and
+    /// never one below its baseline.
+    pub fn reset_stats(&self) {
+        let mut baseline = self.stats_baseline.lock().unwrap();
+        *baseline = self.counters();
+    }
+
+    /// Current values of the cumulative counters, never reset.
+    fn counters(&self) -> Counters {
+        Counters {
+            total_items: self.total_items.load(Ordering::Relaxed),
+            get_hits: self.get_hits.load(Ordering::Relaxed),
+            get_misses: self.get_misses.load(Ordering::Relaxed),
+            cmd_set: self.cmd_set.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+            expired_unfetched: self.expired_unfetched.load(Ordering::Relaxed),
+            evicted_bytes: self.evicted_bytes.load(Ordering::Relaxed),
+            expired_bytes: self.expired_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// How stressed the cache is, for autoscaling and load shedding.
+    pub fn pressure(&self) -> Pressure {
+        Pressure {
+            eviction_rate: self.eviction_rate_at(Instant::now()),
+            memory_pressure: match self.max_memory {
+                0 => 0.0,
+                max => self.memory_used.load(Ordering::SeqCst) as f64 / max as f64,
+            },
+        }
+    }
+
+    /// Evictions per second over about the last `EVICTION_RATE_WINDOW`,
+    /// recording a reading of the counter at `now`.
+    ///
+    /// Measured from the newest reading at least a window old, so with
+    /// sparse reports the rate covers the time since the previous one. Not
+    /// affected by `reset_stats`.
+    fn eviction_rate_at(&self, now: Instant) -> f64 {
+        let evictions = self.evictions.load(Ordering::Relaxed);
+        let mut samples = self.eviction_samples.lock().unwrap();
+        while samples.len() > 1
+            && now.saturating_duration_since(samples[1].0) >= EVICTION_RATE_WINDOW
+        {
+            samples.pop_front();
+        }
+        let rate = match samples.front() {
+            Some(&(at, count)) if now > at => {
+                evictions.saturating_sub(count) as f64 / (now - at).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        if samples
+            .back()
+            .is_none_or(|&(at, _)| now.saturating_duration_since(at) >= EVICTION_SAMPLE_INTERVAL)
+        {
+            samples.push_back((now, evictions));
+        }
+        rate
+    }
+
+    /// Item size histogram and oldest item age.
+    ///
+    /// Holds the read lock for at most `sample_size` items (0 = no bound).
+    /// Beyond that the first items in hash order stand in for the rest: the
+    /// counts are scaled up to the full item count, and the oldest age is
+    /// only the oldest seen.
+    pub fn detail_stats(&self, sample_size: usize) -> DetailStats {
+        let data = self.data.read().unwrap();
+        let total = data.len();
+        let sampled = match sample_size {
+            0 => total,
+            n => total.min(n),
+        };
+
+        let now = Instant::now();
+        let mut stats = DetailStats {
+            approximate: sampled < total,
+            ..DetailStats::default()
+        };
+        for (key, item) in data.iter().take(sampled) {
+            let bucket = footprint(key, item).div_ceil(SIZE_BUCKET) * SIZE_BUCKET;
+            *stats.sizes.entry(bucket).or_default() += 1;
+            let age = now.saturating_duration_since(item.last_accessed);
+            stats.oldest_age = Some(stats.oldest_age.map_or(age, |oldest| oldest.max(age)));
+        }
+        drop(data);
+
+        if stats.approximate {
+            let (total, sampled) = (total as u64, sampled as u64);
+            for count in stats.sizes.values_mut() {
+                *count = (*count * total + sampled / 2) / sampled;
+            }
+        }
+        stats
+    }
+}
+
+/// Introspection details of a single item.
+#[derive(Debug, Clone, Copy)]
+pub struct EntryStats {
+    /// Length of the value as stored, i.e. after internal encoding
+    pub value_len: usize,
+    /// The kind of value stored
+    pub value_type: ValueType,
+    /// How the value is stored
+    pub encoding: ValueEncoding,
+    /// Access sequence number of the last read or write; lower is evicted first
+    pub lru_seq: u64,
+    /// Time since the last read or write
+    pub idle: Duration,
+}
+
+/// Width of a `stats sizes` histogram bucket in bytes, as in memcached.
+pub const SIZE_BUCKET: usize = 32;
+
+/// How stressed the cache is.
+#[derive(Debug, Clone, Copy)]
+pub struct Pressure {
+    /// Evictions per second, averaged over about the last minute
+    pub eviction_rate: f64,
+    /// Fraction of `max_memory` in use (`memory_used / max_memory`)
+    pub memory_pressure: f64,
+}
+
+/// Detailed statistics that take a scan over the items.
+#[derive(Debug, Default)]
+pub struct DetailStats {
+    /// Items per size bucket, keyed by the bucket's upper bound in bytes
+    pub sizes: BTreeMap<usize, u64>,
+    /// Time since the least recently written item was stored
+    pub oldest_age: Option<Duration>,
+    /// Extrapolated from a sample rather than counted exactly
+    pub approximate: bool,
+}
+
+/// Storage statistics
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct StorageStats {
+    pub item_count: usize,
+    pub memory_used: usize,
+    pub max_memory: usize,
+    /// Sum of stored value lengths
+    pub value_bytes: usize,
+    pub max_value_bytes: Option<usize>,
+    pub cas_counter: u64,
+    /// Keys created since startup
+    pub total_items: u64,
+    /// Keys looked up and found
+    pub get_hits: u64,
+    /// Keys looked up and missing or expired
+    pub get_misses: u64,
+    /// Store commands received
+    pub cmd_set: u64,
+    /// Live items evicted
+    pub evictions: u64,
+    /// Expired items removed
+    pub expired: u64,
+    /// Items that expired without being read
+    pub expired_unfetched: u64,
+    /// Bytes reclaimed by evicting live items
+    pub evicted_bytes: u64,
+    /// Bytes reclaimed by removing expired items
+    pub expired_bytes: u64,
+}
+
+/// Memory charged for an item stored under `key`.
+fn footprint(key: &str, item: &CacheItem) -> usize {
+    item.memory_size() + key.len()
+}
+
+/// Memory and value bytes `ops` need beyond what they replace or delete.
+fn txn_growth(data: &HashMap<String, CacheItem>, ops: &[TxnOp]) -> (usize, usize) {
+    // (footprint, value bytes, live) of a key as it stands
+    let current = |key: &str| {
+        data.get(key).map_or((0, 0, false), |item| {
+            (footprint(key, item), item.value.len(), !item.is_expired())
+        })
+    };
+    // The same for each key the ops name, as they leave it
+    let mut after: HashMap<&str, (usize, usize, bool)> = HashMap::new();
+    for op in ops {
+        let key = op.key();
+        let slot = after.entry(key).or_insert_with(|| current(key));
+        match op {
+            TxnOp::Set {
+                value, condition, ..
+            } if condition.allows(slot.2) => {
+                let size = std::mem::size_of::<CacheItem>() + value.len() + key.len();
+                *slot = (size, value.len(), true);
+            }
+            TxnOp::Delete { .. } => *slot = (0, 0, false),
+            _ => {}
+        }
+    }
+
+    let (mut before_total, mut after_total) = ((0, 0), (0, 0));
+    for (key, (size, len, _)) in after {
+        let (old_size, old_len, _) = current(key);
+        before_total = (before_total.0 + old_size, before_total.1 + old_len);
+        after_total = (after_total.0 + size, after_total.1 + len);
+    }
+    (
+        after_total.0.saturating_sub(before_total.0),
+        after_total.1.saturating_sub(before_total.1),
+    )
+}
+
+/// Storage form of a signed counter: integer-encoded when the u64
+/// encoding can hold it, decimal text when negative.
+fn signed_value(n: i64) -> (Vec<u8>, ValueEncoding) {
+    match u64::try_from(n) {
+        Ok(n) => (n.to_le_bytes().to_vec(), ValueEncoding::Integer),
+        Err(_) => (n.to_string().into_bytes(), ValueEncoding::Raw),
+    }
+}
+
+/// Move `counter` from `old` to `new` with a single signed delta.
+fn apply_delta(counter: &AtomicU64, old: usize, new: usize) {
+    if new >= old {
+        counter.fetch_add((new - old) as u64, Ordering::SeqCst);
+    } else {
+        counter.fetch_sub((old - new) as u64, Ordering::SeqCst);
+    }
+}
+
+/// Bytes of an entry's fields in a binary snapshot, besides its key and
+/// value: key len, flags, has-ttl, ttl ms and value len.
+const SNAPSHOT_ENTRY_FIELDS: usize = 4 + 4 + 1 + 8 + 8;
+
+/// Keys a snapshot reads out per hold of the data read lock.
+const SNAPSHOT_BATCH: usize = 64;
+
+/// How far a snapshot has been read, and the items writers have changed
+/// since it was taken that it has yet to read.
+struct SnapshotView {
+    /// Items are recorded as they were at this instant
+    taken_at: Instant,
+    /// Keys with a scan hash below this have been read out (`None` once
+    /// all have)
+    cursor: Option<u64>,
+    /// Keys changed before being read, by scan hash, each with its entry
+    /// when the snapshot was taken (`None` = not live then)
+    saved: BTreeMap<u64, Vec<(String, Option<SnapshotEntry>)>>,
+}
+
+impl SnapshotView {
+    /// `item` as recorded, or `None` if it was not live when taken.
+    fn entry(&self, key: &str, item: &CacheItem) -> Option<SnapshotEntry> {
+        let ttl = self.ttl(item)?;
+        Some(SnapshotEntry {
+            key: key.to_string(),
+            value: item.client_value().into_owned(),
+            flags: item.flags,
+            ttl_ms: ttl.map(|ttl| ttl.as_millis() as u64),
+        })
+    }
+
+    /// Bytes `entry` takes in the binary format, without making it.
+    fn entry_len(&self, key: &str, item: &CacheItem) -> Option<usize> {
+        self.ttl(item)?;
+        Some(SNAPSHOT_ENTRY_FIELDS + key.len() + item.client_value().len())
+    }
+
+    /// The item's remaining TTL when taken (`Some(None)` = never expires),
+    /// or `None` if it had expired.
+    fn ttl(&self, item: &CacheItem) -> Option<Option<Duration>> {
+        match item.expires_at {
+            Some(at) if at <= self.taken_at => None,
+            expires_at => Some(expires_at.map(|at| at - self.taken_at)),
+        }
+    }
+
+    /// Record `key` as it stands, unless already read out or saved: only
+    /// the first write to change it sees it as it was when taken.
+    fn save(&mut self, hash: u64, key: &str, item: Option<&CacheItem>) {
+        if self.cursor.is_none_or(|cursor| hash < cursor) {
+            return;
+        }
+        if let Some(bucket) = self.saved.get(&hash) {
+            if bucket.iter().any(|(saved, _)| saved == key) {
+                return;
+            }
+        }
+        let entry = item.and_then(|item| self.entry(key, item));
+        self.saved
+            .entry(hash)
+            .or_default()
+            .push((key.to_string(), entry));
+    }
+}
+
+/// A point-in-time view of the items, read out in the binary snapshot
+/// format.
+///
+/// Layout: magic, version, item count (u64), then per item:
+/// key len (u32), key, flags (u32), has-ttl (u8), ttl ms (u64),
+/// value len (u64), value. All integers are little-endian.
+///
+/// Items are fetched a batch at a time as reading reaches them, an entry
+/// is serialized only once reading reaches it, and its value is handed out
+/// as is, so only one batch is ever buffered.
+pub struct SnapshotStream {
+    storage: Arc<Storage>,
+    view: Arc<Mutex<SnapshotView>>,
+    /// Fetched entries not yet serialized
+    batch: std::vec::IntoIter<SnapshotEntry>,
+    /// Items in the snapshot
+    items: usize,
+    /// Being read out: the header, an entry's fields, or an entry's value
+    piece: Vec<u8>,
+    /// Bytes of `piece` already read
+    read: usize,
+    /// Value of the entry whose fields are in `piece`, the next piece
+    value: Option<Vec<u8>>,
+    /// Bytes not yet read
+    remaining: usize,
+}
+
+impl SnapshotStream {
+    /// A stream of `items` entries taking `len` bytes, besides the header.
+    fn new(
+        storage: Arc<Storage>,
+        view: Arc<Mutex<SnapshotView>>,
+        items: usize,
+        len: usize,
+    ) -> Self {
+        let mut header = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 1 + 8);
+        header.extend_from_slice(SNAPSHOT_MAGIC);
+        header.push(SNAPSHOT_VERSION);
+        header.extend_from_slice(&(items as u64).to_le_bytes());
+        let remaining = header.len() + len;
+
+        Self {
+            storage,
+            view,
+            batch: Vec::new().into_iter(),
+            items,
+            piece: header,
+            read: 0,
+            value: None,
+            remaining,
+        }
+    }
+
+    /// Bytes left to read, so the whole snapshot's length before any read.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Every entry not yet read, for formats not serialized as they are read.
+    fn into_entries(self) -> Vec<SnapshotEntry> {
+        let mut entries: Vec<SnapshotEntry> = self.batch.collect();
+        while let Some(batch) = self.storage.snapshot_batch(&self.view) {
+            entries.extend(batch);
+        }
+        entries
+    }
+
+    fn next_piece(&mut self) -> Option<Vec<u8>> {
+        if let Some(value) = self.value.take() {
+            return Some(value);
+        }
+        let entry = loop {
+            if let Some(entry) = self.batch.next() {
+                break entry;
+            }
+            self.batch = self.storage.snapshot_batch(&self.view)?.into_iter();
+        };
+        let mut fields = Vec::with_capacity(SNAPSHOT_ENTRY_FIELDS + entry.key.len());
+        fields.extend_from_slice(&(entry.key.len() as u32).to_le_bytes());
+        fields.extend_from_slice(entry.key.as_bytes());
+        fields.extend_from_slice(&entry.flags.to_le_bytes());
+        fields.push(entry.ttl_ms.is_some() as u8);
+        fields.extend_from_slice(&entry.ttl_ms.unwrap_or(0).to_le_bytes());
+        fields.extend_from_slice(&(entry.value.len() as u64).to_le_bytes());
+        self.value = Some(entry.value);
+        Some(fields)
+    }
+}
+
+impl std::fmt::Debug for SnapshotStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotStream")
+            .field("items", &self.items)
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for SnapshotStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Pieces read out are dropped, values included
+        while self.read == self.piece.len() {
+            match self.next_piece() {
+                Some(piece) => {
+                    self.piece = piece;
+                    self.read = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.piece.len() - self.read);
+        buf[..n].copy_from_slice(&self.piece[self.read..self.read + n]);
+        self.read += n;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// Read entries in the binary snapshot format.
+fn read_binary_snapshot<R: Read>(reader: &mut R) -> io::Result<Vec<SnapshotEntry>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != SNAPSHOT_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a grow-a-cache snapshot",
+        ));
+    }
+
+    let mut version = [0u8; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != SNAPSHOT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported snapshot version {}", version[0]),
+        ));
+    }
+
+    let count = read_u64(reader)?;
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        let key_len = read_u32(reader)?.into();
+        let key = read_bytes(reader, key_len)?;
+        let key = String::from_utf8(key)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8 in key"))?;
+
+        let flags = read_u32(reader)?;
+
+        let mut has_ttl = [0u8; 1];
+        reader.read_exact(&mut has_ttl)?;
+        let ttl_ms = read_u64(reader)?;
+
+        let value_len = read_u64(reader)?;
+        let value = read_bytes(reader, value_len)?;
+
+        entries.push(SnapshotEntry {
+            key,
+            value,
+            flags,
+            ttl_ms: (has_ttl[0] != 0).then_some(ttl_ms),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Read `len` bytes, allocating only as they arrive: a corrupt length
+/// runs into the end of the file rather than a huge allocation.
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot ends inside a {len}-byte field"),
+        ));
+    }
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::thread;
+
+    #[test]
+    fn test_basic_set_get() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        let result = storage.set("key1", b"value1".to_vec(), 0, 0);
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value1");
+        assert_eq!(item.flags, 0);
+    }
+
+    #[test]
+    fn test_get_nonexistent() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert!(storage.get("nonexistent").is_none());
+    }
+
+    #[test]
+    fn test_delete() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+        assert!(storage.get("key1").is_some());
+
+        let result = storage.delete("key1");
+        assert_eq!(result, StorageResult::Deleted);
+        assert!(storage.get("key1").is_none());
 
         let result = storage.delete("key1");
         assert_eq!(result, StorageResult::NotFound);
     }
 
     #[test]
-    fn test_add_existing() {
+    fn test_touch() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(storage.touch("key1", 100), StorageResult::NotFound);
+
+        storage.set("key1", b"value1".to_vec(), 5, 0);
+        let cas = storage.cas_of("key1");
+        assert_eq!(storage.touch("key1", 100), StorageResult::Touched);
+        let (item, ttl) = storage.get_with_ttl("key1").unwrap();
+        assert_eq!(item.value, b"value1");
+        assert_eq!(item.flags, 5);
+        assert_eq!(storage.cas_of("key1"), cas);
+        assert!(ttl.is_some_and(|ttl| ttl > Duration::from_secs(90)));
+
+        // Already expired: a miss, and the stale entry goes
+        storage.set("key2", b"value2".to_vec(), 0, 1);
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(storage.touch("key2", 100), StorageResult::NotFound);
+        assert_eq!(storage.stats().item_count, 1);
+        assert!(storage.expired_bytes.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_set_expiry_returns_previous_deadline() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(storage.set_expiry("key", None), None);
+
+        storage.set("key", b"v".to_vec(), 0, 0);
+        let deadline = Instant::now() + Duration::from_secs(100);
+        assert_eq!(storage.set_expiry("key", Some(deadline)), Some(None));
+        assert_eq!(storage.set_expiry("key", None), Some(Some(deadline)));
+        assert_eq!(storage.ttl("key"), Some(None));
+    }
+
+    #[test]
+    fn test_get_and_touch() {
+        let storage = Storage::new(1024 * 1024, 60);
+        storage.set("a", b"1".to_vec(), 3, 0);
+        storage.set("b", b"2".to_vec(), 0, 1);
+        let cas = storage.cas_of("a");
+        std::thread::sleep(Duration::from_millis(1100));
+
+        // "b" has expired and is dropped rather than revived
+        let items = storage.get_and_touch(&["a", "b", "missing"], 100);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].0, "a");
+        assert_eq!(items[0].1.value, b"1");
+        assert_eq!(items[0].1.flags, 3);
+        assert_eq!(storage.cas_of("a"), cas);
+        assert_eq!(storage.stats().item_count, 1);
+        let ttl = storage.ttl("a").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(90));
+
+        // 0 falls back to the default TTL
+        storage.get_and_touch(&["a"], 0);
+        let ttl = storage.ttl("a").unwrap().unwrap();
+        assert!(ttl <= Duration::from_secs(60) && ttl > Duration::from_secs(50));
+    }
+
+    #[test]
+    fn test_add_existing() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+
+        let result = storage.add("key1", b"value2".to_vec(), 0, 0);
+        assert_eq!(result, StorageResult::NotStored);
+
+        // Value should remain unchanged
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value1");
+    }
+
+    #[test]
+    fn test_add_new() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        let result = storage.add("key1", b"value1".to_vec(), 0, 0);
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value1");
+    }
+
+    #[test]
+    fn test_replace_existing() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+
+        let result = storage.replace("key1", b"value2".to_vec(), 0, 0);
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value2");
+    }
+
+    #[test]
+    fn test_replace_nonexistent() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        let result = storage.replace("key1", b"value1".to_vec(), 0, 0);
+        assert_eq!(result, StorageResult::NotStored);
+    }
+
+    #[test]
+    fn test_cas_success() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+        let item = storage.get("key1").unwrap();
+        let cas = item.cas_unique;
+
+        let result = storage.cas("key1", b"value2".to_vec(), 0, 0, cas);
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value2");
+    }
+
+    #[test]
+    fn test_cas_mismatch() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+        let item = storage.get("key1").unwrap();
+        let cas = item.cas_unique;
+
+        // Modify the item
+        storage.set("key1", b"value2".to_vec(), 0, 0);
+
+        // Try CAS with old token
+        let result = storage.cas("key1", b"value3".to_vec(), 0, 0, cas);
+        assert_eq!(result, StorageResult::CasMismatch);
+
+        // Value should remain value2
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"value2");
+    }
+
+    #[test]
+    fn test_cas_race_has_one_winner() {
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+        for round in 0..2000 {
+            storage.set("k", b"start".to_vec(), 0, 0);
+            let token = storage.cas_of("k").unwrap();
+            // Two clients holding the same token; growing values make
+            // each CAS go through the room-making path
+            let results = thread::scope(|scope| {
+                let clients: Vec<_> = (0..2u8)
+                    .map(|t| {
+                        let storage = &storage;
+                        scope
+                            .spawn(move || storage.cas("k", vec![t; 64 + round % 256], 0, 0, token))
+                    })
+                    .collect();
+                clients
+                    .into_iter()
+                    .map(|client| client.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            let stored: Vec<_> = (0..2u8)
+                .filter(|&t| results[t as usize] == StorageResult::Stored)
+                .collect();
+            assert_eq!(stored.len(), 1, "round {round}: {results:?}");
+            assert!(results.contains(&StorageResult::CasMismatch));
+            // The value left is the winner's
+            assert_eq!(storage.get("k").unwrap().value[0], stored[0]);
+        }
+    }
+
+    #[test]
+    fn test_cas_rechecks_after_making_room() {
+        // "k" behind fillers enough for two such CASes: growing "k" must
+        // evict some
+        let fp = std::mem::size_of::<CacheItem>() + 1 + 1;
+        let storage = Storage::new(6 * fp + 10, 0);
+        for key in ["a", "b", "c", "d", "k"] {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        let token = storage.cas_of("k").unwrap();
+
+        // While the first CAS makes room, a second with the same token
+        // gets in first
+        let weak = Arc::downgrade(&storage);
+        let inner = Arc::new(Mutex::new(None));
+        let inner_result = Arc::clone(&inner);
+        assert!(storage.set_removal_callback(Box::new(move |_, _| {
+            let mut inner = inner_result.lock().unwrap();
+            if inner.is_none() {
+                let storage = weak.upgrade().unwrap();
+                *inner = Some(storage.cas("k", vec![b'2'; 2 * fp], 0, 0, token));
+            }
+        })));
+
+        let outer = storage.cas("k", vec![b'1'; 2 * fp], 0, 0, token);
+        assert_eq!(*inner.lock().unwrap(), Some(StorageResult::Stored));
+        assert_eq!(outer, StorageResult::CasMismatch);
+        assert_eq!(storage.get("k").unwrap().value, vec![b'2'; 2 * fp]);
+    }
+
+    #[test]
+    fn test_cas_not_found() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        let result = storage.cas("nonexistent", b"value".to_vec(), 0, 0, 1);
+        assert_eq!(result, StorageResult::NotFound);
+    }
+
+    #[test]
+    fn test_expiration() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        // Set with 1 second TTL
+        storage.set("key1", b"value1".to_vec(), 0, 1);
+
+        // Should exist immediately
+        assert!(storage.get("key1").is_some());
+
+        // Wait for expiration
+        thread::sleep(Duration::from_millis(1100));
+
+        // Should be expired now
+        assert!(storage.get("key1").is_none());
+    }
+
+    #[test]
+    fn test_absolute_exptime() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        // An hour from now, given as a timestamp
+        storage.set("future", b"v".to_vec(), 0, now + 3600);
+        let ttl = storage.ttl("future").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(3590) && ttl <= Duration::from_secs(3600));
+
+        // Timestamps already past, or a negative exptime, store it expired
+        storage.set("past", b"v".to_vec(), 0, now - 60);
+        assert!(storage.get("past").is_none());
+        storage.set("expired", b"v".to_vec(), 0, EXPIRED_TTL);
+        assert!(storage.get("expired").is_none());
+
+        // 30 days is still relative
+        storage.set("month", b"v".to_vec(), 0, MAX_RELATIVE_TTL);
+        let ttl = storage.ttl("month").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(MAX_RELATIVE_TTL - 10));
+    }
+
+    #[test]
+    fn test_get_with_ttl() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 100);
+        storage.set("key2", b"value2".to_vec(), 0, 0);
+
+        let (item, ttl) = storage.get_with_ttl("key1").unwrap();
+        assert_eq!(item.value, b"value1");
+        let ttl = ttl.unwrap();
+        let separate = storage.ttl("key1").unwrap().unwrap();
+        assert!(ttl >= separate);
+        assert!(ttl - separate < Duration::from_millis(100));
+        assert!(ttl <= Duration::from_secs(100));
+
+        // Never-expiring key reports no TTL
+        let (item, ttl) = storage.get_with_ttl("key2").unwrap();
+        assert_eq!(item.value, b"value2");
+        assert!(ttl.is_none());
+        assert_eq!(storage.ttl("key2"), Some(None));
+
+        assert!(storage.get_with_ttl("missing").is_none());
+        assert!(storage.ttl("missing").is_none());
+    }
+
+    #[test]
+    fn test_idle_time_does_not_touch_lru() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("old", b"1".to_vec(), 0, 0);
+        storage.set("new", b"2".to_vec(), 0, 0);
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(storage.idle_time("old").unwrap() >= Duration::from_millis(50));
+        storage.get("new");
+        assert!(storage.idle_time("new").unwrap() < Duration::from_millis(50));
+        assert!(storage.idle_time("missing").is_none());
+
+        // Inspecting "old" didn't make it recently used
+        assert_eq!(storage.find_victim().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn test_eviction_reclaims_expired_before_live() {
+        // Room for roughly ten 100-byte items
+        let storage = Storage::new(10 * 100 + 10 * 90, 0);
+        for i in 0..5 {
+            storage.set(&format!("live{i}"), vec![b'l'; 100], 0, 0);
+        }
+        for i in 0..5 {
+            storage.set(&format!("dying{i}"), vec![b'd'; 100], 0, 1);
+        }
+        // The live keys are now the least recently used
+        thread::sleep(Duration::from_millis(1100));
+
+        for i in 0..5 {
+            storage.set(&format!("new{i}"), vec![b'n'; 100], 0, 0);
+        }
+
+        for i in 0..5 {
+            assert!(
+                storage.get(&format!("live{i}")).is_some(),
+                "live{i} evicted"
+            );
+            assert!(storage.get(&format!("new{i}")).is_some());
+        }
+        let stats = storage.stats();
+        assert_eq!(stats.item_count, 10);
+        assert!(stats.memory_used <= 10 * 100 + 10 * 90);
+    }
+
+    #[test]
+    fn test_eviction_cost_is_independent_of_item_count() {
+        let value = || vec![b'v'; 16];
+        let fp = std::mem::size_of::<CacheItem>() + "key000000".len() + 16;
+
+        // Time 20k evictions from a full cache of `capacity` items
+        let evict_20k = |capacity: usize| {
+            let storage = Storage::new(capacity * fp, 0);
+            for i in 0..capacity {
+                storage.set(&format!("key{i:06}"), value(), 0, 0);
+            }
+            let start = Instant::now();
+            for i in capacity..capacity + 20_000 {
+                storage.set(&format!("key{i:06}"), value(), 0, 0);
+            }
+            let elapsed = start.elapsed();
+
+            // The oldest keys went first
+            assert_eq!(storage.stats().item_count, capacity);
+            assert!(storage.get("key000000").is_none());
+            assert!(storage
+                .get(&format!("key{:06}", capacity + 19_999))
+                .is_some());
+            elapsed
+        };
+
+        let small = evict_20k(1_000);
+        let large = evict_20k(100_000);
+        // A scan per eviction would make the large cache ~100x slower
+        assert!(
+            large < small * 10,
+            "evicting from 100k items took {large:?}, from 1k {small:?}"
+        );
+    }
+
+    #[test]
+    fn test_value_bytes_accounting_is_exact() {
+        let storage = Storage::new(1024 * 1024, 0);
+        let value_bytes = || storage.stats().value_bytes;
+
+        storage.set("a", vec![b'x'; 100], 0, 0);
+        storage.set("b", vec![b'y'; 50], 0, 0);
+        assert_eq!(value_bytes(), 150);
+
+        // Overwrite shrinks, append/prepend grow
+        storage.set("a", vec![b'x'; 30], 0, 0);
+        assert_eq!(value_bytes(), 80);
+        storage.append("b", b"12345");
+        storage.prepend("b", b"123");
+        assert_eq!(value_bytes(), 88);
+
+        // "1000" (4 bytes) becomes an 8-byte counter, then text again
+        storage.set("n", b"1000".to_vec(), 0, 0);
+        assert_eq!(value_bytes(), 92);
+        storage.incr("n", 1);
+        assert_eq!(value_bytes(), 96);
+        storage.append("n", b"0");
+        assert_eq!(value_bytes(), 93);
+
+        let cas = storage.cas_of("a").unwrap();
+        storage.cas("a", vec![b'z'; 10], 0, 0, cas);
+        assert_eq!(value_bytes(), 73);
+
+        storage.delete("b");
+        assert_eq!(value_bytes(), 15);
+        storage.flush_all();
+        assert_eq!(value_bytes(), 0);
+    }
+
+    #[test]
+    fn test_max_value_bytes_evicts_before_max_memory() {
+        // Plenty of memory, but only 300 value bytes
+        let storage = Storage::with_limits(1024 * 1024, Some(300), 0, EvictionPolicy::Lru);
+        for i in 0..3 {
+            storage.set(&format!("key{i}"), vec![b'v'; 100], 0, 0);
+        }
+        assert_eq!(storage.stats().value_bytes, 300);
+
+        storage.set("key3", vec![b'v'; 100], 0, 0);
+
+        let stats = storage.stats();
+        assert!(stats.memory_used < stats.max_memory / 100);
+        assert_eq!(stats.value_bytes, 300);
+        assert_eq!(stats.item_count, 3);
+        assert!(storage.get("key0").is_none(), "LRU item not evicted");
+        assert!(storage.get("key3").is_some());
+    }
+
+    #[test]
+    fn test_cas_counter_wraps_past_zero() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set_cas_counter(u64::MAX - 2);
+
+        let keys: Vec<String> = (0..6).map(|i| format!("key{i}")).collect();
+        for key in &keys {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        let tokens: Vec<u64> = keys.iter().map(|k| storage.cas_of(k).unwrap()).collect();
+        assert_eq!(tokens, [u64::MAX - 2, u64::MAX - 1, u64::MAX, 1, 2, 3]);
+
+        // Tokens from before the wrap don't match rewritten items
+        storage.set("key0", b"w".to_vec(), 0, 0);
+        assert_eq!(
+            storage.cas("key0", b"x".to_vec(), 0, 0, u64::MAX - 2),
+            StorageResult::CasMismatch
+        );
+        let mut live: Vec<u64> = keys.iter().map(|k| storage.cas_of(k).unwrap()).collect();
+        live.sort_unstable();
+        live.dedup();
+        assert_eq!(live.len(), keys.len());
+        assert_eq!(storage.stats().cas_counter, 5);
+    }
+
+    #[test]
+    fn test_set_with_cas_keeps_provided_token() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("local", b"v".to_vec(), 0, 0);
+
+        assert_eq!(
+            storage.set_with_cas("replica", b"v".to_vec(), 0, 0, 1000),
+            StorageResult::Stored
+        );
+        assert_eq!(storage.cas_of("replica"), Some(1000));
+        assert_eq!(
+            storage.cas("replica", b"w".to_vec(), 0, 0, 1000),
+            StorageResult::Stored
+        );
+
+        // Fresh tokens continue above the imported one
+        storage.set("next", b"v".to_vec(), 0, 0);
+        assert!(storage.cas_of("replica").unwrap() > 1000);
+        assert!(storage.cas_of("next").unwrap() > storage.cas_of("replica").unwrap());
+
+        // An older imported token doesn't pull the counter back
+        storage.set_with_cas("old", b"v".to_vec(), 0, 0, 5);
+        storage.set("after", b"v".to_vec(), 0, 0);
+        assert!(storage.cas_of("after").unwrap() > storage.cas_of("next").unwrap());
+    }
+
+    #[test]
+    fn test_reclaimed_bytes_split_by_cause() {
+        let fp = std::mem::size_of::<CacheItem>() + 2 + 100;
+        let storage = Storage::new(4 * fp, 0);
+        let value = || vec![b'v'; 100];
+        storage.set("k0", value(), 0, 0);
+        storage.set("k1", value(), 0, 0);
+        storage.set("d0", value(), 0, 1);
+        storage.set("d1", value(), 0, 1);
+        thread::sleep(Duration::from_millis(1100));
+
+        // Lazily removed on access
+        assert!(storage.get("d0").is_none());
+        assert_eq!(storage.stats().expired_bytes, fp as u64);
+
+        // Fills the freed slot, then the next write reclaims d1 first
+        storage.set("k2", value(), 0, 0);
+        storage.set("k3", value(), 0, 0);
+        let stats = storage.stats();
+        assert_eq!(stats.expired_bytes, 2 * fp as u64);
+        assert_eq!(stats.evicted_bytes, 0);
+
+        // Only live items left, so the LRU one is evicted
+        storage.set("k4", value(), 0, 0);
+        let stats = storage.stats();
+        assert!(storage.get("k0").is_none());
+        assert_eq!(stats.evicted_bytes, fp as u64);
+        assert_eq!(stats.expired_bytes, 2 * fp as u64);
+
+        // Explicit deletes count as neither
+        storage.delete("k1");
+        let stats = storage.stats();
+        assert_eq!(stats.evicted_bytes, fp as u64);
+        assert_eq!(stats.expired_bytes, 2 * fp as u64);
+    }
+
+    #[test]
+    fn test_sampled_size_histogram_tracks_full_scan() {
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+        // 10% / 20% / 30% / 40% of items in four distinct buckets
+        for i in 0..10_000 {
+            let len = match i % 10 {
+                0 => 10,
+                1..=2 => 100,
+                3..=5 => 1000,
+                _ => 5000,
+            };
+            storage.set(&format!("key{i:05}"), vec![b'x'; len], 0, 0);
+        }
+
+        let full = storage.detail_stats(0);
+        assert!(!full.approximate);
+        assert_eq!(full.sizes.len(), 4);
+        assert_eq!(full.sizes.values().sum::<u64>(), 10_000);
+        assert!(full.oldest_age.is_some());
+
+        let sampled = storage.detail_stats(2_000);
+        assert!(sampled.approximate);
+        assert_eq!(
+            sampled.sizes.keys().collect::<Vec<_>>(),
+            full.sizes.keys().collect::<Vec<_>>()
+        );
+        // Sampling error on 2000 items is well under 5% of the total
+        for (bucket, &count) in &full.sizes {
+            let estimate = sampled.sizes[bucket];
+            assert!(
+                estimate.abs_diff(count) < 500,
+                "bucket {bucket}: {estimate} vs {count}"
+            );
+        }
+
+        // A bound above the item count is an exact scan
+        assert!(!storage.detail_stats(10_000).approximate);
+    }
+
+    #[test]
+    fn test_removal_callback_sees_expiry_and_eviction() {
+        let fp = std::mem::size_of::<CacheItem>() + 2 + 100;
+        let storage = Storage::new(4 * fp, 0);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let weak = Arc::downgrade(&storage);
+        let log = Arc::clone(&seen);
+        assert!(storage.set_removal_callback(Box::new(move |key, reason| {
+            // Runs off the data lock, so storage is usable from here
+            let items = weak.upgrade().unwrap().stats().item_count;
+            log.lock().unwrap().push((key.to_string(), reason, items));
+        })));
+
+        let value = || vec![b'v'; 100];
+        for key in ["lz", "r0", "r1"] {
+            storage.set(key, value(), 0, 1);
+        }
+        storage.set("lv", value(), 0, 0);
+        thread::sleep(Duration::from_millis(1100));
+
+        assert!(storage.get("lz").is_none());
+        assert_eq!(storage.cleanup_expired(), 2);
+        let mut reaped = seen.lock().unwrap().split_off(1);
+        reaped.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            seen.lock().unwrap().as_slice(),
+            [("lz".to_string(), RemovalReason::Expired, 3)]
+        );
+        assert_eq!(
+            reaped,
+            [
+                ("r0".to_string(), RemovalReason::Expired, 1),
+                ("r1".to_string(), RemovalReason::Expired, 1),
+            ]
+        );
+
+        // Filling up evicts the LRU item
+        seen.lock().unwrap().clear();
+        for key in ["a0", "a1", "a2", "a3"] {
+            storage.set(key, value(), 0, 0);
+        }
+        assert_eq!(
+            seen.lock().unwrap().as_slice(),
+            [("lv".to_string(), RemovalReason::Evicted, 3)]
+        );
+    }
+
+    #[test]
+    fn test_store_reports_create_vs_replace() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        assert_eq!(storage.store("a", b"1".to_vec(), 0, 0), SetOutcome::Created);
+        assert_eq!(
+            storage.store("a", b"2".to_vec(), 0, 0),
+            SetOutcome::Replaced
+        );
+        assert_eq!(storage.store("b", b"1".to_vec(), 0, 1), SetOutcome::Created);
+        assert_eq!(storage.stats().total_items, 2);
+
+        // Overwriting an expired key is a create
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(storage.store("b", b"2".to_vec(), 0, 0), SetOutcome::Created);
+
+        // Replace-only paths don't count
+        assert_eq!(
+            storage.replace("a", b"3".to_vec(), 0, 0),
+            StorageResult::Stored
+        );
+        let cas = storage.cas_of("a").unwrap();
+        assert_eq!(
+            storage.cas("a", b"4".to_vec(), 0, 0, cas),
+            StorageResult::Stored
+        );
+        storage.delete("a");
+        assert_eq!(storage.add("a", b"5".to_vec(), 0, 0), StorageResult::Stored);
+
+        let stats = storage.stats();
+        assert_eq!(stats.total_items, 4);
+        assert_eq!(stats.item_count, 2);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // Create storage with 500 byte limit
+        let storage = Storage::new(500, 0);
+
+        // Add items until we hit the limit
+        for i in 0..20 {
+            let key = format!("key{i}");
+            let value = vec![0u8; 50];
+            storage.set(&key, value, 0, 0);
+        }
+
+        // Memory should be at or below limit
+        let stats = storage.stats();
+        assert!(stats.memory_used <= 500);
+    }
+
+    #[test]
+    fn test_get_multi() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"value1".to_vec(), 0, 0);
+        storage.set("key2", b"value2".to_vec(), 0, 0);
+        storage.set("key3", b"value3".to_vec(), 0, 0);
+
+        let results = storage.get_multi(&["key1", "key2", "nonexistent"]);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_append() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"Hello".to_vec(), 0, 0);
+
+        let result = storage.append("key1", b" World".as_ref());
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"Hello World");
+    }
+
+    #[test]
+    fn test_prepend() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        storage.set("key1", b"World".to_vec(), 0, 0);
+
+        let result = storage.prepend("key1", b"Hello ".as_ref());
+        assert_eq!(result, StorageResult::Stored);
+
+        let item = storage.get("key1").unwrap();
+        assert_eq!(item.value, b"Hello World");
+    }
+
+    fn assert_snapshot_round_trip(format: SnapshotFormat) {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("plain", b"value1".to_vec(), 0, 0);
+        storage.set("flagged", b"\x00\xffbinary".to_vec(), 42, 0);
+        storage.set("expiring", b"soon".to_vec(), 7, 100);
+
+        let mut snapshot = Vec::new();
+        assert_eq!(storage.dump(&mut snapshot, format).unwrap(), 3);
+
+        let restored = Storage::new(1024 * 1024, 0);
+        assert_eq!(restored.load(&mut &snapshot[..], format).unwrap(), 3);
+
+        let item = restored.get("plain").unwrap();
+        assert_eq!(item.value, b"value1");
+        assert_eq!(item.flags, 0);
+        assert_eq!(restored.ttl("plain"), Some(None));
+
+        let item = restored.get("flagged").unwrap();
+        assert_eq!(item.value, b"\x00\xffbinary");
+        assert_eq!(item.flags, 42);
+
+        let (item, ttl) = restored.get_with_ttl("expiring").unwrap();
+        assert_eq!(item.value, b"soon");
+        assert_eq!(item.flags, 7);
+        let ttl = ttl.unwrap();
+        assert!(ttl > Duration::from_secs(98) && ttl <= Duration::from_secs(100));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip_binary() {
+        assert_snapshot_round_trip(SnapshotFormat::Binary);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip_json() {
+        assert_snapshot_round_trip(SnapshotFormat::Json);
+    }
+
+    #[test]
+    fn test_snapshot_is_point_in_time_and_read_in_pieces() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("a", vec![b'a'; 1000], 1, 0);
+        storage.set("b", Vec::new(), 2, 3600);
+        let mut snapshot = storage.snapshot();
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
+        // Writes after the snapshot is taken aren't in it
+        storage.set("c", b"v".to_vec(), 0, 0);
+        storage.delete("a");
 
-        let result = storage.add("key1", b"value2".to_vec(), 0, 0);
-        assert_eq!(result, StorageResult::NotStored);
+        // Read a few bytes at a time, it counts down to the end
+        let total = snapshot.remaining();
+        let mut data = Vec::new();
+        let mut buf = [0u8; 7];
+        loop {
+            let n = snapshot.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            assert_eq!(snapshot.remaining(), total - data.len());
+        }
+        assert_eq!(data.len(), total);
 
-        // Value should remain unchanged
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value1");
+        let replica = Storage::new(1024 * 1024, 0);
+        assert_eq!(
+            replica
+                .load(&mut &data[..], SnapshotFormat::Binary)
+                .unwrap(),
+            2
+        );
+        assert_eq!(replica.get("a").unwrap().value, vec![b'a'; 1000]);
+        assert_eq!(replica.get("b").unwrap().flags, 2);
+        assert!(replica.get("c").is_none());
     }
 
     #[test]
-    fn test_add_new() {
+    fn test_snapshot_ignores_writes_while_read() {
+        let storage = Storage::new(16 * 1024 * 1024, 0);
+        for i in 0..500 {
+            storage.set(&format!("key{i}"), format!("value{i}").into_bytes(), i, 0);
+        }
+        storage.set("counter", b"10".to_vec(), 0, 0);
+        storage.set("short", b"v".to_vec(), 0, 1);
+        let mut snapshot = storage.snapshot();
+        let total = snapshot.remaining();
+
+        // Read past the first batch, then change items read and unread
+        let mut data = vec![0u8; 4096];
+        snapshot.read_exact(&mut data).unwrap();
+        for i in 0..500 {
+            match i % 4 {
+                0 => storage.set(&format!("key{i}"), b"changed".to_vec(), 0, 0),
+                1 => storage.delete(&format!("key{i}")),
+                2 => storage.append(&format!("key{i}"), b"+more"),
+                _ => storage.set(&format!("new{i}"), b"late".to_vec(), 0, 0),
+            };
+        }
+        storage.incr("counter", 5);
+        storage.touch("short", 0);
+        storage.flush_all();
+        snapshot.read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), total);
+
+        let replica = Storage::new(16 * 1024 * 1024, 0);
+        let loaded = replica.load(&mut &data[..], SnapshotFormat::Binary);
+        assert_eq!(loaded.unwrap(), 502);
+        for i in 0..500 {
+            let item = replica.get(&format!("key{i}")).unwrap();
+            assert_eq!(item.value, format!("value{i}").into_bytes());
+            assert_eq!(item.flags, i);
+            assert!(replica.get(&format!("new{i}")).is_none());
+        }
+        assert_eq!(replica.get("counter").unwrap().value, b"10");
+        assert!(replica.ttl("short").unwrap().is_some());
+    }
+
+    /// One full cursor scan, in pages of `count`, running `between` after
+    /// each page.
+    fn scan_all(storage: &Storage, count: usize, mut between: impl FnMut()) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, page) = storage.scan(cursor, count);
+            keys.extend(page);
+            if next == 0 {
+                return keys;
+            }
+            cursor = next;
+            between();
+        }
+    }
+
+    #[test]
+    fn test_scan_skips_expired_keys() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("live", b"v".to_vec(), 0, 0);
+        storage.set("gone", b"v".to_vec(), 0, EXPIRED_TTL);
+        assert_eq!(scan_all(&storage, 10, || {}), ["live"]);
 
-        let result = storage.add("key1", b"value1".to_vec(), 0, 0);
-        assert_eq!(result, StorageResult::Stored);
+        storage.flush_all();
+        assert_eq!(storage.scan(0, 10), (0, vec![]));
+    }
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value1");
+    #[test]
+    fn test_scan_returns_steady_keys_once_despite_churn() {
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+        let steady: Vec<String> = (0..500).map(|i| format!("steady{i}")).collect();
+        for key in &steady {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+
+        // Between pages, add far more keys than there were, growing the map
+        // through several resizes, and delete some of them again
+        let mut added = 0;
+        let mut churn = || {
+            for _ in 0..50 {
+                storage.set(&format!("added{added}"), b"v".to_vec(), 0, 0);
+                if added % 3 == 0 {
+                    storage.delete(&format!("added{}", added / 2));
+                }
+                added += 1;
+            }
+        };
+        let keys = scan_all(&storage, 25, &mut churn);
+        assert!(added >= 1000, "only {added} added");
+
+        let mut seen = HashSet::new();
+        for key in &keys {
+            assert!(seen.insert(key), "{key} returned twice");
+        }
+        for key in &steady {
+            assert!(seen.contains(key), "{key} missed");
+        }
+
+        // And with writers on other threads
+        let stop = std::sync::atomic::AtomicBool::new(false);
+        let keys = thread::scope(|scope| {
+            for writer in 0..2 {
+                let (storage, stop) = (&storage, &stop);
+                scope.spawn(move || {
+                    let mut i = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let key = format!("writer{writer}-{}", i % 1000);
+                        if i % 2 == 0 {
+                            storage.set(&key, b"v".to_vec(), 0, 0);
+                        } else {
+                            storage.delete(&key);
+                        }
+                        i += 1;
+                    }
+                });
+            }
+            let keys = scan_all(&storage, 7, thread::yield_now);
+            stop.store(true, Ordering::Relaxed);
+            keys
+        });
+        let seen: HashSet<_> = keys.iter().collect();
+        assert_eq!(seen.len(), keys.len(), "a key was returned twice");
+        assert!(steady.iter().all(|key| seen.contains(key)));
     }
 
     #[test]
-    fn test_replace_existing() {
+    fn test_snapshot_file_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("grow-a-cache-snapshot-{}.bin", std::process::id()));
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("plain", b"value1".to_vec(), 3, 0);
+        storage.set("expiring", b"soon".to_vec(), 0, 100);
+        storage.set("gone", b"x".to_vec(), 0, EXPIRED_TTL);
+        assert_eq!(
+            storage
+                .save_snapshot(&path, SnapshotFormat::Binary)
+                .unwrap(),
+            2
+        );
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
+        // Loaded a little later, the TTL keeps counting down from the save
+        std::thread::sleep(Duration::from_millis(1100));
+        let restored = Storage::new(1024 * 1024, 0);
+        assert_eq!(
+            restored
+                .load_snapshot(&path, SnapshotFormat::Binary)
+                .unwrap(),
+            2
+        );
+        std::fs::remove_file(&path).unwrap();
 
-        let result = storage.replace("key1", b"value2".to_vec(), 0, 0);
-        assert_eq!(result, StorageResult::Stored);
+        let item = restored.get("plain").unwrap();
+        assert_eq!((item.value.as_slice(), item.flags), (&b"value1"[..], 3));
+        assert_eq!(restored.ttl("plain"), Some(None));
+        let (item, ttl) = restored.get_with_ttl("expiring").unwrap();
+        assert_eq!(item.value, b"soon");
+        let ttl = ttl.unwrap();
+        assert!(ttl > Duration::from_secs(97) && ttl <= Duration::from_secs(100));
+        assert!(restored.get("gone").is_none());
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value2");
+        let missing = restored.load_snapshot(&path, SnapshotFormat::Binary);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
     }
 
     #[test]
-    fn test_replace_nonexistent() {
+    fn test_snapshot_json_is_inspectable() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("key1", b"hello".to_vec(), 5, 0);
 
-        let result = storage.replace("key1", b"value1".to_vec(), 0, 0);
-        assert_eq!(result, StorageResult::NotStored);
+        let mut snapshot = Vec::new();
+        storage.dump(&mut snapshot, SnapshotFormat::Json).unwrap();
+        let text = String::from_utf8(snapshot).unwrap();
+        assert!(text.contains("\"key\": \"key1\""));
+        assert!(text.contains("\"value\": \"aGVsbG8=\""));
+        assert!(text.contains("\"flags\": 5"));
     }
 
     #[test]
-    fn test_cas_success() {
+    fn test_snapshot_rejects_garbage() {
         let storage = Storage::new(1024 * 1024, 0);
+        assert!(storage
+            .load(&mut &b"nope"[..], SnapshotFormat::Binary)
+            .is_err());
+        assert!(storage
+            .load(&mut &b"nope"[..], SnapshotFormat::Json)
+            .is_err());
+    }
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
-        let item = storage.get("key1").unwrap();
-        let cas = item.cas_unique;
+    #[test]
+    fn test_snapshot_rejects_corrupt_lengths() {
+        let source = Storage::new(1024 * 1024, 0);
+        source.set("key", b"value".to_vec(), 0, 0);
+        let mut snapshot = Vec::new();
+        source.dump(&mut snapshot, SnapshotFormat::Binary).unwrap();
+        // Magic, version and entry count come first, then the key length
+        let key_len_at = SNAPSHOT_MAGIC.len() + 1 + 8;
+        let value_len_at = key_len_at + 4 + 3 + 4 + 1 + 8;
+        assert_eq!(snapshot[value_len_at], 5);
 
-        let result = storage.cas("key1", b"value2".to_vec(), 0, 0, cas);
-        assert_eq!(result, StorageResult::Stored);
+        for (at, len) in [
+            (key_len_at, &u32::MAX.to_le_bytes()[..]),
+            (value_len_at, &u64::MAX.to_le_bytes()),
+            // Just one byte more than is there
+            (value_len_at, &6u64.to_le_bytes()),
+        ] {
+            let mut corrupt = snapshot.clone();
+            corrupt[at..at + len.len()].copy_from_slice(len);
+            let storage = Storage::new(1024 * 1024, 0);
+            let err = storage
+                .load(&mut corrupt.as_slice(), SnapshotFormat::Binary)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{err}");
+            assert_eq!(storage.stats().item_count, 0);
+        }
+    }
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value2");
+    #[test]
+    fn test_snapshot_loads_ttl_out_of_range() {
+        let source = Storage::new(1024 * 1024, 0);
+        source.set("key", b"value".to_vec(), 0, 3600);
+        let mut binary = Vec::new();
+        source.dump(&mut binary, SnapshotFormat::Binary).unwrap();
+        let mut json = Vec::new();
+        source.dump(&mut json, SnapshotFormat::Json).unwrap();
+
+        // After the key and flags come the has-ttl flag and the TTL
+        let ttl_at = SNAPSHOT_MAGIC.len() + 1 + 8 + 4 + 3 + 4 + 1;
+        assert_eq!(binary[ttl_at - 1], 1);
+        binary[ttl_at..ttl_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let json = String::from_utf8(json).unwrap();
+        let (head, tail) = json.split_once("\"ttl_ms\": ").unwrap();
+        let rest = tail.trim_start_matches(|c: char| c.is_ascii_digit());
+        let json = format!("{head}\"ttl_ms\": {}{rest}", u64::MAX);
+
+        for (snapshot, format) in [
+            (binary, SnapshotFormat::Binary),
+            (json.into_bytes(), SnapshotFormat::Json),
+        ] {
+            let storage = Storage::new(1024 * 1024, 0);
+            assert_eq!(storage.load(&mut snapshot.as_slice(), format).unwrap(), 1);
+            assert_eq!(storage.get("key").unwrap().value, b"value");
+            // Past what an Instant holds it never expires, else far out
+            match storage.ttl("key").unwrap() {
+                None => {}
+                Some(ttl) => assert!(ttl > Duration::from_secs(1 << 40), "{ttl:?}"),
+            }
+        }
     }
 
     #[test]
-    fn test_cas_mismatch() {
+    fn test_flush_all() {
         let storage = Storage::new(1024 * 1024, 0);
 
         storage.set("key1", b"value1".to_vec(), 0, 0);
-        let item = storage.get("key1").unwrap();
-        let cas = item.cas_unique;
+        storage.set("key2", b"value2".to_vec(), 0, 0);
 
-        // Modify the item
-        storage.set("key1", b"value2".to_vec(), 0, 0);
+        storage.flush_all();
 
-        // Try CAS with old token
-        let result = storage.cas("key1", b"value3".to_vec(), 0, 0, cas);
-        assert_eq!(result, StorageResult::CasMismatch);
+        assert!(storage.get("key1").is_none());
+        assert!(storage.get("key2").is_none());
 
-        // Value should remain value2
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"value2");
+        let stats = storage.stats();
+        assert_eq!(stats.item_count, 0);
+        assert_eq!(stats.memory_used, 0);
     }
 
     #[test]
-    fn test_cas_not_found() {
+    fn test_incr_million_times() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("hits", b"0".to_vec(), 5, 0);
 
-        let result = storage.cas("nonexistent", b"value".to_vec(), 0, 0, 1);
-        assert_eq!(result, StorageResult::NotFound);
+        for _ in 0..1_000_000 {
+            storage.incr("hits", 1);
+        }
+
+        // Stored compactly, read back as decimal text with flags intact
+        let item = storage.get("hits").unwrap();
+        assert_eq!(item.value, b"1000000");
+        assert_eq!(item.flags, 5);
+        assert_eq!(storage.decr("hits", 1_000_001), CounterResult::Value(0));
+        assert_eq!(storage.get("hits").unwrap().value, b"0");
     }
 
     #[test]
-    fn test_expiration() {
+    fn test_hit_and_miss_counters() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("k0", b"v".to_vec(), 0, 0);
+        storage.set("k1", b"v".to_vec(), 0, 1);
+        assert_eq!(
+            storage.add("k0", b"v".to_vec(), 0, 0),
+            StorageResult::NotStored
+        );
 
-        // Set with 1 second TTL
-        storage.set("key1", b"value1".to_vec(), 0, 1);
+        assert!(storage.get("k0").is_some());
+        assert!(storage.get("nope").is_none());
+        let found = storage.get_multi(&["k0", "nope", "k1", "gone"]);
+        assert_eq!(found.len(), 2);
+        let stats = storage.stats();
+        assert_eq!((stats.get_hits, stats.get_misses), (3, 3));
+        assert_eq!(stats.cmd_set, 3);
 
-        // Should exist immediately
-        assert!(storage.get("key1").is_some());
+        // An expired item is a miss
+        thread::sleep(Duration::from_millis(1100));
+        assert!(storage.get("k1").is_none());
+        let stats = storage.stats();
+        assert_eq!((stats.get_hits, stats.get_misses), (3, 4));
+    }
 
-        // Wait for expiration
+    #[test]
+    fn test_eviction_and_expired_unfetched_counters() {
+        let fp = std::mem::size_of::<CacheItem>() + 2 + 1;
+        let storage = Storage::new(5 * fp, 0);
+        storage.set("k0", b"v".to_vec(), 0, 0);
+        // Read before expiring, so not counted
+        storage.set("k1", b"v".to_vec(), 0, 1);
+        storage.get("k1");
+        // Never read: one replaced once expired, one swept
+        storage.set("k2", b"v".to_vec(), 0, 1);
+        storage.set("k3", b"v".to_vec(), 0, 1);
+        // A rewrite starts unread again
+        storage.get("k0");
+        storage.set("k0", b"w".to_vec(), 0, 1);
         thread::sleep(Duration::from_millis(1100));
 
-        // Should be expired now
-        assert!(storage.get("key1").is_none());
+        storage.set("k2", b"w".to_vec(), 0, 0);
+        storage.cleanup_expired();
+        let stats = storage.stats();
+        assert_eq!(stats.expired_unfetched, 3);
+        // k1 as well, though it was read
+        assert_eq!(stats.expired, 4);
+        assert_eq!(stats.evictions, 0);
+
+        for i in 0..5 {
+            storage.set(&format!("n{i}"), b"v".to_vec(), 0, 0);
+        }
+        // Only k2 was left to make room for the fifth
+        let stats = storage.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.expired_unfetched, 3);
+        assert!(storage.get("k2").is_none());
     }
 
     #[test]
-    fn test_memory_limit() {
-        // Create storage with 500 byte limit
-        let storage = Storage::new(500, 0);
+    fn test_pressure_tracks_evictions_and_memory() {
+        let storage = Storage::new(64 * 1024, 0);
+        let idle = storage.pressure();
+        assert_eq!(idle.eviction_rate, 0.0);
+        assert_eq!(idle.memory_pressure, 0.0);
 
-        // Add items until we hit the limit
-        for i in 0..20 {
-            let key = format!("key{i}");
-            let value = vec![0u8; 50];
-            storage.set(&key, value, 0, 0);
+        for i in 0..200 {
+            storage.set(&format!("key{i}"), vec![b'x'; 1024], 0, 0);
         }
-
-        // Memory should be at or below limit
         let stats = storage.stats();
-        assert!(stats.memory_used <= 500);
+        assert!(stats.evictions > 0);
+        let pressure = storage.pressure();
+        assert!(pressure.eviction_rate > 0.0);
+        assert_eq!(
+            pressure.memory_pressure,
+            stats.memory_used as f64 / (64 * 1024) as f64
+        );
+        assert!(pressure.memory_pressure > 0.5 && pressure.memory_pressure <= 1.0);
+
+        // Evictions age out of the window once it has moved on
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+        storage.eviction_rate_at(later(30));
+        let evictions = storage.stats().evictions as f64;
+        assert!(storage.eviction_rate_at(later(120)) < evictions / 60.0);
+        assert_eq!(storage.eviction_rate_at(later(240)), 0.0);
+
+        // A stats reset doesn't disturb the rate
+        storage.set("more", vec![b'x'; 1024], 0, 0);
+        storage.reset_stats();
+        assert!(storage.eviction_rate_at(later(250)) > 0.0);
     }
 
     #[test]
-    fn test_get_multi() {
+    fn test_compare_value_and_set() {
         let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(
+            storage.compare_value_and_set("k", b"old", b"new".to_vec(), 0, 0),
+            StorageResult::NotFound
+        );
+        assert!(storage.get("k").is_none());
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
-        storage.set("key2", b"value2".to_vec(), 0, 0);
-        storage.set("key3", b"value3".to_vec(), 0, 0);
+        storage.set("k", b"old".to_vec(), 0, 0);
+        let cas = storage.cas_of("k").unwrap();
+        assert_eq!(
+            storage.compare_value_and_set("k", b"other", b"new".to_vec(), 0, 0),
+            StorageResult::CasMismatch
+        );
+        assert_eq!(storage.get("k").unwrap().value, b"old");
+        assert_eq!(storage.cas_of("k"), Some(cas));
 
-        let results = storage.get_multi(&["key1", "key2", "nonexistent"]);
-        assert_eq!(results.len(), 2);
+        assert_eq!(
+            storage.compare_value_and_set("k", b"old", b"new".to_vec(), 5, 0),
+            StorageResult::Stored
+        );
+        let item = storage.get("k").unwrap();
+        assert_eq!((item.value.as_slice(), item.flags), (&b"new"[..], 5));
+        assert!(item.cas_unique > cas);
+
+        // Counters compare by their text form
+        storage.set("n", b"9".to_vec(), 0, 0);
+        storage.incr("n", 1);
+        assert_eq!(
+            storage.compare_value_and_set("n", b"10", b"0".to_vec(), 0, 0),
+            StorageResult::Stored
+        );
+        assert_eq!(storage.get("n").unwrap().value, b"0");
     }
 
     #[test]
-    fn test_append() {
+    fn test_get_and_set_and_delete() {
         let storage = Storage::new(1024 * 1024, 0);
+        assert!(matches!(
+            storage.get_and_set("k", b"one".to_vec(), 0, 0),
+            Some(None)
+        ));
+        let old = storage.get_and_set("k", b"two".to_vec(), 0, 0);
+        assert_eq!(old.unwrap().unwrap().value, b"one");
+        assert_eq!(storage.get("k").unwrap().value, b"two");
 
-        storage.set("key1", b"Hello".to_vec(), 0, 0);
+        // Counters come back in their text form
+        storage.set("n", b"41".to_vec(), 0, 0);
+        storage.incr("n", 1);
+        let old = storage.get_and_delete("n").unwrap();
+        assert_eq!(old.value, b"42");
+        assert!(storage.get("n").is_none());
+        assert!(storage.get_and_delete("n").is_none());
 
-        let result = storage.append("key1", b" World".as_ref());
-        assert_eq!(result, StorageResult::Stored);
+        // An expired item is neither returned nor left behind
+        storage.set("gone", b"v".to_vec(), 0, EXPIRED_TTL);
+        assert!(matches!(
+            storage.get_and_set("gone", b"w".to_vec(), 0, 0),
+            Some(None)
+        ));
+        storage.set("gone", b"v".to_vec(), 0, EXPIRED_TTL);
+        assert!(storage.get_and_delete("gone").is_none());
+        assert_eq!(storage.stats().item_count, 1);
+        // Each call counts as one lookup, like the gets between them
+        let stats = storage.stats();
+        assert_eq!((stats.get_hits, stats.get_misses), (3, 5));
+    }
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"Hello World");
+    #[test]
+    fn test_get_and_set_hands_each_value_on_once() {
+        const THREADS: usize = 4;
+        const SWAPS: usize = 2000;
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+
+        // Every value written is either replaced, and returned to exactly
+        // one swapper, or the one left at the end
+        let returned: Vec<Option<Vec<u8>>> = thread::scope(|scope| {
+            let swappers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let storage = &storage;
+                    scope.spawn(move || {
+                        (0..SWAPS)
+                            .map(|n| {
+                                let value = format!("{t}-{n}").into_bytes();
+                                let old = storage.get_and_set("k", value, 0, 0).unwrap();
+                                old.map(|item| item.value)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            swappers
+                .into_iter()
+                .flat_map(|swapper| swapper.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(returned.iter().filter(|old| old.is_none()).count(), 1);
+        let mut seen: HashSet<Vec<u8>> = returned.into_iter().flatten().collect();
+        assert_eq!(
+            seen.len(),
+            THREADS * SWAPS - 1,
+            "a value was returned twice"
+        );
+        assert!(seen.insert(storage.get("k").unwrap().value));
     }
 
     #[test]
-    fn test_prepend() {
+    fn test_get_and_delete_returns_each_value_once() {
+        const WRITES: usize = 5000;
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+        let done = AtomicBool::new(false);
+
+        let taken: Vec<Vec<u8>> = thread::scope(|scope| {
+            let takers: Vec<_> = (0..3)
+                .map(|_| {
+                    let (storage, done) = (&storage, &done);
+                    scope.spawn(move || {
+                        let mut taken = Vec::new();
+                        while !done.load(Ordering::Relaxed) {
+                            taken.extend(storage.get_and_delete("k").map(|item| item.value));
+                        }
+                        taken
+                    })
+                })
+                .collect();
+            for n in 0..WRITES {
+                storage.set("k", n.to_string().into_bytes(), 0, 0);
+            }
+            done.store(true, Ordering::Relaxed);
+            takers
+                .into_iter()
+                .flat_map(|taker| taker.join().unwrap())
+                .collect()
+        });
+
+        let unique: HashSet<_> = taken.iter().collect();
+        assert_eq!(unique.len(), taken.len(), "a value was taken twice");
+        // What wasn't taken was overwritten, or is still there
+        let left = storage.get("k").map(|item| item.value);
+        assert!(left.is_none_or(|value| !unique.contains(&value)));
+    }
+
+    #[test]
+    fn test_racing_adds_store_once() {
+        let storage = Storage::new(64 * 1024 * 1024, 0);
+        for round in 0..200 {
+            let key = format!("k{round}");
+            let stored = thread::scope(|scope| {
+                let adders: Vec<_> = (0..4)
+                    .map(|t| {
+                        let (storage, key) = (&storage, &key);
+                        scope.spawn(move || storage.add(key, vec![t], 0, 0))
+                    })
+                    .collect();
+                adders
+                    .into_iter()
+                    .map(|adder| adder.join().unwrap())
+                    .filter(|result| *result == StorageResult::Stored)
+                    .count()
+            });
+            assert_eq!(stored, 1, "round {round}");
+        }
+    }
+
+    /// Three one-byte items "a", "b", "c" filling a cache under `policy`,
+    /// with "a" read most often but least recently.
+    fn full_cache(policy: EvictionPolicy) -> Arc<Storage> {
+        let fp = std::mem::size_of::<CacheItem>() + 1 + 1;
+        let storage = Storage::with_limits(3 * fp, None, 0, policy);
+        for key in ["a", "b", "c"] {
+            storage.set(key, b"v".to_vec(), 0, 0);
+        }
+        for _ in 0..5 {
+            storage.get("a");
+        }
+        storage.get("b");
+        storage.get("c");
+        storage
+    }
+
+    fn survivors(storage: &Storage) -> Vec<&'static str> {
+        ["a", "b", "c", "d"]
+            .into_iter()
+            .filter(|key| storage.get(key).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_lru_policy_evicts_least_recent() {
+        let storage = full_cache(EvictionPolicy::Lru);
+        assert_eq!(storage.set("d", b"v".to_vec(), 0, 0), StorageResult::Stored);
+        assert_eq!(survivors(&storage), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_lfu_policy_evicts_least_read() {
+        let storage = full_cache(EvictionPolicy::Lfu);
+        assert_eq!(storage.access_frequency("a"), Some(5));
+        assert_eq!(storage.set("d", b"v".to_vec(), 0, 0), StorageResult::Stored);
+        // b and c were read once each; b is the older
+        assert_eq!(survivors(&storage), ["a", "c", "d"]);
+
+        // A rewrite starts the count over
+        storage.set("a", b"w".to_vec(), 0, 0);
+        assert_eq!(storage.access_frequency("a"), Some(0));
+    }
+
+    #[test]
+    fn test_random_policy_evicts_any_item() {
+        let mut victims = HashSet::new();
+        for _ in 0..32 {
+            let storage = full_cache(EvictionPolicy::Random);
+            assert_eq!(storage.set("d", b"v".to_vec(), 0, 0), StorageResult::Stored);
+            let left = survivors(&storage);
+            assert_eq!(left.len(), 3);
+            assert!(left.contains(&"d"));
+            victims.extend(
+                ["a", "b", "c"]
+                    .into_iter()
+                    .filter(|key| !left.contains(key)),
+            );
+        }
+        // Not just the LRU item every time
+        assert!(victims.len() > 1, "{victims:?}");
+    }
+
+    #[test]
+    fn test_noeviction_policy_refuses_writes() {
+        let storage = full_cache(EvictionPolicy::NoEviction);
+        assert_eq!(
+            storage.set("d", b"v".to_vec(), 0, 0),
+            StorageResult::OutOfMemory
+        );
+        assert_eq!(storage.append("a", b"more"), StorageResult::OutOfMemory);
+        assert_eq!(
+            storage.cas("a", b"longer".to_vec(), 0, 0, storage.cas_of("a").unwrap()),
+            StorageResult::OutOfMemory
+        );
+        assert_eq!(storage.incr_or_create("n", 1), CounterResult::OutOfMemory);
+        assert_eq!(survivors(&storage), ["a", "b", "c"]);
+        assert_eq!(storage.stats().evictions, 0);
+
+        // Writes that fit still work, and freeing space makes room
+        assert_eq!(storage.set("a", b"w".to_vec(), 0, 0), StorageResult::Stored);
+        storage.delete("b");
+        assert_eq!(storage.set("d", b"v".to_vec(), 0, 0), StorageResult::Stored);
+    }
+
+    #[test]
+    fn test_counter_create_on_missing() {
+        let storage = Storage::new(1024 * 1024, 60);
+
+        assert_eq!(storage.incr("hits", 5), CounterResult::NotFound);
+        assert_eq!(storage.incr_or_create("hits", 5), CounterResult::Value(5));
+        assert_eq!(storage.incr_or_create("hits", 5), CounterResult::Value(10));
+        assert_eq!(storage.decr_or_create("misses", 3), CounterResult::Value(0));
+
+        // Created with the default TTL and no flags
+        let item = storage.get("hits").unwrap();
+        assert_eq!(item.value, b"10");
+        assert_eq!(item.flags, 0);
+        let ttl = item.expires_at.unwrap() - Instant::now();
+        assert!(ttl > Duration::from_secs(59) && ttl <= Duration::from_secs(60));
+
+        // Existing non-numeric values are still an error
+        storage.set("name", b"abc".to_vec(), 0, 0);
+        assert_eq!(storage.incr_or_create("name", 1), CounterResult::NotNumeric);
+        assert_eq!(storage.stats().item_count, 3);
+    }
+
+    #[test]
+    fn test_signed_counter() {
         let storage = Storage::new(1024 * 1024, 0);
 
-        storage.set("key1", b"World".to_vec(), 0, 0);
+        assert_eq!(
+            storage.incr_by_signed("n", -3),
+            SignedCounterResult::Value(-3)
+        );
+        assert_eq!(storage.get("n").unwrap().value, b"-3");
+        assert_eq!(
+            storage.incr_by_signed("n", 5),
+            SignedCounterResult::Value(2)
+        );
+        assert_eq!(storage.get("n").unwrap().value, b"2");
+        // Back below zero from the integer encoding
+        assert_eq!(
+            storage.incr_by_signed("n", -4),
+            SignedCounterResult::Value(-2)
+        );
 
-        let result = storage.prepend("key1", b"Hello ".as_ref());
-        assert_eq!(result, StorageResult::Stored);
+        // Out of range either way leaves the value alone
+        storage.set("max", i64::MAX.to_string().into_bytes(), 0, 0);
+        assert_eq!(
+            storage.incr_by_signed("max", 1),
+            SignedCounterResult::Overflow
+        );
+        assert_eq!(
+            storage.incr_by_signed("n", i64::MIN),
+            SignedCounterResult::Overflow
+        );
+        assert_eq!(storage.get("n").unwrap().value, b"-2");
 
-        let item = storage.get("key1").unwrap();
-        assert_eq!(item.value, b"Hello World");
+        // A memcached counter past i64::MAX isn't a signed integer
+        storage.set("big", u64::MAX.to_string().into_bytes(), 0, 0);
+        assert_eq!(
+            storage.incr_by_signed("big", 0),
+            SignedCounterResult::NotNumeric
+        );
+        storage.set("word", b"abc".to_vec(), 0, 0);
+        assert_eq!(
+            storage.incr_by_signed("word", 1),
+            SignedCounterResult::NotNumeric
+        );
     }
 
     #[test]
-    fn test_flush_all() {
+    fn test_counter_keeps_ttl() {
         let storage = Storage::new(1024 * 1024, 0);
+        storage.set("n", b"1".to_vec(), 0, 100);
+        assert_eq!(storage.incr("n", 1), CounterResult::Value(2));
+        let ttl = storage.ttl("n").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(90) && ttl <= Duration::from_secs(100));
 
-        storage.set("key1", b"value1".to_vec(), 0, 0);
-        storage.set("key2", b"value2".to_vec(), 0, 0);
+        storage.set("short", b"1".to_vec(), 0, 1);
+        assert_eq!(storage.decr("short", 1), CounterResult::Value(0));
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(storage.get("short").is_none());
+    }
 
-        storage.flush_all();
+    #[test]
+    fn test_counter_errors_and_text_edits() {
+        let storage = Storage::new(1024 * 1024, 0);
+        assert_eq!(storage.incr("missing", 1), CounterResult::NotFound);
 
-        assert!(storage.get("key1").is_none());
-        assert!(storage.get("key2").is_none());
+        storage.set("word", b"abc".to_vec(), 0, 0);
+        assert_eq!(storage.incr("word", 1), CounterResult::NotNumeric);
 
-        let stats = storage.stats();
-        assert_eq!(stats.item_count, 0);
-        assert_eq!(stats.memory_used, 0);
+        storage.set("n", b"41".to_vec(), 0, 0);
+        assert_eq!(storage.incr("n", 1), CounterResult::Value(42));
+        assert_eq!(storage.append("n", b"0"), StorageResult::Stored);
+        assert_eq!(storage.get("n").unwrap().value, b"420");
+        assert_eq!(storage.incr("n", u64::MAX), CounterResult::Value(419));
+
+        // Memory accounting matches a plain rewrite of the same key
+        storage.delete("word");
+        storage.delete("n");
+        assert_eq!(storage.stats().memory_used, 0);
+    }
+
+    #[test]
+    fn test_replace_never_underreports_memory() {
+        use std::sync::Arc;
+
+        const KEYS: usize = 8;
+        let storage = Arc::new(Storage::new(64 * 1024 * 1024, 0));
+        let small = vec![b's'; 100];
+        let large = vec![b'l'; 1000];
+        for i in 0..KEYS {
+            storage.set(&format!("key{i}"), small.clone(), 0, 0);
+        }
+        // Every key always holds at least the small value
+        let floor = storage.stats().memory_used;
+
+        let done = Arc::new(AtomicBool::new(false));
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let storage = Arc::clone(&storage);
+                let (small, large) = (small.clone(), large.clone());
+                thread::spawn(move || {
+                    for n in 0..5000 {
+                        let key = format!("key{}", (n + t) % KEYS);
+                        let value = if n % 2 == 0 { &large } else { &small };
+                        storage.set(&key, value.clone(), 0, 0);
+                    }
+                })
+            })
+            .collect();
+
+        let reader = {
+            let storage = Arc::clone(&storage);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut reads = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let used = storage.memory_used.load(Ordering::SeqCst) as usize;
+                    assert!(used >= floor, "memory_used {used} dipped below {floor}");
+                    reads += 1;
+                }
+                reads
+            })
+        };
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_reset_stats_under_load() {
+        const READERS: u64 = 4;
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("hit", b"v".to_vec(), 0, 0);
+        let done = Arc::new(AtomicBool::new(false));
+        // Lookups each reader has finished, counted after storage does
+        let lookups = Arc::new(AtomicU64::new(0));
+
+        let readers: Vec<_> = (0..READERS)
+            .map(|t| {
+                let (storage, done, lookups) = (
+                    Arc::clone(&storage),
+                    Arc::clone(&done),
+                    Arc::clone(&lookups),
+                );
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        storage.get(if t % 2 == 0 { "hit" } else { "miss" });
+                        lookups.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        while lookups.load(Ordering::SeqCst) < 10_000 {
+            thread::yield_now();
+        }
+
+        for _ in 0..100 {
+            let before = lookups.load(Ordering::SeqCst);
+            storage.reset_stats();
+            let stats = storage.stats();
+            let since = lookups.load(Ordering::SeqCst) - before;
+            // Nothing from before the reset, and no wrapped negatives: at
+            // most the lookups since, plus one in progress per reader
+            let counted = stats.get_hits + stats.get_misses;
+            assert!(
+                counted <= since + READERS,
+                "{counted} counted, {since} made"
+            );
+            assert_eq!(stats.cmd_set, 0);
+
+            // Counting resumes from the baseline
+            let later = storage.stats();
+            assert!(later.get_hits >= stats.get_hits);
+            assert!(later.get_misses >= stats.get_misses);
+        }
+
+        // Hits and misses both count again after the last reset (readers
+        // may need a while to be scheduled on a busy machine)
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let resumed = loop {
+            let stats = storage.stats();
+            if (stats.get_hits > 0 && stats.get_misses > 0) || Instant::now() > deadline {
+                break stats;
+            }
+            thread::yield_now();
+        };
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert!(resumed.get_hits > 0 && resumed.get_misses > 0);
+        assert_eq!(resumed.item_count, 1, "gauges are not reset");
     }
 }
@@ -0,0 +1,236 @@
+//! Per-connection optimistic transactions (RESP `WATCH`/`MULTI`/`EXEC`).
+//!
+//! A `Transaction` records the CAS token of each watched key at `WATCH`
+//! time and queues commands issued after `MULTI`. `EXEC` hands both to
+//! `Storage::commit_if_unchanged`, which validates every watched key and
+//! runs the queued commands under one lock, or aborts if any key changed.
+
+use crate::storage::{Storage, StorageResult, TxnOp};
+use std::collections::HashMap;
+
+/// Transaction state owned by a single connection.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    /// Watched keys and the CAS token observed at WATCH time (`None` = absent)
+    watched: HashMap<String, Option<u64>>,
+    /// Commands queued since MULTI (`None` = not in MULTI)
+    queued: Option<Vec<TxnOp>>,
+}
+
+impl Transaction {
+    /// Watch `key`, remembering its current CAS token.
+    ///
+    /// Re-watching a key keeps the first observation, matching Redis.
+    pub fn watch(&mut self, storage: &Storage, key: &str) {
+        if !self.watched.contains_key(key) {
+            self.watched.insert(key.to_string(), storage.cas_of(key));
+        }
+    }
+
+    /// Forget all watched keys.
+    pub fn unwatch(&mut self) {
+        self.watched.clear();
+    }
+
+    /// Start queueing commands. Returns `false` if already in MULTI.
+    pub fn multi(&mut self) -> bool {
+        if self.queued.is_some() {
+            return false;
+        }
+        self.queued = Some(Vec::new());
+        true
+    }
+
+    /// Whether commands are currently being queued.
+    pub fn in_multi(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Queue a command. Returns `false` if not in MULTI.
+    pub fn queue(&mut self, op: TxnOp) -> bool {
+        match self.queued.as_mut() {
+            Some(queued) => {
+                queued.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop queued commands and watched keys.
+    pub fn discard(&mut self) {
+        self.queued = None;
+        self.watched.clear();
+    }
+
+    /// Commit queued commands if no watched key changed.
+    ///
+    /// Returns `None` if the transaction aborted (EXEC replies with a null
+    /// array) and the per-write results otherwise. Watches are cleared
+    /// either way. Calling `exec` outside MULTI commits nothing.
+    pub fn exec(&mut self, storage: &Storage) -> Option<Vec<StorageResult>> {
+        let ops = self.queued.take().unwrap_or_default();
+        let watched = std::mem::take(&mut self.watched);
+        storage.commit_if_unchanged(&watched, ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{EvictionPolicy, SetCondition};
+
+    fn set(key: &str, value: &[u8]) -> TxnOp {
+        TxnOp::Set {
+            key: key.to_string(),
+            value: value.to_vec(),
+            flags: 0,
+            expires_at: None,
+            condition: SetCondition::Always,
+        }
+    }
+
+    #[test]
+    fn test_exec_commits_when_unchanged() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("a", b"1".to_vec(), 0, 0);
+
+        let mut txn = Transaction::default();
+        txn.watch(&storage, "a");
+        assert!(txn.multi());
+        assert!(txn.queue(set("a", b"2")));
+        assert!(txn.queue(set("b", b"3")));
+        assert!(txn.queue(TxnOp::Delete {
+            key: "missing".to_string()
+        }));
+
+        let results = txn.exec(&storage).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                StorageResult::Stored,
+                StorageResult::Stored,
+                StorageResult::NotFound
+            ]
+        );
+        assert_eq!(storage.get("a").unwrap().value, b"2");
+        assert_eq!(storage.get("b").unwrap().value, b"3");
+        assert!(!txn.in_multi());
+    }
+
+    #[test]
+    fn test_exec_aborts_when_watched_key_modified() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("balance", b"100".to_vec(), 0, 0);
+
+        // Connection A watches, then queues a write
+        let mut conn_a = Transaction::default();
+        conn_a.watch(&storage, "balance");
+        conn_a.multi();
+        conn_a.queue(set("balance", b"50"));
+        conn_a.queue(set("audit", b"withdrew 50"));
+
+        // Connection B modifies the watched key in between
+        let mut conn_b = Transaction::default();
+        conn_b.multi();
+        conn_b.queue(set("balance", b"200"));
+        assert!(conn_b.exec(&storage).is_some());
+
+        // EXEC on A aborts and applies none of its writes
+        assert!(conn_a.exec(&storage).is_none());
+        assert_eq!(storage.get("balance").unwrap().value, b"200");
+        assert!(storage.get("audit").is_none());
+    }
+
+    #[test]
+    fn test_watch_absent_key_detects_creation() {
+        let storage = Storage::new(1024 * 1024, 0);
+
+        let mut txn = Transaction::default();
+        txn.watch(&storage, "k");
+        storage.set("k", b"v".to_vec(), 0, 0);
+        txn.multi();
+        txn.queue(set("k", b"mine"));
+
+        assert!(txn.exec(&storage).is_none());
+        assert_eq!(storage.get("k").unwrap().value, b"v");
+    }
+
+    #[test]
+    fn test_discard_and_unwatch() {
+        let storage = Storage::new(1024 * 1024, 0);
+        storage.set("k", b"v".to_vec(), 0, 0);
+
+        let mut txn = Transaction::default();
+        txn.watch(&storage, "k");
+        txn.unwatch();
+        storage.set("k", b"changed".to_vec(), 0, 0);
+        txn.multi();
+        assert!(!txn.multi());
+        txn.queue(set("k", b"mine"));
+        txn.discard();
+        assert!(!txn.queue(set("k", b"late")));
+        assert_eq!(txn.exec(&storage), Some(vec![]));
+        assert_eq!(storage.get("k").unwrap().value, b"changed");
+    }
+
+    #[test]
+    fn test_aborted_exec_evicts_nothing() {
+        let storage = Storage::with_limits(2000, None, 0, EvictionPolicy::Lru);
+        storage.set("watched", b"1".to_vec(), 0, 0);
+        storage.set("other", vec![b'x'; 500], 0, 0);
+
+        let mut txn = Transaction::default();
+        txn.watch(&storage, "watched");
+        storage.set("watched", b"2".to_vec(), 0, 0);
+        txn.multi();
+        txn.queue(set("big", &[b'y'; 1200]));
+
+        // The write would need room, but the watch check fails first
+        assert!(txn.exec(&storage).is_none());
+        assert!(storage.get("other").is_some());
+        assert!(storage.get("watched").is_some());
+    }
+
+    #[test]
+    fn test_exec_nets_room_against_replaced_items() {
+        let storage = Storage::with_limits(2000, None, 0, EvictionPolicy::NoEviction);
+        storage.set("a", vec![b'x'; 600], 0, 0);
+        storage.set("b", vec![b'x'; 600], 0, 0);
+
+        // Rewriting both at the same size fits, though twice 600 bytes more
+        // would not
+        let mut txn = Transaction::default();
+        txn.multi();
+        txn.queue(set("a", &[b'y'; 600]));
+        txn.queue(set("b", &[b'y'; 600]));
+        assert_eq!(
+            txn.exec(&storage),
+            Some(vec![StorageResult::Stored, StorageResult::Stored])
+        );
+        assert_eq!(storage.get("a").unwrap().value, vec![b'y'; 600]);
+    }
+
+    #[test]
+    fn test_exec_evicts_around_the_keys_it_names() {
+        let storage = Storage::with_limits(2000, None, 0, EvictionPolicy::Lru);
+        storage.set("old", vec![b'x'; 600], 0, 0);
+        storage.set("mine", vec![b'x'; 600], 0, 0);
+
+        let mut txn = Transaction::default();
+        txn.multi();
+        txn.queue(TxnOp::Get {
+            key: "mine".to_string(),
+        });
+        txn.queue(set("new", &[b'y'; 600]));
+        assert_eq!(
+            txn.exec(&storage),
+            Some(vec![
+                StorageResult::Value(vec![b'x'; 600]),
+                StorageResult::Stored
+            ])
+        );
+        assert!(storage.get("old").is_none());
+        assert!(storage.get("mine").is_some());
+    }
+}
@@ -0,0 +1,78 @@
+//! `--check-config` exits without starting the server.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "grow-a-cache-check-{}-{name}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn check_config(path: &PathBuf) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .arg("--config")
+        .arg(path)
+        .arg("--check-config")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_valid_config_exits_zero() {
+    let path = write_config(
+        "valid",
+        r#"
+            [server]
+            listen = "127.0.0.1:11299"
+
+            [storage]
+            max_memory = 1048576
+            max_value_size = 65536
+        "#,
+    );
+
+    let output = check_config(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("port: 11299"), "{stdout}");
+    assert!(stdout.contains("max_memory: 1048576"), "{stdout}");
+    assert!(stdout.contains("Configuration OK"), "{stdout}");
+}
+
+#[test]
+fn test_invalid_config_exits_nonzero() {
+    let path = write_config(
+        "invalid",
+        r#"
+            [storage]
+            max_memory = 1024
+            max_value_size = 4096
+        "#,
+    );
+
+    let output = check_config(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("max_value_size (4096) exceeds max_memory (1024)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_unparseable_config_exits_nonzero() {
+    let path = write_config("garbage", "[server\nlisten = ");
+
+    let output = check_config(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(!output.status.success());
+}
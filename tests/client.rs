@@ -0,0 +1,100 @@
+//! `CLIENT ID` and `CLIENT INFO` describe the calling connection on either
+//! runtime: one id per connection, kept for its lifetime.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "resp"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> BufReader<TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return BufReader::new(stream);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+/// Send an inline command and read one line of its reply.
+fn line(client: &mut BufReader<TcpStream>, command: &str) -> String {
+    client
+        .get_mut()
+        .write_all(format!("{command}\r\n").as_bytes())
+        .unwrap();
+    let mut line = String::new();
+    client.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
+
+fn client_id(client: &mut BufReader<TcpStream>) -> u64 {
+    let reply = line(client, "CLIENT ID");
+    reply
+        .strip_prefix(':')
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_else(|| panic!("not an id: {reply}"))
+}
+
+fn check_client_ids(runtime: &str) {
+    let server = start(runtime);
+    let mut first = connect(&server);
+    let mut second = connect(&server);
+
+    let id = client_id(&mut first);
+    assert!(id > 0);
+    assert_eq!(line(&mut first, "SET k v"), "+OK");
+    assert_eq!(client_id(&mut first), id);
+    assert_ne!(client_id(&mut second), id);
+
+    // INFO names the connection as the client sees it
+    assert_eq!(line(&mut first, "CLIENT SETNAME worker-1"), "+OK");
+    assert!(line(&mut first, "CLIENT INFO").starts_with('$'));
+    let mut info = String::new();
+    first.read_line(&mut info).unwrap();
+    let local = first.get_ref().local_addr().unwrap();
+    assert_eq!(info, format!("id={id} addr={local} name=worker-1 resp=2\n"));
+}
+
+#[test]
+fn test_mio_client_ids() {
+    check_client_ids("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_client_ids() {
+    check_client_ids("uring");
+}
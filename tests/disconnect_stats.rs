@@ -0,0 +1,132 @@
+//! `stats` tells a peer closing cleanly apart from a connection reset.
+#![cfg(target_os = "linux")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+/// Send `request` and read until the reply ends with `terminator`.
+fn round_trip(stream: &mut TcpStream, request: &[u8], terminator: &[u8]) -> String {
+    stream.write_all(request).unwrap();
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !reply.ends_with(terminator) {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        reply.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8(reply).unwrap()
+}
+
+/// Close with an RST instead of a FIN.
+fn reset(stream: TcpStream) {
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    // SAFETY: the option value is a live `linger` of the size given
+    let rc = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            &linger as *const libc::linger as *const libc::c_void,
+            std::mem::size_of::<libc::linger>() as libc::socklen_t,
+        )
+    };
+    assert_eq!(rc, 0, "{}", std::io::Error::last_os_error());
+}
+
+fn stat(stats: &str, name: &str) -> u64 {
+    let prefix = format!("STAT {name} ");
+    stats
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .unwrap_or_else(|| panic!("no {name} in {stats}"))
+        .parse()
+        .unwrap()
+}
+
+fn assert_counts_disconnects(runtime: &str) {
+    let server = start(runtime);
+    // Held open throughout, so it never counts itself
+    let mut admin = connect(&server);
+
+    // Each peer has a read in flight by the time it goes away
+    let mut clean = connect(&server);
+    round_trip(&mut clean, b"version\r\n", b"\r\n");
+    drop(clean);
+
+    let mut aborted = connect(&server);
+    round_trip(&mut aborted, b"version\r\n", b"\r\n");
+    reset(aborted);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let stats = round_trip(&mut admin, b"stats\r\n", b"END\r\n");
+        let counts = (
+            stat(&stats, "clean_disconnects"),
+            stat(&stats, "read_errors"),
+        );
+        if counts == (1, 1) || Instant::now() > deadline {
+            assert_eq!(counts, (1, 1), "{stats}");
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_uring_counts_clean_and_reset_disconnects() {
+    assert_counts_disconnects("uring");
+}
+
+#[test]
+fn test_mio_counts_clean_and_reset_disconnects() {
+    assert_counts_disconnects("mio");
+}
@@ -0,0 +1,112 @@
+//! A client streaming a huge pipeline shares its worker fairly: another
+//! client's small requests are answered between the big one's turns.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        // Both clients on the one worker
+        .args(["--workers", "1", "--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+/// Commands in each write of the big pipeline, about 55KB.
+const CHUNK: usize = 5000;
+/// Writes making up the big pipeline.
+const CHUNKS: usize = 40;
+/// Slowest round trip allowed for the small client.
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+
+fn check_fairness(runtime: &str) {
+    let server = start(runtime);
+    let mut small = connect(&server);
+    let mut big = connect(&server);
+
+    // Misses: cheap to answer, so the input, not the replies, sets the pace
+    let mut writer = big.try_clone().unwrap();
+    let sender = thread::spawn(move || {
+        let chunk = b"get nokey\r\n".repeat(CHUNK);
+        for _ in 0..CHUNKS {
+            writer.write_all(&chunk).unwrap();
+        }
+    });
+    let receiver = thread::spawn(move || {
+        let expected = b"END\r\n".len() * CHUNK * CHUNKS;
+        let mut received = 0;
+        let mut buf = vec![0u8; 64 * 1024];
+        while received < expected {
+            let n = big.read(&mut buf).unwrap();
+            assert!(n > 0, "server closed the pipelining connection");
+            assert!(buf[..n].iter().all(|b| b"END\r\n".contains(b)));
+            received += n;
+        }
+        received
+    });
+
+    let mut slowest = Duration::ZERO;
+    let mut reply = [0u8; 64];
+    while !receiver.is_finished() {
+        let sent = Instant::now();
+        small.write_all(b"version\r\n").unwrap();
+        let n = small.read(&mut reply).unwrap();
+        assert!(reply[..n].starts_with(b"VERSION "));
+        slowest = slowest.max(sent.elapsed());
+    }
+
+    sender.join().unwrap();
+    assert_eq!(receiver.join().unwrap(), b"END\r\n".len() * CHUNK * CHUNKS);
+    assert!(
+        slowest < MAX_LATENCY,
+        "small request waited {slowest:?} behind the pipeline"
+    );
+}
+
+#[test]
+fn test_mio_shares_worker_with_small_requests() {
+    check_fairness("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_shares_worker_with_small_requests() {
+    check_fairness("uring");
+}
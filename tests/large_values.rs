@@ -0,0 +1,160 @@
+//! Values larger than one I/O buffer are accumulated in chained buffers;
+//! values over the limit are refused.
+#![cfg(target_os = "linux")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    start_with(runtime, &[])
+}
+
+fn start_with(runtime: &str, args: &[&str]) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .args(args)
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+fn read_reply(stream: &mut TcpStream, len: usize) -> String {
+    let mut reply = vec![0u8; len];
+    stream.read_exact(&mut reply).unwrap();
+    String::from_utf8(reply).unwrap()
+}
+
+fn set_request(key: &str, len: usize) -> Vec<u8> {
+    let mut request = format!("set {key} 0 0 {len}\r\n").into_bytes();
+    request.extend((0..len).map(|i| b'a' + (i % 26) as u8));
+    request.extend_from_slice(b"\r\n");
+    request
+}
+
+fn check_sets_value_larger_than_buffer(runtime: &str) -> (Server, TcpStream) {
+    let server = start(runtime);
+    let mut stream = connect(&server);
+
+    stream.write_all(&set_request("big", 256 * 1024)).unwrap();
+    assert_eq!(read_reply(&mut stream, 8), "STORED\r\n");
+    stream.write_all(b"mg big s\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream, 12), "HD s262144\r\n");
+
+    // Behind a small command, and followed by one, all in one write
+    let mut request = b"mg big s\r\n".to_vec();
+    request.extend_from_slice(&set_request("big2", 300 * 1024));
+    request.extend_from_slice(b"mg big2 s\r\n");
+    stream.write_all(&request).unwrap();
+    let expected = "HD s262144\r\nSTORED\r\nHD s307200\r\n";
+    assert_eq!(read_reply(&mut stream, expected.len()), expected);
+    (server, stream)
+}
+
+#[test]
+fn test_mio_sets_value_larger_than_buffer() {
+    check_sets_value_larger_than_buffer("mio");
+}
+
+#[test]
+fn test_uring_sets_value_larger_than_buffer() {
+    let (_server, mut stream) = check_sets_value_larger_than_buffer("uring");
+
+    // A value that fits a buffer, but not together with its command line
+    stream
+        .write_all(&set_request("edge", 64 * 1024 - 4))
+        .unwrap();
+    assert_eq!(read_reply(&mut stream, 8), "STORED\r\n");
+    stream.write_all(b"mg edge s\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream, 11), "HD s65532\r\n");
+}
+
+#[test]
+fn test_uring_gets_value_larger_than_buffer() {
+    let server = start("uring");
+    let mut stream = connect(&server);
+
+    for (key, len) in [("big", 256 * 1024), ("huge", 4 * 1024 * 1024)] {
+        let request = set_request(key, len);
+        stream.write_all(&request).unwrap();
+        assert_eq!(read_reply(&mut stream, 8), "STORED\r\n");
+
+        stream
+            .write_all(format!("get {key}\r\n").as_bytes())
+            .unwrap();
+        // Read only once the reply has filled the socket buffers
+        thread::sleep(Duration::from_millis(100));
+        let header = format!("VALUE {key} 0 {len}\r\n");
+        let mut expected = header.into_bytes();
+        expected.extend_from_slice(&request[request.len() - len - 2..]);
+        expected.extend_from_slice(b"END\r\n");
+        let mut reply = vec![0u8; expected.len()];
+        stream.read_exact(&mut reply).unwrap();
+        assert!(reply == expected, "{key} reply differs");
+    }
+
+    // The connection reads on after a chained reply
+    stream.write_all(b"mg big s\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream, 12), "HD s262144\r\n");
+}
+
+/// An oversized set is refused, then the connection closes rather than
+/// reading the value as commands.
+fn check_oversized_value_closes(runtime: &str) {
+    let server = start_with(runtime, &["--max-value-size", "1024"]);
+    let mut stream = connect(&server);
+
+    // Only the command line, so the server closes with nothing unread
+    stream.write_all(b"set big 0 0 2048\r\n").unwrap();
+    assert_eq!(
+        read_reply(&mut stream, 30),
+        "CLIENT_ERROR value too large\r\n"
+    );
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+}
+
+#[test]
+fn test_mio_closes_after_oversized_value() {
+    check_oversized_value_closes("mio");
+}
+
+#[test]
+fn test_uring_closes_after_oversized_value() {
+    check_oversized_value_closes("uring");
+}
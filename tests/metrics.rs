@@ -0,0 +1,118 @@
+//! The admin endpoint's `/metrics` follows what clients do on either
+//! runtime.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+    admin_port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// A port nothing listens on; released right away for the server to claim.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start(runtime: &str) -> Server {
+    let (port, admin_port) = (free_port(), free_port());
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--admin-listen", &format!("127.0.0.1:{admin_port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server {
+        child,
+        port,
+        admin_port,
+    }
+}
+
+fn connect(port: u16) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+/// The value of `name` in a scrape of `/metrics`.
+fn metric(server: &Server, name: &str) -> f64 {
+    let mut stream = connect(server.admin_port);
+    stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{name} ")))
+        .unwrap_or_else(|| panic!("no {name} in:\n{response}"))
+        .parse()
+        .unwrap()
+}
+
+fn check_metrics(runtime: &str) {
+    let server = start(runtime);
+    let mut first = connect(server.port);
+    let request = b"set k 0 0 1\r\nv\r\nget k\r\nget k\r\nget nope\r\n";
+    first.write_all(request).unwrap();
+    let expected = b"STORED\r\nVALUE k 0 1\r\nv\r\nEND\r\nVALUE k 0 1\r\nv\r\nEND\r\nEND\r\n";
+    let mut reply = vec![0u8; expected.len()];
+    first.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, expected);
+    let second = connect(server.port);
+
+    assert_eq!(metric(&server, "grow_a_cache_items"), 1.0);
+    assert_eq!(metric(&server, "grow_a_cache_hits_total"), 2.0);
+    assert_eq!(metric(&server, "grow_a_cache_misses_total"), 1.0);
+    // The second may still be queued for accept
+    for _ in 0..100 {
+        if metric(&server, "grow_a_cache_connections") == 2.0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(metric(&server, "grow_a_cache_connections"), 2.0);
+
+    drop((first, second));
+    for _ in 0..100 {
+        if metric(&server, "grow_a_cache_connections") == 0.0 {
+            return;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("closed connections still counted on {runtime}");
+}
+
+#[test]
+fn test_mio_reports_metrics() {
+    check_metrics("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_reports_metrics() {
+    check_metrics("uring");
+}
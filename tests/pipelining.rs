@@ -0,0 +1,107 @@
+//! Commands sent together in one write are all answered, in order.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+fn read_reply(stream: &mut TcpStream, len: usize) -> String {
+    let mut reply = vec![0u8; len];
+    stream.read_exact(&mut reply).unwrap();
+    String::from_utf8(reply).unwrap()
+}
+
+fn assert_pipelines(runtime: &str) {
+    let server = start(runtime);
+    let mut stream = connect(&server);
+
+    let mut request = Vec::new();
+    let mut expected = String::new();
+    for (key, value) in [("a", "1"), ("b", "22"), ("c", "333")] {
+        request.extend_from_slice(
+            format!("set {key} 0 0 {}\r\n{value}\r\nget {key}\r\n", value.len()).as_bytes(),
+        );
+        expected.push_str(&format!(
+            "STORED\r\nVALUE {key} 0 {}\r\n{value}\r\nEND\r\n",
+            value.len()
+        ));
+    }
+    stream.write_all(&request).unwrap();
+    assert_eq!(read_reply(&mut stream, expected.len()), expected);
+
+    // Replies to silent commands are skipped, not left owing
+    stream
+        .write_all(b"set d 0 0 1 noreply\r\nd\r\nget d\r\n")
+        .unwrap();
+    let expected = "VALUE d 0 1\r\nd\r\nEND\r\n";
+    assert_eq!(read_reply(&mut stream, expected.len()), expected);
+    stream.write_all(b"set e 0 0 1 noreply\r\ne\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"get e\r\n").unwrap();
+    let expected = "VALUE e 0 1\r\ne\r\nEND\r\n";
+    assert_eq!(read_reply(&mut stream, expected.len()), expected);
+
+    // Replies outgrowing one write are sent over several, all answered
+    let value = "v".repeat(1000);
+    stream
+        .write_all(format!("set f 0 0 1000\r\n{value}\r\n").as_bytes())
+        .unwrap();
+    assert_eq!(read_reply(&mut stream, 8), "STORED\r\n");
+    stream.write_all(&b"get f\r\n".repeat(200)).unwrap();
+    let expected = format!("VALUE f 0 1000\r\n{value}\r\nEND\r\n").repeat(200);
+    assert!(read_reply(&mut stream, expected.len()) == expected);
+}
+
+#[test]
+fn test_mio_answers_pipelined_commands() {
+    assert_pipelines("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_answers_pipelined_commands() {
+    assert_pipelines("uring");
+}
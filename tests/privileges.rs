@@ -0,0 +1,104 @@
+//! Started as root with `--user`, the server binds a privileged port, then
+//! serves it as the unprivileged user.
+//!
+//! Needs root, so it passes without checking anything otherwise.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// A port below 1024 that nothing listens on, from a range of its own for
+/// each test.
+fn privileged_port(mut range: std::ops::Range<u16>) -> u16 {
+    range
+        .find(|&port| TcpListener::bind(("127.0.0.1", port)).is_ok())
+        .expect("no free privileged port")
+}
+
+fn start(runtime: &str, ports: std::ops::Range<u16>) -> Server {
+    let port = privileged_port(ports);
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--user", "nobody", "--workers", "2"])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+/// The real, effective, saved and filesystem uids of process `pid`.
+fn uids(pid: u32) -> Vec<u32> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).unwrap();
+    let line = status.lines().find(|l| l.starts_with("Uid:")).unwrap();
+    line.split_whitespace()
+        .skip(1)
+        .map(|id| id.parse().unwrap())
+        .collect()
+}
+
+/// Uid of the `nobody` account.
+fn nobody_uid() -> u32 {
+    // SAFETY: the name is NUL-terminated; the entry is read right away
+    let entry = unsafe { libc::getpwnam(c"nobody".as_ptr()) };
+    assert!(!entry.is_null(), "no nobody user");
+    unsafe { (*entry).pw_uid }
+}
+
+fn check_serves_after_dropping_root(runtime: &str, ports: std::ops::Range<u16>) {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping: binding a privileged port needs root");
+        return;
+    }
+    let server = start(runtime, ports);
+    let mut stream = connect(&server);
+
+    stream.write_all(b"set k 0 0 1\r\nv\r\nget k\r\n").unwrap();
+    let expected = b"STORED\r\nVALUE k 0 1\r\nv\r\nEND\r\n";
+    let mut reply = vec![0u8; expected.len()];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, expected);
+
+    // nobody, with no way back to root
+    assert_eq!(uids(server.child.id()), [nobody_uid(); 4]);
+}
+
+#[test]
+fn test_mio_serves_after_dropping_root() {
+    check_serves_after_dropping_root("mio", 600..800);
+}
+
+#[test]
+fn test_uring_serves_after_dropping_root() {
+    check_serves_after_dropping_root("uring", 800..1000);
+}
@@ -0,0 +1,113 @@
+//! Each runtime speaks the protocol it was started with; with `auto`,
+//! whichever of memcached and RESP each client opens with.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str, protocol: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", protocol])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+/// Send `request` and read until `expected.len()` bytes have come back.
+fn query(server: &Server, request: &[u8], expected: &[u8]) -> Vec<u8> {
+    let mut stream = None;
+    for _ in 0..100 {
+        match TcpStream::connect(("127.0.0.1", server.port)) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+    let mut stream = stream.expect("server never accepted");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(request).unwrap();
+
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 4096];
+    while reply.len() < expected.len() {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        reply.extend_from_slice(&buf[..n]);
+    }
+    reply
+}
+
+/// A request in each protocol and the exact reply it gets.
+const EXCHANGES: &[(&str, &[u8], &[u8])] = &[
+    (
+        "memcached",
+        b"set k 0 0 1\r\nv\r\nget k\r\n",
+        b"STORED\r\nVALUE k 0 1\r\nv\r\nEND\r\n",
+    ),
+    (
+        "resp",
+        b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n",
+        b"+OK\r\n$1\r\nv\r\n",
+    ),
+    // A malformed command costs only itself, not the connection
+    (
+        "memcached",
+        b"set k 99999999999 0 1\r\nv\r\nget k\r\n",
+        b"CLIENT_ERROR bad command line format\r\nEND\r\n",
+    ),
+    ("ping", b"PING\r\n", b"PONG\r\n"),
+    ("echo", b"11\r\nhello world", b"11\r\nhello world"),
+    // Either of the two, chosen per connection
+    ("auto", b"get k\r\n", b"END\r\n"),
+    ("auto", b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n", b"$-1\r\n"),
+];
+
+fn check_protocols(runtime: &str) {
+    for (protocol, request, expected) in EXCHANGES {
+        let server = start(runtime, protocol);
+        let reply = query(&server, request, expected);
+        assert_eq!(
+            String::from_utf8_lossy(&reply),
+            String::from_utf8_lossy(expected),
+            "{protocol} on {runtime}"
+        );
+    }
+}
+
+#[test]
+fn test_mio_serves_each_protocol() {
+    check_protocols("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_serves_each_protocol() {
+    check_protocols("uring");
+}
@@ -0,0 +1,125 @@
+//! `SYNC` streams its snapshot a few buffers at a time, on either runtime:
+//! however large the reply, the write chain behind it stays short.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "auto"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+fn connect(server: &Server) -> BufReader<TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", server.port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return BufReader::new(stream);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+fn read_line(client: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    client.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
+
+/// The longest write chain the server reports having used.
+fn chain_peak(client: &mut BufReader<TcpStream>) -> usize {
+    client.get_mut().write_all(b"stats\r\n").unwrap();
+    let mut peak = None;
+    loop {
+        let line = read_line(client);
+        if line == "END" {
+            return peak.expect("no response_chain_peak stat");
+        }
+        if let Some(value) = line.strip_prefix("STAT response_chain_peak ") {
+            peak = Some(value.parse().unwrap());
+        }
+    }
+}
+
+fn check_sync_buffering_is_bounded(runtime: &str) {
+    let server = start(runtime);
+    let mut memcached = connect(&server);
+
+    // About 8 MB of items, over a hundred 64 KB buffers' worth
+    let value = "v".repeat(4000);
+    let items = 2000;
+    let mut sets = Vec::new();
+    for i in 0..items {
+        sets.extend_from_slice(format!("set key{i} 0 0 4000\r\n{value}\r\n").as_bytes());
+    }
+    memcached.get_mut().write_all(&sets).unwrap();
+    for _ in 0..items {
+        assert_eq!(read_line(&mut memcached), "STORED");
+    }
+
+    let mut resp = connect(&server);
+    resp.get_mut().write_all(b"*1\r\n$4\r\nSYNC\r\n").unwrap();
+    let header = read_line(&mut resp);
+    let len: usize = header
+        .strip_prefix('$')
+        .and_then(|len| len.parse().ok())
+        .unwrap_or_else(|| panic!("not a bulk string: {header}"));
+    assert!(len > items * 4000, "{len} bytes");
+
+    // Read slowly at first, so the server waits on the socket to drain
+    let mut snapshot = vec![0u8; len + 2];
+    let (start, rest) = snapshot.split_at_mut(64 * 1024);
+    for chunk in start.chunks_mut(4096) {
+        resp.read_exact(chunk).unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    resp.read_exact(rest).unwrap();
+    assert!(snapshot.starts_with(b"GACS"));
+    assert!(snapshot.ends_with(b"\r\n"));
+
+    // The connection carries on after the stream
+    resp.get_mut().write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+    assert_eq!(read_line(&mut resp), "+PONG");
+
+    assert_eq!(chain_peak(&mut memcached), 4);
+}
+
+#[test]
+fn test_mio_sync_buffering_is_bounded() {
+    check_sync_buffering_is_bounded("mio");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_sync_buffering_is_bounded() {
+    check_sync_buffering_is_bounded("uring");
+}
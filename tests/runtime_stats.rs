@@ -0,0 +1,108 @@
+//! `stats` and `INFO` name the runtime backend the server was started with.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str, protocol: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args([
+            "--runtime",
+            runtime,
+            "--protocol",
+            protocol,
+            "--workers",
+            "2",
+        ])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+/// Send `request` and read until the reply ends with `terminator`.
+fn query(server: &Server, request: &[u8], terminator: &[u8]) -> String {
+    let mut stream = None;
+    for _ in 0..100 {
+        match TcpStream::connect(("127.0.0.1", server.port)) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+    let mut stream = stream.expect("server never accepted");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(request).unwrap();
+
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !reply.ends_with(terminator) {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        reply.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8(reply).unwrap()
+}
+
+fn assert_reports(runtime: &str, backend: &str) {
+    let server = start(runtime, "memcached");
+    let stats = query(&server, b"stats\r\n", b"END\r\n");
+    assert!(
+        stats.contains(&format!("STAT runtime {backend}\r\n")),
+        "{stats}"
+    );
+    assert!(stats.contains("STAT worker_count 2\r\n"), "{stats}");
+    drop(server);
+
+    let server = start(runtime, "resp");
+    let info = query(
+        &server,
+        b"*1\r\n$4\r\nINFO\r\n",
+        b"expired_unfetched:0\r\n\r\n",
+    );
+    assert!(info.contains("redis_mode:standalone\r\n"), "{info}");
+    assert!(info.contains(&format!("runtime:{backend}\r\n")), "{info}");
+    assert!(info.contains("io_threads:2\r\n"), "{info}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_uring_reports_io_uring() {
+    assert_reports("uring", "io_uring");
+}
+
+#[test]
+fn test_mio_reports_native_backend() {
+    let backend = if cfg!(target_os = "linux") {
+        "epoll"
+    } else {
+        "kqueue"
+    };
+    assert_reports("mio", backend);
+}
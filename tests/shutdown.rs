@@ -0,0 +1,130 @@
+//! SIGTERM drains the server: replies being written finish, idle
+//! connections are closed, and the process exits successfully.
+#![cfg(target_os = "linux")]
+
+use socket2::{Domain, Socket, Type};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+/// Connect with a small receive buffer, so a large reply stays in flight
+/// until the client reads it.
+fn connect(server: &Server) -> TcpStream {
+    let addr: SocketAddr = ([127, 0, 0, 1], server.port).into();
+    for _ in 0..100 {
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        socket.set_recv_buffer_size(4096).unwrap();
+        if socket.connect(&addr.into()).is_ok() {
+            let stream = TcpStream::from(socket);
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+fn read_reply(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut reply = vec![0u8; len];
+    stream.read_exact(&mut reply).unwrap();
+    reply
+}
+
+fn wait_for_exit(server: &mut Server) -> ExitStatus {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if let Some(status) = server.child.try_wait().unwrap() {
+            return status;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("server did not exit after SIGTERM");
+}
+
+/// Values repeated in the reply left in flight, several MB in all.
+const COPIES: usize = 100;
+
+/// Send SIGTERM while one client is owed a multi-MB reply and another sits
+/// idle.
+fn check_drain(runtime: &str) {
+    // mio stores values up to one buffer
+    let value_len = 60 * 1024;
+    let mut server = start(runtime);
+    let mut idle = connect(&server);
+    let mut busy = connect(&server);
+
+    let value: Vec<u8> = (0..value_len).map(|i| b'a' + (i % 26) as u8).collect();
+    let mut request = format!("set big 0 0 {value_len}\r\n").into_bytes();
+    request.extend_from_slice(&value);
+    request.extend_from_slice(b"\r\n");
+    busy.write_all(&request).unwrap();
+    assert_eq!(read_reply(&mut busy, 8), b"STORED\r\n");
+    idle.write_all(b"version\r\n").unwrap();
+    assert!(idle.read(&mut [0u8; 64]).unwrap() > 0);
+
+    let keys = vec!["big"; COPIES].join(" ");
+    busy.write_all(format!("get {keys}\r\n").as_bytes())
+        .unwrap();
+    // Let the reply back up in the socket buffers before asking to stop
+    thread::sleep(Duration::from_millis(100));
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) };
+
+    let mut expected = Vec::new();
+    for _ in 0..COPIES {
+        expected.extend_from_slice(format!("VALUE big 0 {value_len}\r\n").as_bytes());
+        expected.extend_from_slice(&value);
+        expected.extend_from_slice(b"\r\n");
+    }
+    expected.extend_from_slice(b"END\r\n");
+    assert!(
+        read_reply(&mut busy, expected.len()) == expected,
+        "reply differs"
+    );
+    assert_eq!(busy.read(&mut [0u8; 1]).unwrap(), 0);
+    assert_eq!(idle.read(&mut [0u8; 1]).unwrap(), 0);
+
+    assert!(wait_for_exit(&mut server).success());
+}
+
+#[test]
+fn test_mio_drains_on_sigterm() {
+    check_drain("mio");
+}
+
+#[test]
+fn test_uring_drains_on_sigterm() {
+    check_drain("uring");
+}
@@ -0,0 +1,112 @@
+//! With `--snapshot-path`, the cache survives a restart: SIGTERM saves it
+//! and the next start loads it back.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(runtime: &str, snapshot: &Path) -> Server {
+    // Released right away; the server claims it next
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", runtime, "--protocol", "memcached"])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .arg("--snapshot-path")
+        .arg(snapshot)
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child, port }
+}
+
+/// Send `request` and read the `reply_len` bytes of its reply.
+fn query(server: &Server, request: &[u8], reply_len: usize) -> Vec<u8> {
+    let mut stream = None;
+    for _ in 0..100 {
+        match TcpStream::connect(("127.0.0.1", server.port)) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+    let mut stream = stream.expect("server never accepted");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(request).unwrap();
+    let mut reply = vec![0u8; reply_len];
+    stream.read_exact(&mut reply).unwrap();
+    reply
+}
+
+fn stop(mut server: Server) {
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) };
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if let Some(status) = server.child.try_wait().unwrap() {
+            assert!(status.success(), "exited with {status}");
+            return;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("server did not exit after SIGTERM");
+}
+
+fn check_restart_keeps_items(runtime: &str) {
+    let snapshot = std::env::temp_dir().join(format!(
+        "grow-a-cache-restart-{runtime}-{}.bin",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&snapshot);
+
+    // No snapshot yet: starts empty
+    let server = start(runtime, &snapshot);
+    let reply = query(&server, b"set k 5 300 5\r\nhello\r\n", 8);
+    assert_eq!(reply, b"STORED\r\n");
+    stop(server);
+    assert!(snapshot.exists(), "no snapshot saved on shutdown");
+
+    let server = start(runtime, &snapshot);
+    let expected = b"VALUE k 5 5\r\nhello\r\nEND\r\n";
+    let reply = query(&server, b"get k\r\n", expected.len());
+    assert_eq!(
+        String::from_utf8_lossy(&reply),
+        String::from_utf8_lossy(expected)
+    );
+    drop(server);
+    fs::remove_file(&snapshot).unwrap();
+}
+
+#[test]
+fn test_mio_restart_keeps_items() {
+    check_restart_keeps_items("mio");
+}
+
+#[test]
+fn test_uring_restart_keeps_items() {
+    check_restart_keeps_items("uring");
+}
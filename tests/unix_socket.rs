@@ -0,0 +1,79 @@
+//! Serving over a Unix domain socket (`listen = "unix:<path>"`).
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A running server, killed on drop.
+struct Server {
+    child: Child,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn start(path: &Path) -> Server {
+    let child = Command::new(env!("CARGO_BIN_EXE_grow-a-cache"))
+        .args(["--runtime", "mio", "--protocol", "memcached"])
+        .arg("--listen")
+        .arg(format!("unix:{}", path.display()))
+        .args(["--log-level", "error"])
+        .spawn()
+        .unwrap();
+    Server { child }
+}
+
+fn connect(path: &Path) -> UnixStream {
+    for _ in 0..100 {
+        if let Ok(stream) = UnixStream::connect(path) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server never accepted");
+}
+
+fn socket_path() -> PathBuf {
+    std::env::temp_dir().join(format!("grow-a-cache-{}.sock", std::process::id()))
+}
+
+#[test]
+fn test_version_over_unix_socket() {
+    let path = socket_path();
+    // Left behind by a server that didn't shut down cleanly
+    drop(UnixListener::bind(&path).unwrap());
+
+    let mut server = start(&path);
+    let mut stream = connect(&path);
+    stream.write_all(b"version\r\n").unwrap();
+    let mut reply = [0u8; 64];
+    let n = stream.read(&mut reply).unwrap();
+    assert!(reply[..n].starts_with(b"VERSION "), "{reply:?}");
+
+    // A graceful shutdown removes the socket file
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) };
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = server.child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "server did not exit after SIGTERM"
+        );
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert!(status.success());
+    assert!(!path.exists());
+}